@@ -0,0 +1,81 @@
+//! Compares the cost of encrypting a community message for every member with a per-recipient
+//! payload against encrypting it once with the sender's community key, as done by `CommunityTask`.
+//!
+//! Run with `cargo bench -p warp-ipfs --bench sender_key`
+
+use std::time::{Duration, Instant};
+
+use rust_ipfs::Keypair;
+use warp::crypto::{cipher::Cipher, generate, DID};
+use warp_ipfs::store::{
+    payload::{PayloadBuilder, PayloadMessage},
+    PeerIdExt,
+};
+
+const ITERATIONS: u32 = 10;
+const MEMBERS: [usize; 4] = [10, 100, 250, 500];
+
+fn measure(mut f: impl FnMut() -> anyhow::Result<()>) -> anyhow::Result<Duration> {
+    f()?;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f()?;
+    }
+    Ok(start.elapsed() / ITERATIONS)
+}
+
+fn main() -> anyhow::Result<()> {
+    // roughly the size of a serialized message event with a short text message
+    let event = vec![0u8; 512];
+    let sender = Keypair::generate_ed25519();
+    let community_key = generate::<64>();
+
+    println!(
+        "{:>8} {:>16} {:>16} {:>16} {:>16}",
+        "members", "per recipient", "sender key", "recipient size", "sender key size"
+    );
+
+    for count in MEMBERS {
+        let members = (0..count)
+            .map(|_| Keypair::generate_ed25519())
+            .collect::<Vec<_>>();
+        let recipients = members
+            .iter()
+            .map(|keypair| keypair.to_did())
+            .collect::<Result<Vec<DID>, _>>()?;
+        let member = &members[0];
+
+        let mut recipient_size = 0;
+        let per_recipient = measure(|| {
+            let payload = PayloadBuilder::new(&sender, event.clone())
+                .add_recipients(recipients.clone())?
+                .build()?;
+            let bytes = payload.to_bytes()?;
+            recipient_size = bytes.len();
+
+            let payload = PayloadMessage::<Vec<u8>>::from_bytes(&bytes)?;
+            assert_eq!(payload.message(member)?, event);
+            Ok(())
+        })?;
+
+        let mut sender_key_size = 0;
+        let sender_key = measure(|| {
+            let bytes = Cipher::direct_encrypt(&event, &community_key)?;
+            let payload = PayloadBuilder::new(&sender, bytes).build()?;
+            let bytes = payload.to_bytes()?;
+            sender_key_size = bytes.len();
+
+            let payload = PayloadMessage::<Vec<u8>>::from_bytes(&bytes)?;
+            let message = Cipher::direct_decrypt(&payload.message(None)?, &community_key)?;
+            assert_eq!(message, event);
+            Ok(())
+        })?;
+
+        println!(
+            "{:>8} {:>16?} {:>16?} {:>16} {:>16}",
+            count, per_recipient, sender_key, recipient_size, sender_key_size
+        );
+    }
+
+    Ok(())
+}
@@ -1011,6 +1011,7 @@ async fn message_event_handle<M: MultiPass, R: RayGun>(
         MessageEventKind::MessageReceived {
             conversation_id,
             message_id,
+            ..
         }
         | MessageEventKind::MessageSent {
             conversation_id,
@@ -1039,6 +1040,14 @@ async fn message_event_handle<M: MultiPass, R: RayGun>(
                                     attachment.size()
                                 )?;
 
+                                if let Some(voice_note) = attachment.voice_note() {
+                                    writeln!(
+                                        stdout,
+                                        ">> Voice note of {}s",
+                                        voice_note.duration().as_secs()
+                                    )?;
+                                }
+
                                 writeln!(
                                     stdout,
                                     ">> Do `/download {} {} <path>` to download",
@@ -1048,6 +1057,40 @@ async fn message_event_handle<M: MultiPass, R: RayGun>(
                             }
                         }
                         MessageType::Event => {}
+                        MessageType::Poll => {
+                            if let Some(poll) = message.poll() {
+                                writeln!(stdout, "[{}] @> Poll: {}", username, poll.question())?;
+                                for (index, (option, votes)) in
+                                    poll.options().iter().zip(poll.tally()).enumerate()
+                                {
+                                    writeln!(stdout, ">> {index}. {option} ({votes} votes)")?;
+                                }
+                            }
+                        }
+                        MessageType::Geolocation => {
+                            if let Some(geolocation) = message.geolocation() {
+                                let position = geolocation.position();
+                                writeln!(
+                                    stdout,
+                                    "[{}] @> Location: {}, {}",
+                                    username,
+                                    position.latitude(),
+                                    position.longitude()
+                                )?;
+                            }
+                        }
+                        MessageType::ContactCard => {
+                            if let Some(card) = message.contact_card() {
+                                writeln!(
+                                    stdout,
+                                    "[{}] @> Contact: {}#{} ({})",
+                                    username,
+                                    card.username(),
+                                    card.short_id(),
+                                    card.did()
+                                )?;
+                            }
+                        }
                     }
                 }
             }
@@ -1123,6 +1166,9 @@ async fn message_event_handle<M: MultiPass, R: RayGun>(
                     MessageEvent::Typing => {
                         writeln!(stdout, ">>> {username} is typing",)?;
                     }
+                    MessageEvent::Custom(event) => {
+                        writeln!(stdout, ">>> {username} sent event {}", event.name())?;
+                    }
                 }
             }
         }
@@ -1138,6 +1184,9 @@ async fn message_event_handle<M: MultiPass, R: RayGun>(
                     MessageEvent::Typing => {
                         writeln!(stdout, ">>> {username} is no longer typing",)?;
                     }
+                    MessageEvent::Custom(event) => {
+                        writeln!(stdout, ">>> {username} cancelled event {}", event.name())?;
+                    }
                 }
             }
         }
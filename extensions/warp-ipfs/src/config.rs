@@ -5,6 +5,8 @@ use rust_ipfs as ipfs;
 
 use warp::{constellation::file::FileType, multipass::identity::Identity};
 
+use crate::store::conversation::link_preview::LinkPreviewFetcher;
+
 #[derive(Default, Debug, Clone)]
 pub enum Bootstrap {
     Ipfs,
@@ -137,6 +139,24 @@ pub struct StoreSetting {
     pub announce_to_mesh: bool,
     /// Function to call to provide data for a default profile picture if one is not apart of the identity
     pub default_profile_picture: Option<DefaultPfpFn>,
+    /// Disable sending read receipts to other participants
+    /// Note: Delivery receipts will still be sent
+    pub disable_read_receipts: bool,
+    /// Export the root document to shuttle whenever a conversation or channel is marked as read
+    /// so the last read message is kept in sync across devices
+    pub sync_read_markers: bool,
+    /// Export the root document to shuttle whenever a draft is stored or cleared
+    /// so drafts are kept in sync across devices
+    pub sync_drafts: bool,
+    /// Fetcher used to generate link previews when embeds are enabled for a message.
+    /// Defaults to [`HttpFetcher`](crate::store::conversation::link_preview::HttpFetcher) on native targets
+    /// Note: If `None`, link previews cannot be generated
+    pub link_preview_fetcher: Option<std::sync::Arc<dyn LinkPreviewFetcher>>,
+    /// Publish prekeys with the identity and encrypt direct conversations with a double ratchet session,
+    /// providing forward secrecy. Existing conversations are upgraded once the peer publishes prekeys
+    /// Note: Only messages in transit are covered. Stored messages remain encrypted with the identity keys
+    /// Note: Messages received from a ratchet session are always decrypted, even when disabled
+    pub ratchet_sessions: bool,
 }
 
 impl std::fmt::Debug for StoreSetting {
@@ -145,6 +165,19 @@ impl std::fmt::Debug for StoreSetting {
     }
 }
 
+fn default_link_preview_fetcher() -> Option<std::sync::Arc<dyn LinkPreviewFetcher>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Some(std::sync::Arc::new(
+            crate::store::conversation::link_preview::HttpFetcher::default(),
+        ))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        None
+    }
+}
+
 impl Default for StoreSetting {
     fn default() -> Self {
         Self {
@@ -159,6 +192,11 @@ impl Default for StoreSetting {
             with_friends: false,
             default_profile_picture: None,
             announce_to_mesh: false,
+            disable_read_receipts: false,
+            sync_read_markers: false,
+            sync_drafts: false,
+            link_preview_fetcher: default_link_preview_fetcher(),
+            ratchet_sessions: false,
         }
     }
 }
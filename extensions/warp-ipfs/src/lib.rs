@@ -36,6 +36,11 @@ use crate::store::{ecdh_decrypt, PeerIdExt};
 use crate::store::{MAX_IMAGE_SIZE, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH};
 use crate::utils::{ByteCollection, ReaderStream};
 use config::Config;
+use store::conversation::archive::{ArchiveImport, ImportedMessage};
+use store::conversation::export::{
+    ExportFormat, ExportOptions, ExportProgressStream, ExportedConversation,
+};
+use store::conversation::reference::ReferenceStats;
 use store::document::ResolvedRootDocument;
 use store::event_subscription::EventSubscription;
 use store::files::FileStore;
@@ -66,11 +71,12 @@ use warp::raygun::{
     community::{
         Community, CommunityChannel, CommunityChannelType, CommunityInvite, RayGunCommunity,
     },
-    AttachmentEventStream, Conversation, ConversationImage, EmbedState, GroupPermissionOpt,
-    Location, Message, MessageEvent, MessageEventStream, MessageOptions, MessageReference,
-    MessageStatus, Messages, PinState, RayGun, RayGunAttachment, RayGunConversationInformation,
+    AttachmentEventStream, Conversation, ConversationImage, ConversationInvite, Draft, EmbedState,
+    GeoPosition, GroupPermissionOpt, Location, Message, MessageEvent, MessageEventStream,
+    MessageOptions, MessageReceipt, MessageReference, MessageStatus, Messages,
+    NotificationSettings, PinState, Poll, RayGun, RayGunAttachment, RayGunConversationInformation,
     RayGunEventKind, RayGunEventStream, RayGunEvents, RayGunGroupConversation, RayGunStream,
-    ReactionState,
+    ReactionState, ScheduledMessage, SearchQuery, SearchResult,
 };
 use warp::tesseract::{Tesseract, TesseractEvent};
 use warp::warp::Warp;
@@ -663,6 +669,7 @@ impl WarpIpfs {
 
         let message_store = MessageStore::new(
             &ipfs,
+            &self.inner.config,
             discovery,
             &filestore,
             self.raygun_tx.clone(),
@@ -720,6 +727,71 @@ impl WarpIpfs {
             .ok_or(Error::RayGunExtensionUnavailable)
     }
 
+    /// Compacts the message references of a conversation, returning the resulting layout.
+    /// Note: Compaction is also performed periodically in the background once the references become fragmented
+    pub async fn compact_messages(&self, conversation_id: Uuid) -> Result<ReferenceStats, Error> {
+        self.messaging_store()?
+            .compact_messages(conversation_id)
+            .await
+    }
+
+    /// Exports all messages of a conversation, along with their edits, reactions, replies and pins,
+    /// into `path` as a `conversation.json` file and a self-contained `index.html` viewer.
+    /// Attachment files are included when enabled in [`ExportOptions`]
+    pub async fn export_conversation(
+        &self,
+        conversation_id: Uuid,
+        path: PathBuf,
+        options: ExportOptions,
+    ) -> Result<ExportProgressStream, Error> {
+        self.messaging_store()?
+            .export_conversation(conversation_id, path, options)
+            .await
+    }
+
+    /// Writes the conversation into an archive at `path` that can be restored with [`WarpIpfs::import_conversation_archive`].
+    /// See [`store::conversation::archive`] for the layout of the archive
+    pub async fn export_conversation_archive(
+        &self,
+        conversation_id: Uuid,
+        path: PathBuf,
+        format: ExportFormat,
+    ) -> Result<ExportProgressStream, Error> {
+        self.messaging_store()?
+            .export_conversation_archive(conversation_id, path, format)
+            .await
+    }
+
+    /// Imports an archive into a local read-only conversation, returning a summary of the import.
+    /// Messages that fail signature verification are kept but flagged as unverified
+    pub async fn import_conversation_archive(&self, path: PathBuf) -> Result<ArchiveImport, Error> {
+        self.messaging_store()?
+            .import_conversation_archive(path)
+            .await
+    }
+
+    /// List conversations that were imported from an archive
+    pub async fn list_imported_conversations(&self) -> Result<Vec<ExportedConversation>, Error> {
+        self.messaging_store()?.list_imported_conversations().await
+    }
+
+    /// Messages of a conversation imported from an archive
+    pub async fn get_imported_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ImportedMessage>, Error> {
+        self.messaging_store()?
+            .get_imported_messages(conversation_id)
+            .await
+    }
+
+    /// Removes a conversation imported from an archive
+    pub async fn remove_imported_conversation(&self, conversation_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .remove_imported_conversation(conversation_id)
+            .await
+    }
+
     pub(crate) fn file_store(&self) -> Result<FileStore, Error> {
         self.inner
             .components
@@ -1485,6 +1557,16 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn get_message_history(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<Message>, Error> {
+        self.messaging_store()?
+            .get_message_history(conversation_id, message_id)
+            .await
+    }
+
     async fn get_message_references(
         &self,
         conversation_id: Uuid,
@@ -1515,6 +1597,122 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn message_receipts(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        self.messaging_store()?
+            .message_receipts(conversation_id, message_id)
+            .await
+    }
+
+    async fn mark_message_read(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .mark_message_read(conversation_id, message_id)
+            .await
+    }
+
+    async fn mark_read(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .mark_read(conversation_id, message_id)
+            .await
+    }
+
+    async fn last_read_message(&self, conversation_id: Uuid) -> Result<Option<Uuid>, Error> {
+        self.messaging_store()?
+            .last_read_message(conversation_id)
+            .await
+    }
+
+    async fn unread_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        self.messaging_store()?.unread_count(conversation_id).await
+    }
+
+    async fn unread_mention_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        self.messaging_store()?
+            .unread_mention_count(conversation_id)
+            .await
+    }
+
+    async fn schedule_send(
+        &mut self,
+        conversation_id: Uuid,
+        lines: Vec<String>,
+        send_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .schedule_send(conversation_id, lines, send_at)
+            .await
+    }
+
+    async fn list_scheduled_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ScheduledMessage>, Error> {
+        self.messaging_store()?
+            .list_scheduled_messages(conversation_id)
+            .await
+    }
+
+    async fn cancel_scheduled_message(
+        &mut self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .cancel_scheduled_message(conversation_id, scheduled_id)
+            .await
+    }
+
+    async fn reschedule_message(
+        &mut self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+        send_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .reschedule_message(conversation_id, scheduled_id, send_at)
+            .await
+    }
+
+    async fn set_draft(&mut self, conversation_id: Uuid, draft: Draft) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_draft(conversation_id, draft)
+            .await
+    }
+
+    async fn get_draft(&self, conversation_id: Uuid) -> Result<Option<Draft>, Error> {
+        self.messaging_store()?.get_draft(conversation_id).await
+    }
+
+    async fn clear_draft(&mut self, conversation_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?.clear_draft(conversation_id).await
+    }
+
+    async fn set_notification_settings(
+        &mut self,
+        conversation_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_notification_settings(conversation_id, settings)
+            .await
+    }
+
+    async fn get_notification_settings(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        self.messaging_store()?
+            .get_notification_settings(conversation_id)
+            .await
+    }
+
     async fn get_messages(
         &self,
         conversation_id: Uuid,
@@ -1525,6 +1723,21 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn get_thread(
+        &self,
+        conversation_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        self.messaging_store()?
+            .get_thread(conversation_id, root_message_id, options)
+            .await
+    }
+
+    async fn search_messages(&self, query: SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        self.messaging_store()?.search_messages(query).await
+    }
+
     async fn send(&mut self, conversation_id: Uuid, value: Vec<String>) -> Result<Uuid, Error> {
         self.messaging_store()?
             .send_message(conversation_id, value)
@@ -1577,6 +1790,73 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn send_poll(&mut self, conversation_id: Uuid, poll: Poll) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .send_poll(conversation_id, poll)
+            .await
+    }
+
+    async fn vote(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .vote(conversation_id, message_id, options)
+            .await
+    }
+
+    async fn retract_vote(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .retract_vote(conversation_id, message_id)
+            .await
+    }
+
+    async fn close_poll(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .close_poll(conversation_id, message_id)
+            .await
+    }
+
+    async fn send_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        position: GeoPosition,
+        live: Option<Duration>,
+    ) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .send_geolocation(conversation_id, position, live)
+            .await
+    }
+
+    async fn update_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        position: GeoPosition,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .update_geolocation(conversation_id, message_id, position)
+            .await
+    }
+
+    async fn stop_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .stop_geolocation(conversation_id, message_id)
+            .await
+    }
+
+    async fn send_contact_card(&mut self, conversation_id: Uuid, did: &DID) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .send_contact_card(conversation_id, did)
+            .await
+    }
+
     async fn reply(
         &mut self,
         conversation_id: Uuid,
@@ -1588,8 +1868,26 @@ impl RayGun for WarpIpfs {
             .await
     }
 
-    async fn embeds(&mut self, _: Uuid, _: Uuid, _: EmbedState) -> Result<(), Error> {
-        Err(Error::Unimplemented)
+    async fn forward(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        target_conversation_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .forward(conversation_id, message_id, target_conversation_id)
+            .await
+    }
+
+    async fn embeds(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: EmbedState,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .embeds(conversation_id, message_id, state)
+            .await
     }
 
     async fn update_conversation_permissions<P: Into<GroupPermissionOpt> + Send + Sync>(
@@ -1673,6 +1971,17 @@ impl RayGunAttachment for WarpIpfs {
             .await
     }
 
+    async fn attach_voice_note(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        location: Location,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        self.messaging_store()?
+            .attach_voice_note(conversation_id, message_id, location)
+            .await
+    }
+
     async fn download(
         &self,
         conversation_id: Uuid,
@@ -2037,6 +2346,17 @@ impl RayGunCommunity for WarpIpfs {
             .get_community_channel_messages(community_id, channel_id, options)
             .await
     }
+    async fn get_community_channel_thread(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        self.messaging_store()?
+            .get_community_channel_thread(community_id, channel_id, root_message_id, options)
+            .await
+    }
     async fn get_community_channel_message_count(
         &self,
         community_id: Uuid,
@@ -2076,6 +2396,110 @@ impl RayGunCommunity for WarpIpfs {
             .community_channel_message_status(community_id, channel_id, message_id)
             .await
     }
+    async fn community_channel_message_receipts(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        self.messaging_store()?
+            .community_channel_message_receipts(community_id, channel_id, message_id)
+            .await
+    }
+    async fn mark_community_channel_message_read(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .mark_community_channel_message_read(community_id, channel_id, message_id)
+            .await
+    }
+    async fn mark_community_channel_read(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .mark_community_channel_read(community_id, channel_id, message_id)
+            .await
+    }
+    async fn get_community_channel_last_read_message(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Option<Uuid>, Error> {
+        self.messaging_store()?
+            .get_community_channel_last_read_message(community_id, channel_id)
+            .await
+    }
+    async fn get_community_channel_unread_count(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        self.messaging_store()?
+            .get_community_channel_unread_count(community_id, channel_id)
+            .await
+    }
+    async fn get_community_channel_unread_mention_count(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        self.messaging_store()?
+            .get_community_channel_unread_mention_count(community_id, channel_id)
+            .await
+    }
+    async fn set_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        draft: Draft,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_community_channel_draft(community_id, channel_id, draft)
+            .await
+    }
+    async fn get_community_channel_draft(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Option<Draft>, Error> {
+        self.messaging_store()?
+            .get_community_channel_draft(community_id, channel_id)
+            .await
+    }
+    async fn clear_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .clear_community_channel_draft(community_id, channel_id)
+            .await
+    }
+    async fn set_community_channel_notification_settings(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_community_channel_notification_settings(community_id, channel_id, settings)
+            .await
+    }
+    async fn get_community_channel_notification_settings(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        self.messaging_store()?
+            .get_community_channel_notification_settings(community_id, channel_id)
+            .await
+    }
     async fn send_community_channel_message(
         &mut self,
         community_id: Uuid,
@@ -2108,6 +2532,17 @@ impl RayGunCommunity for WarpIpfs {
             .reply_to_community_channel_message(community_id, channel_id, message_id, message)
             .await
     }
+    async fn forward_to_community_channel(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .forward_to_community_channel(conversation_id, message_id, community_id, channel_id)
+            .await
+    }
     async fn delete_community_channel_message(
         &mut self,
         community_id: Uuid,
@@ -2141,6 +2576,47 @@ impl RayGunCommunity for WarpIpfs {
             .react_to_community_channel_message(community_id, channel_id, message_id, state, emoji)
             .await
     }
+    async fn send_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        poll: Poll,
+    ) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .send_community_channel_poll(community_id, channel_id, poll)
+            .await
+    }
+    async fn vote_on_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .vote_on_community_channel_poll(community_id, channel_id, message_id, options)
+            .await
+    }
+    async fn retract_community_channel_poll_vote(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .retract_community_channel_poll_vote(community_id, channel_id, message_id)
+            .await
+    }
+    async fn close_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .close_community_channel_poll(community_id, channel_id, message_id)
+            .await
+    }
     async fn send_community_channel_messsage_event(
         &mut self,
         community_id: Uuid,
@@ -2242,6 +2718,48 @@ impl RayGunGroupConversation for WarpIpfs {
             .remove_participant(conversation_id, did_key)
             .await
     }
+
+    async fn create_conversation_invite(
+        &mut self,
+        conversation_id: Uuid,
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Result<ConversationInvite, Error> {
+        self.messaging_store()?
+            .create_conversation_invite(conversation_id, expiry, max_uses)
+            .await
+    }
+
+    async fn list_conversation_invites(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ConversationInvite>, Error> {
+        self.messaging_store()?
+            .list_conversation_invites(conversation_id)
+            .await
+    }
+
+    async fn revoke_conversation_invite(
+        &mut self,
+        conversation_id: Uuid,
+        invite_id: Uuid,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .revoke_conversation_invite(conversation_id, invite_id)
+            .await
+    }
+
+    async fn accept_conversation_invite(&mut self, token: &str) -> Result<Uuid, Error> {
+        self.messaging_store()?
+            .accept_conversation_invite(token)
+            .await
+    }
+
+    async fn rotate_conversation_key(&mut self, conversation_id: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .rotate_conversation_key(conversation_id)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -2295,6 +2813,16 @@ impl RayGunConversationInformation for WarpIpfs {
             .set_description(conversation_id, description)
             .await
     }
+
+    async fn set_conversation_message_retention(
+        &mut self,
+        conversation_id: Uuid,
+        retention: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_message_retention(conversation_id, retention)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -33,6 +33,7 @@ pub enum Request {
     RegisterConversation(RegisterConversation),
     MessageUpdate(MessageUpdate),
     FetchMailBox { conversation_id: Uuid },
+    FetchConversation { conversation_id: Uuid },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -82,5 +83,9 @@ pub enum Response {
         conversation_id: Uuid,
         content: BTreeMap<String, Cid>,
     },
+    Conversation {
+        conversation_id: Uuid,
+        document: Cid,
+    },
     Error(String),
 }
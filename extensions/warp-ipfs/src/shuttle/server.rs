@@ -998,8 +998,35 @@ impl ShuttleTask {
 
             match message {
                 message::protocol::Request::RegisterConversation(RegisterConversation {
+                    owner,
+                    conversation_id,
+                    conversation_document,
                     ..
-                }) => todo!(),
+                }) => {
+                    let message = if owner != did {
+                        MessageResponse::Error("owner does not match sender".into())
+                    } else {
+                        match message_storage
+                            .register_conversation(&did, conversation_id, conversation_document)
+                            .await
+                        {
+                            Ok(_) => MessageResponse::Ack,
+                            Err(e) => {
+                                tracing::warn!(%conversation_id, %did, error = %e, "unable to register conversation");
+                                MessageResponse::Error(e.to_string())
+                            }
+                        }
+                    };
+
+                    let payload =
+                        message::protocol::payload_message_construct(keypair, None, message)
+                            .expect("Valid payload construction");
+
+                    let bytes = payload.to_bytes().expect("valid deserialization");
+                    _ = ipfs
+                        .send_response(sender_peer_id, id, (protocols::SHUTTLE_MESSAGE, bytes))
+                        .await;
+                }
                 message::protocol::Request::MessageUpdate(update) => match update {
                     message::protocol::MessageUpdate::Insert {
                         conversation_id,
@@ -1064,6 +1091,27 @@ impl ShuttleTask {
                         Err(e) => message::protocol::Response::Error(e.to_string()),
                     };
 
+                    let payload =
+                        message::protocol::payload_message_construct(keypair, None, message)
+                            .expect("Valid payload construction");
+
+                    let bytes = payload.to_bytes().expect("valid deserialization");
+                    _ = ipfs
+                        .send_response(sender_peer_id, id, (protocols::SHUTTLE_MESSAGE, bytes))
+                        .await;
+                }
+                message::protocol::Request::FetchConversation { conversation_id } => {
+                    let message = match message_storage
+                        .get_conversation(&did, conversation_id)
+                        .await
+                    {
+                        Ok(document) => MessageResponse::Conversation {
+                            conversation_id,
+                            document,
+                        },
+                        Err(e) => MessageResponse::Error(e.to_string()),
+                    };
+
                     let payload =
                         message::protocol::payload_message_construct(keypair, None, message)
                             .expect("Valid payload construction");
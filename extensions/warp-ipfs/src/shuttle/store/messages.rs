@@ -9,7 +9,7 @@ use tokio::sync::RwLock;
 use uuid::Uuid;
 use warp::{crypto::DID, error::Error};
 
-use crate::store::DidExt;
+use crate::store::{conversation::ConversationDocument, DidExt};
 
 use super::{identity::IdentityStorage, root::RootStorage};
 
@@ -21,6 +21,8 @@ pub struct MessageStorage {
 struct MessageStorageInner {
     ipfs: Ipfs,
     list: Option<Cid>,
+    /// Map of the conversation documents registered by their members
+    conversations: Option<Cid>,
     identity: IdentityStorage,
     root: RootStorage,
 }
@@ -30,12 +32,14 @@ impl MessageStorage {
         let root_dag = root.get_root().await;
 
         let list = root_dag.conversation_mailbox;
+        let conversations = root_dag.conversations;
 
         let inner = Arc::new(RwLock::new(MessageStorageInner {
             ipfs: ipfs.clone(),
             root: root.clone(),
             identity: identity.clone(),
             list,
+            conversations,
         }));
 
         Self { inner }
@@ -92,6 +96,27 @@ impl MessageStorage {
         let inner = &*self.inner.read().await;
         inner.list_conversations()
     }
+
+    pub async fn register_conversation(
+        &self,
+        member: &DID,
+        conversation_id: Uuid,
+        document: Cid,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner
+            .register_conversation(member, conversation_id, document)
+            .await
+    }
+
+    pub async fn get_conversation(
+        &self,
+        member: &DID,
+        conversation_id: Uuid,
+    ) -> Result<Cid, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_conversation(member, conversation_id).await
+    }
 }
 
 impl MessageStorageInner {
@@ -433,6 +458,125 @@ impl MessageStorageInner {
         Ok(())
     }
 
+    // Note: The document is fetched from the member registering it and only replaces the stored document
+    //       if it is newer, so a member cannot roll back the conversation to a previous state
+    async fn register_conversation(
+        &mut self,
+        member: &DID,
+        conversation_id: Uuid,
+        document_cid: Cid,
+    ) -> Result<(), Error> {
+        let member_peer_id = member.to_peer_id()?;
+
+        if !self.identity.contains(member).await {
+            return Err(Error::IdentityDoesntExist);
+        }
+
+        let document: ConversationDocument = self
+            .ipfs
+            .get_dag(document_cid)
+            .provider(member_peer_id)
+            .timeout(Duration::from_secs(10))
+            .deserialized()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        if document.id != conversation_id || !document.recipients.contains(member) {
+            return Err(Error::InvalidConversation);
+        }
+
+        document.verify()?;
+
+        let mut list: BTreeMap<String, Cid> = match self.conversations {
+            Some(cid) => self
+                .ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized()
+                .await
+                .unwrap_or_default(),
+            None => BTreeMap::new(),
+        };
+
+        let previous = list.get(&conversation_id.to_string()).copied();
+
+        if let Some(cid) = previous {
+            let current: Option<ConversationDocument> =
+                self.ipfs.get_dag(cid).local().deserialized().await.ok();
+
+            if current.is_some_and(|current| current.modified > document.modified) {
+                return Err(Error::OtherWithContext(
+                    "a newer document is already registered".into(),
+                ));
+            }
+        }
+
+        // The document links to data that is not stored here, such as the messages, so it is pinned directly
+        if !self.ipfs.is_pinned(document_cid).await.unwrap_or_default() {
+            self.ipfs.insert_pin(document_cid).local().await?;
+        }
+
+        list.insert(conversation_id.to_string(), document_cid);
+
+        let root_cid = self.ipfs.put_dag(list).await?;
+
+        if !self.ipfs.is_pinned(root_cid).await.unwrap_or_default() {
+            self.ipfs.insert_pin(root_cid).local().await?;
+        }
+
+        if let Some(cid) = self.conversations.replace(root_cid) {
+            if cid != root_cid {
+                self.ipfs.remove_pin(cid).await?;
+            }
+        }
+
+        if let Some(cid) = previous {
+            if cid != document_cid {
+                _ = self.ipfs.remove_pin(cid).await;
+            }
+        }
+
+        self.root.set_conversations(root_cid).await?;
+        tracing::info!(%conversation_id, %member, "conversation document registered");
+        Ok(())
+    }
+
+    async fn get_conversation(&self, member: &DID, conversation_id: Uuid) -> Result<Cid, Error> {
+        if !self.identity.contains(member).await {
+            return Err(Error::IdentityDoesntExist);
+        }
+
+        let cid = self.conversations.ok_or(Error::InvalidConversation)?;
+
+        let list: BTreeMap<String, Cid> = self
+            .ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(|_| Error::InvalidConversation)?;
+
+        let document_cid = list
+            .get(&conversation_id.to_string())
+            .copied()
+            .ok_or(Error::InvalidConversation)?;
+
+        let document: ConversationDocument = self
+            .ipfs
+            .get_dag(document_cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(|_| Error::InvalidConversation)?;
+
+        // The document is only provided to its members
+        if !document.recipients.contains(member) {
+            return Err(Error::InvalidConversation);
+        }
+
+        Ok(document_cid)
+    }
+
     //TODO: Expose conversation type (after registration is impl)
     fn list_conversations(&self) -> impl Stream<Item = Uuid> {
         let list = self.list;
@@ -214,6 +214,8 @@ impl CommunityDocument {
         permissions.insert(CommunityPermission::DeleteMessages, IndexSet::new());
         permissions.insert(CommunityPermission::PinMessages, IndexSet::new());
 
+        permissions.insert(CommunityPermission::MentionEveryone, IndexSet::new());
+
         let mut members = IndexSet::new();
         members.insert(creator.clone());
 
@@ -441,6 +443,21 @@ impl CommunityChannelDocument {
         Ok(list)
     }
 
+    /// Messages selected by the cursor within [`MessageOptions`], or every message if no cursor is set
+    pub async fn get_message_window(
+        &self,
+        ipfs: &Ipfs,
+        option: &MessageOptions,
+    ) -> Result<Vec<MessageDocument>, Error> {
+        let Some(cursor) = option.cursor() else {
+            return self.get_message_list(ipfs).await.map(Vec::from_iter);
+        };
+
+        let refs = self.message_reference_list(ipfs).await?;
+        let limit = option.limit().unwrap_or(u8::MAX) as usize;
+        refs.list_from_cursor(ipfs, cursor, limit).await
+    }
+
     pub async fn get_messages(
         &self,
         ipfs: &Ipfs,
@@ -461,14 +478,12 @@ impl CommunityChannelDocument {
         ipfs: &Ipfs,
         option: MessageOptions,
     ) -> Result<BoxStream<'a, MessageReference>, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
-        if message_list.is_empty() {
+        if messages.is_empty() {
             return Ok(stream::empty().boxed());
         }
 
-        let mut messages = Vec::from_iter(message_list);
-
         if option.reverse() {
             messages.reverse()
         }
@@ -522,14 +537,12 @@ impl CommunityChannelDocument {
         option: MessageOptions,
         keystore: Either<DID, Keystore>,
     ) -> Result<BoxStream<'a, Message>, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
-        if message_list.is_empty() {
+        if messages.is_empty() {
             return Ok(stream::empty().boxed());
         }
 
-        let mut messages = Vec::from_iter(message_list);
-
         if option.reverse() {
             messages.reverse()
         }
@@ -604,17 +617,15 @@ impl CommunityChannelDocument {
         option: MessageOptions,
         keystore: Either<&DID, &Keystore>,
     ) -> Result<Messages, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
-        if message_list.is_empty() {
+        if messages.is_empty() {
             return Ok(Messages::Page {
                 pages: vec![],
                 total: 0,
             });
         }
 
-        let mut messages = Vec::from_iter(message_list);
-
         if option.reverse() {
             messages.reverse()
         }
@@ -695,6 +706,34 @@ impl CommunityChannelDocument {
         self.set_message_reference_list(ipfs, list).await?;
         Ok(())
     }
+
+    /// Compacts the message references if they are fragmented, or regardless of the fragmentation if `force` is true.
+    /// Returns the blocks that are no longer referenced by the document
+    pub async fn compact_message_references(
+        &mut self,
+        ipfs: &Ipfs,
+        force: bool,
+    ) -> Result<Vec<Cid>, Error> {
+        let Some(root) = self.messages else {
+            return Ok(vec![]);
+        };
+
+        let list = self.message_reference_list(ipfs).await?;
+        let stats = list.stats(ipfs).await?;
+
+        if stats.holes == 0 || (!force && !stats.is_fragmented()) {
+            return Ok(vec![]);
+        }
+
+        let (list, mut superseded) = list.compact(ipfs).await?;
+        self.set_message_reference_list(ipfs, list).await?;
+
+        if self.messages != Some(root) {
+            superseded.push(root);
+        }
+
+        Ok(superseded)
+    }
 }
 impl From<CommunityChannelDocument> for CommunityChannel {
     fn from(value: CommunityChannelDocument) -> Self {
@@ -1,4 +1,12 @@
+pub mod archive;
+pub mod export;
+pub mod geolocation;
+pub mod invite;
+pub mod link_preview;
+pub mod mention;
 pub mod message;
+pub mod poll;
+pub mod read_marker;
 pub mod reference;
 
 use super::{keystore::Keystore, topics::ConversationTopic, verify_serde_sig, PeerIdExt};
@@ -35,6 +43,8 @@ use warp::{
 pub enum ConversationVersion {
     #[default]
     V0,
+    /// Direct conversation encrypted with a ratchet session
+    V1,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq)]
@@ -68,6 +78,8 @@ pub struct ConversationDocument {
     pub banner: Option<Cid>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_retention: Option<Duration>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
 }
@@ -182,6 +194,7 @@ impl ConversationDocument {
             icon: None,
             banner: None,
             description: None,
+            message_retention: None,
         };
 
         if document.signature.is_some() {
@@ -304,7 +317,7 @@ impl ConversationDocument {
         let signature = bs58::decode(signature).into_vec()?;
 
         let construct = match self.version {
-            ConversationVersion::V0 => warp::crypto::hash::sha256_iter(
+            ConversationVersion::V0 | ConversationVersion::V1 => warp::crypto::hash::sha256_iter(
                 [
                     Some(self.id().into_bytes().to_vec()),
                     Some(creator.to_string().as_bytes().to_vec()),
@@ -391,6 +404,21 @@ impl ConversationDocument {
         Ok(list)
     }
 
+    /// Messages selected by the cursor within [`MessageOptions`], or every message if no cursor is set
+    pub async fn get_message_window(
+        &self,
+        ipfs: &Ipfs,
+        option: &MessageOptions,
+    ) -> Result<Vec<MessageDocument>, Error> {
+        let Some(cursor) = option.cursor() else {
+            return self.get_message_list(ipfs).await.map(Vec::from_iter);
+        };
+
+        let refs = self.message_reference_list(ipfs).await?;
+        let limit = option.limit().unwrap_or(u8::MAX) as usize;
+        refs.list_from_cursor(ipfs, cursor, limit).await
+    }
+
     pub async fn get_messages(
         &self,
         ipfs: &Ipfs,
@@ -411,14 +439,12 @@ impl ConversationDocument {
         ipfs: &Ipfs,
         option: MessageOptions,
     ) -> Result<BoxStream<'a, MessageReference>, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
-        if message_list.is_empty() {
+        if messages.is_empty() {
             return Ok(stream::empty().boxed());
         }
 
-        let mut messages = Vec::from_iter(message_list);
-
         if option.reverse() {
             messages.reverse()
         }
@@ -472,79 +498,13 @@ impl ConversationDocument {
         option: MessageOptions,
         keystore: Either<DID, Keystore>,
     ) -> Result<BoxStream<'a, Message>, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
-
-        if message_list.is_empty() {
-            return Ok(stream::empty().boxed());
-        }
-
-        let mut messages = Vec::from_iter(message_list);
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
         if option.reverse() {
             messages.reverse()
         }
 
-        if option.first_message() && !messages.is_empty() {
-            let message = messages
-                .first()
-                .ok_or(Error::MessageNotFound)?
-                .resolve(ipfs, keypair, true, keystore.as_ref())
-                .await?;
-            return Ok(stream::once(async { message }).boxed());
-        }
-
-        if option.last_message() && !messages.is_empty() {
-            let message = messages
-                .last()
-                .ok_or(Error::MessageNotFound)?
-                .resolve(ipfs, keypair, true, keystore.as_ref())
-                .await?;
-            return Ok(stream::once(async { message }).boxed());
-        }
-        let keystore = keystore.clone();
-        let ipfs = ipfs.clone();
-        let keypair = keypair.clone();
-        let stream = async_stream::stream! {
-            let mut remaining = option.limit();
-            for (index, document) in messages.iter().enumerate() {
-                if remaining.as_ref().map(|x| *x == 0).unwrap_or_default() {
-                    break;
-                }
-                if let Some(range) = option.range() {
-                    if range.start > index || range.end < index {
-                        continue
-                    }
-                }
-                if let Some(range) = option.date_range() {
-                    if !(document.date >= range.start && document.date <= range.end) {
-                        continue
-                    }
-                }
-
-                if option.pinned() && !document.pinned {
-                    continue;
-                }
-
-                if let Ok(message) = document.resolve(&ipfs, &keypair, true, keystore.as_ref()).await {
-                    let should_yield = if let Some(keyword) = option.keyword() {
-                         message
-                            .lines()
-                            .iter()
-                            .any(|line| line.to_lowercase().contains(&keyword.to_lowercase()))
-                    } else {
-                        true
-                    };
-                    if should_yield {
-                        if let Some(remaining) = remaining.as_mut() {
-                            *remaining = remaining.saturating_sub(1);
-                        }
-                        yield message;
-                    }
-                }
-            }
-        };
-
-        Ok(stream.boxed())
+        stream_messages(ipfs, keypair, messages, option, keystore).await
     }
 
     pub async fn get_messages_pages(
@@ -554,68 +514,13 @@ impl ConversationDocument {
         option: MessageOptions,
         keystore: Either<&DID, &Keystore>,
     ) -> Result<Messages, Error> {
-        let message_list = self.get_message_list(ipfs).await?;
-
-        if message_list.is_empty() {
-            return Ok(Messages::Page {
-                pages: vec![],
-                total: 0,
-            });
-        }
-
-        let mut messages = Vec::from_iter(message_list);
+        let mut messages = self.get_message_window(ipfs, &option).await?;
 
         if option.reverse() {
             messages.reverse()
         }
 
-        let (page_index, amount_per_page) = match option.messages_type() {
-            MessagesType::Pages {
-                page,
-                amount_per_page,
-            } => (
-                page,
-                amount_per_page
-                    .map(|amount| if amount == 0 { u8::MAX as _ } else { amount })
-                    .unwrap_or(u8::MAX as _),
-            ),
-            _ => (None, u8::MAX as _),
-        };
-
-        let messages_chunk = messages.chunks(amount_per_page as _).collect::<Vec<_>>();
-        let mut pages = vec![];
-        // First check to determine if there is a page that was selected
-        if let Some(index) = page_index {
-            let page = messages_chunk.get(index).ok_or(Error::PageNotFound)?;
-            let mut messages = vec![];
-            for document in page.iter() {
-                if let Ok(message) = document.resolve(ipfs, did, true, keystore).await {
-                    messages.push(message);
-                }
-            }
-            let total = messages.len();
-            pages.push(MessagePage::new(index, messages, total));
-            return Ok(Messages::Page { pages, total: 1 });
-        }
-
-        for (index, chunk) in messages_chunk.iter().enumerate() {
-            let mut messages = vec![];
-            for document in chunk.iter() {
-                if let Ok(message) = document.resolve(ipfs, did, true, keystore).await {
-                    if option.pinned() && !message.pinned() {
-                        continue;
-                    }
-                    messages.push(message);
-                }
-            }
-
-            let total = messages.len();
-            pages.push(MessagePage::new(index, messages, total));
-        }
-
-        let total = pages.len();
-
-        Ok(Messages::Page { pages, total })
+        paginate_messages(ipfs, did, messages, option, keystore).await
     }
 
     pub async fn get_message_document(
@@ -639,12 +544,267 @@ impl ConversationDocument {
             .await
     }
 
+    pub async fn get_message_history(
+        &self,
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        message_id: Uuid,
+        keystore: Either<&DID, &Keystore>,
+    ) -> Result<Vec<Message>, Error> {
+        self.get_message_document(ipfs, message_id)
+            .and_then(|doc| async move { doc.history(ipfs, keypair, true, keystore).await })
+            .await
+    }
+
     pub async fn delete_message(&mut self, ipfs: &Ipfs, message_id: Uuid) -> Result<(), Error> {
         let mut list = self.message_reference_list(ipfs).await?;
         list.remove(ipfs, message_id).await?;
         self.set_message_reference_list(ipfs, list).await?;
         Ok(())
     }
+
+    /// Compacts the message references if they are fragmented, or regardless of the fragmentation if `force` is true.
+    /// Returns the blocks that are no longer referenced by the document
+    pub async fn compact_message_references(
+        &mut self,
+        ipfs: &Ipfs,
+        force: bool,
+    ) -> Result<Vec<Cid>, Error> {
+        let Some(root) = self.messages else {
+            return Ok(vec![]);
+        };
+
+        let list = self.message_reference_list(ipfs).await?;
+        let stats = list.stats(ipfs).await?;
+
+        if stats.holes == 0 || (!force && !stats.is_fragmented()) {
+            return Ok(vec![]);
+        }
+
+        let (list, mut superseded) = list.compact(ipfs).await?;
+        self.set_message_reference_list(ipfs, list).await?;
+
+        if self.messages != Some(root) {
+            superseded.push(root);
+        }
+
+        Ok(superseded)
+    }
+
+    pub async fn delete_messages(
+        &mut self,
+        ipfs: &Ipfs,
+        messages: impl IntoIterator<Item = Uuid>,
+    ) -> Result<(), Error> {
+        let mut list = self.message_reference_list(ipfs).await?;
+        for message_id in messages {
+            list.remove(ipfs, message_id).await?;
+        }
+        self.set_message_reference_list(ipfs, list).await?;
+        Ok(())
+    }
+
+    /// Messages that are older than the retention period of the conversation. The references are walked from the
+    /// oldest message and only resolved up to the first message that has yet to expire
+    pub async fn expired_messages(
+        &self,
+        ipfs: &Ipfs,
+    ) -> Result<(Vec<MessageDocument>, Option<DateTime<Utc>>), Error> {
+        let Some(retention) = self
+            .message_retention
+            .and_then(|retention| chrono::Duration::from_std(retention).ok())
+        else {
+            return Ok((vec![], None));
+        };
+
+        let now = Utc::now();
+        let mut expired = vec![];
+        let mut next_expiry = None;
+
+        let refs = self.message_reference_list(ipfs).await?;
+        let mut messages = refs.list(ipfs);
+
+        while let Some(message) = messages.next().await {
+            let expiry = message.date + retention;
+            if expiry <= now {
+                expired.push(message);
+                continue;
+            }
+            // Since messages are referenced in the order they were stored, the first message that has yet to expire
+            // would be the next to do so
+            next_expiry = Some(expiry);
+            break;
+        }
+
+        Ok((expired, next_expiry))
+    }
+}
+
+/// Resolves a selection of messages, such as the replies of a thread, into [`Messages`] based on [`MessageOptions`]
+pub async fn resolve_messages(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    mut documents: Vec<MessageDocument>,
+    option: MessageOptions,
+    keystore: Either<DID, Keystore>,
+) -> Result<Messages, Error> {
+    documents.sort();
+
+    if option.reverse() {
+        documents.reverse();
+    }
+
+    match option.messages_type() {
+        MessagesType::Pages { .. } => {
+            paginate_messages(ipfs, keypair, documents, option, keystore.as_ref()).await
+        }
+        MessagesType::List => {
+            let stream = stream_messages(ipfs, keypair, documents, option, keystore).await?;
+            Ok(Messages::List(stream.collect().await))
+        }
+        MessagesType::Stream => {
+            let stream = stream_messages(ipfs, keypair, documents, option, keystore).await?;
+            Ok(Messages::Stream(stream))
+        }
+    }
+}
+
+/// Resolve the ordered message documents into a stream, applying the filters of [`MessageOptions`]
+async fn stream_messages<'a>(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    messages: Vec<MessageDocument>,
+    option: MessageOptions,
+    keystore: Either<DID, Keystore>,
+) -> Result<BoxStream<'a, Message>, Error> {
+    if messages.is_empty() {
+        return Ok(stream::empty().boxed());
+    }
+
+    if option.first_message() && !messages.is_empty() {
+        let message = messages
+            .first()
+            .ok_or(Error::MessageNotFound)?
+            .resolve(ipfs, keypair, true, keystore.as_ref())
+            .await?;
+        return Ok(stream::once(async { message }).boxed());
+    }
+
+    if option.last_message() && !messages.is_empty() {
+        let message = messages
+            .last()
+            .ok_or(Error::MessageNotFound)?
+            .resolve(ipfs, keypair, true, keystore.as_ref())
+            .await?;
+        return Ok(stream::once(async { message }).boxed());
+    }
+    let ipfs = ipfs.clone();
+    let keypair = keypair.clone();
+    let stream = async_stream::stream! {
+        let mut remaining = option.limit();
+        for (index, document) in messages.iter().enumerate() {
+            if remaining.as_ref().map(|x| *x == 0).unwrap_or_default() {
+                break;
+            }
+            if let Some(range) = option.range() {
+                if range.start > index || range.end < index {
+                    continue
+                }
+            }
+            if let Some(range) = option.date_range() {
+                if !(document.date >= range.start && document.date <= range.end) {
+                    continue
+                }
+            }
+
+            if option.pinned() && !document.pinned {
+                continue;
+            }
+
+            if let Ok(message) = document.resolve(&ipfs, &keypair, true, keystore.as_ref()).await {
+                let should_yield = if let Some(keyword) = option.keyword() {
+                     message
+                        .lines()
+                        .iter()
+                        .any(|line| line.to_lowercase().contains(&keyword.to_lowercase()))
+                } else {
+                    true
+                };
+                if should_yield {
+                    if let Some(remaining) = remaining.as_mut() {
+                        *remaining = remaining.saturating_sub(1);
+                    }
+                    yield message;
+                }
+            }
+        }
+    };
+
+    Ok(stream.boxed())
+}
+
+/// Resolve the ordered message documents into the pages selected by [`MessageOptions`]
+async fn paginate_messages(
+    ipfs: &Ipfs,
+    did: &Keypair,
+    messages: Vec<MessageDocument>,
+    option: MessageOptions,
+    keystore: Either<&DID, &Keystore>,
+) -> Result<Messages, Error> {
+    if messages.is_empty() {
+        return Ok(Messages::Page {
+            pages: vec![],
+            total: 0,
+        });
+    }
+
+    let (page_index, amount_per_page) = match option.messages_type() {
+        MessagesType::Pages {
+            page,
+            amount_per_page,
+        } => (
+            page,
+            amount_per_page
+                .map(|amount| if amount == 0 { u8::MAX as _ } else { amount })
+                .unwrap_or(u8::MAX as _),
+        ),
+        _ => (None, u8::MAX as _),
+    };
+
+    let messages_chunk = messages.chunks(amount_per_page as _).collect::<Vec<_>>();
+    let mut pages = vec![];
+    // First check to determine if there is a page that was selected
+    if let Some(index) = page_index {
+        let page = messages_chunk.get(index).ok_or(Error::PageNotFound)?;
+        let mut messages = vec![];
+        for document in page.iter() {
+            if let Ok(message) = document.resolve(ipfs, did, true, keystore).await {
+                messages.push(message);
+            }
+        }
+        let total = messages.len();
+        pages.push(MessagePage::new(index, messages, total));
+        return Ok(Messages::Page { pages, total: 1 });
+    }
+
+    for (index, chunk) in messages_chunk.iter().enumerate() {
+        let mut messages = vec![];
+        for document in chunk.iter() {
+            if let Ok(message) = document.resolve(ipfs, did, true, keystore).await {
+                if option.pinned() && !message.pinned() {
+                    continue;
+                }
+                messages.push(message);
+            }
+        }
+
+        let total = messages.len();
+        pages.push(MessagePage::new(index, messages, total));
+    }
+
+    let total = pages.len();
+
+    Ok(Messages::Page { pages, total })
 }
 
 impl From<ConversationDocument> for Conversation {
@@ -667,6 +827,7 @@ impl From<&ConversationDocument> for Conversation {
         conversation.set_favorite(document.favorite);
         conversation.set_description(document.description.clone());
         conversation.set_archived(document.archived);
+        conversation.set_message_retention(document.message_retention);
         conversation
     }
 }
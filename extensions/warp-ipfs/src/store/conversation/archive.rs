@@ -0,0 +1,453 @@
+//! Conversation archives allow the history of a conversation to be restored without relying on
+//! peers being online, such as after losing a device.
+//!
+//! An archive is either a directory or a zip file with the following layout:
+//! - `archive.json`: [`ConversationArchive`] manifest holding the conversation information, the
+//!   keystore of the conversation (if any) and every message as a signed [`MessageDocument`].
+//!   Messages remain encrypted as they were stored, so only the owner of the archive is able to
+//!   read them.
+//! - `blobs/<cid>`: contents of each attachment, named after the `data` field of its
+//!   [`FileAttachmentDocument`]
+//!
+//! The manifest carries a `version` that is bumped on any incompatible change to this layout.
+//! When imported, the signature of each message is verified against the sender and messages that
+//! fail verification are flagged instead of being discarded.
+
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use either::Either;
+use futures::StreamExt;
+use ipld_core::cid::Cid;
+use rust_ipfs::unixfs::UnixfsStatus;
+use rust_ipfs::{Ipfs, Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::constellation::Progression;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::{ConversationType, Message};
+
+use super::export::{
+    ExportFormat, ExportProgressStream, ExportProgression, ExportWriter, ExportedConversation,
+    ZipFile,
+};
+use super::message::MessageDocument;
+use super::ConversationDocument;
+use crate::store::document::FileAttachmentDocument;
+use crate::store::keystore::Keystore;
+use crate::store::PeerIdExt;
+use crate::utils::ByteCollection;
+
+/// Version of the archive layout
+pub const ARCHIVE_VERSION: u8 = 1;
+
+pub const ARCHIVE_MANIFEST_FILE: &str = "archive.json";
+pub const ARCHIVE_BLOB_DIRECTORY: &str = "blobs";
+
+pub const MAX_ARCHIVE_MANIFEST_SIZE: usize = 256 * 1024 * 1024;
+pub const MAX_ARCHIVE_BLOB_SIZE: usize = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationArchive {
+    pub version: u8,
+    pub created: DateTime<Utc>,
+    /// Identity that created the archive and is able to decrypt its messages
+    pub owner: DID,
+    pub conversation: ExportedConversation,
+    /// Keystore of a group conversation, with every key encrypted for the owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<Keystore>,
+    pub messages: Vec<MessageDocument>,
+}
+
+/// Conversation restored from an archive. Imported conversations are only stored locally and are read-only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedConversationDocument {
+    pub conversation: ExportedConversation,
+    pub imported: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<Keystore>,
+    /// list of [`MessageDocument`]
+    pub messages: Cid,
+    /// messages whose signature could not be verified
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub unverified: BTreeSet<Uuid>,
+}
+
+impl ImportedConversationDocument {
+    pub async fn message_documents(&self, ipfs: &Ipfs) -> Result<Vec<MessageDocument>, Error> {
+        ipfs.get_dag(self.messages)
+            .local()
+            .deserialized()
+            .await
+            .map_err(|e| anyhow::Error::from(e).into())
+    }
+
+    pub async fn resolve_messages(
+        &self,
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+    ) -> Result<Vec<ImportedMessage>, Error> {
+        let own_did = keypair.to_did()?;
+
+        let key = match self.conversation.conversation_type {
+            ConversationType::Direct => Either::Left(
+                self.conversation
+                    .recipients
+                    .iter()
+                    .find(|did| own_did.ne(did))
+                    .cloned()
+                    .ok_or(Error::InvalidConversation)?,
+            ),
+            ConversationType::Group => {
+                Either::Right(self.keystore.clone().ok_or(Error::InvalidConversation)?)
+            }
+        };
+
+        let documents = self.message_documents(ipfs).await?;
+
+        let mut messages = Vec::with_capacity(documents.len());
+
+        for document in documents {
+            let verified = !self.unverified.contains(&document.id);
+            let result = match verified {
+                true => document.resolve(ipfs, keypair, true, key.as_ref()).await,
+                false => {
+                    document
+                        .resolve_unverified(ipfs, keypair, true, key.as_ref())
+                        .await
+                }
+            };
+
+            match result {
+                Ok(message) => messages.push(ImportedMessage { message, verified }),
+                Err(e) => {
+                    tracing::warn!(id = %self.conversation.id, message_id = %document.id, error = %e, "unable to resolve imported message");
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    pub message: Message,
+    /// false if the signature of the message did not match the sender
+    pub verified: bool,
+}
+
+/// Summary of an imported archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveImport {
+    pub conversation_id: Uuid,
+    pub messages: usize,
+    /// messages that failed verification
+    pub unverified: Vec<Uuid>,
+    /// attachments that were missing from the archive or did not match their reference
+    pub missing_attachments: Vec<Uuid>,
+}
+
+/// Attachment blobs stored locally by an import
+#[derive(Debug, Clone, Default)]
+pub struct ImportedBlobs {
+    /// blobs referenced by each message
+    pub messages: Vec<(Uuid, Vec<Cid>)>,
+}
+
+enum ArchiveReader {
+    Directory(PathBuf),
+    Zip(Box<zip::ZipArchive<ZipFile>>),
+}
+
+impl ArchiveReader {
+    async fn open(path: &Path) -> Result<Self, Error> {
+        if fs::file_size(path.join(ARCHIVE_MANIFEST_FILE))
+            .await
+            .is_ok()
+        {
+            return Ok(ArchiveReader::Directory(path.to_path_buf()));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let file = std::fs::File::open(path)?;
+        #[cfg(target_arch = "wasm32")]
+        let file = std::io::Cursor::new(fs::read(path).await?);
+
+        let archive = zip::ZipArchive::new(file).map_err(anyhow::Error::from)?;
+        Ok(ArchiveReader::Zip(Box::new(archive)))
+    }
+
+    /// Reads the file `name`, failing if it exceeds `maximum` bytes
+    async fn read(&mut self, name: &str, maximum: usize) -> Result<Option<Vec<u8>>, Error> {
+        let bytes = match self {
+            ArchiveReader::Directory(path) => {
+                let path = path.join(name);
+                match fs::file_size(&path).await {
+                    Ok(size) if size > maximum => return Err(too_large(name, size, maximum)),
+                    Ok(_) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                }
+                fs::read(&path).await?
+            }
+            ArchiveReader::Zip(archive) => {
+                let file = match archive.by_name(name) {
+                    Ok(file) => file,
+                    Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+                    Err(e) => return Err(anyhow::Error::from(e).into()),
+                };
+                // Note: The size in the header is not trusted since the entry is read up to the limit regardless
+                let mut buffer = Vec::with_capacity((file.size() as usize).min(maximum));
+                file.take(maximum as u64 + 1).read_to_end(&mut buffer)?;
+                buffer
+            }
+        };
+
+        if bytes.len() > maximum {
+            return Err(too_large(name, bytes.len(), maximum));
+        }
+
+        Ok(Some(bytes))
+    }
+}
+
+fn too_large(name: &str, size: usize, maximum: usize) -> Error {
+    Error::InvalidLength {
+        context: name.into(),
+        current: size,
+        minimum: None,
+        maximum: Some(maximum),
+    }
+}
+
+fn blob_path(attachment: &FileAttachmentDocument) -> String {
+    format!("{ARCHIVE_BLOB_DIRECTORY}/{}", attachment.data)
+}
+
+pub fn export_archive(
+    ipfs: Ipfs,
+    owner: DID,
+    keystore: Option<Keystore>,
+    document: ConversationDocument,
+    members: Vec<PeerId>,
+    path: PathBuf,
+    format: ExportFormat,
+) -> ExportProgressStream {
+    let stream = async_stream::stream! {
+        let mut writer = match ExportWriter::new(&path, format).await {
+            Ok(writer) => writer,
+            Err(error) => {
+                yield ExportProgression::Failed { error };
+                return;
+            }
+        };
+
+        let list = match document.get_message_list(&ipfs).await {
+            Ok(list) => list,
+            Err(error) => {
+                yield ExportProgression::Failed { error };
+                return;
+            }
+        };
+
+        let total = list.len();
+        let mut messages = Vec::with_capacity(total);
+
+        for (index, message) in list.into_iter().enumerate() {
+            for attachment in message.attachments() {
+                let name = attachment.name.clone();
+                let stream = attachment.download_stream(&ipfs, &members, None);
+                let result = match ByteCollection::new(stream).await {
+                    Ok(bytes) => writer.write(&blob_path(attachment), &bytes).await,
+                    Err(e) => Err(e.into()),
+                };
+
+                match result {
+                    Ok(_) => yield ExportProgression::Attachment(Progression::ProgressComplete {
+                        name,
+                        total: Some(attachment.size),
+                    }),
+                    Err(error) => yield ExportProgression::Attachment(Progression::ProgressFailed {
+                        name,
+                        last_size: None,
+                        error,
+                    }),
+                }
+            }
+
+            messages.push(message);
+            yield ExportProgression::Messages { current: index + 1, total };
+        }
+
+        let archive = ConversationArchive {
+            version: ARCHIVE_VERSION,
+            created: Utc::now(),
+            owner,
+            conversation: ExportedConversation::from(&document),
+            keystore,
+            messages,
+        };
+
+        let result = async {
+            let manifest = serde_json::to_vec(&archive)?;
+            writer.write(ARCHIVE_MANIFEST_FILE, &manifest).await?;
+            writer.finish().await
+        }
+        .await;
+
+        match result {
+            Ok(path) => yield ExportProgression::Complete { path },
+            Err(error) => yield ExportProgression::Failed { error },
+        }
+    };
+
+    stream.boxed()
+}
+
+/// Reads the archive at `path`, verifying every message and storing the attachment blobs locally.
+/// The import is rejected before anything is stored if `exists` returns true for the conversation of the archive.
+/// The blobs are stored without being pinned, leaving it to the caller to pin those in [`ImportedBlobs`]
+pub async fn import_archive(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    path: &Path,
+    exists: impl FnOnce(Uuid) -> bool,
+) -> Result<(ImportedConversationDocument, ArchiveImport, ImportedBlobs), Error> {
+    let mut reader = ArchiveReader::open(path).await?;
+
+    let manifest = reader
+        .read(ARCHIVE_MANIFEST_FILE, MAX_ARCHIVE_MANIFEST_SIZE)
+        .await?
+        .ok_or_else(|| Error::OtherWithContext("archive manifest is missing".into()))?;
+
+    let archive: ConversationArchive = serde_json::from_slice(&manifest)?;
+
+    if archive.version > ARCHIVE_VERSION {
+        return Err(Error::OtherWithContext(format!(
+            "unsupported archive version {}",
+            archive.version
+        )));
+    }
+
+    // Messages are encrypted for the owner so they would be unreadable by anyone else
+    if archive.owner != keypair.to_did()? {
+        return Err(Error::Unauthorized);
+    }
+
+    let conversation = archive.conversation;
+
+    if exists(conversation.id) {
+        return Err(Error::OtherWithContext(
+            "conversation already exist and cannot be imported".into(),
+        ));
+    }
+
+    let mut unverified = BTreeSet::new();
+    let mut missing_attachments = vec![];
+    let mut blobs = ImportedBlobs::default();
+    let mut messages = Vec::with_capacity(archive.messages.len());
+
+    for mut message in archive.messages {
+        let sender = message.sender.to_did();
+        if message.conversation_id != conversation.id
+            || !conversation.recipients.contains(&sender)
+            || !message.verify()
+        {
+            tracing::warn!(id = %conversation.id, message_id = %message.id, "imported message failed verification");
+            unverified.insert(message.id);
+        }
+
+        let mut cids = vec![];
+
+        for attachment in message.attachments.iter_mut() {
+            // Note: The thumbnail is not covered by the signature and is not part of the archive
+            attachment.thumbnail = None;
+
+            let imported = match reader
+                .read(&blob_path(attachment), MAX_ARCHIVE_BLOB_SIZE)
+                .await?
+            {
+                Some(bytes) => import_blob(ipfs, attachment, bytes).await,
+                None => None,
+            };
+
+            match imported {
+                Some(cid) => cids.push(cid),
+                None => missing_attachments.push(attachment.id),
+            }
+        }
+
+        if !cids.is_empty() {
+            blobs.messages.push((message.id, cids));
+        }
+
+        messages.push(message);
+    }
+
+    let summary = ArchiveImport {
+        conversation_id: conversation.id,
+        messages: messages.len(),
+        unverified: unverified.iter().copied().collect(),
+        missing_attachments,
+    };
+
+    let messages = ipfs.put_dag(messages).await?;
+
+    let document = ImportedConversationDocument {
+        conversation,
+        imported: Utc::now(),
+        keystore: archive.keystore,
+        messages,
+        unverified,
+    };
+
+    Ok((document, summary, blobs))
+}
+
+/// Adds the blob to the node without pinning it, returning its cid if it matches the reference of the attachment.
+/// A blob that does not match is removed from the node unless it is pinned, in which case it was already stored
+async fn import_blob(
+    ipfs: &Ipfs,
+    attachment: &FileAttachmentDocument,
+    bytes: Vec<u8>,
+) -> Option<Cid> {
+    let mut stream = ipfs.add_unixfs(bytes::Bytes::from(bytes)).pin(false);
+    let mut returned_path = None;
+
+    while let Some(status) = stream.next().await {
+        match status {
+            UnixfsStatus::CompletedStatus { path, .. } => returned_path = Some(path),
+            UnixfsStatus::FailedStatus { error, .. } => {
+                tracing::warn!(attachment = %attachment.id, error = %error, "unable to import attachment");
+                return None;
+            }
+            _ => {}
+        }
+    }
+
+    let cid = returned_path
+        .as_ref()
+        .and_then(|path| path.root().cid())
+        .copied()?;
+
+    if cid.to_string() == attachment.data {
+        return Some(cid);
+    }
+
+    tracing::warn!(attachment = %attachment.id, "imported attachment does not match its reference");
+
+    if ipfs.is_pinned(cid).await.unwrap_or_default() {
+        return None;
+    }
+
+    if let Err(e) = ipfs.remove_block(cid, true).await {
+        tracing::warn!(attachment = %attachment.id, error = %e, "unable to remove mismatched attachment");
+    }
+
+    None
+}
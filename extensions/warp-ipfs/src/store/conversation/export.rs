@@ -0,0 +1,433 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use either::Either;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use indexmap::IndexMap;
+use rust_ipfs::{Ipfs, Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::constellation::file::FileType;
+use warp::constellation::Progression;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::{ConversationType, Message, MessageType};
+
+use super::message::MessageDocument;
+use super::ConversationDocument;
+use crate::store::document::FileAttachmentDocument;
+use crate::store::keystore::Keystore;
+use crate::utils::ByteCollection;
+
+/// Version of the export layout written to [`EXPORT_JSON_FILE`]
+pub const EXPORT_VERSION: u8 = 1;
+
+pub const EXPORT_JSON_FILE: &str = "conversation.json";
+pub const EXPORT_HTML_FILE: &str = "index.html";
+pub const EXPORT_ATTACHMENT_DIRECTORY: &str = "attachments";
+
+const VIEWER_TEMPLATE: &str = include_str!("export/viewer.html");
+const VIEWER_DATA_PLACEHOLDER: &str = "{{CONVERSATION_DATA}}";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Files are written into the directory at the given path
+    #[default]
+    Directory,
+    /// Files are written into a single zip archive at the given path
+    Zip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    format: ExportFormat,
+    attachments: bool,
+}
+
+impl ExportOptions {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            attachments: false,
+        }
+    }
+
+    /// Fetch and include the attachment files alongside the messages
+    pub fn set_attachments(mut self, attachments: bool) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+
+    pub fn attachments(&self) -> bool {
+        self.attachments
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportProgression {
+    /// Number of messages exported so far
+    Messages { current: usize, total: usize },
+    /// Progress of an attachment being fetched
+    Attachment(Progression),
+    /// Export has been written to `path`
+    Complete { path: PathBuf },
+    /// Export failed and was not completed
+    Failed { error: Error },
+}
+
+pub type ExportProgressStream = BoxStream<'static, ExportProgression>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationExport {
+    pub version: u8,
+    pub exported: DateTime<Utc>,
+    pub conversation: ExportedConversation,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedConversation {
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub conversation_type: ConversationType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<DID>,
+    pub created: DateTime<Utc>,
+    pub recipients: Vec<DID>,
+}
+
+impl From<&ConversationDocument> for ExportedConversation {
+    fn from(document: &ConversationDocument) -> Self {
+        Self {
+            id: document.id,
+            name: document.name.clone(),
+            description: document.description.clone(),
+            conversation_type: document.conversation_type(),
+            creator: document.creator.clone(),
+            created: document.created,
+            recipients: document.recipients(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub id: Uuid,
+    pub message_type: MessageType,
+    pub sender: DID,
+    pub date: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    pub pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replied: Option<Uuid>,
+    pub lines: Vec<String>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub reactions: IndexMap<String, Vec<DID>>,
+    /// Previous revisions of the message, newest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<ExportedRevision>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+impl ExportedMessage {
+    fn new(message: &Message, history: &[Message]) -> Self {
+        // Note: history is ordered from the original revision to the current one
+        let edits = history
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|revision| ExportedRevision {
+                date: revision.modified().unwrap_or(revision.date()),
+                lines: revision.lines().to_vec(),
+            })
+            .collect();
+
+        Self {
+            id: message.id(),
+            message_type: message.message_type(),
+            sender: message.sender().clone(),
+            date: message.date(),
+            modified: message.modified(),
+            pinned: message.pinned(),
+            replied: message.replied(),
+            lines: message.lines().to_vec(),
+            reactions: message.reactions().clone(),
+            edits,
+            attachments: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedRevision {
+    pub date: DateTime<Utc>,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAttachment {
+    pub id: Uuid,
+    pub name: String,
+    pub size: usize,
+    pub file_type: FileType,
+    /// Path of the file relative to the root of the export, if it was included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// Backing file of a zip archive. Entries are written to and read from the file directly, except on wasm
+/// where files are kept as a whole in local storage
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type ZipFile = std::fs::File;
+#[cfg(target_arch = "wasm32")]
+pub(crate) type ZipFile = std::io::Cursor<Vec<u8>>;
+
+pub(crate) enum ExportWriter {
+    Directory {
+        path: PathBuf,
+    },
+    Zip {
+        path: PathBuf,
+        writer: zip::ZipWriter<ZipFile>,
+    },
+}
+
+impl ExportWriter {
+    pub(crate) async fn new(path: &Path, format: ExportFormat) -> Result<Self, Error> {
+        let writer = match format {
+            ExportFormat::Directory => {
+                fs::create_dir_all(path).await?;
+                ExportWriter::Directory {
+                    path: path.to_path_buf(),
+                }
+            }
+            ExportFormat::Zip => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent).await?;
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                let file = std::fs::File::create(path)?;
+                #[cfg(target_arch = "wasm32")]
+                let file = std::io::Cursor::new(Vec::new());
+
+                ExportWriter::Zip {
+                    path: path.to_path_buf(),
+                    writer: zip::ZipWriter::new(file),
+                }
+            }
+        };
+
+        Ok(writer)
+    }
+
+    pub(crate) async fn write(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        match self {
+            ExportWriter::Directory { path } => {
+                let path = path.join(name);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(path, data).await?;
+            }
+            ExportWriter::Zip { writer, .. } => {
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated);
+                writer
+                    .start_file(name, options)
+                    .map_err(anyhow::Error::from)?;
+                writer.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn finish(self) -> Result<PathBuf, Error> {
+        match self {
+            ExportWriter::Directory { path } => Ok(path),
+            ExportWriter::Zip { path, writer } => {
+                let file = writer.finish().map_err(anyhow::Error::from)?;
+                #[cfg(not(target_arch = "wasm32"))]
+                file.sync_all()?;
+                #[cfg(target_arch = "wasm32")]
+                fs::write(&path, file.into_inner()).await?;
+                Ok(path)
+            }
+        }
+    }
+}
+
+/// Renders the self-contained html viewer with the export embedded into it
+pub fn render_viewer(export: &ConversationExport) -> Result<String, Error> {
+    let data = serde_json::to_string(export)?;
+    // Prevent the embedded json from terminating the script element early
+    let data = data.replace("</", "<\\/");
+    Ok(VIEWER_TEMPLATE.replace(VIEWER_DATA_PLACEHOLDER, &data))
+}
+
+/// Strips any directory component from an attachment name so it cannot be written outside
+/// of the export
+fn sanitize_name(name: &str) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    match name {
+        "" | "." | ".." => "file".into(),
+        name => name.into(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn export_conversation(
+    ipfs: Ipfs,
+    keypair: Keypair,
+    keystore: Either<DID, Keystore>,
+    document: ConversationDocument,
+    members: Vec<PeerId>,
+    path: PathBuf,
+    options: ExportOptions,
+) -> ExportProgressStream {
+    let stream = async_stream::stream! {
+        let mut writer = match ExportWriter::new(&path, options.format()).await {
+            Ok(writer) => writer,
+            Err(error) => {
+                yield ExportProgression::Failed { error };
+                return;
+            }
+        };
+
+        let list = match document.get_message_list(&ipfs).await {
+            Ok(list) => list,
+            Err(error) => {
+                yield ExportProgression::Failed { error };
+                return;
+            }
+        };
+
+        let total = list.len();
+        let mut messages = Vec::with_capacity(total);
+
+        for (index, message_document) in list.into_iter().enumerate() {
+            let message = match message_document
+                .resolve(&ipfs, &keypair, true, keystore.as_ref())
+                .await
+            {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(
+                        id = %document.id,
+                        message_id = %message_document.id,
+                        error = %e,
+                        "unable to resolve message for export"
+                    );
+                    yield ExportProgression::Messages { current: index + 1, total };
+                    continue;
+                }
+            };
+
+            let history = message_document
+                .history(&ipfs, &keypair, true, keystore.as_ref())
+                .await
+                .unwrap_or_default();
+
+            let mut exported = ExportedMessage::new(&message, &history);
+
+            for attachment in message_document.attachments() {
+                let mut entry = ExportedAttachment {
+                    id: attachment.id,
+                    name: attachment.name.clone(),
+                    size: attachment.size,
+                    file_type: attachment.file_type.clone(),
+                    path: None,
+                };
+
+                if options.attachments() {
+                    let result = export_attachment(
+                        &ipfs,
+                        &members,
+                        &mut writer,
+                        &message_document,
+                        attachment,
+                    )
+                    .await;
+
+                    match result {
+                        Ok(path) => {
+                            entry.path = Some(path);
+                            yield ExportProgression::Attachment(Progression::ProgressComplete {
+                                name: attachment.name.clone(),
+                                total: Some(attachment.size),
+                            });
+                        }
+                        Err(error) => {
+                            yield ExportProgression::Attachment(Progression::ProgressFailed {
+                                name: attachment.name.clone(),
+                                last_size: None,
+                                error,
+                            });
+                        }
+                    }
+                }
+
+                exported.attachments.push(entry);
+            }
+
+            messages.push(exported);
+            yield ExportProgression::Messages { current: index + 1, total };
+        }
+
+        let export = ConversationExport {
+            version: EXPORT_VERSION,
+            exported: Utc::now(),
+            conversation: ExportedConversation::from(&document),
+            messages,
+        };
+
+        let result = async {
+            let json = serde_json::to_vec_pretty(&export)?;
+            writer.write(EXPORT_JSON_FILE, &json).await?;
+            let html = render_viewer(&export)?;
+            writer.write(EXPORT_HTML_FILE, html.as_bytes()).await?;
+            writer.finish().await
+        }
+        .await;
+
+        match result {
+            Ok(path) => yield ExportProgression::Complete { path },
+            Err(error) => yield ExportProgression::Failed { error },
+        }
+    };
+
+    stream.boxed()
+}
+
+async fn export_attachment(
+    ipfs: &Ipfs,
+    members: &[PeerId],
+    writer: &mut ExportWriter,
+    message: &MessageDocument,
+    attachment: &FileAttachmentDocument,
+) -> Result<String, Error> {
+    let path = format!(
+        "{EXPORT_ATTACHMENT_DIRECTORY}/{}/{}",
+        message.id,
+        sanitize_name(&attachment.name)
+    );
+
+    let bytes = ByteCollection::new(attachment.download_stream(ipfs, members, None)).await?;
+    writer.write(&path, &bytes).await?;
+
+    Ok(path)
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::hash::sha256_iter;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::{GeoPosition, MAX_LIVE_GEOLOCATION_DURATION};
+
+use super::message::MessageSignature;
+use crate::store::DidExt;
+
+/// Update of a live position signed by the sender of the geolocation message.
+/// Updates are sent through the event topic of the conversation and are not stored
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GeolocationUpdateDocument {
+    pub position: GeoPosition,
+    pub date: DateTime<Utc>,
+    pub signature: MessageSignature,
+}
+
+impl GeolocationUpdateDocument {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        position: GeoPosition,
+    ) -> Result<Self, Error> {
+        position.validate()?;
+        let date = Utc::now();
+        let hash = update_hash(conversation_id, message_id, &position, date);
+        let signature = keypair.sign(&hash).expect("not RSA");
+
+        Ok(Self {
+            position,
+            date,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn verify(&self, sender: &DID, conversation_id: Uuid, message_id: Uuid) -> bool {
+        let Ok(sender_pk) = sender.to_public_key() else {
+            return false;
+        };
+
+        if self.position.validate().is_err() {
+            return false;
+        }
+
+        let hash = update_hash(conversation_id, message_id, &self.position, self.date);
+        sender_pk.verify(&hash, self.signature.as_ref())
+    }
+}
+
+/// Stop of a live position signed by the sender of the geolocation message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GeolocationStopDocument {
+    pub date: DateTime<Utc>,
+    pub signature: MessageSignature,
+}
+
+impl GeolocationStopDocument {
+    pub fn new(keypair: &Keypair, conversation_id: Uuid, message_id: Uuid) -> Result<Self, Error> {
+        let date = Utc::now();
+        let hash = stop_hash(conversation_id, message_id, date);
+        let signature = keypair.sign(&hash).expect("not RSA");
+        Ok(Self {
+            date,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn verify(&self, sender: &DID, conversation_id: Uuid, message_id: Uuid) -> bool {
+        let Ok(sender_pk) = sender.to_public_key() else {
+            return false;
+        };
+
+        let hash = stop_hash(conversation_id, message_id, self.date);
+        sender_pk.verify(&hash, self.signature.as_ref())
+    }
+}
+
+/// Check that the duration a position is shared live for is within limits
+pub fn validate_live_duration(duration: Duration) -> Result<(), Error> {
+    if duration.is_zero() || duration > MAX_LIVE_GEOLOCATION_DURATION {
+        return Err(Error::OtherWithContext(format!(
+            "live duration must be greater than zero and no more than {}s",
+            MAX_LIVE_GEOLOCATION_DURATION.as_secs()
+        )));
+    }
+    Ok(())
+}
+
+fn update_hash(
+    conversation_id: Uuid,
+    message_id: Uuid,
+    position: &GeoPosition,
+    date: DateTime<Utc>,
+) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(message_id.as_bytes().to_vec()),
+            Some(position.latitude().to_be_bytes().to_vec()),
+            Some(position.longitude().to_be_bytes().to_vec()),
+            position
+                .accuracy()
+                .map(|accuracy| accuracy.to_be_bytes().to_vec()),
+            Some(date.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+fn stop_hash(conversation_id: Uuid, message_id: Uuid, date: DateTime<Utc>) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(message_id.as_bytes().to_vec()),
+            Some(b"stop".to_vec()),
+            Some(date.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::PeerIdExt;
+
+    #[test]
+    fn signed_updates_and_stop() -> anyhow::Result<()> {
+        let conversation_id = Uuid::new_v4();
+        let message_id = Uuid::new_v4();
+
+        let sender = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519();
+
+        let mut position = GeoPosition::new(52.52, 13.405)?;
+        position.set_accuracy(Some(12.5))?;
+
+        let update =
+            GeolocationUpdateDocument::new(&sender, conversation_id, message_id, position)?;
+        assert!(update.verify(&sender.to_did()?, conversation_id, message_id));
+        assert!(!update.verify(&other.to_did()?, conversation_id, message_id));
+        assert!(!update.verify(&sender.to_did()?, conversation_id, Uuid::new_v4()));
+
+        let mut tampered = update.clone();
+        tampered.position = GeoPosition::new(48.85, 2.35)?;
+        assert!(!tampered.verify(&sender.to_did()?, conversation_id, message_id));
+
+        let stop = GeolocationStopDocument::new(&sender, conversation_id, message_id)?;
+        assert!(stop.verify(&sender.to_did()?, conversation_id, message_id));
+        assert!(!stop.verify(&other.to_did()?, conversation_id, message_id));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_positions_are_rejected() {
+        assert!(GeoPosition::new(90.1, 0.0).is_err());
+        assert!(GeoPosition::new(0.0, -180.1).is_err());
+        assert!(GeoPosition::new(f64::NAN, 0.0).is_err());
+        assert!(GeoPosition::new(0.0, 0.0)
+            .expect("valid position")
+            .set_accuracy(Some(-1.0))
+            .is_err());
+
+        assert!(validate_live_duration(Duration::ZERO).is_err());
+        assert!(
+            validate_live_duration(MAX_LIVE_GEOLOCATION_DURATION + Duration::from_secs(1)).is_err()
+        );
+        assert!(validate_live_duration(Duration::from_secs(15 * 60)).is_ok());
+    }
+}
@@ -0,0 +1,231 @@
+use chrono::{DateTime, Utc};
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::hash::sha256_iter;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::ConversationInvite;
+
+use super::message::MessageSignature;
+use crate::store::{DidExt, PeerIdExt};
+
+/// Token of an invite to a group conversation, signed by the member that created the invite.
+/// The token is shared out of band and presented to the inviter to join the conversation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConversationInviteToken {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub inviter: DID,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<DateTime<Utc>>,
+    pub signature: MessageSignature,
+}
+
+impl ConversationInviteToken {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        expiry: Option<DateTime<Utc>>,
+    ) -> Result<Self, Error> {
+        let id = Uuid::new_v4();
+        let inviter = keypair.to_did()?;
+        let hash = token_hash(id, conversation_id, &inviter, expiry);
+        let signature = keypair.sign(&hash).expect("not RSA");
+
+        Ok(Self {
+            id,
+            conversation_id,
+            inviter,
+            expiry,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn verify(&self) -> bool {
+        let Ok(inviter_pk) = self.inviter.to_public_key() else {
+            return false;
+        };
+
+        let hash = token_hash(self.id, self.conversation_id, &self.inviter, self.expiry);
+        inviter_pk.verify(&hash, self.signature.as_ref())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= Utc::now())
+    }
+
+    /// Encode the token into a string that can be shared or used as a QR payload
+    pub fn encode(&self) -> Result<String, Error> {
+        let bytes = serde_json::to_vec(self)?;
+        Ok(bs58::encode(bytes).into_string())
+    }
+
+    /// Decode and verify a token previously encoded with [`ConversationInviteToken::encode`]
+    pub fn decode(token: &str) -> Result<Self, Error> {
+        let bytes = bs58::decode(token.trim())
+            .into_vec()
+            .map_err(|_| Error::InvalidConversion)?;
+
+        let token: Self = serde_json::from_slice(&bytes)?;
+
+        if !token.verify() {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(token)
+    }
+}
+
+fn token_hash(
+    id: Uuid,
+    conversation_id: Uuid,
+    inviter: &DID,
+    expiry: Option<DateTime<Utc>>,
+) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(id.as_bytes().to_vec()),
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(inviter.public_key_bytes()),
+            expiry.map(|expiry| expiry.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+/// Invite stored by the member that created it, tracking how often it has been used
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConversationInviteDocument {
+    pub token: ConversationInviteToken,
+    pub created: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<u32>,
+    /// Identities that joined using the invite
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<DID>,
+}
+
+impl ConversationInviteDocument {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Result<Self, Error> {
+        if expiry.is_some_and(|expiry| expiry <= Utc::now()) {
+            return Err(Error::OtherWithContext(
+                "invite cannot expire in the past".into(),
+            ));
+        }
+
+        if max_uses == Some(0) {
+            return Err(Error::InvalidLength {
+                context: "max_uses".into(),
+                current: 0,
+                minimum: Some(1),
+                maximum: None,
+            });
+        }
+
+        Ok(Self {
+            token: ConversationInviteToken::new(keypair, conversation_id, expiry)?,
+            created: Utc::now(),
+            max_uses,
+            members: vec![],
+        })
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.token.id
+    }
+
+    pub fn uses(&self) -> u32 {
+        self.members.len() as u32
+    }
+
+    /// Returns true if the invite has not expired and has uses remaining
+    pub fn is_valid(&self) -> bool {
+        !self.token.is_expired() && !self.max_uses.is_some_and(|max| self.uses() >= max)
+    }
+}
+
+impl TryFrom<&ConversationInviteDocument> for ConversationInvite {
+    type Error = Error;
+    fn try_from(document: &ConversationInviteDocument) -> Result<Self, Self::Error> {
+        let mut invite = ConversationInvite::default();
+        invite.set_id(document.token.id);
+        invite.set_conversation_id(document.token.conversation_id);
+        invite.set_inviter(document.token.inviter.clone());
+        invite.set_created(document.created);
+        invite.set_expiry(document.token.expiry);
+        invite.set_max_uses(document.max_uses);
+        invite.set_uses(document.uses());
+        invite.set_token(document.token.encode()?);
+        Ok(invite)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use rust_ipfs::Keypair;
+    use uuid::Uuid;
+
+    use super::{ConversationInviteDocument, ConversationInviteToken};
+    use crate::store::PeerIdExt;
+
+    #[test]
+    fn token_roundtrip() {
+        let keypair = Keypair::generate_ed25519();
+        let conversation_id = Uuid::new_v4();
+        let document = ConversationInviteDocument::new(
+            &keypair,
+            conversation_id,
+            Some(Utc::now() + Duration::hours(1)),
+            Some(1),
+        )
+        .expect("valid invite");
+
+        let encoded = document.token.encode().expect("encoded");
+        let token = ConversationInviteToken::decode(&encoded).expect("valid token");
+        assert_eq!(token, document.token);
+        assert!(!token.is_expired());
+        assert!(document.is_valid());
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let keypair = Keypair::generate_ed25519();
+        let mut token = ConversationInviteToken::new(&keypair, Uuid::new_v4(), None).unwrap();
+        token.conversation_id = Uuid::new_v4();
+
+        let encoded = token.encode().expect("encoded");
+        assert!(ConversationInviteToken::decode(&encoded).is_err());
+        assert!(ConversationInviteToken::decode("not a token").is_err());
+    }
+
+    #[test]
+    fn invite_limits() {
+        let keypair = Keypair::generate_ed25519();
+        let conversation_id = Uuid::new_v4();
+
+        assert!(ConversationInviteDocument::new(
+            &keypair,
+            conversation_id,
+            Some(Utc::now() - Duration::seconds(1)),
+            None
+        )
+        .is_err());
+        assert!(ConversationInviteDocument::new(&keypair, conversation_id, None, Some(0)).is_err());
+
+        let mut document =
+            ConversationInviteDocument::new(&keypair, conversation_id, None, Some(1)).unwrap();
+        assert!(document.is_valid());
+        document
+            .members
+            .push(Keypair::generate_ed25519().to_did().unwrap());
+        assert!(!document.is_valid());
+    }
+}
@@ -0,0 +1,729 @@
+//! Link previews are generated by the sender of a message when embeds are enabled for it.
+//! Pages are fetched through the [`LinkPreviewFetcher`] provided in the store settings, which defaults
+//! to [`HttpFetcher`] on native targets, and the resulting previews are encrypted and signed alongside the message, so recipients never contact
+//! the linked site themselves.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use ipld_core::cid::Cid;
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::hash::sha256_iter;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::LinkPreview;
+
+use super::message::MessageSignature;
+use crate::store::document::image_dag::ImageDag;
+use crate::store::{DidExt, MAX_THUMBNAIL_SIZE};
+use crate::thumbnail::ThumbnailGenerator;
+
+/// Maximum number of links within a message that previews are generated for
+pub const MAX_LINK_PREVIEWS: usize = 5;
+
+/// Maximum size of a page or image fetched for a preview
+pub const MAX_LINK_RESOURCE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Maximum duration to generate the previews of a message
+pub const LINK_PREVIEW_TIMEOUT: Duration = Duration::from_secs(30);
+
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 1024;
+
+/// Resource returned by a [`LinkPreviewFetcher`]
+#[derive(Debug, Clone)]
+pub struct LinkResource {
+    /// Value of the `Content-Type` header, if provided
+    pub content_type: Option<String>,
+    pub body: Bytes,
+}
+
+/// Fetches the pages and images used to generate link previews.
+/// Implementations are expected to follow redirects and to limit the size of the body
+#[async_trait::async_trait]
+pub trait LinkPreviewFetcher: Send + Sync + 'static {
+    async fn fetch(&self, url: &str) -> Result<LinkResource, Error>;
+}
+
+/// [`LinkPreviewFetcher`] requesting links over http(s).
+/// The body is read up to [`MAX_LINK_RESOURCE_SIZE`] so a large resource is not downloaded in full
+///
+/// Note: Not available on wasm, where a fetcher has to be provided in the store settings
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .redirect(reqwest::redirect::Policy::limited(5))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait::async_trait]
+impl LinkPreviewFetcher for HttpFetcher {
+    async fn fetch(&self, url: &str) -> Result<LinkResource, Error> {
+        use futures::StreamExt;
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(anyhow::Error::from)?;
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let too_large = |current| Error::InvalidLength {
+            context: "link".into(),
+            current,
+            minimum: None,
+            maximum: Some(MAX_LINK_RESOURCE_SIZE),
+        };
+
+        if let Some(length) = response.content_length() {
+            if length > MAX_LINK_RESOURCE_SIZE as u64 {
+                return Err(too_large(length as usize));
+            }
+        }
+
+        let mut body = bytes::BytesMut::new();
+        let mut stream = response.bytes_stream();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(anyhow::Error::from)?;
+            if body.len() + chunk.len() > MAX_LINK_RESOURCE_SIZE {
+                return Err(too_large(body.len() + chunk.len()));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(LinkResource {
+            content_type,
+            body: body.freeze(),
+        })
+    }
+}
+
+/// Preview of a link as stored within [`LinkPreviewsDocument`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LinkPreviewDocument {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    /// [`ImageDag`] of the thumbnail
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<Cid>,
+}
+
+impl LinkPreviewDocument {
+    pub async fn resolve(&self, ipfs: &Ipfs, local: bool) -> LinkPreview {
+        let mut preview = LinkPreview::new(&self.url);
+        preview.set_title(self.title.clone());
+        preview.set_description(self.description.clone());
+        preview.set_site_name(self.site_name.clone());
+
+        if let Some(cid) = self.thumbnail {
+            match resolve_thumbnail(ipfs, cid, local).await {
+                Ok((image, data)) => {
+                    preview.set_thumbnail_format(image.mime.into());
+                    preview.set_thumbnail(data.to_vec());
+                }
+                Err(e) => {
+                    tracing::warn!(url = %self.url, error = %e, "unable to resolve link preview thumbnail")
+                }
+            }
+        }
+
+        preview
+    }
+}
+
+async fn resolve_thumbnail(ipfs: &Ipfs, cid: Cid, local: bool) -> Result<(ImageDag, Bytes), Error> {
+    let image: ImageDag = ipfs
+        .get_dag(cid)
+        .timeout(Duration::from_secs(10))
+        .set_local(local)
+        .deserialized()
+        .await?;
+
+    let data = ipfs
+        .cat_unixfs(image.link)
+        .set_local(local)
+        .timeout(Duration::from_secs(10))
+        .max_length(MAX_THUMBNAIL_SIZE)
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    Ok((image, data))
+}
+
+/// Previews of a message signed by its sender. Previews that are removed are represented without any data
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LinkPreviewsDocument {
+    /// Encrypted list of [`LinkPreviewDocument`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Bytes>,
+    pub date: DateTime<Utc>,
+    pub signature: MessageSignature,
+}
+
+impl LinkPreviewsDocument {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        data: Option<Bytes>,
+    ) -> Result<Self, Error> {
+        let date = Utc::now();
+        let hash = previews_hash(conversation_id, message_id, data.as_ref(), date);
+        let signature = keypair.sign(&hash).expect("not RSA");
+        Ok(Self {
+            data,
+            date,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn verify(&self, sender: &DID, conversation_id: Uuid, message_id: Uuid) -> bool {
+        let Ok(sender_pk) = sender.to_public_key() else {
+            return false;
+        };
+
+        let hash = previews_hash(conversation_id, message_id, self.data.as_ref(), self.date);
+        sender_pk.verify(&hash, self.signature.as_ref())
+    }
+}
+
+fn previews_hash(
+    conversation_id: Uuid,
+    message_id: Uuid,
+    data: Option<&Bytes>,
+    date: DateTime<Utc>,
+) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(message_id.as_bytes().to_vec()),
+            data.map(|data| data.to_vec()),
+            Some(date.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+/// Generate previews for the links found in `lines`. Links that cannot be fetched or that do not
+/// provide any metadata are skipped
+pub async fn generate(
+    ipfs: &Ipfs,
+    fetcher: &dyn LinkPreviewFetcher,
+    lines: &[String],
+    (width, height): (u32, u32),
+    exact: bool,
+) -> Vec<LinkPreviewDocument> {
+    let mut previews = vec![];
+
+    for url in extract_urls(lines) {
+        match generate_preview(ipfs, fetcher, &url, width, height, exact).await {
+            Ok(Some(preview)) => previews.push(preview),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(%url, error = %e, "unable to generate link preview"),
+        }
+    }
+
+    previews
+}
+
+async fn generate_preview(
+    ipfs: &Ipfs,
+    fetcher: &dyn LinkPreviewFetcher,
+    url: &str,
+    width: u32,
+    height: u32,
+    exact: bool,
+) -> Result<Option<LinkPreviewDocument>, Error> {
+    let resource = fetcher.fetch(url).await?;
+
+    if resource.body.len() > MAX_LINK_RESOURCE_SIZE {
+        return Err(Error::InvalidLength {
+            context: "link".into(),
+            current: resource.body.len(),
+            minimum: None,
+            maximum: Some(MAX_LINK_RESOURCE_SIZE),
+        });
+    }
+
+    let is_html = resource
+        .content_type
+        .as_deref()
+        .map(|ty| ty.to_ascii_lowercase().contains("html"))
+        .unwrap_or(true);
+
+    if !is_html {
+        return Ok(None);
+    }
+
+    let html = String::from_utf8_lossy(&resource.body);
+    let metadata = parse_metadata(&html);
+
+    if metadata.is_empty() {
+        return Ok(None);
+    }
+
+    let thumbnail = match metadata
+        .image
+        .as_deref()
+        .and_then(|image| join_url(url, image))
+    {
+        Some(image) => {
+            match generate_thumbnail(ipfs, fetcher, &image, width, height, exact).await {
+                Ok(cid) => Some(cid),
+                Err(e) => {
+                    tracing::warn!(%url, %image, error = %e, "unable to generate thumbnail for link preview");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    Ok(Some(LinkPreviewDocument {
+        url: url.to_string(),
+        title: metadata.title,
+        description: metadata.description,
+        site_name: metadata.site_name,
+        thumbnail,
+    }))
+}
+
+async fn generate_thumbnail(
+    ipfs: &Ipfs,
+    fetcher: &dyn LinkPreviewFetcher,
+    url: &str,
+    width: u32,
+    height: u32,
+    exact: bool,
+) -> Result<Cid, Error> {
+    let resource = fetcher.fetch(url).await?;
+
+    if resource.body.len() > MAX_LINK_RESOURCE_SIZE {
+        return Err(Error::InvalidLength {
+            context: "image".into(),
+            current: resource.body.len(),
+            minimum: None,
+            maximum: Some(MAX_LINK_RESOURCE_SIZE),
+        });
+    }
+
+    // The thumbnail generator relies on the extension of the name to determine the format
+    let extension = resource
+        .content_type
+        .as_deref()
+        .and_then(|ty| ty.split(';').next())
+        .and_then(|ty| ty.trim().strip_prefix("image/"))
+        .map(str::to_string)
+        .or_else(|| {
+            let path = url.split(['?', '#']).next().unwrap_or_default();
+            let name = path.rsplit('/').next().unwrap_or_default();
+            name.rsplit_once('.')
+                .map(|(_, extension)| extension.to_string())
+        })
+        .ok_or(Error::InvalidConversion)?;
+
+    let generator = ThumbnailGenerator::new(ipfs);
+    let id = generator
+        .insert_buffer(
+            format!("thumbnail.{extension}"),
+            &resource.body,
+            width,
+            height,
+            exact,
+        )
+        .await;
+
+    let (_, path, _) = generator.get(id).await?;
+    path.root().cid().copied().ok_or(Error::Other)
+}
+
+/// Metadata of a page taken from its OpenGraph and Twitter card tags
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub image: Option<String>,
+}
+
+impl PageMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image.is_none()
+    }
+}
+
+/// Returns the unique http(s) links found within `lines`, up to [`MAX_LINK_PREVIEWS`]
+pub fn extract_urls(lines: &[String]) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+
+    for word in lines.iter().flat_map(|line| line.split_whitespace()) {
+        let lower = word.to_ascii_lowercase();
+        let Some(start) = ["https://", "http://"]
+            .iter()
+            .filter_map(|scheme| lower.find(scheme))
+            .min()
+        else {
+            continue;
+        };
+
+        let url = word[start..].trim_end_matches(|c: char| {
+            matches!(
+                c,
+                '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '}' | '>' | '"' | '\''
+            )
+        });
+
+        let Some((_, rest)) = url.split_once("://") else {
+            continue;
+        };
+
+        if rest.is_empty() || rest.starts_with('/') {
+            continue;
+        }
+
+        if urls.iter().any(|existing| existing == url) {
+            continue;
+        }
+
+        urls.push(url.to_string());
+
+        if urls.len() == MAX_LINK_PREVIEWS {
+            break;
+        }
+    }
+
+    urls
+}
+
+/// Parses the OpenGraph and Twitter card meta tags of a page, falling back to the `<title>` of the page
+pub fn parse_metadata(html: &str) -> PageMetadata {
+    // Note: Lowercasing ascii keeps the byte offsets identical to the original
+    let lower = html.to_ascii_lowercase();
+    let mut properties: HashMap<String, String> = HashMap::new();
+
+    let mut offset = 0;
+    while let Some(position) = lower[offset..].find("<meta") {
+        let start = offset + position + "<meta".len();
+        let Some(length) = lower[start..].find('>') else {
+            break;
+        };
+        let end = start + length;
+        offset = end;
+
+        let attributes = parse_attributes(&html[start..end]);
+        let key = attributes
+            .get("property")
+            .or_else(|| attributes.get("name"))
+            .map(|key| key.to_ascii_lowercase());
+
+        if let (Some(key), Some(content)) = (key, attributes.get("content")) {
+            properties.entry(key).or_insert_with(|| content.clone());
+        }
+    }
+
+    let find = |keys: &[&str], max: usize| {
+        keys.iter()
+            .filter_map(|key| properties.get(*key))
+            .map(|value| normalize_text(value, max))
+            .find(|value| !value.is_empty())
+    };
+
+    let title = find(&["og:title", "twitter:title"], MAX_TITLE_LENGTH).or_else(|| {
+        let start = lower.find("<title")?;
+        let start = start + lower[start..].find('>')? + 1;
+        let end = start + lower[start..].find("</title")?;
+        Some(normalize_text(&html[start..end], MAX_TITLE_LENGTH)).filter(|title| !title.is_empty())
+    });
+
+    let description = find(
+        &["og:description", "twitter:description", "description"],
+        MAX_DESCRIPTION_LENGTH,
+    );
+
+    let site_name = find(&["og:site_name", "application-name"], MAX_TITLE_LENGTH);
+
+    let image = [
+        "og:image:secure_url",
+        "og:image:url",
+        "og:image",
+        "twitter:image",
+        "twitter:image:src",
+    ]
+    .iter()
+    .filter_map(|key| properties.get(*key))
+    .map(|value| decode_entities(value).trim().to_string())
+    .find(|value| !value.is_empty());
+
+    PageMetadata {
+        title,
+        description,
+        site_name,
+        image,
+    }
+}
+
+fn parse_attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut chars = tag.char_indices().peekable();
+
+    loop {
+        while chars
+            .next_if(|(_, c)| c.is_whitespace() || *c == '/')
+            .is_some()
+        {}
+
+        let Some(&(start, _)) = chars.peek() else {
+            break;
+        };
+
+        let mut end = tag.len();
+        while let Some(&(index, c)) = chars.peek() {
+            if c.is_whitespace() || c == '=' || c == '/' {
+                end = index;
+                break;
+            }
+            chars.next();
+        }
+
+        let name = tag[start..end].to_ascii_lowercase();
+
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        if chars.next_if(|(_, c)| *c == '=').is_none() {
+            if !name.is_empty() {
+                attributes.entry(name).or_default();
+            }
+            continue;
+        }
+
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let value = match chars.peek().copied() {
+            Some((index, quote @ ('"' | '\''))) => {
+                chars.next();
+                let mut end = tag.len();
+                for (position, c) in chars.by_ref() {
+                    if c == quote {
+                        end = position;
+                        break;
+                    }
+                }
+                &tag[index + 1..end]
+            }
+            Some((index, _)) => {
+                let mut end = tag.len();
+                while let Some(&(position, c)) = chars.peek() {
+                    if c.is_whitespace() {
+                        end = position;
+                        break;
+                    }
+                    chars.next();
+                }
+                &tag[index..end]
+            }
+            None => "",
+        };
+
+        if !name.is_empty() {
+            attributes.entry(name).or_insert_with(|| value.to_string());
+        }
+    }
+
+    attributes
+}
+
+fn normalize_text(value: &str, max: usize) -> String {
+    let value = decode_entities(value);
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    value.chars().take(max).collect()
+}
+
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(position) = rest.find('&') {
+        decoded.push_str(&rest[..position]);
+        rest = &rest[position..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            entity => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Resolves `reference` against the url of the page it was found on
+fn join_url(base: &str, reference: &str) -> Option<String> {
+    let lower = reference.to_ascii_lowercase();
+    if lower.starts_with("https://") || lower.starts_with("http://") {
+        return Some(reference.to_string());
+    }
+
+    let (scheme, rest) = base.split_once("://")?;
+
+    if let Some(reference) = reference.strip_prefix("//") {
+        return Some(format!("{scheme}://{reference}"));
+    }
+
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(authority_end);
+
+    if authority.is_empty() || (reference.contains(':') && !reference.starts_with('/')) {
+        return None;
+    }
+
+    if reference.starts_with('/') {
+        return Some(format!("{scheme}://{authority}{reference}"));
+    }
+
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let directory = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+
+    Some(format!("{scheme}://{authority}{directory}/{reference}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extract_urls, join_url, parse_metadata, MAX_LINK_PREVIEWS};
+
+    #[test]
+    fn extract_urls_from_lines() {
+        let lines = vec![
+            "check https://example.com/page, and (http://example.org/a?b=c).".to_string(),
+            "again https://example.com/page".to_string(),
+            "not a link: https:// or ftp://example.com".to_string(),
+        ];
+
+        assert_eq!(
+            extract_urls(&lines),
+            vec![
+                "https://example.com/page".to_string(),
+                "http://example.org/a?b=c".to_string(),
+            ]
+        );
+
+        let lines = (0..10)
+            .map(|i| format!("https://example.com/{i}"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(extract_urls(&lines).len(), MAX_LINK_PREVIEWS);
+    }
+
+    #[test]
+    fn parse_open_graph_metadata() {
+        let html = r#"<html><head>
+            <title>Fallback title</title>
+            <META property="og:title" content="Example &amp; Co">
+            <meta name="twitter:title" content="Twitter title" />
+            <meta name=description content='A   page
+                about &quot;things&quot;'>
+            <meta property="og:site_name" content="Example">
+            <meta name="twitter:image" content="/images/preview.png">
+        </head></html>"#;
+
+        let metadata = parse_metadata(html);
+        assert_eq!(metadata.title.as_deref(), Some("Example & Co"));
+        assert_eq!(
+            metadata.description.as_deref(),
+            Some("A page about \"things\"")
+        );
+        assert_eq!(metadata.site_name.as_deref(), Some("Example"));
+        assert_eq!(metadata.image.as_deref(), Some("/images/preview.png"));
+    }
+
+    #[test]
+    fn parse_title_fallback() {
+        let metadata =
+            parse_metadata("<html><head><title>\n Plain &#x27;page&#39; </title></head>");
+        assert_eq!(metadata.title.as_deref(), Some("Plain 'page'"));
+        assert!(metadata.description.is_none());
+        assert!(metadata.image.is_none());
+
+        assert!(parse_metadata("<html><body>nothing</body></html>").is_empty());
+    }
+
+    #[test]
+    fn join_relative_urls() {
+        let base = "https://example.com/blog/post?id=1";
+        assert_eq!(
+            join_url(base, "/image.png").as_deref(),
+            Some("https://example.com/image.png")
+        );
+        assert_eq!(
+            join_url(base, "image.png").as_deref(),
+            Some("https://example.com/blog/image.png")
+        );
+        assert_eq!(
+            join_url(base, "//cdn.example.com/image.png").as_deref(),
+            Some("https://cdn.example.com/image.png")
+        );
+        assert_eq!(
+            join_url(base, "http://other.com/image.png").as_deref(),
+            Some("http://other.com/image.png")
+        );
+        assert_eq!(join_url(base, "data:image/png;base64,AAAA"), None);
+    }
+}
@@ -0,0 +1,136 @@
+use warp::crypto::DID;
+use warp::raygun::community::RoleId;
+use warp::raygun::Mention;
+
+use crate::store::document::identity::IdentityDocument;
+
+/// Parses `@username#shortid`, `@everyone`, `@here` and `@role` mentions from the lines of a message.
+/// Identities are matched against `members`, and roles against `roles` by name
+pub fn parse_mentions(
+    lines: &[String],
+    members: &[IdentityDocument],
+    roles: &[(RoleId, String)],
+) -> Vec<Mention> {
+    let mut mentions = vec![];
+
+    for word in lines.iter().flat_map(|line| line.split_whitespace()) {
+        let Some(name) = word.strip_prefix('@') else {
+            continue;
+        };
+
+        // Trailing punctuation, such as `@everyone!`, is not part of the mention
+        let name = name.trim_end_matches(|c: char| c.is_ascii_punctuation());
+
+        let mention = match name {
+            "everyone" => Some(Mention::Everyone),
+            "here" => Some(Mention::Here),
+            name => match name.split_once('#') {
+                Some((username, short_id)) => members
+                    .iter()
+                    .find(|member| {
+                        member.username.eq_ignore_ascii_case(username)
+                            && member.short_id.as_slice() == short_id.as_bytes()
+                    })
+                    .map(|member| Mention::Identity(member.did.clone())),
+                None => roles
+                    .iter()
+                    .find(|(_, role)| role.eq_ignore_ascii_case(name))
+                    .map(|(id, _)| Mention::Role(*id)),
+            },
+        };
+
+        if let Some(mention) = mention {
+            if !mentions.contains(&mention) {
+                mentions.push(mention);
+            }
+        }
+    }
+
+    mentions
+}
+
+/// Returns true if `did` is mentioned directly, through one of its `roles` or by a mass mention
+pub fn is_mentioned(mentions: &[Mention], did: &DID, roles: &[RoleId]) -> bool {
+    mentions.iter().any(|mention| match mention {
+        Mention::Identity(id) => id == did,
+        Mention::Everyone | Mention::Here => true,
+        Mention::Role(id) => roles.contains(id),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rust_ipfs::Keypair;
+    use uuid::Uuid;
+    use warp::raygun::Mention;
+
+    use super::{is_mentioned, parse_mentions};
+    use crate::store::document::identity::IdentityDocument;
+    use crate::store::PeerIdExt;
+
+    fn member(username: &str, short_id: &[u8; 8]) -> IdentityDocument {
+        let did = Keypair::generate_ed25519().to_did().expect("valid did");
+        let mut identity = warp::multipass::identity::Identity::default();
+        identity.set_username(username);
+        identity.set_short_id(*short_id);
+        identity.set_did_key(did);
+        IdentityDocument::from(identity)
+    }
+
+    #[test]
+    fn parse_mentions_from_lines() {
+        let alice = member("Alice", b"abcd1234");
+        let bob = member("Bob", b"efgh5678");
+        let role_id = Uuid::new_v4();
+        let roles = vec![(role_id, String::from("moderators"))];
+
+        let lines = vec![
+            "Hey @alice#abcd1234, @everyone!".to_string(),
+            "cc @moderators and @bob#wrongid @alice#abcd1234".to_string(),
+        ];
+
+        let mentions = parse_mentions(&lines, &[alice.clone(), bob.clone()], &roles);
+
+        assert_eq!(
+            mentions,
+            vec![
+                Mention::Identity(alice.did.clone()),
+                Mention::Everyone,
+                Mention::Role(role_id),
+            ]
+        );
+
+        assert!(is_mentioned(&mentions, &bob.did, &[]));
+        assert!(!is_mentioned(
+            &[Mention::Identity(alice.did.clone())],
+            &bob.did,
+            &[]
+        ));
+        assert!(is_mentioned(
+            &[Mention::Role(role_id)],
+            &bob.did,
+            &[role_id]
+        ));
+    }
+
+    #[test]
+    fn mention_serde_is_compatible_with_identities() -> anyhow::Result<()> {
+        let did = Keypair::generate_ed25519().to_did()?;
+        let mentions = vec![
+            Mention::Identity(did.clone()),
+            Mention::Here,
+            Mention::Role(Uuid::new_v4()),
+        ];
+
+        let bytes = serde_json::to_vec(&mentions)?;
+        assert_eq!(serde_json::from_slice::<Vec<Mention>>(&bytes)?, mentions);
+
+        // Mentions were previously a list of identities
+        let bytes = serde_json::to_vec(&vec![did.clone()])?;
+        assert_eq!(
+            serde_json::from_slice::<Vec<Mention>>(&bytes)?,
+            vec![Mention::Identity(did)]
+        );
+        Ok(())
+    }
+}
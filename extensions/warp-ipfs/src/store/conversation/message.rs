@@ -1,3 +1,7 @@
+use crate::store::conversation::geolocation::GeolocationStopDocument;
+use crate::store::conversation::link_preview::{LinkPreviewDocument, LinkPreviewsDocument};
+use crate::store::conversation::poll::{self, PollCloseDocument, PollVoteDocument};
+use crate::store::document::identity::IdentityDocument;
 use crate::store::document::FileAttachmentDocument;
 use crate::store::keystore::Keystore;
 use crate::store::{
@@ -9,6 +13,7 @@ use chrono::{DateTime, Utc};
 use either::Either;
 use futures::stream::{FuturesUnordered, StreamExt};
 use indexmap::IndexMap;
+use ipld_core::cid::Cid;
 use rust_ipfs::{Ipfs, Keypair};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::future::IntoFuture;
@@ -17,7 +22,10 @@ use warp::crypto::cipher::Cipher;
 use warp::crypto::hash::sha256_iter;
 use warp::crypto::{DIDKey, Ed25519KeyPair, KeyMaterial, DID};
 use warp::error::Error;
-use warp::raygun::{Message, MessageReference, MessageType};
+use warp::raygun::{
+    ContactCard, Geolocation, Mention, Message, MessageReference, MessageType, Poll,
+    FORWARDED_CONVERSATION_KEY, FORWARDED_MESSAGE_KEY, FORWARDED_SENDER_KEY,
+};
 
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -48,6 +56,110 @@ pub struct MessageDocument {
     pub message: Option<Bytes>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub signature: Option<MessageSignature>,
+    /// Previous revision of the message prior to it being edited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Cid>,
+    /// Signature of the previous revision, covered by the signature of this revision to link them together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_signature: Option<MessageSignature>,
+    /// Encrypted poll for `MessageType::Poll`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Bytes>,
+    /// Latest vote of each member on the poll
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<PollVoteDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_closed: Option<PollCloseDocument>,
+    /// Origin of the message if it was forwarded from another conversation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded: Option<ForwardedFrom>,
+    /// Previews of the links within the message, signed separately by the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_previews: Option<LinkPreviewsDocument>,
+    /// Mentions parsed from the lines by the sender
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<Mention>,
+    /// Encrypted geolocation for `MessageType::Geolocation`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geolocation: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geolocation_stopped: Option<GeolocationStopDocument>,
+    /// Encrypted identity document for `MessageType::ContactCard`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact_card: Option<Bytes>,
+}
+
+/// Origin of a forwarded message as claimed by the member forwarding it.
+/// The original signature is not carried over, so recipients outside of the original
+/// conversation are unable to verify that `sender` authored the message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ForwardedFrom {
+    pub conversation_id: Uuid,
+    pub message_id: Uuid,
+    pub sender: DID,
+}
+
+impl ForwardedFrom {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(self.conversation_id.as_bytes());
+        bytes.extend_from_slice(self.message_id.as_bytes());
+        bytes.extend(self.sender.public_key_bytes());
+        bytes
+    }
+}
+
+/// Contents of a message that is about to be forwarded into another conversation
+#[derive(Debug, Clone)]
+pub struct ForwardedMessage {
+    pub lines: Vec<String>,
+    pub attachments: Vec<FileAttachmentDocument>,
+    pub origin: ForwardedFrom,
+}
+
+impl ForwardedMessage {
+    /// Applies the same limits as sending the message directly, so the forwarded copy would not be rejected by peers.
+    /// Lines are optional if the message has attachments
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.lines.is_empty() && self.attachments.is_empty() {
+            return Err(Error::EmptyMessage);
+        }
+
+        let lines_value_length: usize = self
+            .lines
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim())
+            .map(|s| s.chars().count())
+            .sum();
+
+        if (self.attachments.is_empty() && lines_value_length < MIN_MESSAGE_SIZE)
+            || lines_value_length > MAX_MESSAGE_SIZE
+        {
+            tracing::error!(
+                current_size = lines_value_length,
+                max = MAX_MESSAGE_SIZE,
+                "length of message is invalid"
+            );
+            return Err(Error::InvalidLength {
+                context: "message".into(),
+                current: lines_value_length,
+                minimum: self.attachments.is_empty().then_some(MIN_MESSAGE_SIZE),
+                maximum: Some(MAX_MESSAGE_SIZE),
+            });
+        }
+
+        if self.attachments.len() > MAX_ATTACHMENT {
+            return Err(Error::InvalidLength {
+                context: "attachments".into(),
+                current: self.attachments.len(),
+                minimum: None,
+                maximum: Some(MAX_ATTACHMENT),
+            });
+        }
+
+        Ok(())
+    }
 }
 
 impl From<MessageDocument> for MessageReference {
@@ -91,6 +203,59 @@ impl MessageDocument {
         keypair: &Keypair,
         message: Message,
         key: Either<&DID, &Keystore>,
+    ) -> Result<Self, Error> {
+        let attachments = message.attachments();
+
+        if attachments.len() > MAX_ATTACHMENT {
+            return Err(Error::InvalidLength {
+                context: "attachments".into(),
+                current: attachments.len(),
+                minimum: None,
+                maximum: Some(MAX_ATTACHMENT),
+            });
+        }
+
+        let attachments = FuturesUnordered::from_iter(
+            attachments
+                .iter()
+                .map(|file| FileAttachmentDocument::new(ipfs, file).into_future()),
+        )
+        .filter_map(|result| async move { result.ok() })
+        .collect::<Vec<_>>()
+        .await;
+
+        Self::build(keypair, message, attachments, None, None, key)
+    }
+
+    /// Create a message sharing the signed identity document of another identity
+    pub fn contact_card(
+        keypair: &Keypair,
+        message: Message,
+        identity: &IdentityDocument,
+        key: Either<&DID, &Keystore>,
+    ) -> Result<Self, Error> {
+        Self::build(keypair, message, vec![], None, Some(identity), key)
+    }
+
+    /// Create a message referencing the attachments of the original message, so the files do not
+    /// need to be uploaded again
+    pub fn forward(
+        keypair: &Keypair,
+        message: Message,
+        attachments: Vec<FileAttachmentDocument>,
+        origin: ForwardedFrom,
+        key: Either<&DID, &Keystore>,
+    ) -> Result<Self, Error> {
+        Self::build(keypair, message, attachments, Some(origin), None, key)
+    }
+
+    fn build(
+        keypair: &Keypair,
+        message: Message,
+        attachments: Vec<FileAttachmentDocument>,
+        forwarded: Option<ForwardedFrom>,
+        identity: Option<&IdentityDocument>,
+        key: Either<&DID, &Keystore>,
     ) -> Result<Self, Error> {
         let id = message.id();
         let message_type = message.message_type();
@@ -102,7 +267,7 @@ impl MessageDocument {
         let replied = message.replied();
         let lines = message.lines();
         let reactions = message.reactions().to_owned();
-        let attachments = message.attachments();
+        let mentions = message.mentions().to_vec();
 
         if attachments.len() > MAX_ATTACHMENT {
             return Err(Error::InvalidLength {
@@ -122,15 +287,6 @@ impl MessageDocument {
             });
         }
 
-        let attachments = FuturesUnordered::from_iter(
-            attachments
-                .iter()
-                .map(|file| FileAttachmentDocument::new(ipfs, file).into_future()),
-        )
-        .filter_map(|result| async move { result.ok() })
-        .collect::<Vec<_>>()
-        .await;
-
         if !lines.is_empty() {
             let lines_value_length: usize = lines
                 .iter()
@@ -151,12 +307,51 @@ impl MessageDocument {
 
         let bytes = serde_json::to_vec(&lines)?;
 
-        let data = match key {
-            Either::Right(keystore) => {
-                let key = keystore.get_latest(keypair, sender)?;
-                Cipher::direct_encrypt(&bytes, &key)?.into()
+        let data = encrypt_field(keypair, sender, key, &bytes, None)?.into();
+
+        let poll = match message_type {
+            MessageType::Poll => {
+                let poll = message.poll().ok_or(Error::InvalidMessage)?;
+                poll::validate_poll(poll)?;
+
+                let mut poll = poll.clone();
+                poll.set_votes(Default::default());
+                poll.set_closed(None);
+
+                let bytes = serde_json::to_vec(&poll)?;
+                Some(encrypt_field(keypair, sender, key, &bytes, None)?.into())
+            }
+            _ => None,
+        };
+
+        let geolocation = match message_type {
+            MessageType::Geolocation => {
+                let geolocation = message.geolocation().ok_or(Error::InvalidMessage)?;
+                geolocation.position().validate()?;
+
+                let mut geolocation = geolocation.clone();
+                geolocation.set_stopped(None);
+
+                let bytes = serde_json::to_vec(&geolocation)?;
+                Some(encrypt_field(keypair, sender, key, &bytes, None)?.into())
+            }
+            _ => None,
+        };
+
+        let contact_card = match message_type {
+            MessageType::ContactCard => {
+                let identity = identity.ok_or(Error::InvalidMessage)?;
+                identity.verify()?;
+
+                // Note: The prekeys are signed on their own and are not needed by recipients of the card
+                let mut identity = identity.clone();
+                identity.prekey = None;
+                identity.one_time_prekeys.clear();
+
+                let bytes = serde_json::to_vec(&identity)?;
+                Some(encrypt_field(keypair, sender, key, &bytes, None)?.into())
             }
-            Either::Left(key) => ecdh_encrypt(keypair, Some(key), &bytes)?.into(),
+            _ => None,
         };
 
         let message = Some(data);
@@ -177,6 +372,17 @@ impl MessageDocument {
             modified,
             replied,
             signature: None,
+            previous: None,
+            previous_signature: None,
+            poll,
+            votes: vec![],
+            poll_closed: None,
+            forwarded,
+            link_previews: None,
+            mentions,
+            geolocation,
+            geolocation_stopped: None,
+            contact_card,
         };
 
         document.sign(keypair)
@@ -214,6 +420,13 @@ impl MessageDocument {
                     self.replied.map(|id| id.as_bytes().to_vec()),
                     attachments_hash,
                     self.message.as_ref().map(|m| m.to_vec()),
+                    self.poll.as_ref().map(|p| p.to_vec()),
+                    self.forwarded.as_ref().map(ForwardedFrom::to_bytes),
+                    self.mentions_bytes(),
+                    self.geolocation.as_ref().map(|g| g.to_vec()),
+                    self.contact_card.as_ref().map(|c| c.to_vec()),
+                    self.voice_notes_bytes(),
+                    self.previous_signature.map(|s| s.as_ref().to_vec()),
                 ]
                 .into_iter(),
                 None,
@@ -223,6 +436,29 @@ impl MessageDocument {
         sender_pk.verify(&hash, signature.as_ref())
     }
 
+    fn mentions_bytes(&self) -> Option<Vec<u8>> {
+        (!self.mentions.is_empty()).then(|| {
+            self.mentions
+                .iter()
+                .flat_map(|mention| mention.to_string().into_bytes())
+                .collect()
+        })
+    }
+
+    fn voice_notes_bytes(&self) -> Option<Vec<u8>> {
+        let bytes = self
+            .attachments
+            .iter()
+            .filter_map(|attachment| attachment.voice_note.as_ref())
+            .flat_map(|voice_note| {
+                let mut bytes = voice_note.duration().as_millis().to_be_bytes().to_vec();
+                bytes.extend_from_slice(voice_note.waveform());
+                bytes
+            })
+            .collect::<Vec<_>>();
+        (!bytes.is_empty()).then_some(bytes)
+    }
+
     pub fn raw_encrypted_message(&self) -> Result<&Bytes, Error> {
         self.message.as_ref().ok_or(Error::MessageNotFound)
     }
@@ -262,6 +498,8 @@ impl MessageDocument {
             return Err(Error::InvalidMessage);
         }
 
+        let previous = self.clone();
+
         self.pinned = message.pinned();
         self.modified = message.modified();
 
@@ -309,22 +547,11 @@ impl MessageDocument {
 
             let bytes = serde_json::to_vec(&lines)?;
 
-            let data = match (key, nonce) {
-                (Either::Right(keystore), Some(nonce)) => {
-                    let key = keystore.get_latest(keypair, &sender)?;
-                    Cipher::direct_encrypt_with_nonce(&bytes, &key, nonce)?
-                }
-                (Either::Left(key), Some(nonce)) => {
-                    ecdh_encrypt_with_nonce(keypair, Some(key), &bytes, nonce)?
-                }
-                (Either::Right(keystore), None) => {
-                    let key = keystore.get_latest(keypair, &sender)?;
-                    Cipher::direct_encrypt(&bytes, &key)?
-                }
-                (Either::Left(key), None) => ecdh_encrypt(keypair, Some(key), &bytes)?,
-            };
+            let data = encrypt_field(keypair, &sender, key, &bytes, nonce)?;
 
             self.message = (!data.is_empty()).then_some(data.into());
+            self.mentions = message.mentions().to_vec();
+            self.previous_signature = previous.signature;
 
             match (sender.eq(did), signature) {
                 (true, None) => {
@@ -340,6 +567,11 @@ impl MessageDocument {
                     }
                 }
             };
+
+            // Note: The cid of the revision may differ between peers, so the signature covers the signature
+            //       of the revision instead, which is checked when the history is resolved
+            let previous_cid = ipfs.put_dag(previous).pin(false).await?;
+            self.previous = Some(previous_cid);
         }
 
         tracing::info!(id = %self.conversation_id, message_id = %self.id, "Message is updated");
@@ -356,6 +588,18 @@ impl MessageDocument {
         if !self.verify() {
             return Err(Error::InvalidMessage);
         }
+        self.resolve_unverified(ipfs, keypair, local, key).await
+    }
+
+    /// Resolve the message without verifying its signature.
+    /// Note: This should only be used for messages that are already flagged as unverified
+    pub async fn resolve_unverified(
+        &self,
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        local: bool,
+        key: Either<&DID, &Keystore>,
+    ) -> Result<Message, Error> {
         let message_cipher = self.message.as_ref().ok_or(Error::MessageNotFound)?;
         let mut message = Message::default();
         message.set_id(self.id);
@@ -368,6 +612,20 @@ impl MessageDocument {
         }
         message.set_pinned(self.pinned);
         message.set_replied(self.replied);
+        message.set_mentions(self.mentions.clone());
+
+        if let Some(forwarded) = self.forwarded.as_ref() {
+            let metadata = message.metadata_mut();
+            metadata.insert(
+                FORWARDED_CONVERSATION_KEY.into(),
+                forwarded.conversation_id.to_string(),
+            );
+            metadata.insert(
+                FORWARDED_MESSAGE_KEY.into(),
+                forwarded.message_id.to_string(),
+            );
+            metadata.insert(FORWARDED_SENDER_KEY.into(), forwarded.sender.to_string());
+        }
 
         let attachments = self.attachments();
 
@@ -404,10 +662,7 @@ impl MessageDocument {
 
         let sender = self.sender.to_did();
 
-        let data = match key {
-            Either::Left(exchange) => ecdh_decrypt(keypair, Some(exchange), message_cipher)?,
-            Either::Right(keystore) => keystore.try_decrypt(keypair, &sender, message_cipher)?,
-        };
+        let data = decrypt_field(keypair, &sender, key, message_cipher)?;
 
         let lines: Vec<String> = serde_json::from_slice(&data)?;
 
@@ -429,9 +684,213 @@ impl MessageDocument {
 
         message.set_lines(lines);
 
+        if let Some(poll) = self.poll.as_ref() {
+            let data = decrypt_field(keypair, &sender, key, poll)?;
+
+            let mut poll: Poll = serde_json::from_slice(&data)?;
+            poll.set_votes(poll::tally(&self.votes));
+            poll.set_closed(self.poll_closed.as_ref().map(|close| close.date));
+            message.set_poll(Some(poll));
+        }
+
+        if let Some(geolocation) = self.geolocation.as_ref() {
+            let data = decrypt_field(keypair, &sender, key, geolocation)?;
+
+            let mut geolocation: Geolocation = serde_json::from_slice(&data)?;
+            geolocation.position().validate()?;
+            geolocation.set_stopped(self.geolocation_stopped.as_ref().map(|stop| stop.date));
+            message.set_geolocation(Some(geolocation));
+        }
+
+        if let Some(contact_card) = self.contact_card.as_ref() {
+            let data = decrypt_field(keypair, &sender, key, contact_card)?;
+
+            let identity: IdentityDocument = serde_json::from_slice(&data)?;
+            identity.verify()?;
+            message.set_contact_card(Some(ContactCard::from(&identity)));
+        }
+
+        if let Some(data) = self
+            .link_previews
+            .as_ref()
+            .filter(|previews| previews.verify(&sender, self.conversation_id, self.id))
+            .and_then(|previews| previews.data.as_ref())
+        {
+            let data = decrypt_field(keypair, &sender, key, data)?;
+
+            let documents: Vec<LinkPreviewDocument> = serde_json::from_slice(&data)?;
+
+            let previews = futures::future::join_all(
+                documents
+                    .iter()
+                    .map(|document| document.resolve(ipfs, local)),
+            )
+            .await;
+
+            message.set_link_previews(previews);
+        }
+
         Ok(message)
     }
 
+    /// Encrypt and sign the link previews of a message sent by the own identity.
+    /// An empty list removes any previews from the message
+    pub fn set_link_previews(
+        &mut self,
+        keypair: &Keypair,
+        previews: &[LinkPreviewDocument],
+        key: Either<&DID, &Keystore>,
+    ) -> Result<LinkPreviewsDocument, Error> {
+        let sender = self.sender.to_did();
+        if sender != keypair.to_did()? {
+            return Err(Error::Unauthorized);
+        }
+
+        let data = match previews.is_empty() {
+            true => None,
+            false => {
+                let bytes = serde_json::to_vec(previews)?;
+                Some(encrypt_field(keypair, &sender, key, &bytes, None)?.into())
+            }
+        };
+
+        let document = LinkPreviewsDocument::new(keypair, self.conversation_id, self.id, data)?;
+        self.apply_link_previews(document.clone())?;
+        Ok(document)
+    }
+
+    /// Apply link previews signed by the sender, returning true if they replaced the current previews
+    pub fn apply_link_previews(&mut self, previews: LinkPreviewsDocument) -> Result<bool, Error> {
+        if !previews.verify(&self.sender.to_did(), self.conversation_id, self.id) {
+            return Err(Error::InvalidSignature);
+        }
+
+        if matches!(self.link_previews.as_ref(), Some(current) if current.date >= previews.date) {
+            return Ok(false);
+        }
+
+        self.link_previews = Some(previews);
+        Ok(true)
+    }
+
+    /// Apply a vote on the poll of this message, returning true if the vote was applied.
+    /// `poll` is expected to be the resolved poll of this message
+    pub fn vote(&mut self, poll: &Poll, vote: PollVoteDocument) -> Result<bool, Error> {
+        if self.message_type != MessageType::Poll {
+            return Err(Error::InvalidMessage);
+        }
+
+        if !vote.verify(self.conversation_id, self.id) {
+            return Err(Error::InvalidSignature);
+        }
+
+        poll::apply_vote(poll, self.poll_closed.as_ref(), &mut self.votes, vote)
+    }
+
+    /// Close the poll of this message. Votes made after the poll was closed are discarded
+    pub fn close_poll(&mut self, close: PollCloseDocument) -> Result<bool, Error> {
+        if self.message_type != MessageType::Poll {
+            return Err(Error::InvalidMessage);
+        }
+
+        if !close.verify(&self.sender.to_did(), self.conversation_id, self.id) {
+            return Err(Error::InvalidSignature);
+        }
+
+        if matches!(self.poll_closed.as_ref(), Some(current) if current.date <= close.date) {
+            return Ok(false);
+        }
+
+        self.votes.retain(|vote| vote.date <= close.date);
+        self.poll_closed = Some(close);
+        Ok(true)
+    }
+
+    /// Stop the live geolocation of this message, returning true if it was not already stopped
+    pub fn stop_geolocation(&mut self, stop: GeolocationStopDocument) -> Result<bool, Error> {
+        if self.message_type != MessageType::Geolocation {
+            return Err(Error::InvalidMessage);
+        }
+
+        if !stop.verify(&self.sender.to_did(), self.conversation_id, self.id) {
+            return Err(Error::InvalidSignature);
+        }
+
+        if self.geolocation_stopped.is_some() {
+            return Ok(false);
+        }
+
+        self.geolocation_stopped = Some(stop);
+        Ok(true)
+    }
+
+    /// Resolve every revision of the message, starting with the original and ending with the current revision.
+    /// Each revision must be signed by the sender of the message.
+    pub async fn history(
+        &self,
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        local: bool,
+        key: Either<&DID, &Keystore>,
+    ) -> Result<Vec<Message>, Error> {
+        let mut revisions = vec![self.resolve(ipfs, keypair, local, key).await?];
+        let mut previous = self.previous;
+        let mut previous_signature = self.previous_signature;
+
+        while let Some(cid) = previous {
+            let document: MessageDocument = ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized()
+                .await
+                .map_err(anyhow::Error::from)?;
+
+            if document.id != self.id
+                || document.conversation_id != self.conversation_id
+                || document.sender != self.sender
+            {
+                tracing::error!(id = %self.conversation_id, message_id = %self.id, "Revision does not belong to message");
+                return Err(Error::InvalidMessage);
+            }
+
+            if !document.verify() {
+                tracing::error!(id = %self.conversation_id, message_id = %self.id, "Revision is not signed by sender");
+                return Err(Error::InvalidSignature);
+            }
+
+            if document.signature != previous_signature {
+                tracing::error!(id = %self.conversation_id, message_id = %self.id, "Revision does not match the signed link");
+                return Err(Error::InvalidSignature);
+            }
+
+            revisions.push(document.resolve(ipfs, keypair, local, key).await?);
+            previous = document.previous;
+            previous_signature = document.previous_signature;
+        }
+
+        revisions.reverse();
+        Ok(revisions)
+    }
+
+    /// Unpins the previous revisions of the message that are stored locally
+    pub async fn unpin_revisions(&self, ipfs: &Ipfs) {
+        let mut previous = self.previous;
+
+        while let Some(cid) = previous {
+            previous = ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized::<MessageDocument>()
+                .await
+                .ok()
+                .and_then(|document| document.previous);
+
+            if ipfs.is_pinned(cid).await.unwrap_or_default() {
+                _ = ipfs.remove_pin(cid).await;
+            }
+        }
+    }
+
     fn sign(mut self, keypair: &Keypair) -> Result<MessageDocument, Error> {
         let did = &keypair.to_did()?;
         let sender = self.sender.to_did();
@@ -458,6 +917,13 @@ impl MessageDocument {
                 self.replied.map(|id| id.as_bytes().to_vec()),
                 attachments_hash,
                 self.message.as_ref().map(|m| m.to_vec()),
+                self.poll.as_ref().map(|p| p.to_vec()),
+                self.forwarded.as_ref().map(ForwardedFrom::to_bytes),
+                self.mentions_bytes(),
+                self.geolocation.as_ref().map(|g| g.to_vec()),
+                self.contact_card.as_ref().map(|c| c.to_vec()),
+                self.voice_notes_bytes(),
+                self.previous_signature.map(|s| s.as_ref().to_vec()),
             ]
             .into_iter(),
             None,
@@ -470,6 +936,45 @@ impl MessageDocument {
     }
 }
 
+/// Encrypt a field of a message with the latest key of the sender from the keystore, or the shared key
+/// of the direct conversation
+fn encrypt_field(
+    keypair: &Keypair,
+    sender: &DID,
+    key: Either<&DID, &Keystore>,
+    bytes: &[u8],
+    nonce: Option<&[u8]>,
+) -> Result<Vec<u8>, Error> {
+    let data = match (key, nonce) {
+        (Either::Right(keystore), Some(nonce)) => {
+            let key = keystore.get_latest(keypair, sender)?;
+            Cipher::direct_encrypt_with_nonce(bytes, &key, nonce)?
+        }
+        (Either::Left(key), Some(nonce)) => {
+            ecdh_encrypt_with_nonce(keypair, Some(key), bytes, nonce)?
+        }
+        (Either::Right(keystore), None) => {
+            let key = keystore.get_latest(keypair, sender)?;
+            Cipher::direct_encrypt(bytes, &key)?
+        }
+        (Either::Left(key), None) => ecdh_encrypt(keypair, Some(key), bytes)?,
+    };
+    Ok(data)
+}
+
+/// Decrypt a field of a message that was encrypted with [`encrypt_field`]
+fn decrypt_field(
+    keypair: &Keypair,
+    sender: &DID,
+    key: Either<&DID, &Keystore>,
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    match key {
+        Either::Left(exchange) => ecdh_decrypt(keypair, Some(exchange), data),
+        Either::Right(keystore) => keystore.try_decrypt(keypair, sender, data),
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DIDEd25519Reference([u8; 32]);
 
@@ -576,3 +1081,119 @@ impl<'d> Deserialize<'d> for MessageSignature {
         Self::try_from(bytes).map_err(serde::de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use either::Either;
+    use rust_ipfs::{Keypair, UninitializedIpfsDefault};
+    use uuid::Uuid;
+    use warp::crypto::DID;
+    use warp::error::Error;
+    use warp::raygun::Message;
+
+    use super::{ForwardedFrom, ForwardedMessage, MessageDocument};
+    use crate::store::{PeerIdExt, MAX_MESSAGE_SIZE};
+
+    fn message(keypair: &Keypair, id: Uuid, conversation_id: Uuid, line: &str) -> Message {
+        let mut message = Message::default();
+        message.set_id(id);
+        message.set_conversation_id(conversation_id);
+        message.set_sender(keypair.to_did().expect("valid keypair"));
+        message.set_date(Utc::now());
+        message.set_lines(vec![line.into()]);
+        message
+    }
+
+    #[tokio::test]
+    async fn history_rejects_unlinked_revision() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let keypair = Keypair::generate_ed25519();
+        let recipient = DID::default();
+        let key = Either::Left(&recipient);
+
+        let id = Uuid::new_v4();
+        let conversation_id = Uuid::new_v4();
+
+        let mut document = MessageDocument::new(
+            &ipfs,
+            &keypair,
+            message(&keypair, id, conversation_id, "first"),
+            key,
+        )
+        .await?;
+
+        let mut edit = message(&keypair, id, conversation_id, "second");
+        edit.set_modified(Utc::now());
+        document
+            .update(&ipfs, &keypair, edit, None, key, None)
+            .await?;
+
+        let previous = document.previous.expect("previous revision");
+        assert!(ipfs.is_pinned(previous).await?);
+
+        let history = document.history(&ipfs, &keypair, true, key).await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].lines(), ["first"]);
+        assert_eq!(history[1].lines(), ["second"]);
+
+        // A revision signed by the sender that was never part of the history of the message
+        let foreign = MessageDocument::new(
+            &ipfs,
+            &keypair,
+            message(&keypair, id, conversation_id, "forged"),
+            key,
+        )
+        .await?;
+
+        let mut tampered = document.clone();
+        tampered.previous = Some(ipfs.put_dag(foreign).await?);
+        assert!(tampered.verify());
+        assert!(matches!(
+            tampered.history(&ipfs, &keypair, true, key).await,
+            Err(Error::InvalidSignature)
+        ));
+
+        // The link to the previous revision is covered by the signature
+        let mut tampered = document.clone();
+        tampered.previous_signature = None;
+        assert!(!tampered.verify());
+
+        document.unpin_revisions(&ipfs).await;
+        assert!(!ipfs.is_pinned(previous).await?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_message_length_is_validated() {
+        let origin = ForwardedFrom {
+            conversation_id: Uuid::new_v4(),
+            message_id: Uuid::new_v4(),
+            sender: DID::default(),
+        };
+        let forwarded = |lines: Vec<String>| ForwardedMessage {
+            lines,
+            attachments: vec![],
+            origin: origin.clone(),
+        };
+
+        assert!(forwarded(vec!["Hello".into()]).validate().is_ok());
+        assert!(matches!(
+            forwarded(vec![]).validate(),
+            Err(Error::EmptyMessage)
+        ));
+        assert!(matches!(
+            forwarded(vec!["  ".into()]).validate(),
+            Err(Error::InvalidLength { .. })
+        ));
+        assert!(matches!(
+            forwarded(vec!["a".repeat(MAX_MESSAGE_SIZE + 1)]).validate(),
+            Err(Error::InvalidLength { .. })
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::hash::sha256_iter;
+use warp::crypto::DID;
+use warp::error::Error;
+use warp::raygun::Poll;
+
+use super::message::MessageSignature;
+use crate::store::{
+    DidExt, PeerIdExt, MAX_MESSAGE_SIZE, MAX_POLL_OPTIONS, MAX_POLL_OPTION_LENGTH,
+    MIN_MESSAGE_SIZE, MIN_POLL_OPTIONS,
+};
+
+/// Vote on a poll signed by the voter. A vote without any options is a retracted vote
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PollVoteDocument {
+    pub voter: DID,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<usize>,
+    pub date: DateTime<Utc>,
+    pub signature: MessageSignature,
+}
+
+impl PollVoteDocument {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<Self, Error> {
+        let voter = keypair.to_did()?;
+        let date = Utc::now();
+        let hash = vote_hash(conversation_id, message_id, &voter, &options, date);
+        let signature = keypair.sign(&hash).expect("not RSA");
+
+        Ok(Self {
+            voter,
+            options,
+            date,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn is_retracted(&self) -> bool {
+        self.options.is_empty()
+    }
+
+    pub fn verify(&self, conversation_id: Uuid, message_id: Uuid) -> bool {
+        let Ok(voter_pk) = self.voter.to_public_key() else {
+            return false;
+        };
+
+        let hash = vote_hash(
+            conversation_id,
+            message_id,
+            &self.voter,
+            &self.options,
+            self.date,
+        );
+
+        voter_pk.verify(&hash, self.signature.as_ref())
+    }
+
+    /// Returns true if this vote takes precedence over `other`.
+    /// Ties are broken by the signature so every participant ends up with the same vote
+    fn supersedes(&self, other: &PollVoteDocument) -> bool {
+        (self.date, self.signature.as_ref()) > (other.date, other.signature.as_ref())
+    }
+}
+
+/// Closure of a poll signed by the creator of the poll
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PollCloseDocument {
+    pub date: DateTime<Utc>,
+    pub signature: MessageSignature,
+}
+
+impl PollCloseDocument {
+    pub fn new(keypair: &Keypair, conversation_id: Uuid, message_id: Uuid) -> Result<Self, Error> {
+        let date = Utc::now();
+        let hash = close_hash(conversation_id, message_id, date);
+        let signature = keypair.sign(&hash).expect("not RSA");
+        Ok(Self {
+            date,
+            signature: MessageSignature::try_from(signature)?,
+        })
+    }
+
+    pub fn verify(&self, creator: &DID, conversation_id: Uuid, message_id: Uuid) -> bool {
+        let Ok(creator_pk) = creator.to_public_key() else {
+            return false;
+        };
+
+        let hash = close_hash(conversation_id, message_id, self.date);
+        creator_pk.verify(&hash, self.signature.as_ref())
+    }
+}
+
+/// Check that the poll can be sent
+pub fn validate_poll(poll: &Poll) -> Result<(), Error> {
+    let question_length = poll.question().trim().chars().count();
+    if question_length < MIN_MESSAGE_SIZE || question_length > MAX_MESSAGE_SIZE {
+        return Err(Error::InvalidLength {
+            context: "question".into(),
+            current: question_length,
+            minimum: Some(MIN_MESSAGE_SIZE),
+            maximum: Some(MAX_MESSAGE_SIZE),
+        });
+    }
+
+    let options = poll.options();
+    if options.len() < MIN_POLL_OPTIONS || options.len() > MAX_POLL_OPTIONS {
+        return Err(Error::InvalidLength {
+            context: "options".into(),
+            current: options.len(),
+            minimum: Some(MIN_POLL_OPTIONS),
+            maximum: Some(MAX_POLL_OPTIONS),
+        });
+    }
+
+    for option in options {
+        let length = option.trim().chars().count();
+        if length == 0 || length > MAX_POLL_OPTION_LENGTH {
+            return Err(Error::InvalidLength {
+                context: "option".into(),
+                current: length,
+                minimum: Some(1),
+                maximum: Some(MAX_POLL_OPTION_LENGTH),
+            });
+        }
+    }
+
+    if poll.closes().is_some_and(|closes| closes <= Utc::now()) {
+        return Err(Error::OtherWithContext(
+            "poll cannot close in the past".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that the options voted on are valid for the poll
+pub fn validate_vote(poll: &Poll, options: &[usize]) -> Result<(), Error> {
+    if options.is_empty() {
+        return Err(Error::InvalidLength {
+            context: "options".into(),
+            current: 0,
+            minimum: Some(1),
+            maximum: Some(poll.options().len()),
+        });
+    }
+
+    if !poll.multiple_choice() && options.len() > 1 {
+        return Err(Error::OtherWithContext(
+            "poll only allows a single option to be voted on".into(),
+        ));
+    }
+
+    let mut seen = options.to_vec();
+    seen.sort_unstable();
+    seen.dedup();
+
+    if seen.len() != options.len() || seen.iter().any(|index| *index >= poll.options().len()) {
+        return Err(Error::OtherWithContext("invalid poll option".into()));
+    }
+
+    Ok(())
+}
+
+/// Apply the vote to the list of votes, keeping only the latest vote of each voter.
+/// Returns true if the vote was applied
+pub fn apply_vote(
+    poll: &Poll,
+    closed: Option<&PollCloseDocument>,
+    votes: &mut Vec<PollVoteDocument>,
+    vote: PollVoteDocument,
+) -> Result<bool, Error> {
+    if !vote.is_retracted() {
+        validate_vote(poll, &vote.options)?;
+    }
+
+    let closes = match (poll.closes(), closed.map(|close| close.date)) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    if closes.is_some_and(|closes| vote.date > closes) {
+        return Err(Error::OtherWithContext("poll is closed".into()));
+    }
+
+    match votes.iter_mut().find(|current| current.voter == vote.voter) {
+        Some(current) if vote.supersedes(current) => *current = vote,
+        Some(_) => return Ok(false),
+        None => votes.push(vote),
+    }
+
+    votes.sort_by(|a, b| (a.date, a.voter.to_string()).cmp(&(b.date, b.voter.to_string())));
+    Ok(true)
+}
+
+/// Options voted on by each voter, excluding retracted votes
+pub fn tally(votes: &[PollVoteDocument]) -> IndexMap<DID, Vec<usize>> {
+    votes
+        .iter()
+        .filter(|vote| !vote.is_retracted())
+        .map(|vote| (vote.voter.clone(), vote.options.clone()))
+        .collect()
+}
+
+fn vote_hash(
+    conversation_id: Uuid,
+    message_id: Uuid,
+    voter: &DID,
+    options: &[usize],
+    date: DateTime<Utc>,
+) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(message_id.as_bytes().to_vec()),
+            Some(voter.public_key_bytes()),
+            Some(
+                options
+                    .iter()
+                    .flat_map(|index| (*index as u64).to_be_bytes())
+                    .collect(),
+            ),
+            Some(date.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+fn close_hash(conversation_id: Uuid, message_id: Uuid, date: DateTime<Utc>) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(conversation_id.as_bytes().to_vec()),
+            Some(message_id.as_bytes().to_vec()),
+            Some(b"close".to_vec()),
+            Some(date.to_string().into_bytes()),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_ipfs::Keypair;
+
+    fn poll() -> Poll {
+        Poll::new(
+            "lunch?",
+            vec!["pizza".into(), "tacos".into(), "salad".into()],
+        )
+    }
+
+    #[test]
+    fn votes_are_tallied_deterministically() -> anyhow::Result<()> {
+        let conversation_id = Uuid::new_v4();
+        let message_id = Uuid::new_v4();
+        let poll = poll();
+
+        let alice = Keypair::generate_ed25519();
+        let bob = Keypair::generate_ed25519();
+
+        let first = PollVoteDocument::new(&alice, conversation_id, message_id, vec![0])?;
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let second = PollVoteDocument::new(&alice, conversation_id, message_id, vec![1])?;
+        let other = PollVoteDocument::new(&bob, conversation_id, message_id, vec![1])?;
+
+        assert!(first.verify(conversation_id, message_id));
+        assert!(!first.verify(conversation_id, Uuid::new_v4()));
+
+        let mut in_order = vec![];
+        for vote in [first.clone(), second.clone(), other.clone()] {
+            apply_vote(&poll, None, &mut in_order, vote)?;
+        }
+
+        let mut out_of_order = vec![];
+        for vote in [other, second, first] {
+            apply_vote(&poll, None, &mut out_of_order, vote)?;
+        }
+
+        assert_eq!(in_order, out_of_order);
+
+        let mut resolved = poll.clone();
+        resolved.set_votes(tally(&in_order));
+        assert_eq!(resolved.tally(), vec![0, 2, 0]);
+
+        let retract = PollVoteDocument::new(&bob, conversation_id, message_id, vec![])?;
+        apply_vote(&poll, None, &mut in_order, retract)?;
+        resolved.set_votes(tally(&in_order));
+        assert_eq!(resolved.tally(), vec![0, 1, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_votes_are_rejected() -> anyhow::Result<()> {
+        let conversation_id = Uuid::new_v4();
+        let message_id = Uuid::new_v4();
+        let mut poll = poll();
+        let keypair = Keypair::generate_ed25519();
+
+        assert!(validate_vote(&poll, &[3]).is_err());
+        assert!(validate_vote(&poll, &[0, 1]).is_err());
+        poll.set_multiple_choice(true);
+        assert!(validate_vote(&poll, &[0, 1]).is_ok());
+        assert!(validate_vote(&poll, &[1, 1]).is_err());
+
+        let creator = Keypair::generate_ed25519();
+        let close = PollCloseDocument::new(&creator, conversation_id, message_id)?;
+        assert!(close.verify(&creator.to_did()?, conversation_id, message_id));
+        assert!(!close.verify(&keypair.to_did()?, conversation_id, message_id));
+
+        let mut votes = vec![];
+        let vote = PollVoteDocument::new(&keypair, conversation_id, message_id, vec![0])?;
+        assert!(apply_vote(&poll, Some(&close), &mut votes, vote).is_err());
+        assert!(votes.is_empty());
+        Ok(())
+    }
+}
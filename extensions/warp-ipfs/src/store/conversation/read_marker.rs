@@ -0,0 +1,91 @@
+use chrono::{DateTime, Utc};
+use either::Either;
+use futures::StreamExt;
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::DID;
+use warp::raygun::community::RoleId;
+
+use super::mention;
+use super::message::MessageDocument;
+use super::reference::MessageReferenceList;
+use crate::store::keystore::Keystore;
+
+/// Last message read in a conversation or community channel
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReadMarker {
+    pub message_id: Uuid,
+    pub date: DateTime<Utc>,
+}
+
+impl ReadMarker {
+    pub fn new(message: &MessageDocument) -> Self {
+        Self {
+            message_id: message.id,
+            date: message.date,
+        }
+    }
+
+    /// Returns true if the marker is ahead of `other`, in which case `other` should not replace it
+    pub fn is_ahead_of(&self, other: &ReadMarker) -> bool {
+        self.date > other.date
+    }
+
+    /// Returns true if the message was sent after the marker
+    pub fn is_unread(marker: Option<&ReadMarker>, message: &MessageDocument) -> bool {
+        marker.map_or(true, |marker| {
+            message.date > marker.date && message.id != marker.message_id
+        })
+    }
+}
+
+/// Messages sent by others after the marker
+pub fn unread_messages<'a>(
+    ipfs: &'a Ipfs,
+    list: &'a MessageReferenceList,
+    marker: Option<ReadMarker>,
+    own_did: &'a DID,
+) -> impl futures::Stream<Item = MessageDocument> + 'a {
+    list.list(ipfs).filter(move |message| {
+        let unread =
+            message.sender.to_did().ne(own_did) && ReadMarker::is_unread(marker.as_ref(), message);
+        futures::future::ready(unread)
+    })
+}
+
+/// Number of messages sent by others after the marker
+pub async fn unread_count(
+    ipfs: &Ipfs,
+    list: &MessageReferenceList,
+    marker: Option<ReadMarker>,
+    own_did: &DID,
+) -> usize {
+    unread_messages(ipfs, list, marker, own_did).count().await
+}
+
+/// Number of messages sent by others after the marker that mention `own_did` or one of its `roles`
+pub async fn unread_mention_count(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    list: &MessageReferenceList,
+    marker: Option<ReadMarker>,
+    own_did: &DID,
+    roles: &[RoleId],
+    keystore: Either<&DID, &Keystore>,
+) -> usize {
+    let mut stream = unread_messages(ipfs, list, marker, own_did).boxed();
+    let mut count = 0;
+
+    while let Some(document) = stream.next().await {
+        let Ok(message) = document.resolve(ipfs, keypair, true, keystore).await else {
+            continue;
+        };
+
+        if mention::is_mentioned(message.mentions(), own_did, roles) {
+            count += 1;
+        }
+    }
+
+    count
+}
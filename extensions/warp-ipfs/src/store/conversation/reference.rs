@@ -5,13 +5,38 @@ use indexmap::IndexMap;
 use ipld_core::cid::Cid;
 use rust_ipfs::{Ipfs, IpfsPath};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 use std::time::Duration;
 use uuid::Uuid;
 use warp::error::Error;
+use warp::raygun::MessageCursor;
 
-//TODO: Implement a defragmentation for the references
 const REFERENCE_LENGTH: usize = 500;
 
+/// Layout of a [`MessageReferenceList`] chain
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferenceStats {
+    /// Number of pages within the chain
+    pub pages: usize,
+    /// Number of messages referenced
+    pub messages: usize,
+    /// Number of entries left behind by removed messages
+    pub holes: usize,
+}
+
+impl ReferenceStats {
+    /// Number of pages needed to reference every message without any holes
+    pub fn optimal_pages(&self) -> usize {
+        self.messages.div_ceil(REFERENCE_LENGTH)
+    }
+
+    /// Returns true if compacting the chain would free a page or a significant amount of holes
+    pub fn is_fragmented(&self) -> bool {
+        self.holes > 0
+            && (self.pages > self.optimal_pages().max(1) || self.holes >= REFERENCE_LENGTH / 4)
+    }
+}
+
 #[derive(Default, Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct MessageReferenceList {
     pub messages: Option<Cid>, // resolves to IndexMap<String, Option<Cid>>
@@ -160,6 +185,103 @@ impl MessageReferenceList {
         stream.boxed()
     }
 
+    /// Walks the references to find the message the cursor is anchored on, only resolving the
+    /// messages within the cursor, up to `limit`. Messages are returned in order of their date.
+    pub async fn list_from_cursor(
+        &self,
+        ipfs: &Ipfs,
+        cursor: MessageCursor,
+        limit: usize,
+    ) -> Result<Vec<MessageDocument>, Error> {
+        let anchor_id = cursor.message_id().to_string();
+
+        let (before_limit, after_limit) = match cursor {
+            MessageCursor::Before(_) => (limit, 0),
+            MessageCursor::After(_) => (0, limit),
+            MessageCursor::Around(_) => {
+                let remaining = limit.saturating_sub(1);
+                (remaining / 2, remaining - remaining / 2)
+            }
+        };
+
+        let mut before: VecDeque<Cid> = VecDeque::with_capacity(before_limit);
+        let mut after: Vec<Cid> = Vec::with_capacity(after_limit);
+        let mut anchor = None;
+
+        let mut current = Some(*self);
+
+        'walk: while let Some(refs) = current.take() {
+            if let Some(cid) = refs.messages {
+                let list = ipfs
+                    .get_dag(cid)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<IndexMap<String, Option<Cid>>>()
+                    .await?;
+
+                for (id, message_cid) in list {
+                    let Some(message_cid) = message_cid else {
+                        continue;
+                    };
+
+                    if anchor.is_some() {
+                        if after.len() >= after_limit {
+                            break 'walk;
+                        }
+                        after.push(message_cid);
+                        continue;
+                    }
+
+                    if id == anchor_id {
+                        anchor = Some(message_cid);
+                        continue;
+                    }
+
+                    if before_limit == 0 {
+                        continue;
+                    }
+
+                    if before.len() == before_limit {
+                        before.pop_front();
+                    }
+
+                    before.push_back(message_cid);
+                }
+            }
+
+            if anchor.is_some() && after.len() >= after_limit {
+                break;
+            }
+
+            let Some(next) = refs.next else {
+                break;
+            };
+
+            current = Some(
+                ipfs.get_dag(next)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<MessageReferenceList>()
+                    .await?,
+            );
+        }
+
+        let anchor = anchor.ok_or(Error::MessageNotFound)?;
+
+        let anchor = matches!(cursor, MessageCursor::Around(_)).then_some(anchor);
+
+        let mut messages = Vec::with_capacity(before.len() + after.len() + 1);
+
+        for cid in before.into_iter().chain(anchor).chain(after) {
+            if let Ok(message_document) = ipfs.get_dag(cid).deserialized::<MessageDocument>().await
+            {
+                messages.push(message_document);
+            }
+        }
+
+        messages.sort();
+
+        Ok(messages)
+    }
+
     #[async_recursion::async_recursion]
     pub async fn get(&self, ipfs: &Ipfs, message_id: Uuid) -> Result<MessageDocument, Error> {
         let cid = self.messages.ok_or(Error::MessageNotFound)?;
@@ -267,15 +389,22 @@ impl MessageReferenceList {
             .await?;
 
         if let Some(item) = list.get_mut(id) {
-            if item.is_none() {
+            let Some(document) = item.take() else {
                 return Err(Error::MessageNotFound);
-            }
-
-            item.take();
+            };
 
             let cid = ipfs.put_dag(list).await?;
             self.messages.replace(cid);
 
+            if let Ok(document) = ipfs
+                .get_dag(document)
+                .local()
+                .deserialized::<MessageDocument>()
+                .await
+            {
+                document.unpin_revisions(ipfs).await;
+            }
+
             return Ok(());
         }
 
@@ -296,6 +425,109 @@ impl MessageReferenceList {
         Ok(())
     }
 
+    pub async fn stats(&self, ipfs: &Ipfs) -> Result<ReferenceStats, Error> {
+        let mut stats = ReferenceStats::default();
+        let mut current = Some(*self);
+
+        while let Some(refs) = current.take() {
+            if let Some(cid) = refs.messages {
+                let list = ipfs
+                    .get_dag(cid)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<IndexMap<String, Option<Cid>>>()
+                    .await?;
+
+                let messages = list.values().filter(|item| item.is_some()).count();
+                stats.pages += 1;
+                stats.messages += messages;
+                stats.holes += list.len() - messages;
+            }
+
+            let Some(next) = refs.next else {
+                break;
+            };
+
+            current = Some(
+                ipfs.get_dag(next)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<MessageReferenceList>()
+                    .await?,
+            );
+        }
+
+        Ok(stats)
+    }
+
+    /// Rewrites the chain into dense pages, dropping entries of removed messages while reusing the existing
+    /// message documents. Returns the new list along with the blocks that are no longer referenced by it.
+    /// Note: Like [`MessageReferenceList::shrink`], this should only be used at the root of the list
+    pub async fn compact(&self, ipfs: &Ipfs) -> Result<(MessageReferenceList, Vec<Cid>), Error> {
+        let mut superseded = vec![];
+        let mut entries: Vec<(String, Cid)> = vec![];
+        let mut current = Some(*self);
+
+        while let Some(refs) = current.take() {
+            if let Some(cid) = refs.messages {
+                let list = ipfs
+                    .get_dag(cid)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<IndexMap<String, Option<Cid>>>()
+                    .await?;
+
+                entries.extend(
+                    list.into_iter()
+                        .filter_map(|(id, cid)| cid.map(|cid| (id, cid))),
+                );
+                superseded.push(cid);
+            }
+
+            let Some(next) = refs.next else {
+                break;
+            };
+
+            current = Some(
+                ipfs.get_dag(next)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<MessageReferenceList>()
+                    .await?,
+            );
+
+            superseded.push(next);
+        }
+
+        let mut retained = HashSet::new();
+        let mut list = MessageReferenceList::default();
+
+        // Pages are built from the tail of the chain since each page links to the one after it
+        for chunk in entries.chunks(REFERENCE_LENGTH).rev() {
+            let next = match list.messages.is_some() {
+                true => {
+                    let cid = ipfs.put_dag(list).await?;
+                    retained.insert(cid);
+                    Some(cid)
+                }
+                false => None,
+            };
+
+            let page = chunk
+                .iter()
+                .map(|(id, cid)| (id.clone(), Some(*cid)))
+                .collect::<IndexMap<_, _>>();
+
+            let messages = ipfs.put_dag(page).await?;
+            retained.insert(messages);
+
+            list = MessageReferenceList {
+                messages: Some(messages),
+                next,
+            };
+        }
+
+        superseded.retain(|cid| !retained.contains(cid));
+
+        Ok((list, superseded))
+    }
+
     // Since we have `IndexMap<String, Option<Cid>>` where the value is an `Option`, it is possible that
     // that there could be some fragmentation when it comes to removing messages. This function would consume
     // the current `MessageReferenceList` and walk down the reference list via `MessageReferenceList::list`
@@ -303,7 +535,6 @@ impl MessageReferenceList {
     // the new list
     // Note: This should be used at the root of the `MessageReferenceList` and not any nested reference
     //       to prevent possible fragmentation.
-    // TODO: Use in the near future under a schedule to shrink reference list
     pub async fn shrink(self, ipfs: &Ipfs) -> Result<MessageReferenceList, Error> {
         let mut new_list = MessageReferenceList::default();
         let mut list = self.list(ipfs);
@@ -313,3 +544,106 @@ impl MessageReferenceList {
         Ok(new_list)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use futures::StreamExt;
+    use rust_ipfs::{Ipfs, Keypair, UninitializedIpfsDefault};
+    use uuid::Uuid;
+    use warp::raygun::{MessageCursor, MessageType};
+
+    use super::{MessageReferenceList, REFERENCE_LENGTH};
+    use crate::store::conversation::message::{DIDEd25519Reference, MessageVersion};
+    use crate::store::conversation::MessageDocument;
+    use crate::store::PeerIdExt;
+
+    async fn populated_list(ipfs: &Ipfs, amount: usize) -> (MessageReferenceList, Vec<Uuid>) {
+        let keypair = Keypair::generate_ed25519();
+        let sender = DIDEd25519Reference::from_did(&keypair.to_did().expect("valid keypair"));
+        let conversation_id = Uuid::new_v4();
+
+        let mut list = MessageReferenceList::default();
+        let mut ids = Vec::with_capacity(amount);
+
+        for _ in 0..amount {
+            let document = MessageDocument {
+                id: Uuid::new_v4(),
+                message_type: MessageType::Message,
+                conversation_id,
+                version: MessageVersion::V0,
+                sender,
+                date: Utc::now(),
+                reactions: Default::default(),
+                attachments: vec![],
+                modified: None,
+                pinned: false,
+                replied: None,
+                message: None,
+                signature: None,
+                previous: None,
+                previous_signature: None,
+                poll: None,
+                votes: vec![],
+                poll_closed: None,
+                forwarded: None,
+                link_previews: None,
+                geolocation: None,
+                geolocation_stopped: None,
+                contact_card: None,
+                mentions: vec![],
+            };
+            list.insert(ipfs, &document).await.expect("inserted");
+            ids.push(document.id);
+        }
+
+        (list, ids)
+    }
+
+    #[tokio::test]
+    async fn compact_reference_list() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let (mut list, mut ids) = populated_list(&ipfs, REFERENCE_LENGTH * 2 + 100).await;
+
+        let removed = ids.drain(..REFERENCE_LENGTH + 100).collect::<Vec<_>>();
+        for id in removed {
+            list.remove(&ipfs, id).await?;
+        }
+
+        let stats = list.stats(&ipfs).await?;
+        assert_eq!(stats.messages, ids.len());
+        assert_eq!(stats.holes, REFERENCE_LENGTH + 100);
+        assert!(stats.pages > stats.optimal_pages());
+        assert!(stats.is_fragmented());
+
+        let (compacted, superseded) = list.compact(&ipfs).await?;
+        assert!(!superseded.is_empty());
+
+        let stats = compacted.stats(&ipfs).await?;
+        assert_eq!(stats.messages, ids.len());
+        assert_eq!(stats.holes, 0);
+        assert_eq!(stats.pages, stats.optimal_pages());
+        assert!(!stats.is_fragmented());
+
+        let list_ids = compacted
+            .list(&ipfs)
+            .map(|document| document.id)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(list_ids, ids);
+
+        let after = compacted
+            .list_from_cursor(&ipfs, MessageCursor::After(ids[0]), 2)
+            .await?
+            .iter()
+            .map(|document| document.id)
+            .collect::<Vec<_>>();
+        assert_eq!(after, ids[1..3]);
+
+        Ok(())
+    }
+}
@@ -8,20 +8,24 @@ use ipfs::{Ipfs, Keypair, PeerId};
 use ipld_core::cid::Cid;
 use pollable_map::futures::FutureMap;
 use rust_ipfs as ipfs;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::future::IntoFuture;
 use std::{collections::BTreeMap, path::Path, str::FromStr, time::Duration};
 use uuid::Uuid;
 
-use super::{keystore::Keystore, DidExt, MAX_IMAGE_SIZE};
+use super::{
+    conversation::read_marker::ReadMarker, ecdh_decrypt, ecdh_encrypt, keystore::Keystore, DidExt,
+    MAX_IMAGE_SIZE,
+};
 use warp::{
     constellation::{
         directory::Directory,
-        file::{File, FileType},
+        file::{File, FileType, VoiceNote},
         Progression,
     },
     error::Error,
     multipass::identity::{Identity, IdentityStatus},
+    raygun::{Draft, NotificationSettings, ScheduledMessage},
 };
 
 use self::{
@@ -48,6 +52,14 @@ pub struct ResolvedRootDocument {
     pub file_index: Option<Directory>,
     pub request: Vec<u8>,
     pub conversation_keystore: BTreeMap<Uuid, Keystore>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub read_markers: BTreeMap<Uuid, ReadMarker>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub drafts: BTreeMap<Uuid, Draft>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scheduled_messages: BTreeMap<Uuid, Vec<ScheduledMessage>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub notification_settings: BTreeMap<Uuid, NotificationSettings>,
     pub signature: Option<Vec<u8>>,
 }
 
@@ -100,6 +112,24 @@ pub struct RootDocument {
     /// index to constellation
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file_index: Option<Cid>,
+    /// map of last read message of conversations and community channels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_markers: Option<Cid>,
+    /// map of encrypted messages scheduled to be sent to conversations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduled_messages: Option<Cid>,
+    /// map of encrypted drafts of conversations and community channels, next to the keystore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drafts: Option<Cid>,
+    /// map of notification settings of conversations and community channels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification_settings: Option<Cid>,
+    /// map of conversations imported from an archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imported_conversations: Option<Cid>,
+    /// map of encrypted invites created for group conversations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation_invites: Option<Cid>,
     /// Online/Away/Busy/Offline status
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<IdentityStatus>,
@@ -161,49 +191,10 @@ impl RootDocument {
 
         let identity = document.resolve()?;
 
-        let friends = futures::future::ready(self.friends.ok_or(Error::Other))
-            .and_then(|document| async move {
-                ipfs.get_dag(document)
-                    .local()
-                    .deserialized()
-                    .await
-                    .map_err(Error::from)
-            })
-            .await
-            .unwrap_or_default();
-
-        let block_list = futures::future::ready(self.blocks.ok_or(Error::Other))
-            .and_then(|document| async move {
-                ipfs.get_dag(document)
-                    .local()
-                    .deserialized()
-                    .await
-                    .map_err(Error::from)
-            })
-            .await
-            .unwrap_or_default();
-
-        let block_by_list = futures::future::ready(self.block_by.ok_or(Error::Other))
-            .and_then(|document| async move {
-                ipfs.get_dag(document)
-                    .local()
-                    .deserialized()
-                    .await
-                    .map_err(Error::from)
-            })
-            .await
-            .unwrap_or_default();
-
-        let request = futures::future::ready(self.request.ok_or(Error::Other))
-            .and_then(|document| async move {
-                ipfs.get_dag(document)
-                    .local()
-                    .deserialized()
-                    .await
-                    .map_err(Error::from)
-            })
-            .await
-            .unwrap_or_default();
+        let friends = get_local_dag(ipfs, self.friends).await.unwrap_or_default();
+        let block_list = get_local_dag(ipfs, self.blocks).await.unwrap_or_default();
+        let block_by_list = get_local_dag(ipfs, self.block_by).await.unwrap_or_default();
+        let request = get_local_dag(ipfs, self.request).await.unwrap_or_default();
 
         let conversation_keystore = futures::future::ready(self.keystore.ok_or(Error::Other))
             .and_then(|document| async move {
@@ -223,6 +214,13 @@ impl RootDocument {
             .await
             .unwrap_or_default();
 
+        let kp = keypair.unwrap_or_else(|| ipfs.keypair());
+
+        let read_markers = get_local_map(ipfs, self.read_markers).await;
+        let notification_settings = get_local_map(ipfs, self.notification_settings).await;
+        let drafts = get_local_encrypted_map(ipfs, kp, self.drafts).await;
+        let scheduled_messages = get_local_encrypted_map(ipfs, kp, self.scheduled_messages).await;
+
         // TODO: Uncomment when tying the files portion to shuttle
         // let file_index = futures::future::ready(self.file_index.ok_or(Error::Other))
         //     .and_then(|document| async move {
@@ -248,11 +246,14 @@ impl RootDocument {
             request,
             file_index,
             conversation_keystore,
+            read_markers,
+            drafts,
+            scheduled_messages,
+            notification_settings,
             signature: None,
         };
 
         let bytes = serde_json::to_vec(&exported)?;
-        let kp = keypair.unwrap_or_else(|| ipfs.keypair());
         let signature = kp.sign(&bytes).expect("not RSA key");
 
         exported.signature = Some(signature);
@@ -272,49 +273,16 @@ impl RootDocument {
 
         resolve_verify_image(&ipfs, &document).await;
 
-        let fut_friends =
-            futures::future::ready(self.friends.ok_or(Error::Other)).and_then(|document| {
-                let ipfs = ipfs.clone();
-                async move {
-                    ipfs.get_dag(document)
-                        .await
-                        .map_err(anyhow::Error::from)
-                        .map_err(Error::from)
-                }
-            });
-
-        let fut_block_list =
-            futures::future::ready(self.blocks.ok_or(Error::Other)).and_then(|document| {
-                let ipfs = ipfs.clone();
-                async move {
-                    ipfs.get_dag(document)
-                        .await
-                        .map_err(anyhow::Error::from)
-                        .map_err(Error::from)
-                }
-            });
-
-        let fut_blocked_by_list = futures::future::ready(self.block_by.ok_or(Error::Other))
-            .and_then(|document| {
-                let ipfs = ipfs.clone();
-                async move {
-                    ipfs.get_dag(document)
-                        .await
-                        .map_err(anyhow::Error::from)
-                        .map_err(Error::from)
-                }
-            });
-
-        let fut_requests_list =
-            futures::future::ready(self.request.ok_or(Error::Other)).and_then(|document| {
-                let ipfs = ipfs.clone();
-                async move {
-                    ipfs.get_dag(document)
-                        .await
-                        .map_err(anyhow::Error::from)
-                        .map_err(Error::from)
-                }
-            });
+        let fetch = |cid: Option<Cid>| {
+            let ipfs = ipfs.clone();
+            async move {
+                let cid = cid.ok_or(Error::Other)?;
+                ipfs.get_dag(cid)
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .map_err(Error::from)
+            }
+        };
 
         let fut_keystore = futures::future::ready(self.keystore.ok_or(Error::Other)).and_then(
             |document| {
@@ -347,11 +315,17 @@ impl RootDocument {
         );
 
         let _ = tokio::join!(
-            fut_friends,
-            fut_block_list,
-            fut_blocked_by_list,
-            fut_requests_list,
-            fut_keystore
+            fetch(self.friends),
+            fetch(self.blocks),
+            fetch(self.block_by),
+            fetch(self.request),
+            fut_keystore,
+            fetch(self.read_markers),
+            fetch(self.scheduled_messages),
+            fetch(self.drafts),
+            fetch(self.notification_settings),
+            fetch(self.imported_conversations),
+            fetch(self.conversation_invites)
         );
 
         self.verify(&ipfs).await
@@ -388,6 +362,12 @@ impl RootDocument {
             keystore: None,
             communities: None,
             file_index: None,
+            read_markers: None,
+            scheduled_messages: None,
+            drafts: None,
+            notification_settings: None,
+            imported_conversations: None,
+            conversation_invites: None,
             status: None,
             signature: None,
         };
@@ -425,6 +405,12 @@ impl RootDocument {
             root_document.keystore = ipfs.put_dag(pointer_map).await.ok();
         }
 
+        root_document.read_markers = put_map(ipfs, data.read_markers).await;
+        root_document.notification_settings = put_map(ipfs, data.notification_settings).await;
+        root_document.drafts = put_encrypted_map(ipfs, keypair, data.drafts).await;
+        root_document.scheduled_messages =
+            put_encrypted_map(ipfs, keypair, data.scheduled_messages).await;
+
         if let Some(root) = data.file_index {
             let cid = DirectoryDocument::new(ipfs, &root)
                 .and_then(|document| async move {
@@ -442,6 +428,71 @@ impl RootDocument {
     }
 }
 
+async fn get_local_dag<T: DeserializeOwned + Send>(
+    ipfs: &Ipfs,
+    cid: Option<Cid>,
+) -> Result<T, Error> {
+    let cid = cid.ok_or(Error::Other)?;
+    ipfs.get_dag(cid)
+        .local()
+        .deserialized()
+        .await
+        .map_err(Error::from)
+}
+
+/// Resolve a map of the root document keyed by conversation id, skipping entries with an invalid key
+async fn get_local_map<T: DeserializeOwned + Send>(
+    ipfs: &Ipfs,
+    cid: Option<Cid>,
+) -> BTreeMap<Uuid, T> {
+    let map: BTreeMap<String, T> = get_local_dag(ipfs, cid).await.unwrap_or_default();
+    map.into_iter()
+        .filter_map(|(k, v)| Uuid::from_str(&k).map(|k| (k, v)).ok())
+        .collect()
+}
+
+/// Resolve a map of the root document with entries encrypted to ourselves, skipping entries that cannot be decrypted
+async fn get_local_encrypted_map<T: DeserializeOwned + Send>(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    cid: Option<Cid>,
+) -> BTreeMap<Uuid, T> {
+    get_local_map::<Bytes>(ipfs, cid)
+        .await
+        .into_iter()
+        .filter_map(|(k, bytes)| {
+            let bytes = ecdh_decrypt(keypair, None, bytes).ok()?;
+            let value = serde_json::from_slice(&bytes).ok()?;
+            Some((k, value))
+        })
+        .collect()
+}
+
+async fn put_map<T: Serialize + Send>(ipfs: &Ipfs, map: BTreeMap<Uuid, T>) -> Option<Cid> {
+    if map.is_empty() {
+        return None;
+    }
+
+    let map: BTreeMap<String, T> = map.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+    ipfs.put_dag(map).await.ok()
+}
+
+async fn put_encrypted_map<T: Serialize + Send>(
+    ipfs: &Ipfs,
+    keypair: &Keypair,
+    map: BTreeMap<Uuid, T>,
+) -> Option<Cid> {
+    let map: BTreeMap<Uuid, Bytes> = map
+        .into_iter()
+        .filter_map(|(k, v)| {
+            let bytes = serde_json::to_vec(&v).ok()?;
+            let bytes = ecdh_encrypt(keypair, None, bytes).ok()?;
+            Some((k, Bytes::from(bytes)))
+        })
+        .collect();
+    put_map(ipfs, map).await
+}
+
 async fn resolve_to_img_doc(ipfs: &Ipfs, cid: Cid) -> Result<(), Error> {
     let dag: ImageDag = ipfs.get_dag(cid).deserialized().await?;
     if dag.size > MAX_IMAGE_SIZE as _ {
@@ -496,12 +547,17 @@ pub struct FileAttachmentDocument {
     //       since we dont want to calculate the depth of the message to prevent the fetching before
     //       it is requested
     pub data: String,
+    /// Duration and waveform if the file was attached as a voice note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice_note: Option<VoiceNote>,
 }
 
 impl FileAttachmentDocument {
     pub async fn new(ipfs: &Ipfs, file: &File) -> Result<Self, Error> {
         let file_document = FileDocument::new(ipfs, file).await?;
-        file_document.to_attachment()
+        let mut attachment = file_document.to_attachment()?;
+        attachment.voice_note = file.voice_note();
+        Ok(attachment)
     }
 
     pub async fn resolve_to_file(&self, ipfs: &Ipfs, local: bool) -> Result<File, Error> {
@@ -509,6 +565,7 @@ impl FileAttachmentDocument {
         file.set_id(self.id);
         file.set_size(self.size);
         file.set_file_type(self.file_type.clone());
+        file.set_voice_note(self.voice_note.clone());
 
         if let Some(cid) = self.thumbnail {
             let image: ImageDag = ipfs
@@ -281,6 +281,8 @@ mod test {
             modified: time,
             status_message: None,
             metadata: Default::default(),
+            prekey: None,
+            one_time_prekeys: vec![],
             version: Default::default(),
             signature: None,
         };
@@ -251,6 +251,7 @@ impl FileDocument {
             thumbnail: self.thumbnail,
             file_type: self.file_type.clone(),
             data,
+            voice_note: None,
         })
     }
 
@@ -7,9 +7,13 @@ use warp::{
     crypto::{Fingerprint, DID},
     error::Error,
     multipass::identity::{Identity, IdentityStatus, Platform, SHORT_ID_SIZE},
+    raygun::ContactCard,
 };
 
-use crate::store::{DidExt, MAX_STATUS_LENGTH, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH};
+use crate::store::{
+    ratchet::{SignedPreKey, ONE_TIME_PREKEYS},
+    DidExt, MAX_STATUS_LENGTH, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH,
+};
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -35,6 +39,16 @@ pub struct IdentityDocument {
 
     pub metadata: IdentityMetadata,
 
+    /// Prekey used by peers to establish a ratchet session.
+    /// Note: This is signed on its own and is not apart of the document signature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prekey: Option<SignedPreKey>,
+
+    /// One-time prekeys, one of which is used by a peer establishing a ratchet session alongside the prekey.
+    /// Note: These are signed on their own and are not apart of the document signature
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub one_time_prekeys: Vec<SignedPreKey>,
+
     #[serde(default)]
     pub version: IdentityDocumentVersion,
 
@@ -77,6 +91,8 @@ impl From<Identity> for IdentityDocument {
             created,
             modified,
             metadata: Default::default(),
+            prekey: None,
+            one_time_prekeys: vec![],
             version: IdentityDocumentVersion::V0,
             signature: None,
         }
@@ -102,6 +118,19 @@ impl From<&IdentityDocument> for Identity {
     }
 }
 
+impl From<&IdentityDocument> for ContactCard {
+    fn from(document: &IdentityDocument) -> Self {
+        let mut card = ContactCard::new(
+            document.did.clone(),
+            &document.username,
+            document.short_id.into(),
+        );
+        card.set_picture(document.metadata.profile_picture.map(|cid| cid.to_string()));
+        card.set_modified(document.modified);
+        card
+    }
+}
+
 impl PartialEq for IdentityDocument {
     fn eq(&self, other: &Self) -> bool {
         self.did.eq(&other.did) && self.short_id.eq(&other.short_id)
@@ -133,6 +162,8 @@ impl IdentityDocument {
         self.username != other.username
             || self.status_message != other.status_message
             || self.metadata != other.metadata
+            || self.prekey != other.prekey
+            || self.one_time_prekeys != other.one_time_prekeys
     }
 }
 
@@ -144,6 +175,8 @@ impl IdentityDocument {
 
     pub fn sign(mut self, keypair: &Keypair) -> Result<Self, Error> {
         let metadata = self.metadata;
+        let prekey = self.prekey.take();
+        let one_time_prekeys = std::mem::take(&mut self.one_time_prekeys);
 
         //We blank out the metadata since it will not be used as apart of the
         //identification process, but will include it after it is signed
@@ -155,6 +188,8 @@ impl IdentityDocument {
         let bytes = serde_json::to_vec(&self)?;
         let signature = bs58::encode(keypair.sign(&bytes).expect("not RSA")).into_string();
         self.metadata = metadata;
+        self.prekey = prekey;
+        self.one_time_prekeys = one_time_prekeys;
         self.signature = Some(signature);
         Ok(self)
     }
@@ -202,7 +237,18 @@ impl IdentityDocument {
             }
         }
 
+        if payload.one_time_prekeys.len() > ONE_TIME_PREKEYS {
+            return Err(Error::InvalidLength {
+                context: "one-time prekeys".into(),
+                current: payload.one_time_prekeys.len(),
+                minimum: None,
+                maximum: Some(ONE_TIME_PREKEYS),
+            });
+        }
+
         let _ = std::mem::take(&mut payload.metadata);
+        let _ = payload.prekey.take();
+        let _ = std::mem::take(&mut payload.one_time_prekeys);
 
         let signature = std::mem::take(&mut payload.signature).ok_or(Error::InvalidSignature)?;
         let signature_bytes = bs58::decode(signature).into_vec()?;
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use chrono::Utc;
 use futures::{
     stream::{BoxStream, FuturesUnordered},
@@ -6,20 +7,31 @@ use futures::{
 use indexmap::IndexMap;
 use ipld_core::cid::Cid;
 use rust_ipfs::{Ipfs, IpfsPath, Keypair};
+use serde::{de::DeserializeOwned, Serialize};
 use std::borrow::Borrow;
 use std::{collections::BTreeMap, future::IntoFuture, sync::Arc};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use warp::{
-    constellation::directory::Directory, crypto::DID, error::Error,
+    constellation::directory::Directory,
+    crypto::DID,
+    error::Error,
     multipass::identity::IdentityStatus,
+    raygun::{Draft, NotificationSettings, ScheduledMessage},
 };
 
 use crate::store::{
-    community::CommunityDocument, conversation::ConversationDocument, ds_key::DataStoreKey,
-    ecdh_decrypt, ecdh_encrypt, identity::Request, keystore::Keystore, VecExt,
-    MAX_METADATA_ENTRIES, MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
+    community::CommunityDocument,
+    conversation::{
+        archive::ImportedConversationDocument, invite::ConversationInviteDocument,
+        read_marker::ReadMarker, ConversationDocument,
+    },
+    ds_key::DataStoreKey,
+    ecdh_decrypt, ecdh_encrypt,
+    identity::Request,
+    keystore::Keystore,
+    VecExt, MAX_METADATA_ENTRIES, MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
 };
 
 use super::{
@@ -221,6 +233,110 @@ impl RootDocumentMap {
         inner.set_keystore(document).await
     }
 
+    pub async fn get_read_marker(&self, id: Uuid) -> Result<Option<ReadMarker>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_read_marker(id).await
+    }
+
+    pub async fn set_read_marker(&self, id: Uuid, marker: ReadMarker) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_read_marker(id, marker).await
+    }
+
+    pub async fn remove_read_markers(&self, ids: &[Uuid]) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.remove_read_markers(ids).await
+    }
+
+    pub async fn get_scheduled_messages(&self, id: Uuid) -> Result<Vec<ScheduledMessage>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_scheduled_messages(id).await
+    }
+
+    pub async fn set_scheduled_messages(
+        &self,
+        id: Uuid,
+        list: Vec<ScheduledMessage>,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_scheduled_messages(id, list).await
+    }
+
+    pub async fn get_conversation_invites(
+        &self,
+        id: Uuid,
+    ) -> Result<Vec<ConversationInviteDocument>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_conversation_invites(id).await
+    }
+
+    pub async fn set_conversation_invites(
+        &self,
+        id: Uuid,
+        list: Vec<ConversationInviteDocument>,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_conversation_invites(id, list).await
+    }
+
+    pub async fn get_draft(&self, id: Uuid) -> Result<Option<Draft>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_draft(id).await
+    }
+
+    pub async fn set_draft(&self, id: Uuid, draft: Option<Draft>) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_draft(id, draft).await
+    }
+
+    pub async fn remove_drafts(&self, ids: &[Uuid]) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.remove_drafts(ids).await
+    }
+
+    pub async fn get_notification_settings(&self, id: Uuid) -> Result<NotificationSettings, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_notification_settings(id).await
+    }
+
+    pub async fn set_notification_settings(
+        &self,
+        id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_notification_settings(id, settings).await
+    }
+
+    pub async fn remove_notification_settings(&self, ids: &[Uuid]) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.remove_notification_settings(ids).await
+    }
+
+    pub async fn get_imported_conversation(
+        &self,
+        id: Uuid,
+    ) -> Result<ImportedConversationDocument, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_imported_conversation(id).await
+    }
+
+    pub async fn list_imported_conversations(
+        &self,
+    ) -> Result<Vec<ImportedConversationDocument>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.list_imported_conversations().await
+    }
+
+    pub async fn set_imported_conversation(
+        &self,
+        id: Uuid,
+        document: Option<ImportedConversationDocument>,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_imported_conversation(id, document).await
+    }
+
     pub async fn get_directory_index(&self) -> Result<Directory, Error> {
         let inner = &*self.inner.read().await;
         inner.get_root_index().await
@@ -250,6 +366,32 @@ impl RootDocumentMap {
     }
 }
 
+/// Maps in the root document that are keyed by conversation id
+#[derive(Debug, Clone, Copy)]
+enum RootMap {
+    Keystore,
+    ReadMarkers,
+    ScheduledMessages,
+    ConversationInvites,
+    Drafts,
+    NotificationSettings,
+    ImportedConversations,
+}
+
+impl RootMap {
+    fn field(self, document: &mut RootDocument) -> &mut Option<Cid> {
+        match self {
+            RootMap::Keystore => &mut document.keystore,
+            RootMap::ReadMarkers => &mut document.read_markers,
+            RootMap::ScheduledMessages => &mut document.scheduled_messages,
+            RootMap::ConversationInvites => &mut document.conversation_invites,
+            RootMap::Drafts => &mut document.drafts,
+            RootMap::NotificationSettings => &mut document.notification_settings,
+            RootMap::ImportedConversations => &mut document.imported_conversations,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct RootDocumentInner {
     keypair: Option<Keypair>,
@@ -886,16 +1028,13 @@ impl RootDocumentInner {
         Ok(())
     }
 
-    async fn set_keystore(&mut self, map: BTreeMap<String, Cid>) -> Result<(), Error> {
+    async fn get_map<T: DeserializeOwned + Send>(
+        &self,
+        map: RootMap,
+    ) -> Result<BTreeMap<String, T>, Error> {
         let mut document = self.get_root_document().await?;
-        document.keystore = Some(self.ipfs.put_dag(map).await?);
-        self.set_root_document(document).await
-    }
 
-    async fn get_keystore_map(&self) -> Result<BTreeMap<String, Cid>, Error> {
-        let document = self.get_root_document().await?;
-
-        let cid = match document.keystore {
+        let cid = match *map.field(&mut document) {
             Some(cid) => cid,
             None => return Ok(BTreeMap::new()),
         };
@@ -908,6 +1047,98 @@ impl RootDocumentInner {
             .map_err(Error::from)
     }
 
+    async fn set_map<T: Serialize + Send>(
+        &mut self,
+        map: RootMap,
+        entries: BTreeMap<String, T>,
+    ) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+        *map.field(&mut document) = match entries.is_empty() {
+            true => None,
+            false => Some(self.ipfs.put_dag(entries).await?),
+        };
+        self.set_root_document(document).await
+    }
+
+    /// Insert or, if `entry` is `None`, remove an entry from the map, leaving the root document untouched
+    /// if there is nothing to remove
+    async fn set_entry<T: Serialize + DeserializeOwned + Send>(
+        &mut self,
+        map: RootMap,
+        id: Uuid,
+        entry: Option<T>,
+    ) -> Result<(), Error> {
+        let mut entries = self.get_map(map).await?;
+
+        match entry {
+            Some(entry) => {
+                entries.insert(id.to_string(), entry);
+            }
+            None => {
+                if entries.remove(&id.to_string()).is_none() {
+                    return Ok(());
+                }
+            }
+        }
+
+        self.set_map(map, entries).await
+    }
+
+    async fn remove_entries<T: Serialize + DeserializeOwned + Send>(
+        &mut self,
+        map: RootMap,
+        ids: &[Uuid],
+    ) -> Result<(), Error> {
+        let mut entries = self.get_map::<T>(map).await?;
+        let len = entries.len();
+        for id in ids {
+            entries.remove(&id.to_string());
+        }
+        if entries.len() == len {
+            return Ok(());
+        }
+        self.set_map(map, entries).await
+    }
+
+    async fn get_encrypted_entry<T: DeserializeOwned + Send>(
+        &self,
+        map: RootMap,
+        id: Uuid,
+    ) -> Result<Option<T>, Error> {
+        let mut entries = self.get_map::<Bytes>(map).await?;
+        let Some(bytes) = entries.remove(&id.to_string()) else {
+            return Ok(None);
+        };
+
+        let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+        serde_json::from_slice(&bytes).map_err(Error::from)
+    }
+
+    async fn set_encrypted_entry<T: Serialize + Send>(
+        &mut self,
+        map: RootMap,
+        id: Uuid,
+        entry: Option<T>,
+    ) -> Result<(), Error> {
+        let entry = match entry {
+            Some(entry) => {
+                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&entry)?)?;
+                Some(Bytes::from(bytes))
+            }
+            None => None,
+        };
+
+        self.set_entry(map, id, entry).await
+    }
+
+    async fn set_keystore(&mut self, map: BTreeMap<String, Cid>) -> Result<(), Error> {
+        self.set_map(RootMap::Keystore, map).await
+    }
+
+    async fn get_keystore_map(&self) -> Result<BTreeMap<String, Cid>, Error> {
+        self.get_map(RootMap::Keystore).await
+    }
+
     async fn get_keystore(&self, id: Uuid) -> Result<Keystore, Error> {
         let document = self.get_root_document().await?;
 
@@ -925,6 +1156,146 @@ impl RootDocumentInner {
             .map_err(Error::from)
     }
 
+    async fn get_read_marker(&self, id: Uuid) -> Result<Option<ReadMarker>, Error> {
+        let mut markers = self.get_map(RootMap::ReadMarkers).await?;
+        Ok(markers.remove(&id.to_string()))
+    }
+
+    async fn set_read_marker(&mut self, id: Uuid, marker: ReadMarker) -> Result<(), Error> {
+        self.set_entry(RootMap::ReadMarkers, id, Some(marker)).await
+    }
+
+    async fn remove_read_markers(&mut self, ids: &[Uuid]) -> Result<(), Error> {
+        self.remove_entries::<ReadMarker>(RootMap::ReadMarkers, ids)
+            .await
+    }
+
+    async fn get_scheduled_messages(&self, id: Uuid) -> Result<Vec<ScheduledMessage>, Error> {
+        self.get_encrypted_entry(RootMap::ScheduledMessages, id)
+            .await
+            .map(Option::unwrap_or_default)
+    }
+
+    async fn set_scheduled_messages(
+        &mut self,
+        id: Uuid,
+        list: Vec<ScheduledMessage>,
+    ) -> Result<(), Error> {
+        let list = (!list.is_empty()).then_some(list);
+        self.set_encrypted_entry(RootMap::ScheduledMessages, id, list)
+            .await
+    }
+
+    async fn get_conversation_invites(
+        &self,
+        id: Uuid,
+    ) -> Result<Vec<ConversationInviteDocument>, Error> {
+        self.get_encrypted_entry(RootMap::ConversationInvites, id)
+            .await
+            .map(Option::unwrap_or_default)
+    }
+
+    async fn set_conversation_invites(
+        &mut self,
+        id: Uuid,
+        list: Vec<ConversationInviteDocument>,
+    ) -> Result<(), Error> {
+        let list = (!list.is_empty()).then_some(list);
+        self.set_encrypted_entry(RootMap::ConversationInvites, id, list)
+            .await
+    }
+
+    async fn get_draft(&self, id: Uuid) -> Result<Option<Draft>, Error> {
+        self.get_encrypted_entry(RootMap::Drafts, id).await
+    }
+
+    async fn set_draft(&mut self, id: Uuid, draft: Option<Draft>) -> Result<(), Error> {
+        self.set_encrypted_entry(RootMap::Drafts, id, draft).await
+    }
+
+    async fn remove_drafts(&mut self, ids: &[Uuid]) -> Result<(), Error> {
+        self.remove_entries::<Bytes>(RootMap::Drafts, ids).await
+    }
+
+    async fn get_notification_settings(&self, id: Uuid) -> Result<NotificationSettings, Error> {
+        let mut map = self.get_map(RootMap::NotificationSettings).await?;
+        Ok(map.remove(&id.to_string()).unwrap_or_default())
+    }
+
+    async fn set_notification_settings(
+        &mut self,
+        id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        // Default settings are not stored
+        let settings = (!settings.is_default()).then_some(settings);
+        self.set_entry(RootMap::NotificationSettings, id, settings)
+            .await
+    }
+
+    async fn remove_notification_settings(&mut self, ids: &[Uuid]) -> Result<(), Error> {
+        self.remove_entries::<NotificationSettings>(RootMap::NotificationSettings, ids)
+            .await
+    }
+
+    async fn get_imported_conversation(
+        &self,
+        id: Uuid,
+    ) -> Result<ImportedConversationDocument, Error> {
+        let map: BTreeMap<String, Cid> = self.get_map(RootMap::ImportedConversations).await?;
+        let cid = map
+            .get(&id.to_string())
+            .copied()
+            .ok_or(Error::InvalidConversation)?;
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn list_imported_conversations(
+        &self,
+    ) -> Result<Vec<ImportedConversationDocument>, Error> {
+        let map: BTreeMap<String, Cid> = self.get_map(RootMap::ImportedConversations).await?;
+
+        let list = FuturesUnordered::from_iter(map.into_values().map(|cid| {
+            self.ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized::<ImportedConversationDocument>()
+                .into_future()
+        }))
+        .filter_map(|result| async move { result.ok() })
+        .collect()
+        .await;
+
+        Ok(list)
+    }
+
+    async fn set_imported_conversation(
+        &mut self,
+        id: Uuid,
+        document: Option<ImportedConversationDocument>,
+    ) -> Result<(), Error> {
+        let cid = match document {
+            Some(document) => Some(self.ipfs.put_dag(document).await?),
+            None => {
+                let map: BTreeMap<String, Cid> =
+                    self.get_map(RootMap::ImportedConversations).await?;
+                if !map.contains_key(&id.to_string()) {
+                    return Err(Error::InvalidConversation);
+                }
+                None
+            }
+        };
+
+        self.set_entry(RootMap::ImportedConversations, id, cid)
+            .await
+    }
+
     async fn get_conversation_document(&self, id: Uuid) -> Result<ConversationDocument, Error> {
         let document = self.get_root_document().await?;
 
@@ -26,7 +26,10 @@ use warp::{
     multipass::identity::{IdentityImage, Platform},
 };
 use warp::{
-    crypto::{DIDKey, Ed25519KeyPair, Fingerprint, DID},
+    crypto::{
+        rand::{self, prelude::SliceRandom},
+        DIDKey, Ed25519KeyPair, Fingerprint, DID,
+    },
     error::Error,
     multipass::{
         identity::{Identity, IdentityStatus, SHORT_ID_SIZE},
@@ -41,12 +44,14 @@ use super::{
         cache::IdentityCache, identity::IdentityDocument, image_dag::get_image,
         root::RootDocumentMap, ResolvedRootDocument, RootDocument,
     },
+    ds_key::{self, DataStoreKey},
     ecdh_decrypt, ecdh_encrypt,
     event_subscription::EventSubscription,
     payload::PayloadMessage,
     phonebook::PhoneBook,
     protocols,
     queue::Queue,
+    ratchet::{PreKeyStore, RatchetMessage, RatchetSession, SessionReset, SignedPreKey},
     topics::IDENTITY_ANNOUNCEMENT,
     MAX_IMAGE_SIZE, MAX_METADATA_ENTRIES, MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
 };
@@ -59,6 +64,9 @@ use crate::{
     store::{discovery::Discovery, topics::PeerTopic, DidExt, PeerIdExt},
 };
 
+/// Interval at which our prekeys are checked for rotation
+const PREKEY_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 // TODO: Split into its own task
 #[allow(clippy::type_complexity)]
 #[allow(dead_code)]
@@ -80,6 +88,8 @@ pub struct IdentityStore {
 
     signal: Arc<RwLock<HashMap<DID, oneshot::Sender<Result<(), Error>>>>>,
 
+    prekeys: Arc<RwLock<PreKeyStore>>,
+
     discovery: Discovery,
 
     config: config::Config,
@@ -349,6 +359,10 @@ impl IdentityStore {
 
         let signal = Default::default();
 
+        let prekeys = ds_key::load_secret(ipfs, root_document.keypair(), &ipfs.prekeys())
+            .await
+            .unwrap_or_default();
+
         let store = Self {
             ipfs: ipfs.clone(),
             root_document,
@@ -360,6 +374,7 @@ impl IdentityStore {
             queue,
             phonebook: phonebook.clone(),
             signal,
+            prekeys: Arc::new(RwLock::new(prekeys)),
             span: span.clone(),
             executor: LocalExecutor,
         };
@@ -371,6 +386,9 @@ impl IdentityStore {
             async move {
                 if let Ok(ident) = store.own_identity().await {
                     tracing::info!(did = %ident.did_key(), "Identity loaded");
+                    if let Err(e) = store.publish_prekeys().await {
+                        tracing::warn!(did = %ident.did_key(), error = %e, "Unable to publish prekeys");
+                    }
                     match store.is_registered().await.is_ok() {
                         true => {
                             if let Err(e) = store.fetch_mailbox().await {
@@ -449,6 +467,8 @@ impl IdentityStore {
 
                 let mut tick = Delay::new(interval);
 
+                let mut prekey_tick = Delay::new(PREKEY_REFRESH_INTERVAL);
+
                 loop {
                     tokio::select! {
                         biased;
@@ -613,6 +633,12 @@ impl IdentityStore {
                             }
                             tick.reset(interval)
                         }
+                        _ = &mut prekey_tick => {
+                            if let Err(e) = store.publish_prekeys().await {
+                                tracing::warn!(error = %e, "Unable to publish prekeys");
+                            }
+                            prekey_tick.reset(PREKEY_REFRESH_INTERVAL)
+                        }
                     }
                 }
             }
@@ -1671,11 +1697,17 @@ impl IdentityStore {
             modified: time,
             status_message: None,
             metadata: Default::default(),
+            prekey: None,
+            one_time_prekeys: vec![],
             version: Default::default(),
             signature: None,
         };
 
-        let identity = identity.sign(self.root_document.keypair())?;
+        let mut identity = identity.sign(self.root_document.keypair())?;
+
+        if self.config.store_setting().ratchet_sessions {
+            (identity.prekey, identity.one_time_prekeys) = self.refresh_prekeys().await?;
+        }
 
         let ident_cid = self.ipfs.put_dag(identity).await?;
 
@@ -2425,6 +2457,119 @@ impl IdentityStore {
             .map_err(anyhow::Error::from)
     }
 
+    /// Rotates and replenishes our prekeys, storing them if they changed.
+    /// Returns the signed prekey and one-time prekeys to be published
+    async fn refresh_prekeys(&self) -> Result<(Option<SignedPreKey>, Vec<SignedPreKey>), Error> {
+        let keypair = self.root_document.keypair();
+        let mut prekeys = self.prekeys.write().await;
+
+        if prekeys.refresh() {
+            ds_key::store_secret(&self.ipfs, keypair, &self.ipfs.prekeys(), &*prekeys).await?;
+        }
+
+        Ok((
+            prekeys.signed_prekey(keypair)?,
+            prekeys.one_time_prekeys(keypair)?,
+        ))
+    }
+
+    /// Publishes our prekeys with our identity if ratchet sessions are enabled and they differ from those published
+    async fn publish_prekeys(&mut self) -> Result<(), Error> {
+        if !self.config.store_setting().ratchet_sessions {
+            return Ok(());
+        }
+
+        let (prekey, one_time_prekeys) = self.refresh_prekeys().await?;
+
+        let mut identity = self.own_identity_document().await?;
+
+        if identity.prekey == prekey && identity.one_time_prekeys == one_time_prekeys {
+            return Ok(());
+        }
+
+        identity.prekey = prekey;
+        identity.one_time_prekeys = one_time_prekeys;
+        self.identity_update(identity).await
+    }
+
+    /// Returns the prekey published by `did` along with one of their one-time prekeys, if any.
+    /// Note: The one-time prekey may have been used by another peer since it was published, in which case
+    ///       `did` replies with a reset and the session is initiated again without a one-time prekey
+    pub async fn identity_prekeys(
+        &self,
+        did: &DID,
+    ) -> Option<(SignedPreKey, Option<SignedPreKey>)> {
+        let document = self.identity_cache.get(did).await.ok()?;
+        let prekey = document
+            .prekey
+            .filter(|prekey| prekey.verify(&document.did).is_ok())?;
+        let one_time_prekeys = document
+            .one_time_prekeys
+            .into_iter()
+            .filter(|prekey| prekey.verify(&document.did).is_ok())
+            .collect::<Vec<_>>();
+        let one_time_prekey = one_time_prekeys.choose(&mut rand::thread_rng()).cloned();
+        Some((prekey, one_time_prekey))
+    }
+
+    /// Decrypts a message sent by `peer` over a ratchet session, establishing the session against our prekeys if needed.
+    /// A one-time prekey used to establish the session is deleted and replaced
+    pub async fn ratchet_receive(
+        &self,
+        peer: &DID,
+        session: Option<RatchetSession>,
+        message: &RatchetMessage,
+    ) -> Result<(Vec<u8>, RatchetSession), Error> {
+        let keypair = self.root_document.keypair();
+        let mut prekeys = self.prekeys.write().await;
+        let result = RatchetSession::receive(keypair, peer, session, message, &mut prekeys)?;
+
+        if prekeys.refresh() {
+            if let Err(e) =
+                ds_key::store_secret(&self.ipfs, keypair, &self.ipfs.prekeys(), &*prekeys).await
+            {
+                tracing::error!(error = %e, "Unable to store prekeys");
+            }
+
+            drop(prekeys);
+
+            let mut store = self.clone();
+            self.executor.dispatch(async move {
+                if let Err(e) = store.publish_prekeys().await {
+                    tracing::warn!(error = %e, "Unable to publish prekeys");
+                }
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the reset to send to `peer` if `message` could not be decrypted because its key agreement
+    /// refers to one of our prekeys that no longer exists
+    pub async fn ratchet_reset(
+        &self,
+        peer: &DID,
+        session: Option<&RatchetSession>,
+        message: &RatchetMessage,
+    ) -> Option<SessionReset> {
+        let keypair = self.root_document.keypair();
+        let prekeys = self.prekeys.read().await;
+        RatchetSession::reset(keypair, peer, session, message, &prekeys)
+            .ok()
+            .flatten()
+    }
+
+    /// Returns the cached identity documents of `list`, skipping any that have yet to be resolved
+    pub async fn cached_identities(&self, list: &[DID]) -> Vec<IdentityDocument> {
+        let mut documents = Vec::with_capacity(list.len());
+        for did in list {
+            if let Ok(document) = self.identity_cache.get(did).await {
+                documents.push(document);
+            }
+        }
+        documents
+    }
+
     pub async fn own_identity_document(&self) -> Result<IdentityDocument, Error> {
         let identity = self.root_document.identity().await?;
         identity.verify()?;
@@ -6,13 +6,23 @@ use std::{
 use rust_ipfs::Keypair;
 use serde::{Deserialize, Serialize};
 use warp::{
-    crypto::{cipher::Cipher, zeroize::Zeroize, DID},
+    crypto::{
+        cipher::Cipher,
+        zeroize::{Zeroize, Zeroizing},
+        DID,
+    },
     error::Error,
 };
 
+use super::ratchet::RatchetSession;
+
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Keystore {
     recipient_key: HashMap<DID, BTreeSet<KeyEntry>>,
+    /// Encrypted ratchet session of a direct conversation.
+    /// Note: Sessions are now stored on their own and this is only read to move existing sessions out of the keystore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<Vec<u8>>,
 }
 
 #[allow(dead_code)]
@@ -51,6 +61,35 @@ impl Keystore {
         self.recipient_key.contains_key(recipient)
     }
 
+    /// Records the keys used by `recipient`, oldest first, so that the last one is the latest.
+    /// Known keys that are missing from `keys` are kept ahead of them
+    pub fn set_history<K: AsRef<[u8]>>(
+        &mut self,
+        keypair: &Keypair,
+        recipient: &DID,
+        keys: &[K],
+    ) -> Result<(), Error> {
+        let known = self.get_all(keypair, recipient).unwrap_or_default();
+
+        let list = known
+            .into_iter()
+            .map(Zeroizing::new)
+            .filter(|known| !keys.iter().any(|key| key.as_ref() == known.as_slice()))
+            .chain(keys.iter().map(|key| Zeroizing::new(key.as_ref().to_vec())))
+            .enumerate()
+            .map(|(id, key)| {
+                Ok(KeyEntry::new(
+                    id,
+                    super::ecdh_encrypt(keypair, None, &*key)?,
+                ))
+            })
+            .collect::<Result<BTreeSet<_>, Error>>()?;
+
+        self.recipient_key.insert(recipient.clone(), list);
+
+        Ok(())
+    }
+
     pub fn get_latest(&self, keypair: &Keypair, recipient: &DID) -> Result<Vec<u8>, Error> {
         self.recipient_key
             .get(recipient)
@@ -78,6 +117,18 @@ impl Keystore {
             .map(|list| list.len())
             .ok_or(Error::PublicKeyDoesntExist)
     }
+
+    /// Takes the ratchet session out of the keystore, if one was stored within it
+    pub fn take_session(&mut self, keypair: &Keypair) -> Result<Option<RatchetSession>, Error> {
+        let Some(mut session) = self.session.take() else {
+            return Ok(None);
+        };
+
+        let bytes = Zeroizing::new(super::ecdh_decrypt(keypair, None, &session)?);
+        session.zeroize();
+        let session = serde_json::from_slice(&bytes)?;
+        Ok(Some(session))
+    }
 }
 
 #[allow(dead_code)]
@@ -204,6 +255,32 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn keystore_set_history() -> anyhow::Result<()> {
+        let mut keystore = Keystore::default();
+
+        let keypair = Keypair::generate_ed25519();
+        let recipient = DID::default();
+
+        let keys = (0..3).map(|_| generate::<32>()).collect::<Vec<_>>();
+
+        keystore.insert(&keypair, &recipient, &keys[1])?;
+        keystore.set_history(&keypair, &recipient, &keys)?;
+
+        assert_eq!(keystore.get_all(&keypair, &recipient)?, keys);
+        assert_eq!(keystore.get_latest(&keypair, &recipient)?, keys[2]);
+
+        let unknown = generate::<32>();
+        keystore.insert(&keypair, &recipient, &unknown)?;
+        keystore.set_history(&keypair, &recipient, &keys)?;
+
+        let all = keystore.get_all(&keypair, &recipient)?;
+        assert_eq!(all[0], unknown);
+        assert_eq!(&all[1..], keys.as_slice());
+
+        Ok(())
+    }
+
     #[test]
     fn keystore_try_decrypt() -> anyhow::Result<()> {
         let mut rng = rand::thread_rng();
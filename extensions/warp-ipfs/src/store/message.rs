@@ -1,9 +1,16 @@
 mod attachment;
 mod community_task;
+mod ephemeral;
+mod pins;
+mod receipts;
 mod task;
+mod threads;
+mod voice_note;
 
 use community_task::CommunityTaskCommand;
+use futures_timeout::TimeoutExt;
 use futures_timer::Delay;
+use pins::AttachmentPins;
 use task::ConversationTaskCommand;
 
 use bytes::Bytes;
@@ -26,14 +33,21 @@ use futures::{
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
 
-use rust_ipfs::{Ipfs, PeerId};
+use rust_ipfs::{p2p::MultiaddrExt, Ipfs, PeerId};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::{document::root::RootDocumentMap, ds_key::DataStoreKey, PeerIdExt};
 use crate::store::{
-    conversation::ConversationDocument,
+    conversation::{
+        archive::{self, ArchiveImport, ImportedMessage},
+        export::{ExportFormat, ExportOptions, ExportProgressStream, ExportedConversation},
+        invite::ConversationInviteToken,
+        message::ForwardedMessage,
+        reference::ReferenceStats,
+        ConversationDocument,
+    },
     discovery::Discovery,
     ecdh_decrypt, ecdh_encrypt,
     event_subscription::EventSubscription,
@@ -42,12 +56,17 @@ use crate::store::{
     identity::IdentityStore,
     keystore::Keystore,
     payload::{PayloadBuilder, PayloadMessage},
+    protocols,
+    search::SearchIndex,
     sign_serde,
     topics::PeerTopic,
     ConversationEvents, ConversationRequestKind, ConversationRequestResponse, DidExt,
+    CONVERSATION_JOIN_TIMEOUT, MAX_ATTACHMENT, MAX_MESSAGE_SIZE, MAX_PENDING_PAYLOADS,
 };
 
+use crate::config;
 use crate::rt::{AbortableJoinHandle, Executor, LocalExecutor};
+use crate::shuttle;
 
 use crate::store::community::CommunityDocument;
 use chrono::{DateTime, Utc};
@@ -55,16 +74,19 @@ use warp::raygun::community::{
     Community, CommunityChannel, CommunityChannelPermission, CommunityChannelType, CommunityInvite,
     CommunityPermission, CommunityRole, RoleId,
 };
-use warp::raygun::{ConversationImage, GroupPermissionOpt, Message};
+use warp::raygun::{
+    ConversationImage, ConversationInvite, EmbedState, GroupPermissionOpt, Message,
+};
 use warp::{
     constellation::ConstellationProgressStream,
     crypto::DID,
     error::Error,
     multipass::MultiPassEventKind,
     raygun::{
-        AttachmentEventStream, Conversation, ConversationType, Location, MessageEvent,
-        MessageEventKind, MessageOptions, MessageReference, MessageStatus, Messages, PinState,
-        RayGunEventKind, ReactionState,
+        AttachmentEventStream, Conversation, ConversationType, Draft, GeoPosition, Location,
+        LocationKind, MessageEvent, MessageEventKind, MessageOptions, MessageReceipt,
+        MessageReference, MessageStatus, Messages, NotificationSettings, PinState, Poll,
+        RayGunEventKind, ReactionState, ScheduledMessage, SearchQuery, SearchResult,
     },
 };
 
@@ -81,6 +103,7 @@ pub struct MessageStore {
 impl MessageStore {
     pub async fn new(
         ipfs: &Ipfs,
+        config: &config::Config,
         discovery: Discovery,
         file: &FileStore,
         event: EventSubscription<RayGunEventKind>,
@@ -91,8 +114,12 @@ impl MessageStore {
 
         let root = identity.root_document().clone();
 
+        let search_index = SearchIndex::new(ipfs, root.keypair()).await;
+        let attachment_pins = AttachmentPins::load(ipfs).await;
+
         let mut inner = ConversationInner {
             ipfs: ipfs.clone(),
+            config: config.clone(),
             conversation_task: HashMap::new(),
             community_task: HashMap::new(),
             identity: identity.clone(),
@@ -101,7 +128,10 @@ impl MessageStore {
             file: file.clone(),
             event,
             queue: Default::default(),
+            search_index,
+            attachment_pins,
             executor,
+            pending_joins: HashMap::new(),
         };
 
         if let Err(e) = inner.migrate().await {
@@ -126,14 +156,27 @@ impl MessageStore {
 
 impl MessageStore {
     pub async fn get_conversation(&self, id: Uuid) -> Result<Conversation, Error> {
-        let document = self.get(id).await?;
-        Ok(document.into())
+        let inner = &*self.inner.read().await;
+        let document = inner.get(id).await?;
+        let mut conversation = Conversation::from(document);
+        let settings = inner.root.get_notification_settings(id).await?;
+        conversation.set_notification_settings(settings);
+        Ok(conversation)
     }
 
     pub async fn list_conversations(&self) -> Result<Vec<Conversation>, Error> {
-        self.list()
-            .await
-            .map(|list| list.into_iter().map(|document| document.into()).collect())
+        let inner = &*self.inner.read().await;
+        let mut list = vec![];
+        for document in inner.list().await {
+            let mut conversation = Conversation::from(document);
+            let settings = inner
+                .root
+                .get_notification_settings(conversation.id())
+                .await?;
+            conversation.set_notification_settings(settings);
+            list.push(conversation);
+        }
+        Ok(list)
     }
 
     pub async fn get_conversation_stream(
@@ -275,6 +318,28 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn get_message_history(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<warp::raygun::Message>, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::GetMessageHistory {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
     pub async fn get_messages(
         &self,
         conversation_id: Uuid,
@@ -297,6 +362,30 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn get_thread(
+        &self,
+        conversation_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::GetThread {
+                root_message_id,
+                options,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
     pub async fn messages_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -312,6 +401,171 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn compact_messages(&self, conversation_id: Uuid) -> Result<ReferenceStats, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::CompactMessages { response: tx })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn export_conversation(
+        &self,
+        conversation_id: Uuid,
+        path: PathBuf,
+        options: ExportOptions,
+    ) -> Result<ExportProgressStream, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ExportConversation {
+                path,
+                options,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn export_conversation_archive(
+        &self,
+        conversation_id: Uuid,
+        path: PathBuf,
+        format: ExportFormat,
+    ) -> Result<ExportProgressStream, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ExportArchive {
+                path,
+                format,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn import_conversation_archive(&self, path: PathBuf) -> Result<ArchiveImport, Error> {
+        let inner = &*self.inner.read().await;
+        let imported = inner
+            .root
+            .list_imported_conversations()
+            .await?
+            .into_iter()
+            .map(|document| document.conversation.id)
+            .collect::<HashSet<_>>();
+
+        let (document, summary, blobs) =
+            archive::import_archive(&inner.ipfs, inner.root.keypair(), &path, |id| {
+                inner.conversation_task.contains_key(&id) || imported.contains(&id)
+            })
+            .await?;
+
+        // Note: The blobs may already be pinned, such as by an attachment of another conversation,
+        // so they are tracked alongside other attachments to only be unpinned once the last reference is removed
+        for (message_id, cids) in blobs.messages {
+            match inner.attachment_pins.pin(cids.clone()).await {
+                Ok(pinned) => {
+                    inner
+                        .attachment_pins
+                        .insert(&[message_id], &cids, &pinned)
+                        .await
+                }
+                Err(e) => {
+                    tracing::warn!(%message_id, error = %e, "unable to pin imported attachments")
+                }
+            }
+        }
+
+        inner
+            .root
+            .set_imported_conversation(summary.conversation_id, Some(document))
+            .await?;
+
+        Ok(summary)
+    }
+
+    pub async fn list_imported_conversations(&self) -> Result<Vec<ExportedConversation>, Error> {
+        let inner = &*self.inner.read().await;
+        let list = inner.root.list_imported_conversations().await?;
+        Ok(list
+            .into_iter()
+            .map(|document| document.conversation)
+            .collect())
+    }
+
+    pub async fn get_imported_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ImportedMessage>, Error> {
+        let inner = &*self.inner.read().await;
+        let document = inner
+            .root
+            .get_imported_conversation(conversation_id)
+            .await?;
+        document
+            .resolve_messages(&inner.ipfs, inner.root.keypair())
+            .await
+    }
+
+    pub async fn remove_imported_conversation(&self, conversation_id: Uuid) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let document = inner
+            .root
+            .get_imported_conversation(conversation_id)
+            .await?;
+
+        inner
+            .root
+            .set_imported_conversation(conversation_id, None)
+            .await?;
+
+        for message in document.message_documents(&inner.ipfs).await? {
+            let cids = pins::attachment_cids(&message.attachments);
+            let released = inner.attachment_pins.release(message.id, cids).await;
+            inner.attachment_pins.unpin(released).await;
+        }
+
+        // The messages are no longer linked to the root document, so they are removed rather than left for
+        // garbage collection
+        if !inner
+            .ipfs
+            .is_pinned(document.messages)
+            .await
+            .unwrap_or_default()
+        {
+            if let Err(e) = inner.ipfs.remove_block(document.messages, false).await {
+                tracing::warn!(%conversation_id, error = %e, "unable to remove imported messages");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn search_messages(&self, query: SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        let inner = &*self.inner.read().await;
+        Ok(inner.search_index.search(&query).await)
+    }
+
     pub async fn get_message_reference(
         &self,
         conversation_id: Uuid,
@@ -442,11 +696,12 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn message_status(
+    pub async fn create_conversation_invite(
         &self,
         conversation_id: Uuid,
-        message_id: Uuid,
-    ) -> Result<MessageStatus, Error> {
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Result<ConversationInvite, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -456,19 +711,19 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::MessageStatus {
-                message_id,
+            .send(ConversationTaskCommand::CreateInvite {
+                expiry,
+                max_uses,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn send_message(
+    pub async fn list_conversation_invites(
         &self,
         conversation_id: Uuid,
-        lines: Vec<String>,
-    ) -> Result<Uuid, Error> {
+    ) -> Result<Vec<ConversationInvite>, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -478,19 +733,15 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::SendMessage {
-                lines,
-                response: tx,
-            })
+            .send(ConversationTaskCommand::ListInvites { response: tx })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn edit_message(
+    pub async fn revoke_conversation_invite(
         &self,
         conversation_id: Uuid,
-        message_id: Uuid,
-        lines: Vec<String>,
+        invite_id: Uuid,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -501,21 +752,15 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::EditMessage {
-                message_id,
-                lines,
+            .send(ConversationTaskCommand::RevokeInvite {
+                invite_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn reply(
-        &self,
-        conversation_id: Uuid,
-        message_id: Uuid,
-        lines: Vec<String>,
-    ) -> Result<Uuid, Error> {
+    pub async fn rotate_conversation_key(&self, conversation_id: Uuid) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -525,20 +770,60 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::ReplyMessage {
-                message_id,
-                lines,
-                response: tx,
-            })
+            .send(ConversationTaskCommand::RotateKey { response: tx })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn delete_message(
+    pub async fn accept_conversation_invite(&self, token: &str) -> Result<Uuid, Error> {
+        // Subscribe before sending the request so the answer of the inviter is not missed
+        let mut stream = self.inner.read().await.event.subscribe().await?;
+
+        let conversation_id = {
+            let inner = &mut *self.inner.write().await;
+            inner.accept_conversation_invite(token).await?
+        };
+
+        let inner = self.inner.clone();
+
+        let join = async move {
+            let mut shuttle_timer = Delay::new(Duration::from_secs(5));
+            loop {
+                tokio::select! {
+                    Some(event) = stream.next() => match event {
+                        RayGunEventKind::ConversationCreated { conversation_id: id } if id == conversation_id => {
+                            return Ok(conversation_id);
+                        }
+                        RayGunEventKind::ConversationJoinRejected { conversation_id: id } if id == conversation_id => {
+                            return Err(Error::OtherWithContext("the inviter rejected the request to join".into()));
+                        }
+                        _ => {}
+                    },
+                    _ = &mut shuttle_timer => {
+                        // The inviter may have added us while we were unreachable, in which case the document is
+                        // obtained from shuttle instead
+                        let inner = &mut *inner.write().await;
+                        if inner.join_from_shuttle(conversation_id).await.is_ok() {
+                            return Ok(conversation_id);
+                        }
+                        shuttle_timer.reset(Duration::from_secs(5));
+                    }
+                }
+            }
+        };
+
+        join.timeout(CONVERSATION_JOIN_TIMEOUT).await.map_err(|_| {
+            Error::OtherWithContext(
+                "timed out waiting for the inviter to accept the request".into(),
+            )
+        })?
+    }
+
+    pub async fn message_status(
         &self,
         conversation_id: Uuid,
         message_id: Uuid,
-    ) -> Result<(), Error> {
+    ) -> Result<MessageStatus, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -548,7 +833,7 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::DeleteMessage {
+            .send(ConversationTaskCommand::MessageStatus {
                 message_id,
                 response: tx,
             })
@@ -556,12 +841,11 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn pin_message(
+    pub async fn message_receipts(
         &self,
         conversation_id: Uuid,
         message_id: Uuid,
-        state: PinState,
-    ) -> Result<(), Error> {
+    ) -> Result<Vec<MessageReceipt>, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -571,22 +855,24 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::PinMessage {
+            .send(ConversationTaskCommand::MessageReceipts {
                 message_id,
-                state,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn react(
+    pub async fn mark_message_read(
         &self,
         conversation_id: Uuid,
         message_id: Uuid,
-        state: ReactionState,
-        emoji: String,
     ) -> Result<(), Error> {
+        // Note: Reading a message also marks every message before it as read, so both share the same command
+        self.mark_read(conversation_id, message_id).await
+    }
+
+    pub async fn mark_read(&self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -596,23 +882,15 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::ReactMessage {
+            .send(ConversationTaskCommand::MarkRead {
                 message_id,
-                state,
-                emoji,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn attach(
-        &self,
-        conversation_id: Uuid,
-        message_id: Option<Uuid>,
-        locations: Vec<Location>,
-        messages: Vec<String>,
-    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+    pub async fn last_read_message(&self, conversation_id: Uuid) -> Result<Option<Uuid>, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -622,24 +900,12 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::AttachMessage {
-                message_id,
-                locations,
-                lines: messages,
-                response: tx,
-            })
+            .send(ConversationTaskCommand::GetLastReadMessage { response: tx })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn download<P: AsRef<Path>>(
-        &self,
-        conversation_id: Uuid,
-        message_id: Uuid,
-        file: &str,
-        path: P,
-    ) -> Result<ConstellationProgressStream, Error> {
-        let path = path.as_ref().to_path_buf();
+    pub async fn unread_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -649,22 +915,12 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::DownloadAttachment {
-                message_id,
-                file: file.to_owned(),
-                path,
-                response: tx,
-            })
+            .send(ConversationTaskCommand::GetUnreadCount { response: tx })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn download_stream(
-        &self,
-        conversation_id: Uuid,
-        message_id: Uuid,
-        file: &str,
-    ) -> Result<DownloadStream, Error> {
+    pub async fn unread_mention_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -674,20 +930,654 @@ impl MessageStore {
         let _ = conversation_meta
             .command_tx
             .clone()
-            .send(ConversationTaskCommand::DownloadAttachmentStream {
-                message_id,
-                file: file.to_owned(),
-                response: tx,
-            })
+            .send(ConversationTaskCommand::GetUnreadMentionCount { response: tx })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
-    pub async fn send_event(
+    pub async fn schedule_send(
         &self,
         conversation_id: Uuid,
-        event: MessageEvent,
-    ) -> Result<(), Error> {
+        lines: Vec<String>,
+        send_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ScheduleMessage {
+                lines,
+                send_at,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn list_scheduled_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ScheduledMessage>, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ListScheduledMessages { response: tx })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn cancel_scheduled_message(
+        &self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::CancelScheduledMessage {
+                scheduled_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn reschedule_message(
+        &self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+        send_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::RescheduleMessage {
+                scheduled_id,
+                send_at,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn set_draft(&self, conversation_id: Uuid, draft: Draft) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        if !inner.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+        inner.store_draft(conversation_id, draft).await
+    }
+
+    pub async fn get_draft(&self, conversation_id: Uuid) -> Result<Option<Draft>, Error> {
+        let inner = &*self.inner.read().await;
+        if !inner.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+        inner.root.get_draft(conversation_id).await
+    }
+
+    pub async fn clear_draft(&self, conversation_id: Uuid) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        if !inner.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+        inner.clear_draft(conversation_id).await
+    }
+
+    pub async fn set_notification_settings(
+        &self,
+        conversation_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        if !inner.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+        inner
+            .root
+            .set_notification_settings(conversation_id, settings)
+            .await
+    }
+
+    pub async fn get_notification_settings(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        let inner = &*self.inner.read().await;
+        if !inner.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+        inner.root.get_notification_settings(conversation_id).await
+    }
+
+    pub async fn send_message(
+        &self,
+        conversation_id: Uuid,
+        lines: Vec<String>,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SendMessage {
+                lines,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn edit_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        lines: Vec<String>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::EditMessage {
+                message_id,
+                lines,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn reply(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        lines: Vec<String>,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ReplyMessage {
+                message_id,
+                lines,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn forward(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        target_conversation_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let (message, pinned) = inner.prepare_forward(conversation_id, message_id).await?;
+        let cids = pins::attachment_cids(&message.attachments);
+
+        let result: Result<Uuid, Error> = async {
+            let conversation_meta = inner
+                .conversation_task
+                .get(&target_conversation_id)
+                .ok_or(Error::InvalidConversation)?;
+            let (tx, rx) = oneshot::channel();
+            let _ = conversation_meta
+                .command_tx
+                .clone()
+                .send(ConversationTaskCommand::ForwardMessage {
+                    message,
+                    response: tx,
+                })
+                .await;
+            rx.await.map_err(anyhow::Error::from)?
+        }
+        .await;
+
+        inner
+            .reference_forwarded(message_id, &result, &cids, pinned)
+            .await;
+        result
+    }
+
+    pub async fn forward_to_community_channel(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let (message, pinned) = inner.prepare_forward(conversation_id, message_id).await?;
+        let cids = pins::attachment_cids(&message.attachments);
+
+        let result: Result<Uuid, Error> = async {
+            let community_meta = inner
+                .community_task
+                .get(&community_id)
+                .ok_or(Error::InvalidCommunity)?;
+            let (tx, rx) = oneshot::channel();
+            let _ = community_meta
+                .command_tx
+                .clone()
+                .send(CommunityTaskCommand::ForwardMessage {
+                    channel_id,
+                    message,
+                    response: tx,
+                })
+                .await;
+            rx.await.map_err(anyhow::Error::from)?
+        }
+        .await;
+
+        inner
+            .reference_forwarded(message_id, &result, &cids, pinned)
+            .await;
+        result
+    }
+
+    pub async fn delete_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::DeleteMessage {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn pin_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: PinState,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::PinMessage {
+                message_id,
+                state,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn react(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: ReactionState,
+        emoji: String,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ReactMessage {
+                message_id,
+                state,
+                emoji,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn send_poll(&self, conversation_id: Uuid, poll: Poll) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SendPoll { poll, response: tx })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn vote(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::Vote {
+                message_id,
+                options,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn retract_vote(&self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::RetractVote {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn close_poll(&self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::ClosePoll {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn send_geolocation(
+        &self,
+        conversation_id: Uuid,
+        position: GeoPosition,
+        live: Option<Duration>,
+    ) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SendGeolocation {
+                position,
+                live,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn update_geolocation(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        position: GeoPosition,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::UpdateGeolocation {
+                message_id,
+                position,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn stop_geolocation(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::StopGeolocation {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn send_contact_card(&self, conversation_id: Uuid, did: &DID) -> Result<Uuid, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SendContactCard {
+                did: did.clone(),
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn embeds(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: EmbedState,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::Embeds {
+                message_id,
+                state,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn attach(
+        &self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        locations: Vec<Location>,
+        messages: Vec<String>,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::AttachMessage {
+                message_id,
+                locations,
+                lines: messages,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn attach_voice_note(
+        &self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        location: Location,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::AttachVoiceNote {
+                message_id,
+                location,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn download<P: AsRef<Path>>(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        file: &str,
+        path: P,
+    ) -> Result<ConstellationProgressStream, Error> {
+        let path = path.as_ref().to_path_buf();
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::DownloadAttachment {
+                message_id,
+                file: file.to_owned(),
+                path,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn download_stream(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        file: &str,
+    ) -> Result<DownloadStream, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::DownloadAttachmentStream {
+                message_id,
+                file: file.to_owned(),
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn send_event(
+        &self,
+        conversation_id: Uuid,
+        event: MessageEvent,
+    ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
             .conversation_task
@@ -857,6 +1747,27 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
+    pub async fn set_message_retention(
+        &self,
+        conversation_id: Uuid,
+        retention: Option<Duration>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SetMessageRetention {
+                retention,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
     pub async fn archived_conversation(&self, conversation_id: Uuid) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -1373,12 +2284,269 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn revoke_community_permission(
-        &mut self,
+    pub async fn revoke_community_permission(
+        &mut self,
+        community_id: Uuid,
+        permission: CommunityPermission,
+        role_id: RoleId,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::RevokeCommunityPermission {
+                permission,
+                role_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn grant_community_permission_for_all(
+        &mut self,
+        community_id: Uuid,
+        permission: CommunityPermission,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::GrantCommunityPermissionForAll {
+                permission,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn revoke_community_permission_for_all(
+        &mut self,
+        community_id: Uuid,
+        permission: CommunityPermission,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::RevokeCommunityPermissionForAll {
+                permission,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn remove_community_member(
+        &mut self,
+        community_id: Uuid,
+        member: DID,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::RemoveCommunityMember {
+                member,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn edit_community_channel_name(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        name: &str,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::EditCommunityChannelName {
+                channel_id,
+                name: name.to_string(),
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn edit_community_channel_description(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        description: Option<String>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::EditCommunityChannelDescription {
+                channel_id,
+                description,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn grant_community_channel_permission(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        permission: CommunityChannelPermission,
+        role_id: RoleId,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::GrantCommunityChannelPermission {
+                channel_id,
+                permission,
+                role_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn revoke_community_channel_permission(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        permission: CommunityChannelPermission,
+        role_id: RoleId,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::RevokeCommunityChannelPermission {
+                channel_id,
+                permission,
+                role_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn grant_community_channel_permission_for_all(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        permission: CommunityChannelPermission,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(
+                CommunityTaskCommand::GrantCommunityChannelPermissionForAll {
+                    channel_id,
+                    permission,
+                    response: tx,
+                },
+            )
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn revoke_community_channel_permission_for_all(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        permission: CommunityChannelPermission,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(
+                CommunityTaskCommand::RevokeCommunityChannelPermissionForAll {
+                    channel_id,
+                    permission,
+                    response: tx,
+                },
+            )
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn get_community_channel_message(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Message, Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::GetCommunityChannelMessage {
+                channel_id,
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+    pub async fn get_community_channel_messages(
+        &self,
         community_id: Uuid,
-        permission: CommunityPermission,
-        role_id: RoleId,
-    ) -> Result<(), Error> {
+        channel_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1388,19 +2556,21 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::RevokeCommunityPermission {
-                permission,
-                role_id,
+            .send(CommunityTaskCommand::GetCommunityChannelMessages {
+                channel_id,
+                options,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn grant_community_permission_for_all(
-        &mut self,
+    pub async fn get_community_channel_thread(
+        &self,
         community_id: Uuid,
-        permission: CommunityPermission,
-    ) -> Result<(), Error> {
+        channel_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1410,18 +2580,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GrantCommunityPermissionForAll {
-                permission,
+            .send(CommunityTaskCommand::GetCommunityChannelThread {
+                channel_id,
+                root_message_id,
+                options,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn revoke_community_permission_for_all(
-        &mut self,
+    pub async fn get_community_channel_message_count(
+        &self,
         community_id: Uuid,
-        permission: CommunityPermission,
-    ) -> Result<(), Error> {
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1431,18 +2603,19 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::RevokeCommunityPermissionForAll {
-                permission,
+            .send(CommunityTaskCommand::GetCommunityChannelMessageCount {
+                channel_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn remove_community_member(
-        &mut self,
+    pub async fn get_community_channel_message_reference(
+        &self,
         community_id: Uuid,
-        member: DID,
-    ) -> Result<(), Error> {
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<MessageReference, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1452,20 +2625,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::RemoveCommunityMember {
-                member,
+            .send(CommunityTaskCommand::GetCommunityChannelMessageReference {
+                channel_id,
+                message_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-
-    pub async fn edit_community_channel_name(
-        &mut self,
+    pub async fn get_community_channel_message_references(
+        &self,
         community_id: Uuid,
         channel_id: Uuid,
-        name: &str,
-    ) -> Result<(), Error> {
+        options: MessageOptions,
+    ) -> Result<BoxStream<'static, MessageReference>, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1475,20 +2648,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::EditCommunityChannelName {
+            .send(CommunityTaskCommand::GetCommunityChannelMessageReferences {
                 channel_id,
-                name: name.to_string(),
+                options,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn edit_community_channel_description(
-        &mut self,
+    pub async fn community_channel_message_status(
+        &self,
         community_id: Uuid,
         channel_id: Uuid,
-        description: Option<String>,
-    ) -> Result<(), Error> {
+        message_id: Uuid,
+    ) -> Result<MessageStatus, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1498,21 +2671,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::EditCommunityChannelDescription {
+            .send(CommunityTaskCommand::CommunityChannelMessageStatus {
                 channel_id,
-                description,
+                message_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn grant_community_channel_permission(
-        &mut self,
+    pub async fn community_channel_message_receipts(
+        &self,
         community_id: Uuid,
         channel_id: Uuid,
-        permission: CommunityChannelPermission,
-        role_id: RoleId,
-    ) -> Result<(), Error> {
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1522,21 +2694,19 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GrantCommunityChannelPermission {
+            .send(CommunityTaskCommand::CommunityChannelMessageReceipts {
                 channel_id,
-                permission,
-                role_id,
+                message_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn revoke_community_channel_permission(
+    pub async fn mark_community_channel_message_read(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
-        permission: CommunityChannelPermission,
-        role_id: RoleId,
+        message_id: Uuid,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1547,20 +2717,19 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::RevokeCommunityChannelPermission {
+            .send(CommunityTaskCommand::MarkCommunityChannelMessageRead {
                 channel_id,
-                permission,
-                role_id,
+                message_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn grant_community_channel_permission_for_all(
+    pub async fn mark_community_channel_read(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
-        permission: CommunityChannelPermission,
+        message_id: Uuid,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1571,22 +2740,19 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(
-                CommunityTaskCommand::GrantCommunityChannelPermissionForAll {
-                    channel_id,
-                    permission,
-                    response: tx,
-                },
-            )
+            .send(CommunityTaskCommand::MarkCommunityChannelRead {
+                channel_id,
+                message_id,
+                response: tx,
+            })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn revoke_community_channel_permission_for_all(
-        &mut self,
+    pub async fn get_community_channel_last_read_message(
+        &self,
         community_id: Uuid,
         channel_id: Uuid,
-        permission: CommunityChannelPermission,
-    ) -> Result<(), Error> {
+    ) -> Result<Option<Uuid>, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1596,23 +2762,18 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(
-                CommunityTaskCommand::RevokeCommunityChannelPermissionForAll {
-                    channel_id,
-                    permission,
-                    response: tx,
-                },
-            )
+            .send(CommunityTaskCommand::GetCommunityChannelLastReadMessage {
+                channel_id,
+                response: tx,
+            })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-
-    pub async fn get_community_channel_message(
+    pub async fn get_community_channel_unread_count(
         &self,
         community_id: Uuid,
         channel_id: Uuid,
-        message_id: Uuid,
-    ) -> Result<Message, Error> {
+    ) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1622,20 +2783,18 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GetCommunityChannelMessage {
+            .send(CommunityTaskCommand::GetCommunityChannelUnreadCount {
                 channel_id,
-                message_id,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn get_community_channel_messages(
+    pub async fn get_community_channel_unread_mention_count(
         &self,
         community_id: Uuid,
         channel_id: Uuid,
-        options: MessageOptions,
-    ) -> Result<Messages, Error> {
+    ) -> Result<usize, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1645,19 +2804,81 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GetCommunityChannelMessages {
-                channel_id,
-                options,
-                response: tx,
-            })
+            .send(
+                CommunityTaskCommand::GetCommunityChannelUnreadMentionCount {
+                    channel_id,
+                    response: tx,
+                },
+            )
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn get_community_channel_message_count(
+    pub async fn set_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        draft: Draft,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        inner
+            .check_community_channel(community_id, channel_id)
+            .await?;
+        inner.store_draft(channel_id, draft).await
+    }
+    pub async fn get_community_channel_draft(
         &self,
         community_id: Uuid,
         channel_id: Uuid,
-    ) -> Result<usize, Error> {
+    ) -> Result<Option<Draft>, Error> {
+        let inner = &*self.inner.read().await;
+        inner
+            .check_community_channel(community_id, channel_id)
+            .await?;
+        inner.root.get_draft(channel_id).await
+    }
+    pub async fn clear_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        inner
+            .check_community_channel(community_id, channel_id)
+            .await?;
+        inner.clear_draft(channel_id).await
+    }
+    pub async fn set_community_channel_notification_settings(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        inner
+            .check_community_channel(community_id, channel_id)
+            .await?;
+        inner
+            .root
+            .set_notification_settings(channel_id, settings)
+            .await
+    }
+    pub async fn get_community_channel_notification_settings(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        let inner = &*self.inner.read().await;
+        inner
+            .check_community_channel(community_id, channel_id)
+            .await?;
+        inner.root.get_notification_settings(channel_id).await
+    }
+    pub async fn send_community_channel_message(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message: Vec<String>,
+    ) -> Result<Uuid, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1667,19 +2888,21 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GetCommunityChannelMessageCount {
+            .send(CommunityTaskCommand::SendCommunityChannelMessage {
                 channel_id,
+                message,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn get_community_channel_message_reference(
-        &self,
+    pub async fn edit_community_channel_message(
+        &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
-    ) -> Result<MessageReference, Error> {
+        message: Vec<String>,
+    ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1689,20 +2912,22 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GetCommunityChannelMessageReference {
+            .send(CommunityTaskCommand::EditCommunityChannelMessage {
                 channel_id,
                 message_id,
+                message,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn get_community_channel_message_references(
-        &self,
+    pub async fn reply_to_community_channel_message(
+        &mut self,
         community_id: Uuid,
         channel_id: Uuid,
-        options: MessageOptions,
-    ) -> Result<BoxStream<'static, MessageReference>, Error> {
+        message_id: Uuid,
+        message: Vec<String>,
+    ) -> Result<Uuid, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1712,20 +2937,21 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::GetCommunityChannelMessageReferences {
+            .send(CommunityTaskCommand::ReplyToCommunityChannelMessage {
                 channel_id,
-                options,
+                message_id,
+                message,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn community_channel_message_status(
-        &self,
+    pub async fn delete_community_channel_message(
+        &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
-    ) -> Result<MessageStatus, Error> {
+    ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1735,7 +2961,7 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::CommunityChannelMessageStatus {
+            .send(CommunityTaskCommand::DeleteCommunityChannelMessage {
                 channel_id,
                 message_id,
                 response: tx,
@@ -1743,12 +2969,13 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn send_community_channel_message(
+    pub async fn pin_community_channel_message(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
-        message: Vec<String>,
-    ) -> Result<Uuid, Error> {
+        message_id: Uuid,
+        state: PinState,
+    ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
             .community_task
@@ -1758,20 +2985,22 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::SendCommunityChannelMessage {
+            .send(CommunityTaskCommand::PinCommunityChannelMessage {
                 channel_id,
-                message,
+                message_id,
+                state,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn edit_community_channel_message(
+    pub async fn react_to_community_channel_message(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
-        message: Vec<String>,
+        state: ReactionState,
+        emoji: String,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1782,21 +3011,21 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::EditCommunityChannelMessage {
+            .send(CommunityTaskCommand::ReactToCommunityChannelMessage {
                 channel_id,
                 message_id,
-                message,
+                state,
+                emoji,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn reply_to_community_channel_message(
+    pub async fn send_community_channel_poll(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
-        message_id: Uuid,
-        message: Vec<String>,
+        poll: Poll,
     ) -> Result<Uuid, Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1807,20 +3036,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::ReplyToCommunityChannelMessage {
+            .send(CommunityTaskCommand::SendCommunityChannelPoll {
                 channel_id,
-                message_id,
-                message,
+                poll,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn delete_community_channel_message(
+    pub async fn vote_on_community_channel_poll(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
+        options: Vec<usize>,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1831,20 +3060,20 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::DeleteCommunityChannelMessage {
+            .send(CommunityTaskCommand::VoteOnCommunityChannelPoll {
                 channel_id,
                 message_id,
+                options,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn pin_community_channel_message(
+    pub async fn retract_community_channel_poll_vote(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
-        state: PinState,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1855,22 +3084,19 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::PinCommunityChannelMessage {
+            .send(CommunityTaskCommand::RetractCommunityChannelPollVote {
                 channel_id,
                 message_id,
-                state,
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
-    pub async fn react_to_community_channel_message(
+    pub async fn close_community_channel_poll(
         &mut self,
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
-        state: ReactionState,
-        emoji: String,
     ) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let community_meta = inner
@@ -1881,11 +3107,9 @@ impl MessageStore {
         let _ = community_meta
             .command_tx
             .clone()
-            .send(CommunityTaskCommand::ReactToCommunityChannelMessage {
+            .send(CommunityTaskCommand::CloseCommunityChannelPoll {
                 channel_id,
                 message_id,
-                state,
-                emoji,
                 response: tx,
             })
             .await;
@@ -2120,6 +3344,7 @@ struct CommunityInnerMeta {
 
 struct ConversationInner {
     ipfs: Ipfs,
+    config: config::Config,
     conversation_task: HashMap<Uuid, ConversationInnerMeta>,
     community_task: HashMap<Uuid, CommunityInnerMeta>,
     root: RootDocumentMap,
@@ -2130,7 +3355,11 @@ struct ConversationInner {
 
     // Note: Temporary
     queue: HashMap<DID, Vec<Queue>>,
+    search_index: SearchIndex,
+    attachment_pins: AttachmentPins,
     executor: LocalExecutor,
+    /// Conversations requested to be joined with an invite, along with the inviter
+    pending_joins: HashMap<Uuid, DID>,
 }
 
 impl ConversationInner {
@@ -2190,6 +3419,9 @@ impl ConversationInner {
             &self.identity,
             &self.file,
             &self.discovery,
+            &self.search_index,
+            &self.attachment_pins,
+            &self.config,
             crx,
             self.event.clone(),
         )
@@ -2439,6 +3671,13 @@ impl ConversationInner {
         meta.command_tx.close_channel();
         meta.handle.abort();
 
+        self.search_index.remove_conversation(id).await;
+        _ = self.root.remove_read_markers(&[id]).await;
+        _ = self.root.set_scheduled_messages(id, vec![]).await;
+        _ = self.root.set_conversation_invites(id, vec![]).await;
+        _ = self.root.remove_drafts(&[id]).await;
+        _ = self.root.remove_notification_settings(&[id]).await;
+
         Ok(conversation)
     }
 
@@ -2460,6 +3699,17 @@ impl ConversationInner {
         meta.command_tx.close_channel();
         meta.handle.abort();
 
+        self.search_index.remove_conversation(id).await;
+
+        let channels = community
+            .channels
+            .keys()
+            .filter_map(|id| id.parse::<Uuid>().ok())
+            .collect::<Vec<_>>();
+        _ = self.root.remove_read_markers(&channels).await;
+        _ = self.root.remove_drafts(&channels).await;
+        _ = self.root.remove_notification_settings(&channels).await;
+
         Ok(community)
     }
 
@@ -2634,9 +3884,10 @@ impl ConversationInner {
     }
 
     async fn request_community_key(&mut self, community_id: Uuid, did: &DID) -> Result<(), Error> {
+        // Note: Having just joined, none of the keys that were used for earlier messages are known
         let request = ConversationRequestResponse::Request {
             conversation_id: community_id,
-            kind: ConversationRequestKind::Key,
+            kind: ConversationRequestKind::KeyHistory,
         };
 
         let community = self.get_community_document(community_id).await?;
@@ -2789,6 +4040,207 @@ impl ConversationInner {
         Ok(())
     }
 
+    /// Sends the invite token to the inviter, who adds us to the conversation if the invite is still valid.
+    /// The conversation document is received from the inviter once we have been added
+    async fn accept_conversation_invite(&mut self, token: &str) -> Result<Uuid, Error> {
+        let token = ConversationInviteToken::decode(token)?;
+        let conversation_id = token.conversation_id;
+
+        if token.is_expired() {
+            return Err(Error::OtherWithContext("invite has expired".into()));
+        }
+
+        let own_did = self.identity.did_key();
+
+        if token.inviter == own_did {
+            return Err(Error::CannotCreateConversation);
+        }
+
+        if let Ok(conversation) = self.get(conversation_id).await {
+            let conversation = Conversation::from(&conversation);
+            return Err(Error::ConversationExist { conversation });
+        }
+
+        if self
+            .root
+            .is_blocked(&token.inviter)
+            .await
+            .unwrap_or_default()
+        {
+            return Err(Error::PublicKeyIsBlocked);
+        }
+
+        let inviter = token.inviter.clone();
+
+        self.send_single_conversation_event(
+            conversation_id,
+            &inviter,
+            ConversationEvents::JoinConversation { token },
+        )
+        .await?;
+
+        self.pending_joins.insert(conversation_id, inviter);
+
+        Ok(conversation_id)
+    }
+
+    /// Join a group conversation from its document, once the own identity has been added to it
+    async fn join_group_conversation(
+        &mut self,
+        mut conversation: ConversationDocument,
+    ) -> Result<(), Error> {
+        let did = self.identity.did_key();
+
+        let conversation_id = conversation.id;
+
+        if self.contains(conversation_id).await {
+            tracing::warn!(%conversation_id, "Conversation exist");
+            return Ok(());
+        }
+
+        if !conversation.recipients.contains(&did) {
+            tracing::warn!(%conversation_id, "was added to conversation but never was apart of the conversation.");
+            return Ok(());
+        }
+
+        tracing::info!(%conversation_id, "Creating group conversation");
+
+        let conversation_type = conversation.conversation_type();
+
+        conversation.verify()?;
+
+        //TODO: Resolve message list
+        conversation.messages = None;
+        conversation.archived = false;
+        conversation.favorite = false;
+
+        self.set_document(conversation).await?;
+
+        self.create_conversation_task(conversation_id).await?;
+
+        let conversation = self.get(conversation_id).await?;
+
+        tracing::info!(%conversation_id, "{} conversation created", conversation_type);
+
+        self.pending_joins.remove(&conversation_id);
+
+        for recipient in conversation.recipients.iter().filter(|d| did.ne(d)) {
+            if let Err(e) = self.request_key(conversation_id, recipient).await {
+                tracing::warn!(%conversation_id, error = %e, %recipient, "Failed to send exchange request");
+            }
+        }
+
+        self.event
+            .emit(RayGunEventKind::ConversationCreated { conversation_id })
+            .await;
+
+        Ok(())
+    }
+
+    /// Sends a request to the shuttle nodes, returning the first response along with the node that answered
+    async fn shuttle_request(
+        &self,
+        request: shuttle::message::protocol::Request,
+    ) -> Result<(PeerId, shuttle::message::protocol::Response), Error> {
+        let config::Discovery::Shuttle { addresses } = self.discovery.discovery_config() else {
+            return Err(Error::OtherWithContext("shuttle is not configured".into()));
+        };
+
+        let payload = PayloadBuilder::new(self.root.keypair(), request).build()?;
+        let bytes = payload.to_bytes()?;
+
+        for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+            let response = match self
+                .ipfs
+                .send_request(peer_id, (protocols::SHUTTLE_MESSAGE, bytes.clone()))
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
+                    continue;
+                }
+            };
+
+            let payload: PayloadMessage<shuttle::message::protocol::Response> =
+                match PayloadMessage::from_bytes(&response) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::error!(error = %e, %peer_id, "unable to process payload");
+                        continue;
+                    }
+                };
+
+            return Ok((peer_id, payload.message(None)?));
+        }
+
+        Err(Error::OtherWithContext("no shuttle node responded".into()))
+    }
+
+    /// Stores the document of a group conversation with shuttle, so members added while unreachable can obtain it
+    async fn register_conversation_with_shuttle(&self, conversation_id: Uuid) -> Result<(), Error> {
+        let mut document = self.get(conversation_id).await?;
+
+        document.messages = None;
+        document.archived = false;
+        document.favorite = false;
+
+        let conversation_document = self.ipfs.put_dag(document).await?;
+
+        let request = shuttle::message::protocol::RegisterConversation {
+            owner: self.identity.did_key(),
+            conversation_id,
+            conversation_type: shuttle::message::protocol::ConversationType::Group,
+            conversation_document,
+        };
+
+        match self.shuttle_request(request.into()).await? {
+            (_, shuttle::message::protocol::Response::Ack) => Ok(()),
+            (_, shuttle::message::protocol::Response::Error(e)) => Err(Error::OtherWithContext(e)),
+            _ => Err(Error::Other),
+        }
+    }
+
+    /// Joins a conversation from the document stored with shuttle, if the own identity was added to it
+    async fn join_from_shuttle(&mut self, conversation_id: Uuid) -> Result<(), Error> {
+        if self.contains(conversation_id).await {
+            return Ok(());
+        }
+
+        let request = shuttle::message::protocol::Request::FetchConversation { conversation_id };
+
+        let (peer_id, document) = match self.shuttle_request(request).await? {
+            (
+                peer_id,
+                shuttle::message::protocol::Response::Conversation {
+                    conversation_id: id,
+                    document,
+                },
+            ) if id == conversation_id => (peer_id, document),
+            (_, shuttle::message::protocol::Response::Error(e)) => {
+                return Err(Error::OtherWithContext(e))
+            }
+            _ => return Err(Error::Other),
+        };
+
+        let conversation: ConversationDocument = self
+            .ipfs
+            .get_dag(document)
+            .provider(peer_id)
+            .timeout(Duration::from_secs(10))
+            .deserialized()
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        if conversation.id != conversation_id
+            || !conversation.recipients.contains(&self.identity.did_key())
+        {
+            return Err(Error::InvalidConversation);
+        }
+
+        self.join_group_conversation(conversation).await
+    }
+
     async fn leave_group_conversation(
         &mut self,
         creator: &DID,
@@ -2884,6 +4336,9 @@ impl ConversationInner {
             &self.identity,
             &self.file,
             &self.discovery,
+            &self.search_index,
+            &self.attachment_pins,
+            &self.config,
             crx,
             self.event.clone(),
         )
@@ -2907,6 +4362,153 @@ impl ConversationInner {
         self.root.get_community_document(id).await
     }
 
+    /// Retrieve the contents of a message to be forwarded out of the conversation,
+    /// along with the attachments that were pinned for it
+    async fn prepare_forward(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(ForwardedMessage, Vec<Cid>), Error> {
+        let conversation_meta = self
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::PrepareForward {
+                message_id,
+                response: tx,
+            })
+            .await;
+        let message = rx.await.map_err(anyhow::Error::from)??;
+
+        // Note: The attachments are not uploaded again, so they are fetched and pinned to allow
+        //       members of the target conversation to retrieve them from us. The pins are kept
+        //       until both the original and the forwarded message are removed
+        let pinned = self
+            .attachment_pins
+            .pin(pins::attachment_cids(&message.attachments))
+            .await?;
+
+        Ok((message, pinned))
+    }
+
+    /// Record the original and the forwarded message as referencing the attachments,
+    /// or remove the pins created for them if the message could not be forwarded
+    async fn reference_forwarded(
+        &self,
+        message_id: Uuid,
+        forwarded: &Result<Uuid, Error>,
+        cids: &[Cid],
+        pinned: Vec<Cid>,
+    ) {
+        match forwarded {
+            Ok(forwarded_id) if !cids.is_empty() => {
+                self.attachment_pins
+                    .insert(&[message_id, *forwarded_id], cids, &pinned)
+                    .await;
+            }
+            Ok(_) => {}
+            Err(_) => self.attachment_pins.unpin(pinned).await,
+        }
+    }
+
+    /// Checks that the channel exists and is visible to own identity
+    async fn check_community_channel(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<(), Error> {
+        if !self.community_task.contains_key(&community_id) {
+            return Err(Error::InvalidCommunity);
+        }
+
+        let document = self.get_community_document(community_id).await?;
+        let own_did = &self.identity.did_key();
+
+        if !document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        if !document.channels.contains_key(&channel_id.to_string()) {
+            return Err(Error::CommunityChannelDoesntExist);
+        }
+
+        Ok(())
+    }
+
+    /// Stores the draft, clearing it if the draft is empty
+    async fn store_draft(&self, id: Uuid, mut draft: Draft) -> Result<(), Error> {
+        let lines_value_length: usize = draft
+            .lines()
+            .iter()
+            .map(|s| s.trim())
+            .map(|s| s.chars().count())
+            .sum();
+
+        if lines_value_length > MAX_MESSAGE_SIZE {
+            return Err(Error::InvalidLength {
+                context: "message".into(),
+                current: lines_value_length,
+                minimum: None,
+                maximum: Some(MAX_MESSAGE_SIZE),
+            });
+        }
+
+        if draft.attachments().len() > MAX_ATTACHMENT {
+            return Err(Error::InvalidLength {
+                context: "attachments".into(),
+                current: draft.attachments().len(),
+                minimum: None,
+                maximum: Some(MAX_ATTACHMENT),
+            });
+        }
+
+        if draft
+            .attachments()
+            .iter()
+            .any(|kind| matches!(kind, LocationKind::Stream { .. }))
+        {
+            return Err(Error::OtherWithContext(
+                "only constellation and disk attachments can be stored in a draft".into(),
+            ));
+        }
+
+        let draft = match draft.is_empty() {
+            true => None,
+            false => {
+                draft.set_modified(Utc::now());
+                Some(draft)
+            }
+        };
+
+        self.root.set_draft(id, draft).await?;
+        self.sync_drafts().await;
+        Ok(())
+    }
+
+    async fn clear_draft(&self, id: Uuid) -> Result<(), Error> {
+        self.root.set_draft(id, None).await?;
+        self.sync_drafts().await;
+        Ok(())
+    }
+
+    async fn sync_drafts(&self) {
+        if !self.config.store_setting().sync_drafts {
+            return;
+        }
+
+        if let Err(e) = self.identity.export_root_document().await {
+            tracing::warn!(error = %e, "unable to sync drafts");
+        }
+    }
+
     pub async fn set_community_document<B: BorrowMut<CommunityDocument>>(
         &mut self,
         mut document: B,
@@ -3130,50 +4732,10 @@ async fn process_conversation(
                 .emit(RayGunEventKind::ConversationCreated { conversation_id })
                 .await;
         }
-        ConversationEvents::NewGroupConversation { mut conversation } => {
-            let did = this.identity.did_key();
-
+        ConversationEvents::NewGroupConversation { conversation } => {
             let conversation_id = conversation.id;
             tracing::info!(%conversation_id, "New group conversation event received");
-
-            if this.contains(conversation_id).await {
-                tracing::warn!(%conversation_id, "Conversation exist");
-                return Ok(());
-            }
-
-            if !conversation.recipients.contains(&did) {
-                tracing::warn!(%conversation_id, "was added to conversation but never was apart of the conversation.");
-                return Ok(());
-            }
-
-            tracing::info!(%conversation_id, "Creating group conversation");
-
-            let conversation_type = conversation.conversation_type();
-
-            conversation.verify()?;
-
-            //TODO: Resolve message list
-            conversation.messages = None;
-            conversation.archived = false;
-            conversation.favorite = false;
-
-            this.set_document(conversation).await?;
-
-            this.create_conversation_task(conversation_id).await?;
-
-            let conversation = this.get(conversation_id).await?;
-
-            tracing::info!(%conversation_id, "{} conversation created", conversation_type);
-
-            for recipient in conversation.recipients.iter().filter(|d| did.ne(d)) {
-                if let Err(e) = this.request_key(conversation_id, recipient).await {
-                    tracing::warn!(%conversation_id, error = %e, %recipient, "Failed to send exchange request");
-                }
-            }
-
-            this.event
-                .emit(RayGunEventKind::ConversationCreated { conversation_id })
-                .await;
+            this.join_group_conversation(conversation).await?;
         }
         ConversationEvents::LeaveConversation {
             conversation_id,
@@ -3223,6 +4785,75 @@ async fn process_conversation(
 
             this.delete_conversation(conversation_id, false).await?;
         }
+        ConversationEvents::JoinConversation { token } => {
+            let sender = data.sender().to_did()?;
+            let conversation_id = token.conversation_id;
+            tracing::trace!(%conversation_id, %sender, "Join conversation event received");
+
+            if !token.verify() || token.inviter != this.identity.did_key() {
+                return Err(Error::InvalidSignature);
+            }
+
+            let conversation_meta = this
+                .conversation_task
+                .get(&conversation_id)
+                .ok_or(Error::InvalidConversation)?;
+            let (tx, rx) = oneshot::channel();
+            let _ = conversation_meta
+                .command_tx
+                .clone()
+                .send(ConversationTaskCommand::RedeemInvite {
+                    invite_id: token.id,
+                    member: sender.clone(),
+                    response: tx,
+                })
+                .await;
+
+            if let Err(e) = rx.await.map_err(anyhow::Error::from)? {
+                let event = ConversationEvents::JoinConversationRejected {
+                    conversation_id,
+                    reason: e.to_string(),
+                };
+                if let Err(e) = this
+                    .send_single_conversation_event(conversation_id, &sender, event)
+                    .await
+                {
+                    tracing::warn!(%conversation_id, %sender, error = %e, "unable to reject join request");
+                }
+                return Err(e);
+            }
+
+            if matches!(
+                this.discovery.discovery_config(),
+                config::Discovery::Shuttle { .. }
+            ) {
+                if let Err(e) = this
+                    .register_conversation_with_shuttle(conversation_id)
+                    .await
+                {
+                    tracing::warn!(%conversation_id, error = %e, "unable to register conversation with shuttle");
+                }
+            }
+        }
+        ConversationEvents::JoinConversationRejected {
+            conversation_id,
+            reason,
+        } => {
+            let sender = data.sender().to_did()?;
+
+            // Only the inviter the request was sent to can reject it
+            if this.pending_joins.get(&conversation_id) != Some(&sender) {
+                return Err(Error::Unauthorized);
+            }
+
+            this.pending_joins.remove(&conversation_id);
+
+            tracing::warn!(%conversation_id, %sender, %reason, "request to join conversation was rejected");
+
+            this.event
+                .emit(RayGunEventKind::ConversationJoinRejected { conversation_id })
+                .await;
+        }
         ConversationEvents::NewCommunityInvite {
             community_id,
             invite,
@@ -3475,6 +5106,24 @@ impl Queue {
     }
 }
 
+/// Sets aside a payload from `sender` until their key is received, dropping the oldest payload once
+/// [`MAX_PENDING_PAYLOADS`] are pending for the sender. Returns true if no other payload was pending for the sender
+fn queue_pending_payload(
+    pending: &mut IndexMap<DID, Vec<(Vec<u8>, bool)>>,
+    id: Uuid,
+    sender: DID,
+    data: Vec<u8>,
+) -> bool {
+    let list = pending.entry(sender).or_default();
+    let first = list.is_empty();
+    if list.len() >= MAX_PENDING_PAYLOADS {
+        tracing::warn!(%id, "too many pending payloads. Dropping oldest payload");
+        list.remove(0);
+    }
+    list.push((data, false));
+    first
+}
+
 //TODO: Replace
 async fn _process_queue(this: &mut ConversationInner) {
     let mut changed = false;
@@ -1,6 +1,7 @@
 use crate::store::conversation::message::MessageDocument;
 use crate::store::files::FileStore;
 use crate::store::keystore::Keystore;
+use crate::store::message::voice_note::VoiceNoteAnalyzer;
 use crate::store::message::CHAT_DIRECTORY;
 use crate::store::{MAX_MESSAGE_SIZE, MIN_MESSAGE_SIZE};
 use either::Either;
@@ -9,9 +10,11 @@ use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use futures::stream::SelectAll;
 use futures::{stream, FutureExt, SinkExt, Stream, StreamExt};
+use parking_lot::Mutex;
 use rust_ipfs::{Ipfs, Keypair};
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use uuid::Uuid;
 use warp::constellation::directory::Directory;
@@ -34,6 +37,7 @@ pub struct AttachmentStream {
     locations: Vec<Location>,
     directory: Directory,
     lines: Option<Vec<String>>,
+    voice_note: bool,
     keystore: Either<DID, Keystore>,
     file_store: FileStore,
     state: AttachmentState,
@@ -73,6 +77,7 @@ impl AttachmentStream {
             reply_to: None,
             locations: Vec::new(),
             lines: None,
+            voice_note: false,
             state: AttachmentState::Initialize,
             progressed: Some(SelectAll::new()),
             successful_attachment: Vec::new(),
@@ -165,6 +170,19 @@ impl AttachmentStream {
         self.reply_to = message_id.into();
         self
     }
+
+    /// Attach the location as a voice note, which must be a single stream of Opus audio within an Ogg container.
+    /// The duration and waveform are read from the stream while it is uploaded
+    pub fn set_voice_note(mut self) -> Result<Self, Error> {
+        if !matches!(self.locations.as_slice(), [Location::Stream { .. }]) {
+            return Err(Error::OtherWithContext(
+                "voice note must be provided as a single stream".into(),
+            ));
+        }
+
+        self.voice_note = true;
+        Ok(self)
+    }
 }
 
 impl Stream for AttachmentStream {
@@ -286,6 +304,21 @@ impl Stream for AttachmentStream {
                                 let filename =
                                     format!("/{CHAT_DIRECTORY}/{conversation_id}/{filename}");
 
+                                let analyzer = this
+                                    .voice_note
+                                    .then(|| Arc::new(Mutex::new(VoiceNoteAnalyzer::default())));
+
+                                let bytes_st = match analyzer.clone() {
+                                    Some(analyzer) => bytes_st
+                                        .inspect(move |result| {
+                                            if let Ok(bytes) = result {
+                                                analyzer.lock().update(bytes);
+                                            }
+                                        })
+                                        .boxed(),
+                                    None => bytes_st,
+                                };
+
                                 let st = ConstellationFutureStream::Future {
                                     name: filename.clone(),
                                     fut: {
@@ -299,24 +332,32 @@ impl Stream for AttachmentStream {
 
                                 let directory = this.file_store.root_directory();
 
-                                let st =
-                                    Box::pin(BindKey::new((directory, filename, kind), st).map(
-                                        |((directory, filename, kind), progress)| match progress {
-                                            item @ Progression::CurrentProgress { .. } => {
-                                                (kind, item, None)
-                                            }
-                                            item @ Progression::ProgressComplete { .. } => {
-                                                let file_name = directory
-                                                    .get_item_by_path(&filename)
-                                                    .and_then(|item| item.get_file())
-                                                    .ok();
-                                                (kind, item, file_name)
-                                            }
-                                            item @ Progression::ProgressFailed { .. } => {
-                                                (kind, item, None)
+                                let st = Box::pin(
+                                    BindKey::new((directory, filename, kind, analyzer), st).map(
+                                        |((directory, filename, kind, analyzer), progress)| {
+                                            match progress {
+                                                item @ Progression::CurrentProgress { .. } => {
+                                                    (kind, item, None)
+                                                }
+                                                item @ Progression::ProgressComplete { .. } => {
+                                                    let file_name = directory
+                                                        .get_item_by_path(&filename)
+                                                        .and_then(|item| item.get_file())
+                                                        .ok();
+                                                    match analyzer {
+                                                        Some(analyzer) => describe_voice_note(
+                                                            kind, item, file_name, &analyzer,
+                                                        ),
+                                                        None => (kind, item, file_name),
+                                                    }
+                                                }
+                                                item @ Progression::ProgressFailed { .. } => {
+                                                    (kind, item, None)
+                                                }
                                             }
                                         },
-                                    ));
+                                    ),
+                                );
 
                                 progressed.push(st);
                             }
@@ -505,6 +546,35 @@ impl Stream for AttachmentStream {
     }
 }
 
+/// Set the voice note read from the uploaded stream on the file, failing the upload if the stream
+/// was not an Opus stream
+fn describe_voice_note(
+    kind: LocationKind,
+    item: Progression,
+    file: Option<File>,
+    analyzer: &Mutex<VoiceNoteAnalyzer>,
+) -> (LocationKind, Progression, Option<File>) {
+    let voice_note = std::mem::take(&mut *analyzer.lock()).finish();
+
+    match (item, voice_note) {
+        (item, Ok(voice_note)) => {
+            if let Some(file) = file.as_ref() {
+                file.set_voice_note(Some(voice_note));
+            }
+            (kind, item, file)
+        }
+        (Progression::ProgressComplete { name, total }, Err(error)) => {
+            let item = Progression::ProgressFailed {
+                name,
+                last_size: total,
+                error,
+            };
+            (kind, item, None)
+        }
+        (item, Err(_)) => (kind, item, None),
+    }
+}
+
 enum ConstellationFutureStream {
     Future {
         name: String,
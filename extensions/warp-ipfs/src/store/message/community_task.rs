@@ -3,7 +3,7 @@ use chrono::{DateTime, Utc};
 use either::Either;
 use futures::channel::oneshot;
 use futures::stream::BoxStream;
-use futures::{StreamExt, TryFutureExt};
+use futures::{SinkExt, StreamExt, TryFutureExt};
 use futures_timer::Delay;
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
@@ -21,14 +21,14 @@ use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 use uuid::Uuid;
 use warp::constellation::ConstellationProgressStream;
-use warp::crypto::DID;
+use warp::crypto::{zeroize::Zeroizing, DID};
 use warp::raygun::community::{
     CommunityChannel, CommunityChannelPermission, CommunityChannelType, CommunityInvite,
     CommunityPermission, CommunityRole, RoleId,
 };
 use warp::raygun::{
-    AttachmentEventStream, ConversationImage, Location, MessageEvent, MessageOptions,
-    MessageReference, MessageStatus, MessageType, Messages, MessagesType, PinState,
+    AttachmentEventStream, ConversationImage, Location, Mention, MessageEvent, MessageOptions,
+    MessageReceipt, MessageReference, MessageStatus, MessageType, Messages, MessagesType, PinState,
     RayGunEventKind, ReactionState,
 };
 use warp::{
@@ -38,24 +38,31 @@ use warp::{
 };
 use web_time::Instant;
 
+use crate::config;
 use crate::store::community::{
     CommunityChannelDocument, CommunityDocument, CommunityInviteDocument, CommunityRoleDocument,
 };
-use crate::store::conversation::message::MessageDocument;
+use crate::store::conversation::mention;
+use crate::store::conversation::message::{ForwardedMessage, MessageDocument};
+use crate::store::conversation::poll::{self, PollCloseDocument, PollVoteDocument};
+use crate::store::conversation::read_marker::{self, ReadMarker};
+use crate::store::conversation::resolve_messages;
 use crate::store::discovery::Discovery;
 use crate::store::document::files::FileDocument;
 use crate::store::document::image_dag::ImageDag;
 use crate::store::ds_key::DataStoreKey;
 use crate::store::event_subscription::EventSubscription;
+use crate::store::search::SearchIndex;
 use crate::store::topics::PeerTopic;
 use crate::store::{
     CommunityUpdateKind, ConversationEvents, ConversationImageType, MAX_COMMUNITY_CHANNELS,
     MAX_COMMUNITY_DESCRIPTION, MAX_CONVERSATION_BANNER_SIZE, MAX_CONVERSATION_ICON_SIZE,
-    MAX_MESSAGE_SIZE, MAX_REACTIONS, MIN_MESSAGE_SIZE,
+    MAX_KEY_HISTORY, MAX_MESSAGE_SIZE, MAX_REACTIONS, MAX_RECEIPT_BATCH, MIN_MESSAGE_SIZE,
+    RECEIPT_BATCH_INTERVAL,
 };
 use crate::utils::{ByteCollection, ExtensionType};
 use crate::{
-    // rt::LocalExecutor,
+    rt::{Executor, LocalExecutor},
     store::{
         document::root::RootDocumentMap,
         ecdh_decrypt, ecdh_encrypt,
@@ -64,11 +71,16 @@ use crate::{
         keystore::Keystore,
         payload::{PayloadBuilder, PayloadMessage},
         CommunityMessagingEvents, ConversationRequestKind, ConversationRequestResponse,
-        ConversationResponseKind, DidExt, PeerIdExt,
+        ConversationResponseKind, DidExt, PeerIdExt, ReceiptKind,
     },
 };
 
 use super::attachment::AttachmentStream;
+use super::ephemeral::ActiveEvents;
+use super::pins::{self, AttachmentPins};
+use super::queue_pending_payload;
+use super::receipts::Receipts;
+use super::threads::{self, Reply, Threads};
 
 type AttachmentOneshot = (MessageDocument, oneshot::Sender<Result<(), Error>>);
 
@@ -226,6 +238,12 @@ pub enum CommunityTaskCommand {
         options: MessageOptions,
         response: oneshot::Sender<Result<Messages, Error>>,
     },
+    GetCommunityChannelThread {
+        channel_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+        response: oneshot::Sender<Result<Messages, Error>>,
+    },
     GetCommunityChannelMessageCount {
         channel_id: Uuid,
         response: oneshot::Sender<Result<usize, Error>>,
@@ -245,6 +263,33 @@ pub enum CommunityTaskCommand {
         message_id: Uuid,
         response: oneshot::Sender<Result<MessageStatus, Error>>,
     },
+    CommunityChannelMessageReceipts {
+        channel_id: Uuid,
+        message_id: Uuid,
+        response: oneshot::Sender<Result<Vec<MessageReceipt>, Error>>,
+    },
+    MarkCommunityChannelMessageRead {
+        channel_id: Uuid,
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    MarkCommunityChannelRead {
+        channel_id: Uuid,
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    GetCommunityChannelLastReadMessage {
+        channel_id: Uuid,
+        response: oneshot::Sender<Result<Option<Uuid>, Error>>,
+    },
+    GetCommunityChannelUnreadCount {
+        channel_id: Uuid,
+        response: oneshot::Sender<Result<usize, Error>>,
+    },
+    GetCommunityChannelUnreadMentionCount {
+        channel_id: Uuid,
+        response: oneshot::Sender<Result<usize, Error>>,
+    },
     SendCommunityChannelMessage {
         channel_id: Uuid,
         message: Vec<String>,
@@ -262,6 +307,11 @@ pub enum CommunityTaskCommand {
         message: Vec<String>,
         response: oneshot::Sender<Result<Uuid, Error>>,
     },
+    ForwardMessage {
+        channel_id: Uuid,
+        message: ForwardedMessage,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
     DeleteCommunityChannelMessage {
         channel_id: Uuid,
         message_id: Uuid,
@@ -280,6 +330,27 @@ pub enum CommunityTaskCommand {
         emoji: String,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    SendCommunityChannelPoll {
+        channel_id: Uuid,
+        poll: warp::raygun::Poll,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
+    VoteOnCommunityChannelPoll {
+        channel_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    RetractCommunityChannelPollVote {
+        channel_id: Uuid,
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    CloseCommunityChannelPoll {
+        channel_id: Uuid,
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     SendCommunityChannelMesssageEvent {
         channel_id: Uuid,
         event: MessageEvent,
@@ -329,6 +400,15 @@ pub struct CommunityTask {
     pending_key_exchange: IndexMap<DID, Vec<(Vec<u8>, bool)>>,
     document: CommunityDocument,
     keystore: Keystore,
+    search_index: SearchIndex,
+    attachment_pins: AttachmentPins,
+    /// Receipts of each channel
+    receipts: HashMap<Uuid, Receipts>,
+    /// Messages of each channel with a delivery receipt that has yet to be sent
+    pending_receipts: HashMap<Uuid, Vec<Uuid>>,
+    threads: Threads,
+    active_events: ActiveEvents<(Uuid, DID)>,
+    config: config::Config,
 
     messaging_stream: SubscriptionStream,
     event_stream: SubscriptionStream,
@@ -336,6 +416,8 @@ pub struct CommunityTask {
 
     attachment_tx: futures::channel::mpsc::Sender<AttachmentOneshot>,
     attachment_rx: futures::channel::mpsc::Receiver<AttachmentOneshot>,
+    thread_index_tx: futures::channel::mpsc::Sender<Vec<Reply>>,
+    thread_index_rx: futures::channel::mpsc::Receiver<Vec<Reply>>,
     event_broadcast: tokio::sync::broadcast::Sender<MessageEventKind>,
     _event_subscription: EventSubscription<RayGunEventKind>,
 
@@ -383,6 +465,9 @@ impl CommunityTask {
         identity: &IdentityStore,
         file: &FileStore,
         discovery: &Discovery,
+        search_index: &SearchIndex,
+        attachment_pins: &AttachmentPins,
+        config: &config::Config,
         command_rx: futures::channel::mpsc::Receiver<CommunityTaskCommand>,
         _event_subscription: EventSubscription<RayGunEventKind>,
     ) -> Result<Self, Error> {
@@ -397,7 +482,15 @@ impl CommunityTask {
 
         let request_stream = ipfs.pubsub_subscribe(request_topic).await?;
 
+        let mut receipts = HashMap::new();
+        for channel_id in document.channels.values().map(|channel| channel.id) {
+            let channel_receipts = Receipts::load(ipfs, root.keypair(), channel_id).await;
+            receipts.insert(channel_id, channel_receipts);
+        }
+        let threads = Threads::load(ipfs, community_id).await;
+
         let (atx, arx) = futures::channel::mpsc::channel(256);
+        let (ttx, trx) = futures::channel::mpsc::channel(1);
         let (btx, _) = tokio::sync::broadcast::channel(1024);
         let mut task = Self {
             community_id,
@@ -409,6 +502,13 @@ impl CommunityTask {
             pending_key_exchange: Default::default(),
             document,
             keystore: Keystore::default(),
+            search_index: search_index.clone(),
+            attachment_pins: attachment_pins.clone(),
+            receipts,
+            pending_receipts: HashMap::new(),
+            threads,
+            active_events: ActiveEvents::default(),
+            config: config.clone(),
 
             messaging_stream,
             request_stream,
@@ -416,6 +516,8 @@ impl CommunityTask {
 
             attachment_tx: atx,
             attachment_rx: arx,
+            thread_index_tx: ttx,
+            thread_index_rx: trx,
             event_broadcast: btx,
             _event_subscription,
             command_rx,
@@ -472,12 +574,21 @@ impl CommunityTask {
 
         let community_id = this.community_id;
 
+        this.index_existing_messages().await;
+        this.index_existing_threads().await;
+
         let mut queue_timer = Delay::new(Duration::from_secs(1));
 
         let mut pending_exchange_timer = Delay::new(Duration::from_secs(1));
 
         let mut check_mailbox = Delay::new(Duration::from_secs(5));
 
+        let mut compaction_timer = Delay::new(Duration::from_secs(5 * 60));
+
+        let mut event_expiry_timer = Delay::new(Duration::from_secs(1));
+
+        let mut receipt_timer = Delay::new(RECEIPT_BATCH_INTERVAL);
+
         loop {
             tokio::select! {
                 biased;
@@ -490,6 +601,9 @@ impl CommunityTask {
                 Some((message, response)) = this.attachment_rx.next() => {
                     let _ = response.send(this.store_direct_for_attachment(message).await);
                 }
+                Some(replies) = this.thread_index_rx.next() => {
+                    this.threads.rebuild(replies).await;
+                }
                 Some(request) = this.request_stream.next() => {
                     let source = request.source;
                     if let Err(e) = process_request_response_event(this, request).await {
@@ -521,12 +635,57 @@ impl CommunityTask {
                     _ = this.load_from_mailbox().await;
                     check_mailbox.reset(Duration::from_secs(60));
                 }
+                _ = &mut compaction_timer => {
+                    if let Err(e) = this.compact_channel_messages().await {
+                        tracing::warn!(%community_id, error = %e, "unable to compact message references");
+                    }
+                    compaction_timer.reset(Duration::from_secs(5 * 60));
+                }
+                _ = &mut event_expiry_timer => {
+                    this.expire_events();
+                    event_expiry_timer.reset(Duration::from_secs(1));
+                }
+                _ = &mut receipt_timer => {
+                    this.send_pending_receipts().await;
+                    receipt_timer.reset(RECEIPT_BATCH_INTERVAL);
+                }
             }
         }
     }
 }
 
 impl CommunityTask {
+    /// Compacts the message references of the first fragmented channel, if any, so that
+    /// channels are compacted incrementally rather than all at once
+    async fn compact_channel_messages(&mut self) -> Result<(), Error> {
+        let community_id = self.community_id;
+        let mut superseded = vec![];
+
+        for (channel_id, channel) in self.document.channels.iter_mut() {
+            superseded = channel
+                .compact_message_references(&self.ipfs, false)
+                .await?;
+            if !superseded.is_empty() {
+                tracing::trace!(%community_id, %channel_id, "message references compacted");
+                break;
+            }
+        }
+
+        if superseded.is_empty() {
+            return Ok(());
+        }
+
+        self.set_document().await?;
+
+        for cid in superseded {
+            if self.ipfs.is_pinned(cid).await.unwrap_or_default() {
+                _ = self.ipfs.remove_pin(cid).recursive().await;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn load_from_mailbox(&mut self) -> Result<(), Error> {
         // let config::Discovery::Shuttle { addresses } = self.discovery.discovery_config().clone()
         // else {
@@ -942,6 +1101,17 @@ impl CommunityTask {
                     .await;
                 let _ = response.send(result);
             }
+            CommunityTaskCommand::GetCommunityChannelThread {
+                channel_id,
+                root_message_id,
+                options,
+                response,
+            } => {
+                let result = self
+                    .get_community_channel_thread(channel_id, root_message_id, options)
+                    .await;
+                let _ = response.send(result);
+            }
             CommunityTaskCommand::GetCommunityChannelMessageCount {
                 channel_id,
                 response,
@@ -979,6 +1149,61 @@ impl CommunityTask {
                     .await;
                 let _ = response.send(result);
             }
+            CommunityTaskCommand::CommunityChannelMessageReceipts {
+                channel_id,
+                message_id,
+                response,
+            } => {
+                let result = self
+                    .community_channel_message_receipts(channel_id, message_id)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::MarkCommunityChannelMessageRead {
+                channel_id,
+                message_id,
+                response,
+            } => {
+                let result = self
+                    .mark_community_channel_message_read(channel_id, message_id)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::MarkCommunityChannelRead {
+                channel_id,
+                message_id,
+                response,
+            } => {
+                let result = self
+                    .mark_community_channel_read(channel_id, message_id)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::GetCommunityChannelLastReadMessage {
+                channel_id,
+                response,
+            } => {
+                let result = self
+                    .get_community_channel_last_read_message(channel_id)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::GetCommunityChannelUnreadCount {
+                channel_id,
+                response,
+            } => {
+                let result = self.get_community_channel_unread_count(channel_id).await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::GetCommunityChannelUnreadMentionCount {
+                channel_id,
+                response,
+            } => {
+                let result = self
+                    .get_community_channel_unread_mention_count(channel_id)
+                    .await;
+                let _ = response.send(result);
+            }
             CommunityTaskCommand::SendCommunityChannelMessage {
                 channel_id,
                 message,
@@ -1011,6 +1236,14 @@ impl CommunityTask {
                     .await;
                 let _ = response.send(result);
             }
+            CommunityTaskCommand::ForwardMessage {
+                channel_id,
+                message,
+                response,
+            } => {
+                let result = self.forward_to_community_channel(channel_id, message).await;
+                let _ = response.send(result);
+            }
             CommunityTaskCommand::DeleteCommunityChannelMessage {
                 channel_id,
                 message_id,
@@ -1044,6 +1277,45 @@ impl CommunityTask {
                     .await;
                 let _ = response.send(result);
             }
+            CommunityTaskCommand::SendCommunityChannelPoll {
+                channel_id,
+                poll,
+                response,
+            } => {
+                let result = self.send_community_channel_poll(channel_id, poll).await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::VoteOnCommunityChannelPoll {
+                channel_id,
+                message_id,
+                options,
+                response,
+            } => {
+                let result = self
+                    .vote_on_community_channel_poll(channel_id, message_id, options)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::RetractCommunityChannelPollVote {
+                channel_id,
+                message_id,
+                response,
+            } => {
+                let result = self
+                    .retract_community_channel_poll_vote(channel_id, message_id)
+                    .await;
+                let _ = response.send(result);
+            }
+            CommunityTaskCommand::CloseCommunityChannelPoll {
+                channel_id,
+                message_id,
+                response,
+            } => {
+                let result = self
+                    .close_community_channel_poll(channel_id, message_id)
+                    .await;
+                let _ = response.send(result);
+            }
             CommunityTaskCommand::SendCommunityChannelMesssageEvent {
                 channel_id,
                 event,
@@ -1122,6 +1394,10 @@ impl CommunityTask {
 
         self.document.deleted = true;
         self.set_document().await?;
+        for receipts in self.receipts.values_mut() {
+            receipts.clear().await;
+        }
+        self.threads.clear().await;
         if let Ok(mut ks_map) = self.root.get_keystore_map().await {
             if ks_map.remove(&self.community_id.to_string()).is_some() {
                 if let Err(e) = self.root.set_keystore_map(ks_map).await {
@@ -1233,6 +1509,14 @@ impl CommunityTask {
 
         let id = self.community_id;
 
+        // Note: Someone accepting an invite that is open to anyone is not a participant until their event is processed
+        if !self.document.participants().contains(&sender)
+            && !self.document.has_valid_invite(&sender)
+        {
+            tracing::warn!(id = %id, %sender, "sender is not in community");
+            return Err(Error::IdentityDoesntExist);
+        }
+
         let bytes = {
             let key = match self.keystore.get_latest(keypair, &sender) {
                 Ok(key) => key,
@@ -1245,14 +1529,7 @@ impl CommunityTask {
                     //       however, we may want to eventually validate the data to ensure it havent been tampered in some way
                     //       while waiting for the response.
 
-                    self.pending_key_exchange
-                        .entry(sender)
-                        .or_default()
-                        .push((data.message(None)?, false));
-
-                    // Maybe send a request? Although we could, we should check to determine if one was previously sent or queued first,
-                    // but for now we can leave this commented until the queue is removed and refactored.
-                    // _ = self.request_key(id, &data.sender()).await;
+                    self.set_aside_payload(sender, data.message(None)?).await;
 
                     // Note: We will mark this as `Ok` since this is pending request to be resolved
                     return Ok(());
@@ -1263,7 +1540,19 @@ impl CommunityTask {
                 }
             };
 
-            Cipher::direct_decrypt(&data.message(None)?, &key)?
+            let message = data.message(None)?;
+            match Cipher::direct_decrypt(&message, &key) {
+                Ok(bytes) => bytes,
+                // The payload may have been encrypted with a previous key if it was delayed, or with a
+                // rotated key that we have yet to receive, in which case it is set aside until the key arrives
+                Err(_) => match self.keystore.try_decrypt(keypair, &sender, &message) {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        self.set_aside_payload(sender, message).await;
+                        return Ok(());
+                    }
+                },
+            }
         };
 
         let event = serde_json::from_slice::<CommunityMessagingEvents>(&bytes).map_err(|e| {
@@ -1276,6 +1565,23 @@ impl CommunityTask {
         Ok(())
     }
 
+    /// Sets aside a payload until the key of `sender` is received, requesting the key from them if no other
+    /// payload was pending so a key that was lost, such as one from a rotation, is still received
+    async fn set_aside_payload(&mut self, sender: DID, data: Vec<u8>) {
+        let id = self.community_id;
+        if queue_pending_payload(&mut self.pending_key_exchange, id, sender.clone(), data)
+            && self.document.participants().contains(&sender)
+        {
+            let history = self
+                .keystore
+                .get_latest(self.root.keypair(), &sender)
+                .is_err();
+            if let Err(e) = self.request_key(&sender, history).await {
+                tracing::warn!(id = %id, %sender, error = %e, "unable to request key");
+            }
+        }
+    }
+
     fn community_key(&self, member: Option<&DID>) -> Result<Vec<u8>, Error> {
         let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
@@ -1285,10 +1591,108 @@ impl CommunityTask {
         self.keystore.get_latest(keypair, recipient)
     }
 
-    async fn request_key(&mut self, did: &DID) -> Result<(), Error> {
+    /// Sends the latest key of our messages in the community to `did`. If `history` is true, up to [`MAX_KEY_HISTORY`]
+    /// keys replaced by a rotation are included so a member that joined afterwards is still able to read earlier messages
+    async fn send_key(&mut self, did: &DID, history: bool) -> Result<(), Error> {
+        let community_id = self.community_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut keys = self.keystore.get_all(keypair, &own_did)?;
+        let raw_key = keys.pop().ok_or(Error::PublicKeyDoesntExist)?;
+        let key = ecdh_encrypt(keypair, Some(did), raw_key)?;
+        let previous = match history {
+            true => keys
+                .iter()
+                .skip(keys.len().saturating_sub(MAX_KEY_HISTORY))
+                .map(|key| ecdh_encrypt(keypair, Some(did), key))
+                .collect::<Result<Vec<_>, _>>()?,
+            false => vec![],
+        };
+
+        let response = ConversationRequestResponse::Response {
+            conversation_id: community_id,
+            kind: ConversationResponseKind::Key { key, previous },
+        };
+
+        let topic = self.document.exchange_topic(did);
+
+        let bytes = ecdh_encrypt(keypair, Some(did), serde_json::to_vec(&response)?)?;
+
+        let payload = PayloadBuilder::new(keypair, bytes)
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let peers = self.ipfs.pubsub_peers(Some(topic.clone())).await?;
+
+        let peer_id = did.to_peer_id()?;
+
+        let bytes = payload.to_bytes()?;
+
+        tracing::trace!(%community_id, "Payload size: {} bytes", bytes.len());
+
+        if !peers.contains(&peer_id)
+            || (peers.contains(&peer_id)
+                && self
+                    .ipfs
+                    .pubsub_publish(topic.clone(), bytes)
+                    .await
+                    .is_err())
+        {
+            tracing::warn!(%community_id, "Unable to publish to topic. Queuing event");
+            self.queue_event(
+                did.clone(),
+                QueueItem::direct(
+                    None,
+                    peer_id,
+                    topic.clone(),
+                    payload.message(None)?.to_vec(),
+                ),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Generates a new key for our messages in the community and distributes it to the remaining participants,
+    /// so members that were removed or have left are unable to decrypt anything sent afterwards.
+    /// Previous keys are kept so existing messages remain readable. Only the new key is distributed, since the
+    /// participants already hold the previous ones, while members joining afterwards request them along with it
+    async fn rotate_key(&mut self) -> Result<(), Error> {
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        self.keystore.insert(keypair, &own_did, generate::<64>())?;
+        self.set_keystore(None).await?;
+
+        tracing::info!(community_id = %self.community_id, "Rotated community key");
+
+        let participants = self
+            .document
+            .participants()
+            .into_iter()
+            .filter(|did| own_did.ne(did))
+            .collect::<Vec<_>>();
+
+        for participant in participants {
+            if let Err(e) = self.send_key(&participant, false).await {
+                tracing::warn!(community_id = %self.community_id, error = %e, %participant, "Failed to send rotated key");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn request_key(&mut self, did: &DID, history: bool) -> Result<(), Error> {
+        let kind = match history {
+            true => ConversationRequestKind::KeyHistory,
+            false => ConversationRequestKind::Key,
+        };
+
         let request = ConversationRequestResponse::Request {
             conversation_id: self.community_id,
-            kind: ConversationRequestKind::Key,
+            kind,
         };
 
         let community = &self.document;
@@ -1336,6 +1740,22 @@ impl CommunityTask {
         Ok(())
     }
 
+    /// Cancel custom events from other members that were not refreshed within their ttl
+    fn expire_events(&mut self) {
+        let community_id = self.community_id;
+        for ((community_channel_id, did_key), event) in self.active_events.expired() {
+            let ev = MessageEventKind::CommunityEventCancelled {
+                community_id,
+                community_channel_id,
+                did_key,
+                event,
+            };
+            if let Err(e) = self.event_broadcast.send(ev) {
+                tracing::error!(%community_id, error = %e, "error broadcasting event");
+            }
+        }
+    }
+
     pub async fn send_message_event(&self, event: CommunityMessagingEvents) -> Result<(), Error> {
         let event = serde_json::to_vec(&event)?;
 
@@ -1632,7 +2052,7 @@ impl CommunityTask {
                 },
             )
             .await?;
-            if let Err(_e) = self.request_key(&did_key.clone()).await {}
+            if let Err(_e) = self.request_key(&did_key.clone(), false).await {}
         }
 
         Ok(CommunityInvite::from(invite_doc))
@@ -2031,6 +2451,9 @@ impl CommunityTask {
         self.document.channels.swap_remove(&channel_id.to_string());
         self.set_document().await?;
 
+        self.search_index.remove_conversation(channel_id).await;
+        self.remove_channel_receipts(channel_id).await;
+
         let _ = self
             .event_broadcast
             .send(MessageEventKind::DeletedCommunityChannel {
@@ -2067,7 +2490,10 @@ impl CommunityTask {
             .channels
             .get(&channel_id.to_string())
             .ok_or(Error::CommunityChannelDoesntExist)?;
-        Ok(CommunityChannel::from(channel_doc.clone()))
+        let mut channel = CommunityChannel::from(channel_doc.clone());
+        let settings = self.root.get_notification_settings(channel_id).await?;
+        channel.set_notification_settings(settings);
+        Ok(channel)
     }
 
     pub async fn edit_community_name(&mut self, name: String) -> Result<(), Error> {
@@ -2339,7 +2765,13 @@ impl CommunityTask {
             true,
             vec![],
         )
-        .await
+        .await?;
+
+        if let Err(e) = self.rotate_key().await {
+            tracing::warn!(community_id = %self.community_id, error = %e, "Failed to rotate key");
+        }
+
+        Ok(())
     }
 
     pub async fn edit_community_channel_name(
@@ -2639,14 +3071,18 @@ impl CommunityTask {
         let keypair = self.root.keypair();
         let keystore = pubkey_or_keystore(self)?;
 
-        match self.document.channels.get(&channel_id.to_string()) {
+        let mut message = match self.document.channels.get(&channel_id.to_string()) {
             Some(channel) => {
                 channel
                     .get_message(&self.ipfs, keypair, message_id, keystore.as_ref())
-                    .await
+                    .await?
             }
-            None => Err(Error::CommunityChannelDoesntExist),
-        }
+            None => return Err(Error::CommunityChannelDoesntExist),
+        };
+
+        self.threads.apply(&mut message);
+
+        Ok(message)
     }
     pub async fn get_community_channel_messages(
         &self,
@@ -2665,8 +3101,8 @@ impl CommunityTask {
         let keypair = self.root.keypair();
         let keystore = pubkey_or_keystore(self)?;
 
-        match self.document.channels.get(&channel_id.to_string()) {
-            None => Err(Error::CommunityChannelDoesntExist),
+        let messages = match self.document.channels.get(&channel_id.to_string()) {
+            None => return Err(Error::CommunityChannelDoesntExist),
             Some(channel) => {
                 let m_type = options.messages_type();
                 match m_type {
@@ -2674,22 +3110,67 @@ impl CommunityTask {
                         let stream = channel
                             .get_messages_stream(&self.ipfs, keypair, options, keystore)
                             .await?;
-                        Ok(Messages::Stream(stream))
+                        Messages::Stream(stream)
                     }
                     MessagesType::List => {
                         let list = channel
                             .get_messages(&self.ipfs, keypair, options, keystore)
                             .await?;
-                        Ok(Messages::List(list))
+                        Messages::List(list)
                     }
                     MessagesType::Pages { .. } => {
                         channel
                             .get_messages_pages(&self.ipfs, keypair, options, keystore.as_ref())
-                            .await
+                            .await?
                     }
                 }
             }
-        }
+        };
+
+        Ok(self.threads.apply_all(messages))
+    }
+    pub async fn get_community_channel_thread(
+        &self,
+        channel_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(self)?;
+
+        let channel = self
+            .document
+            .channels
+            .get(&channel_id.to_string())
+            .ok_or(Error::CommunityChannelDoesntExist)?;
+
+        // Confirm that the root message exists within the channel
+        channel
+            .get_message_document(&self.ipfs, root_message_id)
+            .await?;
+
+        let mut documents = vec![];
+        for message_id in self.threads.replies(root_message_id) {
+            match channel.get_message_document(&self.ipfs, message_id).await {
+                Ok(document) => documents.push(document),
+                Err(e) => {
+                    tracing::warn!(%channel_id, %message_id, error = %e, "unable to get reply")
+                }
+            }
+        }
+
+        let messages = resolve_messages(&self.ipfs, keypair, documents, options, keystore).await?;
+
+        Ok(self.threads.apply_all(messages))
     }
     pub async fn get_community_channel_message_count(
         &self,
@@ -2759,6 +3240,64 @@ impl CommunityTask {
         channel_id: Uuid,
         message_id: Uuid,
     ) -> Result<MessageStatus, Error> {
+        let members = self.receipt_members(channel_id, message_id).await?;
+
+        let Some(receipts) = self.receipts.get(&channel_id) else {
+            return Ok(MessageStatus::Sent);
+        };
+
+        // Note: Due to the size of a community, a single member acknowledging the message is
+        //       sufficient for it to be considered delivered or read
+        Ok(receipts.status(message_id, &members, false))
+    }
+    pub async fn community_channel_message_receipts(
+        &self,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        let members = self.receipt_members(channel_id, message_id).await?;
+        let receipts = match self.receipts.get(&channel_id) {
+            Some(receipts) => receipts.receipts(message_id, &members),
+            None => members
+                .into_iter()
+                .map(|did| MessageReceipt::new(did, None, None))
+                .collect(),
+        };
+        Ok(receipts)
+    }
+    pub async fn mark_community_channel_message_read(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        let channel = match self.document.channels.get(&channel_id.to_string()) {
+            None => return Err(Error::CommunityChannelDoesntExist),
+            Some(c) => c,
+        };
+
+        let message = channel.get_message_document(&self.ipfs, message_id).await?;
+
+        if message.sender.to_did().eq(own_did) {
+            return Ok(());
+        }
+
+        self.send_receipt(channel_id, message_id, ReceiptKind::Read)
+            .await
+    }
+    pub async fn mark_community_channel_read(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
         let own_did = &self.identity.did_key();
         if !self.document.has_channel_permission(
             own_did,
@@ -2773,38 +3312,285 @@ impl CommunityTask {
             Some(c) => c,
         };
 
-        let messages = channel.get_message_list(&self.ipfs).await?;
+        let message = channel.get_message_document(&self.ipfs, message_id).await?;
+        let marker = ReadMarker::new(&message);
+
+        if let Some(current) = self.root.get_read_marker(channel_id).await? {
+            if !marker.is_ahead_of(&current) {
+                return Ok(());
+            }
+        }
+
+        self.root.set_read_marker(channel_id, marker).await?;
+
+        if self.config.store_setting().sync_read_markers {
+            if let Err(e) = self.identity.export_root_document().await {
+                tracing::warn!(id = %self.community_id, %channel_id, error = %e, "unable to sync read marker");
+            }
+        }
+
+        Ok(())
+    }
+    pub async fn get_community_channel_last_read_message(
+        &self,
+        channel_id: Uuid,
+    ) -> Result<Option<Uuid>, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        if !self.document.channels.contains_key(&channel_id.to_string()) {
+            return Err(Error::CommunityChannelDoesntExist);
+        }
+
+        let marker = self.root.get_read_marker(channel_id).await?;
+        Ok(marker.map(|marker| marker.message_id))
+    }
+    pub async fn get_community_channel_unread_count(
+        &self,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        let channel = match self.document.channels.get(&channel_id.to_string()) {
+            None => return Err(Error::CommunityChannelDoesntExist),
+            Some(c) => c,
+        };
 
-        if !messages.iter().any(|document| document.id == message_id) {
-            return Err(Error::MessageNotFound);
+        let marker = self.root.get_read_marker(channel_id).await?;
+        let list = channel.message_reference_list(&self.ipfs).await?;
+        Ok(read_marker::unread_count(&self.ipfs, &list, marker, own_did).await)
+    }
+    pub async fn get_community_channel_unread_mention_count(
+        &self,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
         }
 
-        let _list = self
+        let channel = match self.document.channels.get(&channel_id.to_string()) {
+            None => return Err(Error::CommunityChannelDoesntExist),
+            Some(c) => c,
+        };
+
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(self)?;
+        let marker = self.root.get_read_marker(channel_id).await?;
+        let list = channel.message_reference_list(&self.ipfs).await?;
+        Ok(read_marker::unread_mention_count(
+            &self.ipfs,
+            keypair,
+            &list,
+            marker,
+            own_did,
+            &self.own_roles(),
+            keystore.as_ref(),
+        )
+        .await)
+    }
+
+    /// Roles assigned to own identity
+    fn own_roles(&self) -> Vec<RoleId> {
+        let own_did = self.identity.did_key();
+        self.document
+            .roles
+            .values()
+            .filter(|role| role.members.contains(&own_did))
+            .map(|role| role.id)
+            .collect()
+    }
+
+    /// Parses mentions of the community members and roles from `lines`, dropping any mass mentions
+    /// if own identity is not permitted to use them
+    async fn parse_mentions(&self, lines: &[String]) -> Vec<Mention> {
+        let own_did = self.identity.did_key();
+        let members = self
             .document
             .participants()
-            .iter()
+            .into_iter()
             .filter(|did| own_did.ne(did))
+            .collect::<Vec<_>>();
+        let members = self.identity.cached_identities(&members).await;
+        let roles = self
+            .document
+            .roles
+            .values()
+            .map(|role| (role.id, role.name.clone()))
+            .collect::<Vec<_>>();
+
+        let mut mentions = mention::parse_mentions(lines, &members, &roles);
+
+        if !self
+            .document
+            .has_permission(&own_did, &CommunityPermission::MentionEveryone)
+        {
+            mentions.retain(|mention| !mention.is_mass_mention());
+        }
+
+        mentions
+    }
+
+    /// Returns true if a message from `sender` mentions own identity.
+    /// Mass mentions are ignored if the sender is not permitted to use them
+    fn is_mentioned(&self, sender: &DID, mentions: &[Mention]) -> bool {
+        let own_did = self.identity.did_key();
+        if sender.eq(&own_did) {
+            return false;
+        }
+
+        let can_mention_everyone = self
+            .document
+            .has_permission(sender, &CommunityPermission::MentionEveryone);
+
+        let mentions = mentions
+            .iter()
+            .filter(|mention| can_mention_everyone || !mention.is_mass_mention())
             .cloned()
             .collect::<Vec<_>>();
 
-        // TODO:
-        // for peer in list {
-        //     if let Some(list) = self.queue.get(&peer) {
-        //         for item in list {
-        //             let Queue { id, m_id, .. } = item;
-        //             if self.document.id() == *id {
-        //                 if let Some(m_id) = m_id {
-        //                     if message_id == *m_id {
-        //                         return Ok(MessageStatus::NotSent);
-        //                     }
-        //                 }
-        //             }
-        //         }
-        //     }
-        // }
+        mention::is_mentioned(&mentions, &own_did, &self.own_roles())
+    }
+    /// Returns true if a received message should notify, based on the notification settings of the channel
+    async fn should_notify(&self, channel_id: Uuid, mentioned: bool) -> bool {
+        self.root
+            .get_notification_settings(channel_id)
+            .await
+            .unwrap_or_default()
+            .should_notify(mentioned)
+    }
+    /// Members of the channel that are expected to acknowledge the message
+    async fn receipt_members(&self, channel_id: Uuid, message_id: Uuid) -> Result<Vec<DID>, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        let channel = match self.document.channels.get(&channel_id.to_string()) {
+            None => return Err(Error::CommunityChannelDoesntExist),
+            Some(c) => c,
+        };
+
+        let message = channel.get_message_document(&self.ipfs, message_id).await?;
+        let sender = &message.sender.to_did();
+
+        let members = self
+            .document
+            .members
+            .iter()
+            .filter(|did| sender.ne(did))
+            .filter(|did| {
+                self.document.has_channel_permission(
+                    did,
+                    &CommunityChannelPermission::ViewChannel,
+                    channel_id,
+                )
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(members)
+    }
+    async fn send_receipt(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+        kind: ReceiptKind,
+    ) -> Result<(), Error> {
+        if kind == ReceiptKind::Read && self.config.store_setting().disable_read_receipts {
+            return Ok(());
+        }
+
+        let own_did = self.identity.did_key();
+        let date = Utc::now();
+
+        if !self
+            .channel_receipts(channel_id)
+            .await
+            .insert(message_id, &own_did, kind, date)
+            .await
+        {
+            return Ok(());
+        }
+
+        // Delivery receipts are sent together to avoid an event per message per member
+        if kind == ReceiptKind::Delivered {
+            self.pending_receipts
+                .entry(channel_id)
+                .or_default()
+                .push(message_id);
+            return Ok(());
+        }
+
+        let event = CommunityMessagingEvents::Receipt {
+            community_id: self.community_id,
+            channel_id,
+            member: own_did,
+            message_ids: vec![message_id],
+            kind,
+            date,
+        };
+
+        self.send_message_event(event).await
+    }
+    async fn send_pending_receipts(&mut self) {
+        let community_id = self.community_id;
+        let pending = std::mem::take(&mut self.pending_receipts);
+        let own_did = self.identity.did_key();
+        let date = Utc::now();
+
+        for (channel_id, message_ids) in pending {
+            for message_ids in message_ids.chunks(MAX_RECEIPT_BATCH) {
+                let event = CommunityMessagingEvents::Receipt {
+                    community_id,
+                    channel_id,
+                    member: own_did.clone(),
+                    message_ids: message_ids.to_vec(),
+                    kind: ReceiptKind::Delivered,
+                    date,
+                };
 
-        //Not a guarantee that it been sent but for now since the message exist locally and not marked in queue, we will assume it have been sent
-        Ok(MessageStatus::Sent)
+                if let Err(e) = self.send_message_event(event).await {
+                    tracing::warn!(%community_id, %channel_id, error = %e, "unable to send delivery receipts");
+                }
+            }
+        }
+    }
+    /// Receipts of the channel, loading them if the channel was added after the task started
+    async fn channel_receipts(&mut self, channel_id: Uuid) -> &mut Receipts {
+        if !self.receipts.contains_key(&channel_id) {
+            let receipts = Receipts::load(&self.ipfs, self.root.keypair(), channel_id).await;
+            self.receipts.insert(channel_id, receipts);
+        }
+        self.receipts
+            .get_mut(&channel_id)
+            .expect("receipts are loaded")
+    }
+    async fn remove_channel_receipts(&mut self, channel_id: Uuid) {
+        self.pending_receipts.remove(&channel_id);
+        self.channel_receipts(channel_id).await.clear().await;
+        self.receipts.remove(&channel_id);
     }
     pub async fn send_community_channel_message(
         &mut self,
@@ -2849,6 +3635,8 @@ impl CommunityTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
 
@@ -2856,6 +3644,7 @@ impl CommunityTask {
         message.set_conversation_id(channel_id);
         message.set_sender(own_did.clone());
         message.set_lines(messages.clone());
+        message.set_mentions(mentions);
 
         let message_id = message.id();
         let keystore = pubkey_or_keystore(&*self)?;
@@ -2875,6 +3664,9 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -2957,6 +3749,8 @@ impl CommunityTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
 
         let keystore = pubkey_or_keystore(&*self)?;
@@ -2981,6 +3775,7 @@ impl CommunityTask {
         }
 
         message.lines_mut().clone_from(&messages);
+        message.set_mentions(mentions);
         message.set_modified(Utc::now());
 
         message_document
@@ -2998,6 +3793,8 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        self.index_message(&message_document).await;
+
         let _ = tx.send(MessageEventKind::CommunityMessageEdited {
             community_id: self.community_id,
             channel_id,
@@ -3010,6 +3807,7 @@ impl CommunityTask {
             message_id,
             modified: message_document.modified.expect("message to be modified"),
             lines: messages,
+            mentions: message_document.mentions.clone(),
             nonce: nonce.to_vec(),
             signature: signature.into(),
         };
@@ -3034,6 +3832,93 @@ impl CommunityTask {
 
         self.publish(None, event, true, vec![]).await
     }
+    pub async fn forward_to_community_channel(
+        &mut self,
+        channel_id: Uuid,
+        forwarded: ForwardedMessage,
+    ) -> Result<Uuid, Error> {
+        let own_did = &self.identity.did_key();
+        if !self.document.has_channel_permission(
+            own_did,
+            &CommunityChannelPermission::SendMessages,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        if !forwarded.attachments.is_empty()
+            && !self.document.has_channel_permission(
+                own_did,
+                &CommunityChannelPermission::SendAttachments,
+                channel_id,
+            )
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        if !self.document.channels.contains_key(&channel_id.to_string()) {
+            return Err(Error::CommunityChannelDoesntExist);
+        }
+
+        forwarded.validate()?;
+
+        let ForwardedMessage {
+            lines,
+            attachments,
+            origin,
+        } = forwarded;
+
+        let keypair = self.root.keypair();
+
+        let mut message = warp::raygun::Message::default();
+        message.set_conversation_id(channel_id);
+        message.set_sender(own_did.clone());
+        message.set_lines(lines);
+        if !attachments.is_empty() {
+            message.set_message_type(MessageType::Attachment);
+        }
+
+        let message_id = message.id();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message =
+            MessageDocument::forward(keypair, message, attachments, origin, keystore.as_ref())?;
+
+        let channel = match self.document.channels.get_mut(&channel_id.to_string()) {
+            Some(c) => c,
+            None => return Err(Error::CommunityChannelDoesntExist),
+        };
+
+        channel
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
+        let event = MessageEventKind::CommunityMessageSent {
+            community_id: self.community_id,
+            channel_id,
+            message_id,
+        };
+
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%channel_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = CommunityMessagingEvents::New {
+            community_id: self.community_id,
+            channel_id,
+            message,
+        };
+
+        self.publish(Some(message_id), event, true, vec![])
+            .await
+            .map(|_| message_id)
+    }
+
     pub async fn reply_to_community_channel_message(
         &mut self,
         channel_id: Uuid,
@@ -3076,6 +3961,8 @@ impl CommunityTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
 
         let own_did = self.identity.did_key();
@@ -3084,6 +3971,7 @@ impl CommunityTask {
         message.set_conversation_id(channel_id);
         message.set_sender(own_did.clone());
         message.set_lines(messages);
+        message.set_mentions(mentions);
         message.set_replied(Some(message_id));
 
         let keystore = pubkey_or_keystore(&*self)?;
@@ -3105,6 +3993,9 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -3169,10 +4060,26 @@ impl CommunityTask {
             None => return Err(Error::CommunityChannelDoesntExist),
         };
 
+        let cids = channel
+            .get_message_document(&self.ipfs, message_id)
+            .await
+            .map(|document| pins::attachment_cids(document.attachments()))
+            .unwrap_or_default();
+
         channel.delete_message(&self.ipfs, message_id).await?;
 
         self.set_document().await?;
 
+        let released = self.attachment_pins.release(message_id, cids).await;
+        self.attachment_pins.unpin(released).await;
+
+        self.search_index.remove(message_id).await;
+        self.channel_receipts(channel_id)
+            .await
+            .remove(message_id)
+            .await;
+        self.remove_from_thread(channel_id, message_id).await;
+
         // if let config::Discovery::Shuttle { addresses } = self.discovery.discovery_config() {
         //     for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
         //         let _ = self
@@ -3444,6 +4351,7 @@ impl CommunityTask {
         channel_id: Uuid,
         event: MessageEvent,
     ) -> Result<(), Error> {
+        event.validate()?;
         let own_did = &self.identity.did_key();
         if !self.document.has_channel_permission(
             own_did,
@@ -3455,17 +4363,235 @@ impl CommunityTask {
         let event = CommunityMessagingEvents::Event {
             community_id: self.community_id,
             channel_id,
-            member: own_did.clone(),
-            event,
-            cancelled: false,
+            member: own_did.clone(),
+            event,
+            cancelled: false,
+        };
+        self.send_message_event(event).await
+    }
+    pub async fn send_community_channel_poll(
+        &mut self,
+        channel_id: Uuid,
+        poll: warp::raygun::Poll,
+    ) -> Result<Uuid, Error> {
+        let own_did = self.identity.did_key();
+        if !self.document.has_channel_permission(
+            &own_did,
+            &CommunityChannelPermission::SendMessages,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        poll::validate_poll(&poll)?;
+
+        let keypair = self.root.keypair();
+
+        let mut message = warp::raygun::Message::default();
+        message.set_message_type(MessageType::Poll);
+        message.set_conversation_id(channel_id);
+        message.set_sender(own_did);
+        message.set_lines(vec![poll.question().to_string()]);
+        message.set_poll(Some(poll));
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message = MessageDocument::new(&self.ipfs, keypair, message, keystore.as_ref()).await?;
+
+        let channel = match self.document.channels.get_mut(&channel_id.to_string()) {
+            Some(c) => c,
+            None => return Err(Error::CommunityChannelDoesntExist),
+        };
+
+        channel
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+
+        let message_id = message.id;
+
+        let event = MessageEventKind::CommunityMessageSent {
+            community_id: self.community_id,
+            channel_id,
+            message_id,
+        };
+
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%channel_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = CommunityMessagingEvents::New {
+            community_id: self.community_id,
+            channel_id,
+            message,
+        };
+
+        self.publish(Some(message_id), event, true, vec![])
+            .await
+            .map(|_| message_id)
+    }
+    pub async fn vote_on_community_channel_poll(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        if options.is_empty() {
+            return Err(Error::InvalidLength {
+                context: "options".into(),
+                current: 0,
+                minimum: Some(1),
+                maximum: None,
+            });
+        }
+        self.submit_community_channel_poll_vote(channel_id, message_id, options)
+            .await
+    }
+    pub async fn retract_community_channel_poll_vote(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.submit_community_channel_poll_vote(channel_id, message_id, vec![])
+            .await
+    }
+    async fn submit_community_channel_poll_vote(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        let community_id = self.community_id;
+        let own_did = self.identity.did_key();
+        if !self.document.has_channel_permission(
+            &own_did,
+            &CommunityChannelPermission::ViewChannel,
+            channel_id,
+        ) {
+            return Err(Error::Unauthorized);
+        }
+
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let channel = match self.document.channels.get_mut(&channel_id.to_string()) {
+            Some(c) => c,
+            None => return Err(Error::CommunityChannelDoesntExist),
+        };
+
+        let mut message_document = channel.get_message_document(&self.ipfs, message_id).await?;
+
+        let message = message_document
+            .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+            .await?;
+
+        let poll = message.poll().ok_or(Error::InvalidMessage)?;
+
+        if poll.is_closed() {
+            return Err(Error::OtherWithContext("poll is closed".into()));
+        }
+
+        if options.is_empty() && !poll.votes().contains_key(&own_did) {
+            return Err(Error::OtherWithContext("no vote to retract".into()));
+        }
+
+        let vote = PollVoteDocument::new(keypair, channel_id, message_id, options.clone())?;
+
+        message_document.vote(poll, vote.clone())?;
+
+        channel
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        let event = match options.is_empty() {
+            true => MessageEventKind::CommunityPollVoteRetracted {
+                community_id,
+                channel_id,
+                message_id,
+                did_key: own_did,
+            },
+            false => MessageEventKind::CommunityPollVoted {
+                community_id,
+                channel_id,
+                message_id,
+                did_key: own_did,
+                options,
+            },
+        };
+
+        _ = self.event_broadcast.send(event);
+
+        let event = CommunityMessagingEvents::Vote {
+            community_id,
+            channel_id,
+            message_id,
+            vote,
+        };
+
+        self.publish(None, event, true, vec![]).await
+    }
+    pub async fn close_community_channel_poll(
+        &mut self,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        let community_id = self.community_id;
+        let own_did = self.identity.did_key();
+        let keypair = self.root.keypair();
+
+        let channel = match self.document.channels.get_mut(&channel_id.to_string()) {
+            Some(c) => c,
+            None => return Err(Error::CommunityChannelDoesntExist),
+        };
+
+        let mut message_document = channel.get_message_document(&self.ipfs, message_id).await?;
+
+        if message_document.sender.to_did() != own_did {
+            return Err(Error::Unauthorized);
+        }
+
+        if message_document.poll_closed.is_some() {
+            return Err(Error::OtherWithContext("poll is closed".into()));
+        }
+
+        let close = PollCloseDocument::new(keypair, channel_id, message_id)?;
+
+        message_document.close_poll(close.clone())?;
+
+        channel
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        _ = self
+            .event_broadcast
+            .send(MessageEventKind::CommunityPollClosed {
+                community_id,
+                channel_id,
+                message_id,
+            });
+
+        let event = CommunityMessagingEvents::ClosePoll {
+            community_id,
+            channel_id,
+            message_id,
+            close,
         };
-        self.send_message_event(event).await
+
+        self.publish(None, event, true, vec![]).await
     }
     pub async fn cancel_community_channel_messsage_event(
         &mut self,
         channel_id: Uuid,
         event: MessageEvent,
     ) -> Result<(), Error> {
+        event.validate()?;
         let member = self.identity.did_key();
         let event = CommunityMessagingEvents::Event {
             community_id: self.community_id,
@@ -3623,6 +4749,9 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -3737,6 +4866,133 @@ impl CommunityTask {
         self.save_queue().await
     }
 
+    /// Collect the replies of the existing messages of the channels in the background if the threads were not
+    /// previously stored
+    async fn index_existing_threads(&self) {
+        let community_id = self.community_id;
+
+        if self.threads.is_loaded() {
+            return;
+        }
+
+        let mut references = vec![];
+        for channel in self.document.channels.values() {
+            match channel.message_reference_list(&self.ipfs).await {
+                Ok(refs) => references.push(refs),
+                Err(e) => {
+                    tracing::warn!(%community_id, channel_id = %channel.id, error = %e, "unable to index existing threads")
+                }
+            }
+        }
+
+        let ipfs = self.ipfs.clone();
+        let mut tx = self.thread_index_tx.clone();
+        LocalExecutor.dispatch(async move {
+            let mut replies = vec![];
+            for refs in &references {
+                replies.extend(threads::collect_replies(&ipfs, refs).await);
+            }
+            _ = tx.send(replies).await;
+        });
+    }
+
+    /// Record the message as a reply to its thread, if it is a reply
+    async fn add_to_thread(&mut self, message: &MessageDocument) {
+        let Some(root) = message.replied else {
+            return;
+        };
+
+        if !self.threads.insert(root, message.id, message.date).await {
+            return;
+        }
+
+        self.broadcast_thread_update(message.conversation_id, root);
+    }
+
+    async fn remove_from_thread(&mut self, channel_id: Uuid, message_id: Uuid) {
+        if let Some(root) = self.threads.remove(message_id).await {
+            self.broadcast_thread_update(channel_id, root);
+        }
+    }
+
+    fn broadcast_thread_update(&self, channel_id: Uuid, message_id: Uuid) {
+        let community_id = self.community_id;
+        let (reply_count, last_reply) = self.threads.summary(message_id);
+        if let Err(e) = self
+            .event_broadcast
+            .send(MessageEventKind::CommunityThreadUpdated {
+                community_id,
+                channel_id,
+                message_id,
+                reply_count,
+                last_reply,
+            })
+        {
+            tracing::warn!(%community_id, error = %e, "Error broadcasting event");
+        }
+    }
+
+    /// Index the existing messages of the channels in the background, resolving them as they are indexed
+    async fn index_existing_messages(&self) {
+        let community_id = self.community_id;
+
+        let Ok(keystore) = pubkey_or_keystore(self) else {
+            return;
+        };
+
+        for channel in self.document.channels.values() {
+            let channel_id = channel.id;
+
+            if self.search_index.is_indexed(channel_id).await {
+                continue;
+            }
+
+            let messages = match channel
+                .get_messages_stream(
+                    &self.ipfs,
+                    self.root.keypair(),
+                    MessageOptions::default(),
+                    keystore.clone(),
+                )
+                .await
+            {
+                Ok(messages) => messages,
+                Err(e) => {
+                    tracing::warn!(%community_id, %channel_id, error = %e, "unable to index existing messages");
+                    continue;
+                }
+            };
+
+            let search_index = self.search_index.clone();
+            LocalExecutor.dispatch(async move {
+                search_index
+                    .index_conversation(channel_id, Some(community_id), messages)
+                    .await;
+            });
+        }
+    }
+
+    async fn index_message(&self, message: &MessageDocument) {
+        let community_id = self.community_id;
+        let keystore = match pubkey_or_keystore(self) {
+            Ok(keystore) => keystore,
+            Err(e) => {
+                tracing::warn!(%community_id, error = %e, "unable to index message");
+                return;
+            }
+        };
+
+        match message
+            .resolve(&self.ipfs, self.root.keypair(), true, keystore.as_ref())
+            .await
+        {
+            Ok(message) => self.search_index.insert(Some(community_id), &message).await,
+            Err(e) => {
+                tracing::warn!(%community_id, message_id = %message.id, error = %e, "unable to index message")
+            }
+        }
+    }
+
     async fn save_queue(&self) {
         let key = format!("{}/{}", self.ipfs.messaging_queue(), self.community_id);
         let current_cid = self
@@ -3798,12 +5054,16 @@ async fn message_event(
         CommunityMessagingEvents::New {
             community_id,
             channel_id,
-            message,
+            mut message,
         } => {
             if !message.verify() {
                 return Err(Error::InvalidMessage);
             }
 
+            // Votes are only accepted through their own events once the poll is received
+            message.votes.clear();
+            message.poll_closed = None;
+
             let message_id = message.id;
 
             if !this
@@ -3854,16 +5114,50 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.search_index
+                .insert(Some(community_id), &resolved_message)
+                .await;
+            this.add_to_thread(&message).await;
+
+            let is_own_message = resolved_message.sender().eq(&this.identity.did_key());
+            let mentioned =
+                this.is_mentioned(resolved_message.sender(), resolved_message.mentions());
+            let should_notify = !is_own_message && this.should_notify(channel_id, mentioned).await;
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::CommunityMessageReceived {
                     community_id,
                     channel_id,
                     message_id,
+                    should_notify,
                 })
             {
                 tracing::warn!(%channel_id, "Error broadcasting event: {e}");
             }
+
+            if mentioned {
+                if let Err(e) = this
+                    .event_broadcast
+                    .send(MessageEventKind::CommunityMentioned {
+                        community_id,
+                        channel_id,
+                        message_id,
+                        should_notify,
+                    })
+                {
+                    tracing::warn!(%channel_id, "Error broadcasting event: {e}");
+                }
+            }
+
+            if message.sender.to_did().ne(&own_did) {
+                if let Err(e) = this
+                    .send_receipt(channel_id, message_id, ReceiptKind::Delivered)
+                    .await
+                {
+                    tracing::warn!(%channel_id, %message_id, error = %e, "unable to send delivery receipt");
+                }
+            }
         }
         CommunityMessagingEvents::Edit {
             community_id,
@@ -3871,6 +5165,7 @@ async fn message_event(
             message_id,
             modified,
             lines,
+            mentions,
             nonce,
             signature,
         } => {
@@ -3906,7 +5201,10 @@ async fn message_event(
                 });
             }
 
+            let previous_mentions = message.mentions().to_vec();
+
             *message.lines_mut() = lines;
+            message.set_mentions(mentions);
             message.set_modified(modified);
 
             let sender = message.sender().to_owned();
@@ -3928,6 +5226,8 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.index_message(&message_document).await;
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::CommunityMessageEdited {
@@ -3938,6 +5238,24 @@ async fn message_event(
             {
                 tracing::error!(%channel_id, error = %e, "Error broadcasting event");
             }
+
+            // Only notify if the edit added a mention
+            if !this.is_mentioned(&sender, &previous_mentions)
+                && this.is_mentioned(&sender, &message_document.mentions)
+            {
+                let should_notify = this.should_notify(channel_id, true).await;
+                if let Err(e) = this
+                    .event_broadcast
+                    .send(MessageEventKind::CommunityMentioned {
+                        community_id,
+                        channel_id,
+                        message_id,
+                        should_notify,
+                    })
+                {
+                    tracing::error!(%channel_id, error = %e, "Error broadcasting event");
+                }
+            }
         }
         CommunityMessagingEvents::Delete {
             community_id,
@@ -3967,6 +5285,13 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.search_index.remove(message_id).await;
+            this.channel_receipts(channel_id)
+                .await
+                .remove(message_id)
+                .await;
+            this.remove_from_thread(channel_id, message_id).await;
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::CommunityMessageDeleted {
@@ -4143,6 +5468,9 @@ async fn message_event(
             match kind {
                 CommunityUpdateKind::LeaveCommunity => {
                     this.replace_document(community).await?;
+                    if let Err(e) = this.rotate_key().await {
+                        tracing::warn!(%community_id, error = %e, "Failed to rotate key");
+                    }
                     if let Err(e) = this
                         .event_broadcast
                         .send(MessageEventKind::LeftCommunity { community_id })
@@ -4156,7 +5484,7 @@ async fn message_event(
                         if !this.discovery.contains(did).await {
                             let _ = this.discovery.insert(did).await;
                         }
-                        if let Err(e) = this.request_key(did).await {
+                        if let Err(e) = this.request_key(did, false).await {
                             tracing::error!(%community_id, error = %e, "error requesting key");
                         }
                     }
@@ -4284,6 +5612,8 @@ async fn message_event(
                 }
                 CommunityUpdateKind::DeleteCommunityChannel { channel_id } => {
                     this.replace_document(community).await?;
+                    this.search_index.remove_conversation(channel_id).await;
+                    this.remove_channel_receipts(channel_id).await;
                     if let Err(e) =
                         this.event_broadcast
                             .send(MessageEventKind::DeletedCommunityChannel {
@@ -4405,6 +5735,15 @@ async fn message_event(
                 }
                 CommunityUpdateKind::RemoveCommunityMember { member } => {
                     this.replace_document(community).await?;
+                    if this
+                        .document
+                        .participants()
+                        .contains(&this.identity.did_key())
+                    {
+                        if let Err(e) = this.rotate_key().await {
+                            tracing::warn!(%community_id, error = %e, "Failed to rotate key");
+                        }
+                    }
                     if let Err(e) =
                         this.event_broadcast
                             .send(MessageEventKind::RemovedCommunityMember {
@@ -4509,6 +5848,100 @@ async fn message_event(
                 }
             }
         }
+        CommunityMessagingEvents::Vote {
+            community_id,
+            channel_id,
+            message_id,
+            vote,
+        } => {
+            if !this.document.has_channel_permission(
+                &vote.voter,
+                &CommunityChannelPermission::ViewChannel,
+                channel_id,
+            ) {
+                return Err(Error::Unauthorized);
+            }
+
+            let channel = match this.document.channels.get_mut(&channel_id.to_string()) {
+                Some(c) => c,
+                None => return Err(Error::CommunityChannelDoesntExist),
+            };
+
+            let mut message_document = channel.get_message_document(&this.ipfs, message_id).await?;
+
+            let message = message_document
+                .resolve(&this.ipfs, keypair, true, keystore.as_ref())
+                .await?;
+
+            let poll = message.poll().ok_or(Error::InvalidMessage)?;
+
+            let voter = vote.voter.clone();
+            let options = vote.options.clone();
+
+            if !message_document.vote(poll, vote)? {
+                return Ok(());
+            }
+
+            channel
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            let event = match options.is_empty() {
+                true => MessageEventKind::CommunityPollVoteRetracted {
+                    community_id,
+                    channel_id,
+                    message_id,
+                    did_key: voter,
+                },
+                false => MessageEventKind::CommunityPollVoted {
+                    community_id,
+                    channel_id,
+                    message_id,
+                    did_key: voter,
+                    options,
+                },
+            };
+
+            if let Err(e) = this.event_broadcast.send(event) {
+                tracing::warn!(%community_id, error = %e, "Error broadcasting event");
+            }
+        }
+        CommunityMessagingEvents::ClosePoll {
+            community_id,
+            channel_id,
+            message_id,
+            close,
+        } => {
+            let channel = match this.document.channels.get_mut(&channel_id.to_string()) {
+                Some(c) => c,
+                None => return Err(Error::CommunityChannelDoesntExist),
+            };
+
+            let mut message_document = channel.get_message_document(&this.ipfs, message_id).await?;
+
+            if !message_document.close_poll(close)? {
+                return Ok(());
+            }
+
+            channel
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            if let Err(e) = this
+                .event_broadcast
+                .send(MessageEventKind::CommunityPollClosed {
+                    community_id,
+                    channel_id,
+                    message_id,
+                })
+            {
+                tracing::warn!(%community_id, error = %e, "Error broadcasting event");
+            }
+        }
         _ => {}
     }
 
@@ -4536,75 +5969,20 @@ async fn process_request_response_event(
             conversation_id,
             kind,
         } => match kind {
-            ConversationRequestKind::Key => {
+            ConversationRequestKind::Key | ConversationRequestKind::KeyHistory => {
                 if !this.document.participants().contains(&sender) {
                     tracing::warn!(%conversation_id, %sender, "apart of conversation");
                     return Err(Error::IdentityDoesntExist);
                 }
 
-                let keystore = &mut this.keystore;
-
-                let raw_key = match keystore.get_latest(keypair, &own_did) {
-                    Ok(key) => key,
-                    Err(Error::PublicKeyDoesntExist) => {
-                        let key = generate::<64>().into();
-                        keystore.insert(keypair, &own_did, &key)?;
-
-                        this.set_keystore(None).await?;
-                        key
-                    }
-                    Err(e) => {
-                        tracing::error!(%conversation_id, error = %e, "Error getting key from store");
-                        return Err(e);
-                    }
-                };
-
-                let key = ecdh_encrypt(keypair, Some(&sender), raw_key)?;
-
-                let response = ConversationRequestResponse::Response {
-                    conversation_id,
-                    kind: ConversationResponseKind::Key { key },
-                };
-
-                let topic = this.document.exchange_topic(&sender);
-
-                let bytes = ecdh_encrypt(keypair, Some(&sender), serde_json::to_vec(&response)?)?;
-
-                let payload = PayloadBuilder::new(keypair, bytes)
-                    .from_ipfs(&this.ipfs)
-                    .await?;
-
-                let peers = this.ipfs.pubsub_peers(Some(topic.clone())).await?;
-
-                let peer_id = sender.to_peer_id()?;
-
-                let bytes = payload.to_bytes()?;
-
-                tracing::trace!(%conversation_id, "Payload size: {} bytes", bytes.len());
+                if this.keystore.get_latest(keypair, &own_did).is_err() {
+                    this.keystore.insert(keypair, &own_did, generate::<64>())?;
+                    this.set_keystore(None).await?;
+                }
 
                 tracing::info!(%conversation_id, "Responding to {sender}");
-
-                if !peers.contains(&peer_id)
-                    || (peers.contains(&peer_id)
-                        && this
-                            .ipfs
-                            .pubsub_publish(topic.clone(), bytes)
-                            .await
-                            .is_err())
-                {
-                    tracing::warn!(%conversation_id, "Unable to publish to topic. Queuing event");
-                    // TODO
-                    this.queue_event(
-                        sender.clone(),
-                        QueueItem::direct(
-                            None,
-                            peer_id,
-                            topic.clone(),
-                            payload.message(None)?.to_vec(),
-                        ),
-                    )
-                    .await;
-                }
+                let history = kind == ConversationRequestKind::KeyHistory;
+                this.send_key(&sender, history).await?;
             }
             _ => {
                 tracing::info!(%conversation_id, "Unimplemented/Unsupported Event");
@@ -4614,15 +5992,20 @@ async fn process_request_response_event(
             conversation_id,
             kind,
         } => match kind {
-            ConversationResponseKind::Key { key } => {
+            ConversationResponseKind::Key { key, previous } => {
                 if !this.document.participants().contains(&sender) {
                     return Err(Error::IdentityDoesntExist);
                 }
                 let keystore = &mut this.keystore;
 
-                let raw_key = ecdh_decrypt(keypair, Some(&sender), key)?;
+                let keys = previous
+                    .iter()
+                    .skip(previous.len().saturating_sub(MAX_KEY_HISTORY))
+                    .chain(std::iter::once(&key))
+                    .map(|key| ecdh_decrypt(keypair, Some(&sender), key).map(Zeroizing::new))
+                    .collect::<Result<Vec<_>, _>>()?;
 
-                keystore.insert(keypair, &sender, raw_key)?;
+                keystore.set_history(keypair, &sender, &keys)?;
 
                 this.set_keystore(None).await?;
 
@@ -4670,8 +6053,7 @@ async fn process_pending_payload(this: &mut CommunityTask) {
 
         let event_fn = || {
             let keypair = root.keypair();
-            let key = store.get_latest(keypair, &sender)?;
-            let data = Cipher::direct_decrypt(&data, &key)?;
+            let data = store.try_decrypt(keypair, &sender, &data)?;
             let event = serde_json::from_slice(&data)?;
             Ok::<_, Error>(event)
         };
@@ -4698,37 +6080,108 @@ async fn process_community_event(this: &mut CommunityTask, message: Message) ->
 
     let data = Cipher::direct_decrypt(&payload.message(None)?, &key)?;
 
-    let event = match serde_json::from_slice::<CommunityMessagingEvents>(&data)? {
-        event @ CommunityMessagingEvents::Event { .. } => event,
-        _ => return Err(Error::Other),
-    };
-
-    if let CommunityMessagingEvents::Event {
-        community_id,
-        channel_id: community_channel_id,
-        member,
-        event,
-        cancelled,
-    } = event
-    {
-        let ev = match cancelled {
-            true => MessageEventKind::CommunityEventCancelled {
-                community_id,
-                community_channel_id,
-                did_key: member,
-                event,
-            },
-            false => MessageEventKind::CommunityEventReceived {
-                community_id,
-                community_channel_id,
-                did_key: member,
-                event,
-            },
-        };
+    match serde_json::from_slice::<CommunityMessagingEvents>(&data)? {
+        CommunityMessagingEvents::Event {
+            community_id,
+            channel_id: community_channel_id,
+            member,
+            event,
+            cancelled,
+        } => {
+            if member != sender || !this.document.members.contains(&member) {
+                return Err(Error::IdentityDoesntExist);
+            }
+
+            event.validate()?;
+
+            let key = (community_channel_id, member.clone());
+            match cancelled {
+                true => this.active_events.remove(key, &event),
+                false => this.active_events.insert(key, &event),
+            }
+
+            let ev = match cancelled {
+                true => MessageEventKind::CommunityEventCancelled {
+                    community_id,
+                    community_channel_id,
+                    did_key: member,
+                    event,
+                },
+                false => MessageEventKind::CommunityEventReceived {
+                    community_id,
+                    community_channel_id,
+                    did_key: member,
+                    event,
+                },
+            };
+
+            if let Err(e) = this.event_broadcast.send(ev) {
+                tracing::error!(%community_id, error = %e, "error broadcasting event");
+            }
+        }
+        CommunityMessagingEvents::Receipt {
+            community_id,
+            channel_id,
+            member,
+            message_ids,
+            kind,
+            date,
+        } => {
+            if member != sender || !this.document.members.contains(&member) {
+                return Err(Error::IdentityDoesntExist);
+            }
+
+            if message_ids.len() > MAX_RECEIPT_BATCH {
+                return Err(Error::InvalidLength {
+                    context: "message_ids".into(),
+                    current: message_ids.len(),
+                    minimum: None,
+                    maximum: Some(MAX_RECEIPT_BATCH),
+                });
+            }
+
+            let channel = match this.document.channels.get(&channel_id.to_string()) {
+                Some(c) => c,
+                None => return Err(Error::CommunityChannelDoesntExist),
+            };
+
+            let mut known = Vec::with_capacity(message_ids.len());
+            for message_id in message_ids {
+                if channel.contains(&this.ipfs, message_id).await? {
+                    known.push(message_id);
+                }
+            }
+
+            let date = date.min(Utc::now());
+
+            let recorded = this
+                .channel_receipts(channel_id)
+                .await
+                .insert_many(&known, &member, kind, date)
+                .await;
+
+            for message_id in recorded {
+                let ev = match kind {
+                    ReceiptKind::Delivered => MessageEventKind::CommunityMessageDelivered {
+                        community_id,
+                        channel_id,
+                        message_id,
+                        did_key: member.clone(),
+                    },
+                    ReceiptKind::Read => MessageEventKind::CommunityMessageRead {
+                        community_id,
+                        channel_id,
+                        message_id,
+                        did_key: member.clone(),
+                    },
+                };
 
-        if let Err(e) = this.event_broadcast.send(ev) {
-            tracing::error!(%community_id, error = %e, "error broadcasting event");
+                if let Err(e) = this.event_broadcast.send(ev) {
+                    tracing::error!(%community_id, error = %e, "error broadcasting event");
+                }
+            }
         }
+        _ => return Err(Error::Other),
     }
 
     Ok(())
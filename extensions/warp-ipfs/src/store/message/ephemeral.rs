@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use warp::raygun::MessageEvent;
+use web_time::Instant;
+
+/// Custom events received from other members that are still active.
+/// An event expires if it is not received again within its ttl.
+pub struct ActiveEvents<K> {
+    list: HashMap<(K, String), (Instant, MessageEvent)>,
+}
+
+impl<K> Default for ActiveEvents<K> {
+    fn default() -> Self {
+        Self {
+            list: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> ActiveEvents<K> {
+    /// Track or refresh the event sent by `key`
+    pub fn insert(&mut self, key: K, event: &MessageEvent) {
+        let Some(custom) = event.custom() else {
+            return;
+        };
+        let expire = Instant::now() + custom.ttl();
+        self.list
+            .insert((key, custom.name().to_string()), (expire, event.clone()));
+    }
+
+    /// Stop tracking the event sent by `key`
+    pub fn remove(&mut self, key: K, event: &MessageEvent) {
+        let Some(custom) = event.custom() else {
+            return;
+        };
+        self.list.remove(&(key, custom.name().to_string()));
+    }
+
+    /// Remove and return events that were not refreshed in time
+    pub fn expired(&mut self) -> Vec<(K, MessageEvent)> {
+        if self.list.is_empty() {
+            return vec![];
+        }
+
+        let now = Instant::now();
+        let keys = self
+            .list
+            .iter()
+            .filter(|(_, (expire, _))| *expire <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let (_, event) = self.list.remove(&key)?;
+                Some((key.0, event))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use ipld_core::cid::Cid;
+use rust_ipfs::Ipfs;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use warp::error::Error;
+
+use crate::store::{
+    document::FileAttachmentDocument,
+    ds_key::{self, DataStoreKey},
+};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Reference {
+    messages: BTreeSet<Uuid>,
+    /// True if the pin was created for the referencing messages rather than by the original upload
+    #[serde(default)]
+    pinned: bool,
+}
+
+type ReferenceList = HashMap<String, Reference>;
+
+/// Messages referencing attachments that are pinned locally, shared between conversations and communities.
+/// Since pins are not reference counted, an attachment referenced by more than one message, such as one that was
+/// forwarded, is only unpinned once the last message referencing it is removed
+#[derive(Clone)]
+pub struct AttachmentPins {
+    ipfs: Ipfs,
+    list: Arc<Mutex<ReferenceList>>,
+}
+
+impl AttachmentPins {
+    pub async fn load(ipfs: &Ipfs) -> Self {
+        let list = ds_key::load_dag::<ReferenceList>(ipfs, &ipfs.attachment_pins())
+            .await
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            list: Arc::new(Mutex::new(list)),
+        }
+    }
+
+    /// Pin each of `cids` if it is not already pinned, returning those that were pinned
+    pub async fn pin(&self, cids: impl IntoIterator<Item = Cid>) -> Result<Vec<Cid>, Error> {
+        let mut pinned = vec![];
+        for cid in cids {
+            if self.ipfs.is_pinned(cid).await.unwrap_or_default() {
+                continue;
+            }
+            if let Err(e) = self.ipfs.insert_pin(cid).recursive().await {
+                self.unpin(pinned).await;
+                return Err(anyhow::Error::from(e).into());
+            }
+            pinned.push(cid);
+        }
+        Ok(pinned)
+    }
+
+    /// Remove the pin of each of `cids`
+    pub async fn unpin(&self, cids: impl IntoIterator<Item = Cid>) {
+        for cid in cids {
+            if self.ipfs.is_pinned(cid).await.unwrap_or_default() {
+                _ = self.ipfs.remove_pin(cid).recursive().await;
+            }
+        }
+    }
+
+    /// Record each of `messages` as referencing `cids`, where `pinned` are the attachments that were pinned for them
+    pub async fn insert(&self, messages: &[Uuid], cids: &[Cid], pinned: &[Cid]) {
+        let list = &mut *self.list.lock().await;
+        for cid in cids {
+            let reference = list.entry(cid.to_string()).or_default();
+            reference.messages.extend(messages);
+            reference.pinned |= pinned.contains(cid);
+        }
+        self.save(list).await;
+    }
+
+    /// Returns true if `cid` is referenced by any message
+    pub async fn is_referenced(&self, cid: &Cid) -> bool {
+        self.list.lock().await.contains_key(&cid.to_string())
+    }
+
+    /// Remove the reference of `message_id` to each of `cids`, returning the attachments that are no longer
+    /// referenced by any message and were pinned for them, which can be unpinned by the caller
+    pub async fn release(&self, message_id: Uuid, cids: impl IntoIterator<Item = Cid>) -> Vec<Cid> {
+        let list = &mut *self.list.lock().await;
+        let mut changed = false;
+        let mut released = vec![];
+
+        for cid in cids {
+            let key = cid.to_string();
+            let Some(reference) = list.get_mut(&key) else {
+                continue;
+            };
+
+            if !reference.messages.remove(&message_id) {
+                continue;
+            }
+
+            changed = true;
+
+            if reference.messages.is_empty()
+                && list.remove(&key).is_some_and(|reference| reference.pinned)
+            {
+                released.push(cid);
+            }
+        }
+
+        if changed {
+            self.save(list).await;
+        }
+
+        released
+    }
+
+    async fn save(&self, list: &ReferenceList) {
+        if let Err(e) = ds_key::store_dag(&self.ipfs, &self.ipfs.attachment_pins(), list).await {
+            tracing::warn!(error = %e, "unable to store attachment pins");
+        }
+    }
+}
+
+/// Blocks of the attachments that would be pinned, being the data of each attachment and its thumbnail
+pub fn attachment_cids<'a>(
+    attachments: impl IntoIterator<Item = &'a FileAttachmentDocument>,
+) -> Vec<Cid> {
+    attachments
+        .into_iter()
+        .flat_map(|attachment| {
+            attachment
+                .data
+                .parse::<Cid>()
+                .ok()
+                .into_iter()
+                .chain(attachment.thumbnail)
+        })
+        .collect()
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{
+    crypto::DID,
+    raygun::{MessageReceipt, MessageStatus},
+};
+
+use crate::store::{
+    ds_key::{self, DataStoreKey},
+    ReceiptKind,
+};
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+struct Receipt {
+    delivered: Option<DateTime<Utc>>,
+    read: Option<DateTime<Utc>>,
+}
+
+/// Delivery and read acknowledgements of messages within a conversation or community channel,
+/// kept per message per recipient and encrypted with the local keypair before being stored
+pub struct Receipts {
+    ipfs: Ipfs,
+    keypair: Keypair,
+    id: Uuid,
+    list: HashMap<String, IndexMap<DID, Receipt>>,
+}
+
+impl Receipts {
+    pub async fn load(ipfs: &Ipfs, keypair: &Keypair, id: Uuid) -> Self {
+        let key = format!("{}/{}", ipfs.message_receipts(), id);
+
+        let list = ds_key::load_encrypted(ipfs, keypair, &key)
+            .await
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            keypair: keypair.clone(),
+            id,
+            list,
+        }
+    }
+
+    /// Record an acknowledgement from `member`. Returns true if it was not previously recorded.
+    /// Note: A message being read implies it has been delivered
+    pub async fn insert(
+        &mut self,
+        message_id: Uuid,
+        member: &DID,
+        kind: ReceiptKind,
+        date: DateTime<Utc>,
+    ) -> bool {
+        !self
+            .insert_many(&[message_id], member, kind, date)
+            .await
+            .is_empty()
+    }
+
+    /// Record an acknowledgement of each message from `member`, storing the receipts once.
+    /// Returns the messages that did not have the acknowledgement previously recorded.
+    pub async fn insert_many(
+        &mut self,
+        message_ids: &[Uuid],
+        member: &DID,
+        kind: ReceiptKind,
+        date: DateTime<Utc>,
+    ) -> Vec<Uuid> {
+        let mut changed = vec![];
+
+        for message_id in message_ids {
+            let receipt = self
+                .list
+                .entry(message_id.to_string())
+                .or_default()
+                .entry(member.clone())
+                .or_default();
+
+            match kind {
+                ReceiptKind::Delivered if receipt.delivered.is_none() => {
+                    receipt.delivered = Some(date);
+                }
+                ReceiptKind::Read if receipt.read.is_none() => {
+                    receipt.delivered.get_or_insert(date);
+                    receipt.read = Some(date);
+                }
+                _ => continue,
+            }
+
+            changed.push(*message_id);
+        }
+
+        if !changed.is_empty() {
+            self.save().await;
+        }
+
+        changed
+    }
+
+    pub fn contains(&self, message_id: Uuid, member: &DID, kind: ReceiptKind) -> bool {
+        self.list
+            .get(&message_id.to_string())
+            .and_then(|receipts| receipts.get(member))
+            .map(|receipt| match kind {
+                ReceiptKind::Delivered => receipt.delivered.is_some(),
+                ReceiptKind::Read => receipt.read.is_some(),
+            })
+            .unwrap_or_default()
+    }
+
+    pub async fn remove(&mut self, message_id: Uuid) {
+        if self.list.remove(&message_id.to_string()).is_some() {
+            self.save().await;
+        }
+    }
+
+    /// Receipts of a message for each of the `members` provided, including those that have yet to acknowledge it
+    pub fn receipts(&self, message_id: Uuid, members: &[DID]) -> Vec<MessageReceipt> {
+        let receipts = self.list.get(&message_id.to_string());
+        members
+            .iter()
+            .map(|did| {
+                let receipt = receipts
+                    .and_then(|receipts| receipts.get(did))
+                    .copied()
+                    .unwrap_or_default();
+                MessageReceipt::new(did.clone(), receipt.delivered, receipt.read)
+            })
+            .collect()
+    }
+
+    /// Status of a message based on the acknowledgements of `members`.
+    /// If `all` is true, every member must acknowledge the message, otherwise a single member is sufficient
+    pub fn status(&self, message_id: Uuid, members: &[DID], all: bool) -> MessageStatus {
+        let Some(receipts) = self.list.get(&message_id.to_string()) else {
+            return MessageStatus::Sent;
+        };
+
+        if members.is_empty() {
+            return MessageStatus::Sent;
+        }
+
+        let check = |f: fn(&Receipt) -> bool| {
+            let mut iter = members
+                .iter()
+                .map(|did| receipts.get(did).map(f).unwrap_or_default());
+            match all {
+                true => iter.all(|ack| ack),
+                false => iter.any(|ack| ack),
+            }
+        };
+
+        if check(|receipt| receipt.read.is_some()) {
+            return MessageStatus::Read;
+        }
+
+        if check(|receipt| receipt.delivered.is_some()) {
+            return MessageStatus::Delivered;
+        }
+
+        MessageStatus::Sent
+    }
+
+    /// Remove all receipts
+    pub async fn clear(&mut self) {
+        if self.list.is_empty() {
+            return;
+        }
+        self.list.clear();
+        self.save().await;
+    }
+
+    async fn save(&self) {
+        let key = format!("{}/{}", self.ipfs.message_receipts(), self.id);
+        if let Err(e) = ds_key::store_encrypted(&self.ipfs, &self.keypair, &key, &self.list).await {
+            tracing::error!(error = %e, "unable to save message receipts");
+        }
+    }
+}
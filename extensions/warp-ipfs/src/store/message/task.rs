@@ -1,9 +1,10 @@
 use bytes::Bytes;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use either::Either;
 use futures::channel::oneshot;
 use futures::stream::BoxStream;
-use futures::{StreamExt, TryFutureExt};
+use futures::{SinkExt, StreamExt, TryFutureExt};
+use futures_timeout::TimeoutExt;
 use futures_timer::Delay;
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
@@ -12,7 +13,7 @@ use rust_ipfs::{IpfsPath, PeerId, SubscriptionStream};
 use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
@@ -23,37 +24,54 @@ use uuid::Uuid;
 use warp::constellation::ConstellationProgressStream;
 use warp::crypto::DID;
 use warp::raygun::{
-    AttachmentEventStream, ConversationImage, GroupPermissionOpt, Location, MessageEvent,
-    MessageOptions, MessageReference, MessageStatus, MessageType, Messages, MessagesType,
-    RayGunEventKind,
+    AttachmentEventStream, ContactCard, ConversationImage, ConversationInvite, GeoPosition,
+    Geolocation, GroupPermissionOpt, Location, Mention, MessageEvent, MessageOptions, MessagePage,
+    MessageReceipt, MessageReference, MessageStatus, MessageType, Messages, MessagesType,
+    RayGunEventKind, ScheduledMessage,
 };
 use warp::{
-    crypto::{cipher::Cipher, generate},
+    crypto::{cipher::Cipher, generate, zeroize::Zeroizing},
     error::Error,
     raygun::{
-        ConversationType, GroupPermission, ImplGroupPermissions, MessageEventKind, PinState,
-        ReactionState,
+        ConversationType, EmbedState, GroupPermission, ImplGroupPermissions, MessageEventKind,
+        PinState, ReactionState,
     },
 };
 use web_time::Instant;
 
-// use crate::config;
+use crate::config;
 // use crate::shuttle::message::client::MessageCommand;
-use crate::store::conversation::message::MessageDocument;
+use crate::store::conversation::archive;
+use crate::store::conversation::export::{self, ExportFormat, ExportOptions, ExportProgressStream};
+use crate::store::conversation::geolocation::{
+    self, GeolocationStopDocument, GeolocationUpdateDocument,
+};
+use crate::store::conversation::invite::ConversationInviteDocument;
+use crate::store::conversation::link_preview::{self, LinkPreviewDocument, LINK_PREVIEW_TIMEOUT};
+use crate::store::conversation::mention;
+use crate::store::conversation::message::{ForwardedFrom, ForwardedMessage, MessageDocument};
+use crate::store::conversation::poll::{self, PollCloseDocument, PollVoteDocument};
+use crate::store::conversation::read_marker::{self, ReadMarker};
+use crate::store::conversation::reference::ReferenceStats;
+use crate::store::conversation::resolve_messages;
+use crate::store::conversation::ConversationVersion;
 use crate::store::discovery::Discovery;
 use crate::store::document::files::FileDocument;
 use crate::store::document::image_dag::ImageDag;
-use crate::store::ds_key::DataStoreKey;
+use crate::store::ds_key::{self, DataStoreKey};
 use crate::store::event_subscription::EventSubscription;
 use crate::store::message::attachment::AttachmentStream;
+use crate::store::ratchet::{RatchetMessage, RatchetSession, SessionReset};
+use crate::store::search::SearchIndex;
 use crate::store::topics::PeerTopic;
 use crate::store::{
     ecdh_shared_key, verify_serde_sig, ConversationEvents, ConversationImageType,
-    MAX_CONVERSATION_BANNER_SIZE, MAX_CONVERSATION_ICON_SIZE,
+    MAX_CONVERSATION_BANNER_SIZE, MAX_CONVERSATION_ICON_SIZE, MAX_RECEIPT_BATCH,
+    MAX_SCHEDULED_MESSAGE_ATTEMPTS, RECEIPT_BATCH_INTERVAL,
 };
 use crate::utils::{ByteCollection, ExtensionType};
 use crate::{
-    // rt::LocalExecutor,
+    rt::{Executor, LocalExecutor},
     store::{
         conversation::ConversationDocument,
         document::root::RootDocumentMap,
@@ -63,13 +81,19 @@ use crate::{
         keystore::Keystore,
         payload::{PayloadBuilder, PayloadMessage},
         ConversationRequestKind, ConversationRequestResponse, ConversationResponseKind,
-        ConversationUpdateKind, DidExt, MessagingEvents, PeerIdExt, MAX_CONVERSATION_DESCRIPTION,
-        MAX_MESSAGE_SIZE, MAX_REACTIONS, MIN_MESSAGE_SIZE,
+        ConversationUpdateKind, DidExt, MessagingEvents, PeerIdExt, ReceiptKind,
+        MAX_CONVERSATION_DESCRIPTION, MAX_MESSAGE_SIZE, MAX_REACTIONS, MAX_SCHEDULED_MESSAGES,
+        MIN_MESSAGE_SIZE,
     },
 };
 
 type AttachmentOneshot = (MessageDocument, oneshot::Sender<Result<(), Error>>);
 
+use super::ephemeral::ActiveEvents;
+use super::pins::{self, AttachmentPins};
+use super::queue_pending_payload;
+use super::receipts::Receipts;
+use super::threads::{self, Reply, Threads};
 use super::DownloadStream;
 
 #[derive(Debug)]
@@ -79,6 +103,10 @@ pub enum ConversationTaskCommand {
         desc: Option<String>,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    SetMessageRetention {
+        retention: Option<Duration>,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     FavoriteConversation {
         favorite: bool,
         response: oneshot::Sender<Result<(), Error>>,
@@ -87,6 +115,15 @@ pub enum ConversationTaskCommand {
         message_id: Uuid,
         response: oneshot::Sender<Result<warp::raygun::Message, Error>>,
     },
+    GetMessageHistory {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<Vec<warp::raygun::Message>, Error>>,
+    },
+    GetThread {
+        root_message_id: Uuid,
+        options: MessageOptions,
+        response: oneshot::Sender<Result<Messages, Error>>,
+    },
     GetMessages {
         options: MessageOptions,
         response: oneshot::Sender<Result<Messages, Error>>,
@@ -94,6 +131,19 @@ pub enum ConversationTaskCommand {
     GetMessagesCount {
         response: oneshot::Sender<Result<usize, Error>>,
     },
+    CompactMessages {
+        response: oneshot::Sender<Result<ReferenceStats, Error>>,
+    },
+    ExportConversation {
+        path: PathBuf,
+        options: ExportOptions,
+        response: oneshot::Sender<Result<ExportProgressStream, Error>>,
+    },
+    ExportArchive {
+        path: PathBuf,
+        format: ExportFormat,
+        response: oneshot::Sender<Result<ExportProgressStream, Error>>,
+    },
     GetMessageReference {
         message_id: Uuid,
         response: oneshot::Sender<Result<MessageReference, Error>>,
@@ -114,6 +164,26 @@ pub enum ConversationTaskCommand {
         member: DID,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    CreateInvite {
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+        response: oneshot::Sender<Result<ConversationInvite, Error>>,
+    },
+    ListInvites {
+        response: oneshot::Sender<Result<Vec<ConversationInvite>, Error>>,
+    },
+    RevokeInvite {
+        invite_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    RedeemInvite {
+        invite_id: Uuid,
+        member: DID,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    RotateKey {
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     RemoveParticipant {
         member: DID,
         broadcast: bool,
@@ -123,6 +193,40 @@ pub enum ConversationTaskCommand {
         message_id: Uuid,
         response: oneshot::Sender<Result<MessageStatus, Error>>,
     },
+    MessageReceipts {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<Vec<MessageReceipt>, Error>>,
+    },
+    MarkRead {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    GetLastReadMessage {
+        response: oneshot::Sender<Result<Option<Uuid>, Error>>,
+    },
+    GetUnreadCount {
+        response: oneshot::Sender<Result<usize, Error>>,
+    },
+    GetUnreadMentionCount {
+        response: oneshot::Sender<Result<usize, Error>>,
+    },
+    ScheduleMessage {
+        lines: Vec<String>,
+        send_at: DateTime<Utc>,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
+    ListScheduledMessages {
+        response: oneshot::Sender<Result<Vec<ScheduledMessage>, Error>>,
+    },
+    CancelScheduledMessage {
+        scheduled_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    RescheduleMessage {
+        scheduled_id: Uuid,
+        send_at: DateTime<Utc>,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
 
     SendMessage {
         lines: Vec<String>,
@@ -138,6 +242,14 @@ pub enum ConversationTaskCommand {
         lines: Vec<String>,
         response: oneshot::Sender<Result<Uuid, Error>>,
     },
+    PrepareForward {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<ForwardedMessage, Error>>,
+    },
+    ForwardMessage {
+        message: ForwardedMessage,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
     DeleteMessage {
         message_id: Uuid,
         response: oneshot::Sender<Result<(), Error>>,
@@ -153,12 +265,57 @@ pub enum ConversationTaskCommand {
         emoji: String,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    SendPoll {
+        poll: warp::raygun::Poll,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
+    Vote {
+        message_id: Uuid,
+        options: Vec<usize>,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    RetractVote {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    ClosePoll {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    SendGeolocation {
+        position: GeoPosition,
+        live: Option<Duration>,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
+    UpdateGeolocation {
+        message_id: Uuid,
+        position: GeoPosition,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    StopGeolocation {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    SendContactCard {
+        did: DID,
+        response: oneshot::Sender<Result<Uuid, Error>>,
+    },
+    Embeds {
+        message_id: Uuid,
+        state: EmbedState,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     AttachMessage {
         message_id: Option<Uuid>,
         locations: Vec<Location>,
         lines: Vec<String>,
         response: oneshot::Sender<Result<(Uuid, AttachmentEventStream), Error>>,
     },
+    AttachVoiceNote {
+        message_id: Option<Uuid>,
+        location: Location,
+        response: oneshot::Sender<Result<(Uuid, AttachmentEventStream), Error>>,
+    },
     DownloadAttachment {
         message_id: Uuid,
         file: String,
@@ -236,6 +393,19 @@ pub struct ConversationTask {
     pending_key_exchange: IndexMap<DID, Vec<(Vec<u8>, bool)>>,
     document: ConversationDocument,
     keystore: Keystore,
+    search_index: SearchIndex,
+    attachment_pins: AttachmentPins,
+    receipts: Receipts,
+    /// Messages with a delivery receipt that has yet to be sent
+    pending_receipts: Vec<Uuid>,
+    threads: Threads,
+    active_events: ActiveEvents<DID>,
+    /// Sender and expiry of live geolocations shared in the conversation
+    live_geolocations: HashMap<Uuid, (DID, DateTime<Utc>)>,
+    scheduled: Vec<ScheduledMessage>,
+    /// Failed attempts to send each scheduled message
+    scheduled_attempts: HashMap<Uuid, usize>,
+    config: config::Config,
 
     messaging_stream: SubscriptionStream,
     event_stream: SubscriptionStream,
@@ -243,6 +413,12 @@ pub struct ConversationTask {
 
     attachment_tx: futures::channel::mpsc::Sender<AttachmentOneshot>,
     attachment_rx: futures::channel::mpsc::Receiver<AttachmentOneshot>,
+    link_preview_tx: futures::channel::mpsc::Sender<(Uuid, Vec<LinkPreviewDocument>)>,
+    link_preview_rx: futures::channel::mpsc::Receiver<(Uuid, Vec<LinkPreviewDocument>)>,
+    thread_index_tx: futures::channel::mpsc::Sender<Vec<Reply>>,
+    thread_index_rx: futures::channel::mpsc::Receiver<Vec<Reply>>,
+    /// Messages with link previews that are being generated
+    pending_link_previews: HashSet<Uuid>,
     event_broadcast: tokio::sync::broadcast::Sender<MessageEventKind>,
     event_subscription: EventSubscription<RayGunEventKind>,
 
@@ -290,6 +466,9 @@ impl ConversationTask {
         identity: &IdentityStore,
         file: &FileStore,
         discovery: &Discovery,
+        search_index: &SearchIndex,
+        attachment_pins: &AttachmentPins,
+        config: &config::Config,
         command_rx: futures::channel::mpsc::Receiver<ConversationTaskCommand>,
         event_subscription: EventSubscription<RayGunEventKind>,
     ) -> Result<Self, Error> {
@@ -304,7 +483,16 @@ impl ConversationTask {
 
         let request_stream = ipfs.pubsub_subscribe(request_topic).await?;
 
+        let receipts = Receipts::load(ipfs, root.keypair(), conversation_id).await;
+        let threads = Threads::load(ipfs, conversation_id).await;
+        let scheduled = root
+            .get_scheduled_messages(conversation_id)
+            .await
+            .unwrap_or_default();
+
         let (atx, arx) = futures::channel::mpsc::channel(256);
+        let (ltx, lrx) = futures::channel::mpsc::channel(256);
+        let (ttx, trx) = futures::channel::mpsc::channel(1);
         let (btx, _) = tokio::sync::broadcast::channel(1024);
         let mut task = Self {
             conversation_id,
@@ -316,6 +504,16 @@ impl ConversationTask {
             pending_key_exchange: Default::default(),
             document,
             keystore: Keystore::default(),
+            search_index: search_index.clone(),
+            attachment_pins: attachment_pins.clone(),
+            receipts,
+            pending_receipts: vec![],
+            threads,
+            active_events: ActiveEvents::default(),
+            live_geolocations: HashMap::new(),
+            scheduled,
+            scheduled_attempts: HashMap::new(),
+            config: config.clone(),
 
             messaging_stream,
             request_stream,
@@ -323,6 +521,11 @@ impl ConversationTask {
 
             attachment_tx: atx,
             attachment_rx: arx,
+            link_preview_tx: ltx,
+            link_preview_rx: lrx,
+            thread_index_tx: ttx,
+            thread_index_rx: trx,
+            pending_link_previews: HashSet::new(),
             event_broadcast: btx,
             event_subscription,
             command_rx,
@@ -331,7 +534,9 @@ impl ConversationTask {
         };
 
         task.keystore = match task.document.conversation_type() {
-            ConversationType::Direct => Keystore::new(),
+            ConversationType::Direct => {
+                root.get_keystore(conversation_id).await.unwrap_or_default()
+            }
             ConversationType::Group => match root.get_keystore(conversation_id).await {
                 Ok(store) => store,
                 Err(_) => {
@@ -388,12 +593,26 @@ impl ConversationTask {
 
         let conversation_id = this.conversation_id;
 
+        this.index_existing_messages().await;
+        this.index_existing_threads().await;
+
         let mut queue_timer = Delay::new(Duration::from_secs(1));
 
         let mut pending_exchange_timer = Delay::new(Duration::from_secs(1));
 
         let mut check_mailbox = Delay::new(Duration::from_secs(5));
 
+        let mut expiry_timer = Delay::new(Duration::from_secs(1));
+
+        let mut compaction_timer = Delay::new(Duration::from_secs(5 * 60));
+
+        let mut event_expiry_timer = Delay::new(Duration::from_secs(1));
+
+        // Note: Messages that became due while offline are sent on the first tick
+        let mut scheduled_timer = Delay::new(Duration::from_secs(1));
+
+        let mut receipt_timer = Delay::new(RECEIPT_BATCH_INTERVAL);
+
         loop {
             tokio::select! {
                 biased;
@@ -406,6 +625,17 @@ impl ConversationTask {
                 Some((message, response)) = this.attachment_rx.next() => {
                     let _ = response.send(this.store_direct_for_attachment(message).await);
                 }
+                Some((message_id, previews)) = this.link_preview_rx.next() => {
+                    // Previews are dropped if embeds were disabled while they were being generated
+                    if this.pending_link_previews.remove(&message_id) {
+                        if let Err(e) = this.store_link_previews(message_id, previews).await {
+                            tracing::error!(%conversation_id, %message_id, error = %e, "unable to store link previews");
+                        }
+                    }
+                }
+                Some(replies) = this.thread_index_rx.next() => {
+                    this.threads.rebuild(replies).await;
+                }
                 Some(request) = this.request_stream.next() => {
                     let source = request.source;
                     if let Err(e) = process_request_response_event(this, request).await {
@@ -437,6 +667,30 @@ impl ConversationTask {
                     // _ = this.load_from_mailbox().await;
                     check_mailbox.reset(Duration::from_secs(60));
                 }
+                _ = &mut expiry_timer => {
+                    let next = this.remove_expired_messages().await.unwrap_or(Duration::from_secs(1));
+                    expiry_timer.reset(next.clamp(Duration::from_secs(1), Duration::from_secs(60)));
+                }
+                _ = &mut compaction_timer => {
+                    match this.compact_messages(false).await {
+                        Ok(stats) => tracing::trace!(%conversation_id, ?stats, "message references compacted"),
+                        Err(e) => tracing::warn!(%conversation_id, error = %e, "unable to compact message references"),
+                    }
+                    compaction_timer.reset(Duration::from_secs(30 * 60));
+                }
+                _ = &mut event_expiry_timer => {
+                    this.expire_events();
+                    this.expire_geolocations();
+                    event_expiry_timer.reset(Duration::from_secs(1));
+                }
+                _ = &mut scheduled_timer => {
+                    this.send_scheduled_messages().await;
+                    scheduled_timer.reset(Duration::from_secs(1));
+                }
+                _ = &mut receipt_timer => {
+                    this.send_pending_receipts().await;
+                    receipt_timer.reset(RECEIPT_BATCH_INTERVAL);
+                }
             }
         }
     }
@@ -636,6 +890,13 @@ impl ConversationTask {
                 let result = self.set_description(desc.as_deref()).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::SetMessageRetention {
+                retention,
+                response,
+            } => {
+                let result = self.set_message_retention(retention).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::FavoriteConversation { favorite, response } => {
                 let result = self.set_favorite_conversation(favorite).await;
                 let _ = response.send(result);
@@ -647,6 +908,21 @@ impl ConversationTask {
                 let result = self.get_message(message_id).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::GetMessageHistory {
+                message_id,
+                response,
+            } => {
+                let result = self.get_message_history(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::GetThread {
+                root_message_id,
+                options,
+                response,
+            } => {
+                let result = self.get_thread(root_message_id, options).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::GetMessages { options, response } => {
                 let result = self.get_messages(options).await;
                 let _ = response.send(result);
@@ -655,6 +931,26 @@ impl ConversationTask {
                 let result = self.messages_count().await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::CompactMessages { response } => {
+                let result = self.compact_messages(true).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ExportConversation {
+                path,
+                options,
+                response,
+            } => {
+                let result = self.export(path, options);
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ExportArchive {
+                path,
+                format,
+                response,
+            } => {
+                let result = self.export_archive(path, format);
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::GetMessageReference {
                 message_id,
                 response,
@@ -681,6 +977,37 @@ impl ConversationTask {
                 let result = self.add_participant(&member).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::CreateInvite {
+                expiry,
+                max_uses,
+                response,
+            } => {
+                let result = self.create_invite(expiry, max_uses).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ListInvites { response } => {
+                let result = self.list_invites().await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RevokeInvite {
+                invite_id,
+                response,
+            } => {
+                let result = self.revoke_invite(invite_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RedeemInvite {
+                invite_id,
+                member,
+                response,
+            } => {
+                let result = self.redeem_invite(invite_id, &member).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RotateKey { response } => {
+                let result = self.rotate_key().await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::RemoveParticipant {
                 member,
                 broadcast,
@@ -696,6 +1023,58 @@ impl ConversationTask {
                 let result = self.message_status(message_id).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::MessageReceipts {
+                message_id,
+                response,
+            } => {
+                let result = self.message_receipts(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::MarkRead {
+                message_id,
+                response,
+            } => {
+                let result = self.mark_read(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::GetLastReadMessage { response } => {
+                let result = self.last_read_message().await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::GetUnreadCount { response } => {
+                let result = self.unread_count().await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::GetUnreadMentionCount { response } => {
+                let result = self.unread_mention_count().await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ScheduleMessage {
+                lines,
+                send_at,
+                response,
+            } => {
+                let result = self.schedule_message(lines, send_at).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ListScheduledMessages { response } => {
+                let _ = response.send(Ok(self.scheduled.clone()));
+            }
+            ConversationTaskCommand::CancelScheduledMessage {
+                scheduled_id,
+                response,
+            } => {
+                let result = self.cancel_scheduled_message(scheduled_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RescheduleMessage {
+                scheduled_id,
+                send_at,
+                response,
+            } => {
+                let result = self.reschedule_message(scheduled_id, send_at).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::SendMessage { lines, response } => {
                 let result = self.send_message(lines).await;
                 let _ = response.send(result);
@@ -716,6 +1095,17 @@ impl ConversationTask {
                 let result = self.reply_message(message_id, lines).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::PrepareForward {
+                message_id,
+                response,
+            } => {
+                let result = self.prepare_forward(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ForwardMessage { message, response } => {
+                let result = self.forward_message(message).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::DeleteMessage {
                 message_id,
                 response,
@@ -740,6 +1130,67 @@ impl ConversationTask {
                 let result = self.react(message_id, state, emoji).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::SendPoll { poll, response } => {
+                let result = self.send_poll(poll).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::Vote {
+                message_id,
+                options,
+                response,
+            } => {
+                let result = self.vote(message_id, options).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RetractVote {
+                message_id,
+                response,
+            } => {
+                let result = self.retract_vote(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ClosePoll {
+                message_id,
+                response,
+            } => {
+                let result = self.close_poll(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::SendGeolocation {
+                position,
+                live,
+                response,
+            } => {
+                let result = self.send_geolocation(position, live).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::UpdateGeolocation {
+                message_id,
+                position,
+                response,
+            } => {
+                let result = self.update_geolocation(message_id, position).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::StopGeolocation {
+                message_id,
+                response,
+            } => {
+                let result = self.stop_geolocation(message_id).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::SendContactCard { did, response } => {
+                let result = self.send_contact_card(did).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::Embeds {
+                message_id,
+                state,
+                response,
+            } => {
+                let result = self.embeds(message_id, state).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::AttachMessage {
                 message_id,
                 locations,
@@ -749,6 +1200,14 @@ impl ConversationTask {
                 let result = self.attach(message_id, locations, lines);
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::AttachVoiceNote {
+                message_id,
+                location,
+                response,
+            } => {
+                let result = self.attach_voice_note(message_id, location);
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::DownloadAttachment {
                 message_id,
                 file,
@@ -845,9 +1304,22 @@ impl ConversationTask {
 impl ConversationTask {
     pub async fn delete(&mut self) -> Result<(), Error> {
         // TODO: Maybe announce to network of the local node removal here
+        if let Ok(list) = self.document.get_message_list(&self.ipfs).await {
+            for message in list {
+                message.unpin_revisions(&self.ipfs).await;
+                let cids = pins::attachment_cids(message.attachments());
+                let released = self.attachment_pins.release(message.id, cids).await;
+                self.attachment_pins.unpin(released).await;
+            }
+        }
         self.document.messages.take();
         self.document.deleted = true;
         self.set_document().await?;
+        self.receipts.clear().await;
+        self.threads.clear().await;
+        if let Err(e) = ds_key::remove_secret(&self.ipfs, &self.session_key()).await {
+            tracing::warn!(conversation_id = %self.conversation_id, error = %e, "failed to remove ratchet session");
+        }
         if let Ok(mut ks_map) = self.root.get_keystore_map().await {
             if ks_map.remove(&self.conversation_id.to_string()).is_some() {
                 if let Err(e) = self.root.set_keystore_map(ks_map).await {
@@ -868,9 +1340,21 @@ impl ConversationTask {
 
         let cid = self.ipfs.put_dag(keystore).await?;
 
-        map.insert(id, cid);
+        let previous_cid = map
+            .insert(id, cid)
+            .filter(|previous_cid| !map.values().any(|cid| cid == previous_cid));
+
+        self.root.set_keystore_map(map).await?;
+
+        // Note: The previous keystore is removed rather than left unpinned since it holds encrypted keys
+        //       that are no longer in use
+        if let Some(previous_cid) = previous_cid {
+            if let Err(e) = self.ipfs.remove_block(previous_cid, false).await {
+                tracing::debug!(conversation_id = %self.conversation_id, error = %e, "unable to remove previous keystore");
+            }
+        }
 
-        self.root.set_keystore_map(map).await
+        Ok(())
     }
 
     pub async fn set_document(&mut self) -> Result<(), Error> {
@@ -1066,9 +1550,37 @@ impl ConversationTask {
                     return Err(Error::IdentityDoesntExist);
                 };
 
-                ecdh_decrypt(keypair, Some(member), data.message(None)?)?
+                let member = (*member).clone();
+                let message = data.message(None)?;
+
+                match serde_json::from_slice::<RatchetMessage>(&message) {
+                    Ok(message) => self.ratchet_decrypt(&member, &message).await?,
+                    Err(_) => {
+                        if let Ok(reset) = serde_json::from_slice::<SessionReset>(&message) {
+                            if sender != member {
+                                tracing::warn!(id = %id, %sender, "sender is not in conversation");
+                                return Err(Error::IdentityDoesntExist);
+                            }
+                            return self.reset_session(&member, &reset).await;
+                        }
+
+                        // Once upgraded, payloads encrypted with the identity keys are rejected so they cannot be used
+                        // to bypass the forward secrecy of the session
+                        if self.document.version == ConversationVersion::V1 {
+                            tracing::warn!(id = %id, %sender, "rejecting payload that was not sent over the ratchet session");
+                            return Err(Error::DecryptionError);
+                        }
+
+                        ecdh_decrypt(keypair, Some(&member), message)?
+                    }
+                }
             }
             ConversationType::Group => {
+                if !self.document.recipients().contains(&sender) {
+                    tracing::warn!(id = %id, %sender, "sender is not in conversation");
+                    return Err(Error::IdentityDoesntExist);
+                }
+
                 let key = match self.keystore.get_latest(keypair, &sender) {
                     Ok(key) => key,
                     Err(Error::PublicKeyDoesntExist) => {
@@ -1080,10 +1592,12 @@ impl ConversationTask {
                         //       however, we may want to eventually validate the data to ensure it havent been tampered in some way
                         //       while waiting for the response.
 
-                        self.pending_key_exchange
-                            .entry(sender)
-                            .or_default()
-                            .push((data.message(None)?, false));
+                        queue_pending_payload(
+                            &mut self.pending_key_exchange,
+                            id,
+                            sender,
+                            data.message(None)?,
+                        );
 
                         // Maybe send a request? Although we could, we should check to determine if one was previously sent or queued first,
                         // but for now we can leave this commented until the queue is removed and refactored.
@@ -1098,7 +1612,24 @@ impl ConversationTask {
                     }
                 };
 
-                Cipher::direct_decrypt(&data.message(None)?, &key)?
+                let message = data.message(None)?;
+                match Cipher::direct_decrypt(&message, &key) {
+                    Ok(bytes) => bytes,
+                    // The payload may have been encrypted with a previous key if it was delayed, or with a
+                    // rotated key that we have yet to receive, in which case it is set aside until the key arrives
+                    Err(_) => match self.keystore.try_decrypt(keypair, &sender, &message) {
+                        Ok(bytes) => bytes,
+                        Err(_) => {
+                            queue_pending_payload(
+                                &mut self.pending_key_exchange,
+                                id,
+                                sender,
+                                message,
+                            );
+                            return Ok(());
+                        }
+                    },
+                }
             }
         };
 
@@ -1116,17 +1647,191 @@ impl ConversationTask {
         self.document.messages_length(&self.ipfs).await
     }
 
+    /// Compacts the message references of the conversation, unpinning any superseded blocks
+    async fn compact_messages(&mut self, force: bool) -> Result<ReferenceStats, Error> {
+        let superseded = self
+            .document
+            .compact_message_references(&self.ipfs, force)
+            .await?;
+
+        if !superseded.is_empty() {
+            self.set_document().await?;
+            for cid in superseded {
+                if self.ipfs.is_pinned(cid).await.unwrap_or_default() {
+                    _ = self.ipfs.remove_pin(cid).recursive().await;
+                }
+            }
+        }
+
+        self.document
+            .message_reference_list(&self.ipfs)
+            .await?
+            .stats(&self.ipfs)
+            .await
+    }
+
     async fn get_message(&self, message_id: Uuid) -> Result<warp::raygun::Message, Error> {
         let keypair = self.root.keypair();
 
         let keystore = pubkey_or_keystore(self)?;
 
-        self.document
+        let mut message = self
+            .document
             .get_message(&self.ipfs, keypair, message_id, keystore.as_ref())
-            .await
+            .await?;
+
+        self.threads.apply(&mut message);
+        refresh_contact_card(&self.identity, &mut message).await;
+
+        Ok(message)
     }
 
-    async fn get_message_reference(&self, message_id: Uuid) -> Result<MessageReference, Error> {
+    /// Refresh the contact cards of the messages from the identity cache
+    async fn refresh_contact_cards(&self, messages: Messages) -> Messages {
+        match messages {
+            Messages::List(mut list) => {
+                for message in list.iter_mut() {
+                    refresh_contact_card(&self.identity, message).await;
+                }
+                Messages::List(list)
+            }
+            Messages::Stream(stream) => {
+                let identity = self.identity.clone();
+                Messages::Stream(
+                    stream
+                        .then(move |mut message| {
+                            let identity = identity.clone();
+                            async move {
+                                refresh_contact_card(&identity, &mut message).await;
+                                message
+                            }
+                        })
+                        .boxed(),
+                )
+            }
+            Messages::Page { pages, total } => {
+                let mut refreshed = Vec::with_capacity(pages.len());
+                for page in pages {
+                    let mut messages = page.messages().to_vec();
+                    for message in messages.iter_mut() {
+                        refresh_contact_card(&self.identity, message).await;
+                    }
+                    refreshed.push(MessagePage::new(page.id(), messages, page.total()));
+                }
+                Messages::Page {
+                    pages: refreshed,
+                    total,
+                }
+            }
+        }
+    }
+
+    async fn get_thread(
+        &self,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+
+        let keystore = pubkey_or_keystore(self)?;
+
+        // Confirm that the root message exists
+        self.document
+            .get_message_document(&self.ipfs, root_message_id)
+            .await?;
+
+        let mut documents = vec![];
+        for message_id in self.threads.replies(root_message_id) {
+            match self
+                .document
+                .get_message_document(&self.ipfs, message_id)
+                .await
+            {
+                Ok(document) => documents.push(document),
+                Err(e) => {
+                    tracing::warn!(%conversation_id, %message_id, error = %e, "unable to get reply")
+                }
+            }
+        }
+
+        let messages = resolve_messages(&self.ipfs, keypair, documents, options, keystore).await?;
+
+        let messages = self.refresh_contact_cards(messages).await;
+
+        Ok(self.threads.apply_all(messages))
+    }
+
+    /// Collect the replies of the existing messages in the background if the threads were not previously stored
+    async fn index_existing_threads(&self) {
+        let conversation_id = self.conversation_id;
+
+        if self.threads.is_loaded() {
+            return;
+        }
+
+        let refs = match self.document.message_reference_list(&self.ipfs).await {
+            Ok(refs) => refs,
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to index existing threads");
+                return;
+            }
+        };
+
+        let ipfs = self.ipfs.clone();
+        let mut tx = self.thread_index_tx.clone();
+        LocalExecutor.dispatch(async move {
+            let replies = threads::collect_replies(&ipfs, &refs).await;
+            _ = tx.send(replies).await;
+        });
+    }
+
+    /// Record the message as a reply to its thread, if it is a reply
+    async fn add_to_thread(&mut self, message: &MessageDocument) {
+        let Some(root) = message.replied else {
+            return;
+        };
+
+        if !self.threads.insert(root, message.id, message.date).await {
+            return;
+        }
+
+        self.broadcast_thread_update(root);
+    }
+
+    async fn remove_from_thread(&mut self, message_id: Uuid) {
+        if let Some(root) = self.threads.remove(message_id).await {
+            self.broadcast_thread_update(root);
+        }
+    }
+
+    fn broadcast_thread_update(&self, message_id: Uuid) {
+        let conversation_id = self.conversation_id;
+        let (reply_count, last_reply) = self.threads.summary(message_id);
+        if let Err(e) = self.event_broadcast.send(MessageEventKind::ThreadUpdated {
+            conversation_id,
+            message_id,
+            reply_count,
+            last_reply,
+        }) {
+            tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+        }
+    }
+
+    async fn get_message_history(
+        &self,
+        message_id: Uuid,
+    ) -> Result<Vec<warp::raygun::Message>, Error> {
+        let keypair = self.root.keypair();
+
+        let keystore = pubkey_or_keystore(self)?;
+
+        self.document
+            .get_message_history(&self.ipfs, keypair, message_id, keystore.as_ref())
+            .await
+    }
+
+    async fn get_message_reference(&self, message_id: Uuid) -> Result<MessageReference, Error> {
         self.document
             .get_message_document(&self.ipfs, message_id)
             .await
@@ -1148,27 +1853,357 @@ impl ConversationTask {
         let keystore = pubkey_or_keystore(self)?;
 
         let m_type = opt.messages_type();
-        match m_type {
+        let messages = match m_type {
             MessagesType::Stream => {
                 let stream = self
                     .document
                     .get_messages_stream(&self.ipfs, keypair, opt, keystore)
                     .await?;
-                Ok(Messages::Stream(stream))
+                Messages::Stream(stream)
             }
             MessagesType::List => {
                 let list = self
                     .document
                     .get_messages(&self.ipfs, keypair, opt, keystore)
                     .await?;
-                Ok(Messages::List(list))
+                Messages::List(list)
             }
             MessagesType::Pages { .. } => {
                 self.document
                     .get_messages_pages(&self.ipfs, keypair, opt, keystore.as_ref())
+                    .await?
+            }
+        };
+
+        let messages = self.refresh_contact_cards(messages).await;
+
+        Ok(self.threads.apply_all(messages))
+    }
+
+    async fn schedule_message(
+        &mut self,
+        lines: Vec<String>,
+        send_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        if lines.is_empty() {
+            return Err(Error::EmptyMessage);
+        }
+
+        let lines_value_length: usize = lines
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim())
+            .map(|s| s.chars().count())
+            .sum();
+
+        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+            return Err(Error::InvalidLength {
+                context: "message".into(),
+                current: lines_value_length,
+                minimum: Some(MIN_MESSAGE_SIZE),
+                maximum: Some(MAX_MESSAGE_SIZE),
+            });
+        }
+
+        if send_at <= Utc::now() {
+            return Err(Error::OtherWithContext(
+                "message cannot be scheduled in the past".into(),
+            ));
+        }
+
+        if self.scheduled.len() >= MAX_SCHEDULED_MESSAGES {
+            return Err(Error::InvalidLength {
+                context: "scheduled messages".into(),
+                current: self.scheduled.len(),
+                minimum: None,
+                maximum: Some(MAX_SCHEDULED_MESSAGES),
+            });
+        }
+
+        let message = ScheduledMessage::new(self.conversation_id, lines, send_at);
+        let id = message.id();
+
+        let mut list = self.scheduled.clone();
+        list.push(message);
+        self.set_scheduled_messages(list).await?;
+
+        Ok(id)
+    }
+
+    async fn cancel_scheduled_message(&mut self, scheduled_id: Uuid) -> Result<(), Error> {
+        let mut list = self.scheduled.clone();
+        let index = list
+            .iter()
+            .position(|message| message.id() == scheduled_id)
+            .ok_or(Error::MessageNotFound)?;
+        list.remove(index);
+        self.set_scheduled_messages(list).await?;
+        self.scheduled_attempts.remove(&scheduled_id);
+        Ok(())
+    }
+
+    async fn reschedule_message(
+        &mut self,
+        scheduled_id: Uuid,
+        send_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        if send_at <= Utc::now() {
+            return Err(Error::OtherWithContext(
+                "message cannot be scheduled in the past".into(),
+            ));
+        }
+
+        let mut list = self.scheduled.clone();
+        let message = list
+            .iter_mut()
+            .find(|message| message.id() == scheduled_id)
+            .ok_or(Error::MessageNotFound)?;
+        message.set_send_at(send_at);
+        self.set_scheduled_messages(list).await
+    }
+
+    async fn set_scheduled_messages(&mut self, list: Vec<ScheduledMessage>) -> Result<(), Error> {
+        self.root
+            .set_scheduled_messages(self.conversation_id, list.clone())
+            .await?;
+        self.scheduled = list;
+        Ok(())
+    }
+
+    /// Sends the scheduled messages that are due, including those that became due while offline
+    async fn send_scheduled_messages(&mut self) {
+        if !self.scheduled.iter().any(ScheduledMessage::is_due) {
+            return;
+        }
+
+        let conversation_id = self.conversation_id;
+
+        let mut due = self
+            .scheduled
+            .iter()
+            .filter(|message| message.is_due())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        due.sort_by_key(|message| message.send_at());
+
+        for message in due {
+            let scheduled_id = message.id();
+
+            // The message is sent with the id it was scheduled with, so a message that was stored before the task
+            // was interrupted is not sent again
+            let result = match self.document.contains(&self.ipfs, scheduled_id).await {
+                Ok(true) => Ok(()),
+                _ => self
+                    .send_message_with_id(scheduled_id, message.lines().to_vec())
+                    .await
+                    .map(|_| ()),
+            };
+
+            if let Err(e) = result {
+                let attempts = self.scheduled_attempts.entry(scheduled_id).or_default();
+                *attempts += 1;
+
+                if *attempts < MAX_SCHEDULED_MESSAGE_ATTEMPTS {
+                    tracing::warn!(%conversation_id, %scheduled_id, error = %e, attempts = *attempts, "unable to send scheduled message. Retrying");
+                    continue;
+                }
+
+                tracing::error!(%conversation_id, %scheduled_id, error = %e, "unable to send scheduled message");
+
+                let _ = self
+                    .event_broadcast
+                    .send(MessageEventKind::ScheduledMessageFailed {
+                        conversation_id,
+                        scheduled_id,
+                        error: e.to_string(),
+                    });
+            }
+
+            self.scheduled_attempts.remove(&scheduled_id);
+
+            let list = self
+                .scheduled
+                .iter()
+                .filter(|message| message.id() != scheduled_id)
+                .cloned()
+                .collect();
+
+            if let Err(e) = self.set_scheduled_messages(list).await {
+                tracing::error!(%conversation_id, error = %e, "unable to update scheduled messages");
+                return;
+            }
+        }
+    }
+
+    /// Encrypts the payload using the ratchet session of a direct conversation, establishing a session
+    /// if the peer has published a prekey. Returns `None` if the identity keys should be used instead
+    async fn ratchet_encrypt(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if self.document.conversation_type() != ConversationType::Direct {
+            return Ok(None);
+        }
+
+        let session = self.load_session().await?;
+
+        let keypair = self.root.keypair();
+
+        let mut session = match session {
+            Some(session) => session,
+            None => {
+                // Once upgraded, the conversation should never fall back to the identity keys
+                let upgraded = self.document.version == ConversationVersion::V1;
+                if !upgraded && !self.config.store_setting().ratchet_sessions {
+                    return Ok(None);
+                }
+
+                let own_did = self.identity.did_key();
+                let member = self
+                    .document
+                    .recipients()
+                    .into_iter()
+                    .find(|did| own_did.ne(did))
+                    .ok_or(Error::InvalidConversation)?;
+
+                match self.identity.identity_prekeys(&member).await {
+                    Some((prekey, one_time_prekey)) => RatchetSession::initiate(
+                        keypair,
+                        &member,
+                        &prekey,
+                        one_time_prekey.as_ref(),
+                    )?,
+                    None if upgraded => {
+                        return Err(Error::OtherWithContext(
+                            "unable to establish a session without a prekey".into(),
+                        ))
+                    }
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let message = session.encrypt(data)?;
+        self.store_session(&session).await?;
+        self.upgrade_conversation().await?;
+
+        Ok(Some(serde_json::to_vec(&message)?))
+    }
+
+    /// Decrypts a payload sent over the ratchet session of a direct conversation
+    async fn ratchet_decrypt(
+        &mut self,
+        member: &DID,
+        message: &RatchetMessage,
+    ) -> Result<Vec<u8>, Error> {
+        let session = self.load_session().await?;
+        let previous = session.clone();
+        let (bytes, session) = match self
+            .identity
+            .ratchet_receive(member, session, message)
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                if let Some(reset) = self
+                    .identity
+                    .ratchet_reset(member, previous.as_ref(), message)
                     .await
+                {
+                    tracing::info!(conversation_id = %self.conversation_id, "prekey no longer exists. requesting session to be established again");
+                    if let Err(e) = self.send_reset(&reset).await {
+                        tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to send session reset");
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.store_session(&session).await?;
+        self.upgrade_conversation().await?;
+        Ok(bytes)
+    }
+
+    async fn send_reset(&self, reset: &SessionReset) -> Result<(), Error> {
+        let payload = PayloadBuilder::new(self.root.keypair(), serde_json::to_vec(reset)?)
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        self.ipfs
+            .pubsub_publish(self.document.topic(), payload.to_bytes()?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Establishes the session again after the peer was unable to do so, resending the messages they did not receive
+    async fn reset_session(&mut self, member: &DID, reset: &SessionReset) -> Result<(), Error> {
+        let Some(mut session) = self.load_session().await? else {
+            return Ok(());
+        };
+
+        let Some(unconfirmed) = session.take_unconfirmed(reset) else {
+            tracing::debug!(conversation_id = %self.conversation_id, "ignoring reset of a session that is no longer in use");
+            return Ok(());
+        };
+
+        let unconfirmed = Zeroizing::new(unconfirmed);
+
+        let (prekey, _) =
+            self.identity
+                .identity_prekeys(member)
+                .await
+                .ok_or(Error::OtherWithContext(
+                    "unable to establish a session without a prekey".into(),
+                ))?;
+
+        // Note: The cached one-time prekeys may be outdated, which is likely why the session had to be reset,
+        //       so the session is established against the signed prekey alone
+        let session = RatchetSession::initiate(self.root.keypair(), member, &prekey, None)?;
+        self.store_session(&session).await?;
+
+        tracing::info!(conversation_id = %self.conversation_id, resent = unconfirmed.len(), "session established again");
+
+        for event in unconfirmed.iter() {
+            self.publish_bytes(None, event, true).await?;
+        }
+
+        Ok(())
+    }
+
+    fn session_key(&self) -> String {
+        format!("{}/{}", self.ipfs.ratchet_sessions(), self.conversation_id)
+    }
+
+    /// Loads the ratchet session of a direct conversation, moving it out of the keystore if it was stored there
+    async fn load_session(&mut self) -> Result<Option<RatchetSession>, Error> {
+        let keypair = self.root.keypair();
+        match ds_key::load_secret(&self.ipfs, keypair, &self.session_key()).await {
+            Ok(session) => Ok(Some(session)),
+            Err(Error::ObjectNotFound) => {
+                let Some(session) = self.keystore.take_session(keypair)? else {
+                    return Ok(None);
+                };
+                self.store_session(&session).await?;
+                self.set_keystore(None).await?;
+                Ok(Some(session))
             }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores the ratchet session within the data store, overwriting the previous state so that
+    /// keys used by the session are not left behind
+    async fn store_session(&self, session: &RatchetSession) -> Result<(), Error> {
+        let keypair = self.root.keypair();
+        ds_key::store_secret(&self.ipfs, keypair, &self.session_key(), session).await
+    }
+
+    async fn upgrade_conversation(&mut self) -> Result<(), Error> {
+        if self.document.version == ConversationVersion::V1 {
+            return Ok(());
         }
+
+        tracing::info!(conversation_id = %self.conversation_id, "conversation upgraded to ratchet session");
+        self.document.version = ConversationVersion::V1;
+        self.set_document().await
     }
 
     fn conversation_key(&self, member: Option<&DID>) -> Result<Vec<u8>, Error> {
@@ -1196,40 +2231,48 @@ impl ConversationTask {
         }
     }
 
-    async fn request_key(&mut self, did: &DID) -> Result<(), Error> {
-        let request = ConversationRequestResponse::Request {
-            conversation_id: self.conversation_id,
-            kind: ConversationRequestKind::Key,
-        };
+    /// Sends the latest key of our messages in the group conversation to `did`
+    async fn send_key(&mut self, did: &DID) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
 
-        let conversation = &self.document;
+        let raw_key = self.keystore.get_latest(keypair, &own_did)?;
+        let key = ecdh_encrypt(keypair, Some(did), raw_key)?;
 
-        if !conversation.recipients().contains(did) {
-            //TODO: user is not a recipient of the conversation
-            return Err(Error::PublicKeyInvalid);
-        }
+        let response = ConversationRequestResponse::Response {
+            conversation_id,
+            kind: ConversationResponseKind::Key {
+                key,
+                previous: vec![],
+            },
+        };
 
-        let keypair = self.root.keypair();
+        let topic = self.document.exchange_topic(did);
 
-        let bytes = ecdh_encrypt(keypair, Some(did), serde_json::to_vec(&request)?)?;
+        let bytes = ecdh_encrypt(keypair, Some(did), serde_json::to_vec(&response)?)?;
 
         let payload = PayloadBuilder::new(keypair, bytes)
             .from_ipfs(&self.ipfs)
             .await?;
 
-        let topic = conversation.exchange_topic(did);
-
         let peers = self.ipfs.pubsub_peers(Some(topic.clone())).await?;
+
         let peer_id = did.to_peer_id()?;
+
+        let bytes = payload.to_bytes()?;
+
+        tracing::trace!(%conversation_id, "Payload size: {} bytes", bytes.len());
+
         if !peers.contains(&peer_id)
             || (peers.contains(&peer_id)
                 && self
                     .ipfs
-                    .pubsub_publish(topic.clone(), payload.to_bytes()?)
+                    .pubsub_publish(topic.clone(), bytes)
                     .await
                     .is_err())
         {
-            tracing::warn!(id = %self.conversation_id, "Unable to publish to topic");
+            tracing::warn!(%conversation_id, "Unable to publish to topic. Queuing event");
             self.queue_event(
                 did.clone(),
                 QueueItem::direct(None, peer_id, topic.clone(), payload.message(None)?),
@@ -1237,60 +2280,269 @@ impl ConversationTask {
             .await;
         }
 
-        // TODO: Store request locally and hold any messages and events until key is received from peer
-
         Ok(())
     }
 
-    //TODO: Send a request to recipient(s) of the chat to ack if message been delivered if message is marked "sent" unless we receive an event acknowledging the message itself
-    //Note:
-    //  - For group chat, this can be ignored unless we decide to have a full acknowledgement from all recipients in which case, we can mark it as "sent"
-    //    until all confirm to have received the message
-    //  - If member sends an event stating that they do not have the message to grab the message from the store
-    //    and send it them, with a map marking the attempt(s)
-    async fn message_status(&self, message_id: Uuid) -> Result<MessageStatus, Error> {
-        if matches!(self.document.conversation_type(), ConversationType::Group) {
-            //TODO: Handle message status for group
-            return Err(Error::Unimplemented);
+    /// Generates a new key for our messages in the group conversation and distributes it to the current members only,
+    /// preventing anyone removed from the conversation from decrypting messages sent afterwards.
+    /// Previous keys are kept so existing messages remain readable
+    async fn rotate_key(&mut self) -> Result<(), Error> {
+        if self.document.conversation_type() != ConversationType::Group {
+            return Err(Error::InvalidConversation);
         }
 
-        let messages = self.document.get_message_list(&self.ipfs).await?;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
 
-        if !messages.iter().any(|document| document.id == message_id) {
-            return Err(Error::MessageNotFound);
-        }
+        self.keystore.insert(keypair, &own_did, generate::<64>())?;
+        self.set_keystore(None).await?;
 
-        let own_did = self.identity.did_key();
+        tracing::info!(conversation_id = %self.conversation_id, "Rotated conversation key");
 
-        let _list = self
+        let recipients = self
             .document
             .recipients()
-            .iter()
+            .into_iter()
             .filter(|did| own_did.ne(did))
-            .cloned()
             .collect::<Vec<_>>();
 
-        // TODO:
-        // for peer in list {
-        //     if let Some(list) = self.queue.get(&peer) {
-        //         for item in list {
-        //             let Queue { id, m_id, .. } = item;
-        //             if self.document.id() == *id {
-        //                 if let Some(m_id) = m_id {
-        //                     if message_id == *m_id {
-        //                         return Ok(MessageStatus::NotSent);
-        //                     }
-        //                 }
-        //             }
-        //         }
-        //     }
-        // }
+        for recipient in recipients {
+            if let Err(e) = self.send_key(&recipient).await {
+                tracing::warn!(conversation_id = %self.conversation_id, error = %e, %recipient, "Failed to send rotated key");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn request_key(&mut self, did: &DID) -> Result<(), Error> {
+        let request = ConversationRequestResponse::Request {
+            conversation_id: self.conversation_id,
+            kind: ConversationRequestKind::Key,
+        };
+
+        let conversation = &self.document;
+
+        if !conversation.recipients().contains(did) {
+            //TODO: user is not a recipient of the conversation
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let keypair = self.root.keypair();
+
+        let bytes = ecdh_encrypt(keypair, Some(did), serde_json::to_vec(&request)?)?;
+
+        let payload = PayloadBuilder::new(keypair, bytes)
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let topic = conversation.exchange_topic(did);
+
+        let peers = self.ipfs.pubsub_peers(Some(topic.clone())).await?;
+        let peer_id = did.to_peer_id()?;
+        if !peers.contains(&peer_id)
+            || (peers.contains(&peer_id)
+                && self
+                    .ipfs
+                    .pubsub_publish(topic.clone(), payload.to_bytes()?)
+                    .await
+                    .is_err())
+        {
+            tracing::warn!(id = %self.conversation_id, "Unable to publish to topic");
+            self.queue_event(
+                did.clone(),
+                QueueItem::direct(None, peer_id, topic.clone(), payload.message(None)?),
+            )
+            .await;
+        }
+
+        // TODO: Store request locally and hold any messages and events until key is received from peer
+
+        Ok(())
+    }
+
+    async fn message_status(&self, message_id: Uuid) -> Result<MessageStatus, Error> {
+        let message = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        let members = self.receipt_members(&message);
+
+        // Note: Every recipient would need to acknowledge the message before it is considered delivered or read
+        Ok(self.receipts.status(message_id, &members, true))
+    }
+
+    async fn message_receipts(&self, message_id: Uuid) -> Result<Vec<MessageReceipt>, Error> {
+        let message = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        let members = self.receipt_members(&message);
+
+        Ok(self.receipts.receipts(message_id, &members))
+    }
+
+    /// Advance the read marker to the message if it is ahead of the current marker and send a read receipt for it
+    async fn mark_read(&mut self, message_id: Uuid) -> Result<(), Error> {
+        let message = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        self.advance_read_marker(&message).await?;
+
+        if message.sender.to_did() == self.identity.did_key() {
+            return Ok(());
+        }
+
+        self.send_receipt(message_id, ReceiptKind::Read).await
+    }
+
+    async fn advance_read_marker(&mut self, message: &MessageDocument) -> Result<(), Error> {
+        let marker = ReadMarker::new(message);
+
+        if let Some(current) = self.root.get_read_marker(self.conversation_id).await? {
+            if !marker.is_ahead_of(&current) {
+                return Ok(());
+            }
+        }
+
+        self.root
+            .set_read_marker(self.conversation_id, marker)
+            .await?;
+
+        if self.config.store_setting().sync_read_markers {
+            if let Err(e) = self.identity.export_root_document().await {
+                tracing::warn!(id = %self.conversation_id, error = %e, "unable to sync read marker");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn last_read_message(&self) -> Result<Option<Uuid>, Error> {
+        let marker = self.root.get_read_marker(self.conversation_id).await?;
+        Ok(marker.map(|marker| marker.message_id))
+    }
+
+    async fn unread_count(&self) -> Result<usize, Error> {
+        let marker = self.root.get_read_marker(self.conversation_id).await?;
+        let list = self.document.message_reference_list(&self.ipfs).await?;
+        let own_did = self.identity.did_key();
+        Ok(read_marker::unread_count(&self.ipfs, &list, marker, &own_did).await)
+    }
+
+    async fn unread_mention_count(&self) -> Result<usize, Error> {
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(self)?;
+        let marker = self.root.get_read_marker(self.conversation_id).await?;
+        let list = self.document.message_reference_list(&self.ipfs).await?;
+        let own_did = self.identity.did_key();
+        Ok(read_marker::unread_mention_count(
+            &self.ipfs,
+            keypair,
+            &list,
+            marker,
+            &own_did,
+            &[],
+            keystore.as_ref(),
+        )
+        .await)
+    }
+
+    /// Parses mentions of the conversation members from `lines`
+    async fn parse_mentions(&self, lines: &[String]) -> Vec<Mention> {
+        let own_did = self.identity.did_key();
+        let members = self
+            .document
+            .recipients()
+            .into_iter()
+            .filter(|did| own_did.ne(did))
+            .collect::<Vec<_>>();
+        let members = self.identity.cached_identities(&members).await;
+        mention::parse_mentions(lines, &members, &[])
+    }
+
+    /// Returns true if a received message should notify, based on the notification settings of the conversation
+    async fn should_notify(&self, mentioned: bool) -> bool {
+        self.root
+            .get_notification_settings(self.conversation_id)
+            .await
+            .unwrap_or_default()
+            .should_notify(mentioned)
+    }
+
+    /// Recipients that are expected to acknowledge the message
+    fn receipt_members(&self, message: &MessageDocument) -> Vec<DID> {
+        let sender = message.sender.to_did();
+        self.document
+            .recipients()
+            .into_iter()
+            .filter(|did| sender.ne(did))
+            .collect()
+    }
+
+    async fn send_receipt(&mut self, message_id: Uuid, kind: ReceiptKind) -> Result<(), Error> {
+        if kind == ReceiptKind::Read && self.config.store_setting().disable_read_receipts {
+            return Ok(());
+        }
+
+        let own_did = self.identity.did_key();
+        let date = Utc::now();
 
-        //Not a guarantee that it been sent but for now since the message exist locally and not marked in queue, we will assume it have been sent
-        Ok(MessageStatus::Sent)
+        if !self.receipts.insert(message_id, &own_did, kind, date).await {
+            return Ok(());
+        }
+
+        // Delivery receipts are sent together to avoid an event per message per member
+        if kind == ReceiptKind::Delivered {
+            self.pending_receipts.push(message_id);
+            return Ok(());
+        }
+
+        let event = MessagingEvents::Receipt {
+            conversation_id: self.conversation_id,
+            member: own_did,
+            message_ids: vec![message_id],
+            kind,
+            date,
+        };
+
+        self.send_message_event(event).await
+    }
+
+    async fn send_pending_receipts(&mut self) {
+        let conversation_id = self.conversation_id;
+        let pending = std::mem::take(&mut self.pending_receipts);
+        let own_did = self.identity.did_key();
+        let date = Utc::now();
+
+        for message_ids in pending.chunks(MAX_RECEIPT_BATCH) {
+            let event = MessagingEvents::Receipt {
+                conversation_id,
+                member: own_did.clone(),
+                message_ids: message_ids.to_vec(),
+                kind: ReceiptKind::Delivered,
+                date,
+            };
+
+            if let Err(e) = self.send_message_event(event).await {
+                tracing::warn!(%conversation_id, error = %e, "unable to send delivery receipts");
+            }
+        }
     }
 
     pub async fn send_message(&mut self, messages: Vec<String>) -> Result<Uuid, Error> {
+        self.send_message_with_id(Uuid::new_v4(), messages).await
+    }
+
+    async fn send_message_with_id(
+        &mut self,
+        message_id: Uuid,
+        messages: Vec<String>,
+    ) -> Result<Uuid, Error> {
         if messages.is_empty() {
             return Err(Error::EmptyMessage);
         }
@@ -1316,15 +2568,18 @@ impl ConversationTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
 
         let mut message = warp::raygun::Message::default();
+        message.set_id(message_id);
         message.set_conversation_id(self.conversation_id);
         message.set_sender(own_did.clone());
         message.set_lines(messages.clone());
+        message.set_mentions(mentions);
 
-        let message_id = message.id();
         let keystore = pubkey_or_keystore(&*self)?;
 
         let message = MessageDocument::new(&self.ipfs, keypair, message, keystore.as_ref()).await?;
@@ -1338,6 +2593,9 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::MessageSent {
             conversation_id: self.conversation_id,
             message_id,
@@ -1406,6 +2664,8 @@ impl ConversationTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
 
         let keystore = pubkey_or_keystore(&*self)?;
@@ -1428,6 +2688,7 @@ impl ConversationTask {
         }
 
         message.lines_mut().clone_from(&messages);
+        message.set_mentions(mentions);
         message.set_modified(Utc::now());
 
         message_document
@@ -1446,6 +2707,8 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.index_message(&message_document).await;
+
         let _ = tx.send(MessageEventKind::MessageEdited {
             conversation_id: self.conversation_id,
             message_id,
@@ -1456,6 +2719,7 @@ impl ConversationTask {
             message_id,
             modified: message_document.modified.expect("message to be modified"),
             lines: messages,
+            mentions: message_document.mentions.clone(),
             nonce: nonce.to_vec(),
             signature: signature.into(),
         };
@@ -1481,6 +2745,89 @@ impl ConversationTask {
         self.publish(None, event, true).await
     }
 
+    pub async fn prepare_forward(&self, message_id: Uuid) -> Result<ForwardedMessage, Error> {
+        let document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        if !matches!(
+            document.message_type,
+            MessageType::Message | MessageType::Attachment
+        ) {
+            return Err(Error::InvalidMessage);
+        }
+
+        let keystore = pubkey_or_keystore(self)?;
+        let message = document
+            .resolve(&self.ipfs, self.root.keypair(), true, keystore.as_ref())
+            .await?;
+
+        // Note: A message forwarded more than once keeps pointing to the original message
+        let origin = document.forwarded.clone().unwrap_or_else(|| ForwardedFrom {
+            conversation_id: self.conversation_id,
+            message_id,
+            sender: document.sender.to_did(),
+        });
+
+        Ok(ForwardedMessage {
+            lines: message.lines().to_vec(),
+            attachments: document.attachments().to_vec(),
+            origin,
+        })
+    }
+
+    pub async fn forward_message(&mut self, forwarded: ForwardedMessage) -> Result<Uuid, Error> {
+        forwarded.validate()?;
+
+        let ForwardedMessage {
+            lines,
+            attachments,
+            origin,
+        } = forwarded;
+
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut message = warp::raygun::Message::default();
+        message.set_conversation_id(self.conversation_id);
+        message.set_sender(own_did);
+        message.set_lines(lines);
+        if !attachments.is_empty() {
+            message.set_message_type(MessageType::Attachment);
+        }
+
+        let message_id = message.id();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message =
+            MessageDocument::forward(keypair, message, attachments, origin, keystore.as_ref())?;
+
+        self.document
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
+        let event = MessageEventKind::MessageSent {
+            conversation_id: self.conversation_id,
+            message_id,
+        };
+
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%self.conversation_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = MessagingEvents::New { message };
+
+        self.publish(Some(message_id), event, true)
+            .await
+            .map(|_| message_id)
+    }
+
     pub async fn reply_message(
         &mut self,
         message_id: Uuid,
@@ -1513,6 +2860,8 @@ impl ConversationTask {
             });
         }
 
+        let mentions = self.parse_mentions(&messages).await;
+
         let keypair = self.root.keypair();
 
         let own_did = self.identity.did_key();
@@ -1521,6 +2870,7 @@ impl ConversationTask {
         message.set_conversation_id(self.conversation_id);
         message.set_sender(own_did.clone());
         message.set_lines(messages);
+        message.set_mentions(mentions);
         message.set_replied(Some(message_id));
 
         let keystore = pubkey_or_keystore(&*self)?;
@@ -1538,6 +2888,9 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::MessageSent {
             conversation_id: self.conversation_id,
             message_id,
@@ -1580,10 +2933,24 @@ impl ConversationTask {
             message_id,
         };
 
+        let cids = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await
+            .map(|document| pins::attachment_cids(document.attachments()))
+            .unwrap_or_default();
+
         self.document.delete_message(&self.ipfs, message_id).await?;
 
         self.set_document().await?;
 
+        let released = self.attachment_pins.release(message_id, cids).await;
+        self.attachment_pins.unpin(released).await;
+
+        self.search_index.remove(message_id).await;
+        self.receipts.remove(message_id).await;
+        self.remove_from_thread(message_id).await;
+
         // if let config::Discovery::Shuttle { addresses } = self.discovery.discovery_config() {
         //     for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
         //         let _ = self
@@ -1824,22 +3191,542 @@ impl ConversationTask {
         self.publish(None, event, true).await
     }
 
-    pub async fn send_event(&self, event: MessageEvent) -> Result<(), Error> {
-        let conversation_id = self.conversation_id;
-        let member = self.identity.did_key();
+    pub async fn send_poll(&mut self, poll: warp::raygun::Poll) -> Result<Uuid, Error> {
+        poll::validate_poll(&poll)?;
 
-        let event = MessagingEvents::Event {
-            conversation_id,
-            member,
-            event,
-            cancelled: false,
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut message = warp::raygun::Message::default();
+        message.set_message_type(MessageType::Poll);
+        message.set_conversation_id(self.conversation_id);
+        message.set_sender(own_did);
+        message.set_lines(vec![poll.question().to_string()]);
+        message.set_poll(Some(poll));
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message = MessageDocument::new(&self.ipfs, keypair, message, keystore.as_ref()).await?;
+
+        self.document
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+
+        let message_id = message.id;
+
+        let event = MessageEventKind::MessageSent {
+            conversation_id: self.conversation_id,
+            message_id,
         };
-        self.send_message_event(event).await
-    }
 
-    pub async fn cancel_event(&self, event: MessageEvent) -> Result<(), Error> {
-        let member = self.identity.did_key();
-        let conversation_id = self.conversation_id;
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%self.conversation_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = MessagingEvents::New { message };
+
+        self.publish(Some(message_id), event, true)
+            .await
+            .map(|_| message_id)
+    }
+
+    pub async fn vote(&mut self, message_id: Uuid, options: Vec<usize>) -> Result<(), Error> {
+        if options.is_empty() {
+            return Err(Error::InvalidLength {
+                context: "options".into(),
+                current: 0,
+                minimum: Some(1),
+                maximum: None,
+            });
+        }
+        self.submit_vote(message_id, options).await
+    }
+
+    pub async fn retract_vote(&mut self, message_id: Uuid) -> Result<(), Error> {
+        self.submit_vote(message_id, vec![]).await
+    }
+
+    async fn submit_vote(&mut self, message_id: Uuid, options: Vec<usize>) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let mut message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        let message = message_document
+            .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+            .await?;
+
+        let poll = message.poll().ok_or(Error::InvalidMessage)?;
+
+        if poll.is_closed() {
+            return Err(Error::OtherWithContext("poll is closed".into()));
+        }
+
+        if options.is_empty() && !poll.votes().contains_key(&own_did) {
+            return Err(Error::OtherWithContext("no vote to retract".into()));
+        }
+
+        let vote = PollVoteDocument::new(keypair, conversation_id, message_id, options.clone())?;
+
+        message_document.vote(poll, vote.clone())?;
+
+        self.document
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        let event = match options.is_empty() {
+            true => MessageEventKind::PollVoteRetracted {
+                conversation_id,
+                message_id,
+                did_key: own_did,
+            },
+            false => MessageEventKind::PollVoted {
+                conversation_id,
+                message_id,
+                did_key: own_did,
+                options,
+            },
+        };
+
+        _ = self.event_broadcast.send(event);
+
+        let event = MessagingEvents::Vote {
+            conversation_id,
+            message_id,
+            vote,
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    pub async fn close_poll(&mut self, message_id: Uuid) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        if message_document.sender.to_did() != own_did {
+            return Err(Error::Unauthorized);
+        }
+
+        if message_document.poll_closed.is_some() {
+            return Err(Error::OtherWithContext("poll is closed".into()));
+        }
+
+        let close = PollCloseDocument::new(keypair, conversation_id, message_id)?;
+
+        message_document.close_poll(close.clone())?;
+
+        self.document
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        _ = self.event_broadcast.send(MessageEventKind::PollClosed {
+            conversation_id,
+            message_id,
+        });
+
+        let event = MessagingEvents::ClosePoll {
+            conversation_id,
+            message_id,
+            close,
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    pub async fn send_geolocation(
+        &mut self,
+        position: GeoPosition,
+        live: Option<Duration>,
+    ) -> Result<Uuid, Error> {
+        position.validate()?;
+
+        let live_until = match live {
+            Some(live) => {
+                geolocation::validate_live_duration(live)?;
+                let live = chrono::Duration::from_std(live).map_err(anyhow::Error::from)?;
+                Some(Utc::now() + live)
+            }
+            None => None,
+        };
+
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut geolocation = Geolocation::new(position);
+        geolocation.set_live_until(live_until);
+
+        // Note: The position is kept in the lines as a geo uri for clients that do not support geolocations
+        let mut uri = format!("geo:{},{}", position.latitude(), position.longitude());
+        if let Some(accuracy) = position.accuracy() {
+            uri.push_str(&format!(";u={accuracy}"));
+        }
+
+        let mut message = warp::raygun::Message::default();
+        message.set_message_type(MessageType::Geolocation);
+        message.set_conversation_id(self.conversation_id);
+        message.set_sender(own_did.clone());
+        message.set_lines(vec![uri]);
+        message.set_geolocation(Some(geolocation));
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message = MessageDocument::new(&self.ipfs, keypair, message, keystore.as_ref()).await?;
+
+        self.document
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+
+        let message_id = message.id;
+
+        if let Some(until) = live_until {
+            self.live_geolocations.insert(message_id, (own_did, until));
+        }
+
+        let event = MessageEventKind::MessageSent {
+            conversation_id: self.conversation_id,
+            message_id,
+        };
+
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%self.conversation_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = MessagingEvents::New { message };
+
+        self.publish(Some(message_id), event, true)
+            .await
+            .map(|_| message_id)
+    }
+
+    pub async fn send_contact_card(&mut self, did: DID) -> Result<Uuid, Error> {
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let identity = if did == own_did {
+            self.identity.own_identity_document().await?
+        } else {
+            self.identity.lookup(did.clone()).await?;
+            self.identity
+                .cached_identities(std::slice::from_ref(&did))
+                .await
+                .pop()
+                .ok_or(Error::IdentityDoesntExist)?
+        };
+
+        identity.verify()?;
+
+        let card = ContactCard::from(&identity);
+
+        let mut message = warp::raygun::Message::default();
+        message.set_message_type(MessageType::ContactCard);
+        message.set_conversation_id(self.conversation_id);
+        message.set_sender(own_did);
+        // Note: The identity is kept in the lines for clients that do not support contact cards
+        message.set_lines(vec![format!("{}#{}", card.username(), card.short_id())]);
+        message.set_contact_card(Some(card));
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message =
+            MessageDocument::contact_card(keypair, message, &identity, keystore.as_ref())?;
+
+        self.document
+            .insert_message_document(&self.ipfs, &message)
+            .await?;
+
+        self.set_document().await?;
+
+        self.index_message(&message).await;
+
+        let message_id = message.id;
+
+        let event = MessageEventKind::MessageSent {
+            conversation_id: self.conversation_id,
+            message_id,
+        };
+
+        if let Err(e) = self.event_broadcast.clone().send(event) {
+            tracing::error!(conversation_id=%self.conversation_id, error = %e, "Error broadcasting event");
+        }
+
+        let event = MessagingEvents::New { message };
+
+        self.publish(Some(message_id), event, true)
+            .await
+            .map(|_| message_id)
+    }
+
+    pub async fn update_geolocation(
+        &mut self,
+        message_id: Uuid,
+        position: GeoPosition,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        if message_document.sender.to_did() != own_did {
+            return Err(Error::Unauthorized);
+        }
+
+        let message = message_document
+            .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+            .await?;
+
+        let geolocation = message.geolocation().ok_or(Error::InvalidMessage)?;
+
+        if !geolocation.is_live() {
+            return Err(Error::OtherWithContext("geolocation is not live".into()));
+        }
+
+        let update =
+            GeolocationUpdateDocument::new(keypair, conversation_id, message_id, position)?;
+
+        _ = self
+            .event_broadcast
+            .send(MessageEventKind::GeolocationUpdated {
+                conversation_id,
+                message_id,
+                did_key: own_did,
+                position,
+            });
+
+        let event = MessagingEvents::GeolocationUpdate {
+            conversation_id,
+            message_id,
+            update,
+        };
+
+        self.send_message_event(event).await
+    }
+
+    pub async fn stop_geolocation(&mut self, message_id: Uuid) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let mut message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        if message_document.sender.to_did() != own_did {
+            return Err(Error::Unauthorized);
+        }
+
+        let message = message_document
+            .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+            .await?;
+
+        if !message
+            .geolocation()
+            .ok_or(Error::InvalidMessage)?
+            .is_live()
+        {
+            return Err(Error::OtherWithContext("geolocation is not live".into()));
+        }
+
+        let stop = GeolocationStopDocument::new(keypair, conversation_id, message_id)?;
+
+        message_document.stop_geolocation(stop.clone())?;
+
+        self.document
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        self.live_geolocations.remove(&message_id);
+
+        _ = self
+            .event_broadcast
+            .send(MessageEventKind::GeolocationStopped {
+                conversation_id,
+                message_id,
+                did_key: own_did,
+            });
+
+        let event = MessagingEvents::StopGeolocation {
+            conversation_id,
+            message_id,
+            stop,
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    /// Emit a stop for live geolocations that expired without being stopped by their sender
+    fn expire_geolocations(&mut self) {
+        let conversation_id = self.conversation_id;
+        let now = Utc::now();
+        let expired = self
+            .live_geolocations
+            .iter()
+            .filter(|(_, (_, until))| *until <= now)
+            .map(|(message_id, (did_key, _))| (*message_id, did_key.clone()))
+            .collect::<Vec<_>>();
+
+        for (message_id, did_key) in expired {
+            self.live_geolocations.remove(&message_id);
+            let ev = MessageEventKind::GeolocationStopped {
+                conversation_id,
+                message_id,
+                did_key,
+            };
+            if let Err(e) = self.event_broadcast.send(ev) {
+                tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+            }
+        }
+    }
+
+    pub async fn embeds(&mut self, message_id: Uuid, state: EmbedState) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        if message_document.sender.to_did() != own_did {
+            return Err(Error::Unauthorized);
+        }
+
+        match state {
+            EmbedState::Enabled => {
+                let fetcher = self
+                    .config
+                    .store_setting()
+                    .link_preview_fetcher
+                    .clone()
+                    .ok_or_else(|| {
+                        Error::OtherWithContext("link previews are not configured".into())
+                    })?;
+
+                let keystore = pubkey_or_keystore(self)?;
+
+                let message = message_document
+                    .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+                    .await?;
+
+                let ipfs = self.ipfs.clone();
+                let lines = message.lines().to_vec();
+                let size = self.config.thumbnail_size();
+                let exact = self.config.thumbnail_exact_format();
+                let mut tx = self.link_preview_tx.clone();
+
+                self.pending_link_previews.insert(message_id);
+
+                // The links are fetched outside of the task so a slow site does not hold up the conversation.
+                // The previews are stored once generated and `LinkPreviewsUpdated` is emitted
+                LocalExecutor.dispatch(async move {
+                    let previews = link_preview::generate(&ipfs, &*fetcher, &lines, size, exact)
+                        .timeout(LINK_PREVIEW_TIMEOUT)
+                        .await
+                        .unwrap_or_else(|_| {
+                            tracing::warn!(%conversation_id, %message_id, "link preview generation timed out");
+                            vec![]
+                        });
+                    _ = tx.send((message_id, previews)).await;
+                });
+
+                Ok(())
+            }
+            EmbedState::Disable => {
+                self.pending_link_previews.remove(&message_id);
+                self.store_link_previews(message_id, vec![]).await
+            }
+        }
+    }
+
+    async fn store_link_previews(
+        &mut self,
+        message_id: Uuid,
+        previews: Vec<LinkPreviewDocument>,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+
+        let mut message_document = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
+
+        let keystore = pubkey_or_keystore(self)?;
+
+        let previews = message_document.set_link_previews(keypair, &previews, keystore.as_ref())?;
+
+        self.document
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        _ = self
+            .event_broadcast
+            .send(MessageEventKind::LinkPreviewsUpdated {
+                conversation_id,
+                message_id,
+            });
+
+        let event = MessagingEvents::LinkPreviews {
+            conversation_id,
+            message_id,
+            previews,
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    pub async fn send_event(&self, event: MessageEvent) -> Result<(), Error> {
+        event.validate()?;
+        let conversation_id = self.conversation_id;
+        let member = self.identity.did_key();
+
+        let event = MessagingEvents::Event {
+            conversation_id,
+            member,
+            event,
+            cancelled: false,
+        };
+        self.send_message_event(event).await
+    }
+
+    pub async fn cancel_event(&self, event: MessageEvent) -> Result<(), Error> {
+        event.validate()?;
+        let member = self.identity.did_key();
+        let conversation_id = self.conversation_id;
         let event = MessagingEvents::Event {
             conversation_id,
             member,
@@ -1849,6 +3736,21 @@ impl ConversationTask {
         self.send_message_event(event).await
     }
 
+    /// Cancel custom events from other members that were not refreshed within their ttl
+    fn expire_events(&mut self) {
+        let conversation_id = self.conversation_id;
+        for (did_key, event) in self.active_events.expired() {
+            let ev = MessageEventKind::EventCancelled {
+                conversation_id,
+                did_key,
+                event,
+            };
+            if let Err(e) = self.event_broadcast.send(ev) {
+                tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+            }
+        }
+    }
+
     pub async fn send_message_event(&self, event: MessagingEvents) -> Result<(), Error> {
         let event = serde_json::to_vec(&event)?;
 
@@ -1947,6 +3849,104 @@ impl ConversationTask {
         Ok(())
     }
 
+    fn can_invite(&self) -> Result<(), Error> {
+        if self.document.conversation_type() != ConversationType::Group {
+            return Err(Error::InvalidConversation);
+        }
+
+        let own_did = &self.identity.did_key();
+
+        if !self
+            .document
+            .creator
+            .as_ref()
+            .is_some_and(|creator| creator == own_did)
+            && !self
+                .document
+                .permissions
+                .has_permission(own_did, GroupPermission::AddParticipants)
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        Ok(())
+    }
+
+    async fn create_invite(
+        &mut self,
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Result<ConversationInvite, Error> {
+        self.can_invite()?;
+
+        let keypair = self.root.keypair();
+        let document =
+            ConversationInviteDocument::new(keypair, self.conversation_id, expiry, max_uses)?;
+        let invite = ConversationInvite::try_from(&document)?;
+
+        let mut list = self
+            .root
+            .get_conversation_invites(self.conversation_id)
+            .await?;
+        // Invites that can no longer be used are dropped to keep the list from growing indefinitely
+        list.retain(ConversationInviteDocument::is_valid);
+        list.push(document);
+
+        self.root
+            .set_conversation_invites(self.conversation_id, list)
+            .await?;
+
+        Ok(invite)
+    }
+
+    async fn list_invites(&self) -> Result<Vec<ConversationInvite>, Error> {
+        self.root
+            .get_conversation_invites(self.conversation_id)
+            .await?
+            .iter()
+            .map(ConversationInvite::try_from)
+            .collect()
+    }
+
+    async fn revoke_invite(&mut self, invite_id: Uuid) -> Result<(), Error> {
+        let mut list = self
+            .root
+            .get_conversation_invites(self.conversation_id)
+            .await?;
+        let index = list
+            .iter()
+            .position(|invite| invite.id() == invite_id)
+            .ok_or(Error::OtherWithContext("invite not found".into()))?;
+        list.remove(index);
+        self.root
+            .set_conversation_invites(self.conversation_id, list)
+            .await
+    }
+
+    /// Adds `member` to the conversation using an invite previously created by us
+    async fn redeem_invite(&mut self, invite_id: Uuid, member: &DID) -> Result<(), Error> {
+        let mut list = self
+            .root
+            .get_conversation_invites(self.conversation_id)
+            .await?;
+        let invite = list
+            .iter_mut()
+            .find(|invite| invite.id() == invite_id)
+            .ok_or(Error::OtherWithContext("invite not found".into()))?;
+
+        if !invite.is_valid() {
+            return Err(Error::OtherWithContext("invite is no longer valid".into()));
+        }
+
+        // Permissions are checked again here as they may have changed since the invite was created
+        self.add_participant(member).await?;
+
+        invite.members.push(member.clone());
+        self.root
+            .set_conversation_invites(self.conversation_id, list)
+            .await
+    }
+
     pub async fn remove_participant(
         &mut self,
         did_key: &DID,
@@ -1997,6 +3997,10 @@ impl ConversationTask {
 
         self.publish(None, event, true).await?;
 
+        if let Err(e) = self.rotate_key().await {
+            tracing::warn!(conversation_id = %self.conversation_id, error = %e, "Failed to rotate key");
+        }
+
         if broadcast {
             let new_event = ConversationEvents::DeleteConversation {
                 conversation_id: self.conversation_id,
@@ -2437,6 +4441,124 @@ impl ConversationTask {
         self.publish(None, event, true).await
     }
 
+    pub async fn set_message_retention(
+        &mut self,
+        retention: Option<Duration>,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        if self.document.conversation_type() == ConversationType::Group {
+            let Some(creator) = self.document.creator.as_ref() else {
+                return Err(Error::InvalidConversation);
+            };
+
+            let own_did = self.identity.did_key();
+
+            if !&self
+                .document
+                .permissions
+                .has_permission(&own_did, GroupPermission::EditGroupInfo)
+                && own_did.ne(creator)
+            {
+                return Err(Error::Unauthorized);
+            }
+        }
+
+        if retention.is_some_and(|retention| retention.is_zero()) {
+            return Err(Error::OtherWithContext(
+                "Message retention cannot be zero".into(),
+            ));
+        }
+
+        if self.document.message_retention == retention {
+            return Ok(());
+        }
+
+        self.document.message_retention = retention;
+
+        self.set_document().await?;
+
+        let ev = MessageEventKind::ConversationMessageRetentionChanged {
+            conversation_id,
+            retention,
+        };
+
+        let _ = self.event_broadcast.send(ev);
+
+        let event = MessagingEvents::UpdateConversation {
+            conversation: self.document.clone(),
+            kind: ConversationUpdateKind::ChangeMessageRetention { retention },
+        };
+
+        self.publish(None, event, true).await?;
+
+        self.remove_expired_messages().await;
+
+        Ok(())
+    }
+
+    /// Removes messages that have outlived the retention period of the conversation,
+    /// returning the duration until the next message is set to expire
+    pub async fn remove_expired_messages(&mut self) -> Option<Duration> {
+        let retention = self.document.message_retention?;
+        let conversation_id = self.conversation_id;
+
+        let (expired, next_expiry) = match self.document.expired_messages(&self.ipfs).await {
+            Ok(list) => list,
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to check for expired messages");
+                return Some(retention);
+            }
+        };
+
+        let next = next_expiry
+            .and_then(|date| (date - Utc::now()).to_std().ok())
+            .unwrap_or(retention);
+
+        if expired.is_empty() {
+            return Some(next);
+        }
+
+        if let Err(e) = self
+            .document
+            .delete_messages(&self.ipfs, expired.iter().map(|message| message.id))
+            .await
+        {
+            tracing::warn!(%conversation_id, error = %e, "unable to remove expired messages");
+            return Some(retention);
+        }
+
+        if let Err(e) = self.set_document().await {
+            tracing::warn!(%conversation_id, error = %e, "unable to update document");
+            return Some(retention);
+        }
+
+        for message in expired {
+            let message_id = message.id;
+
+            // Note: Attachments that are still referenced by another message, such as a forwarded copy, remain pinned
+            let cids = pins::attachment_cids(&message.attachments);
+            self.attachment_pins.release(message_id, cids.clone()).await;
+            for cid in cids {
+                if !self.attachment_pins.is_referenced(&cid).await {
+                    self.attachment_pins.unpin([cid]).await;
+                }
+            }
+
+            self.search_index.remove(message_id).await;
+            self.receipts.remove(message_id).await;
+            self.remove_from_thread(message_id).await;
+
+            tracing::debug!(%conversation_id, %message_id, "message expired");
+
+            let _ = self.event_broadcast.send(MessageEventKind::MessageDeleted {
+                conversation_id,
+                message_id,
+            });
+        }
+
+        Some(next)
+    }
+
     pub fn attach(
         &mut self,
         reply_id: Option<Uuid>,
@@ -2465,6 +4587,33 @@ impl ConversationTask {
         Ok((message_id, stream.boxed()))
     }
 
+    pub fn attach_voice_note(
+        &mut self,
+        reply_id: Option<Uuid>,
+        location: Location,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        let conversation_id = self.conversation_id;
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let stream = AttachmentStream::new(
+            &self.ipfs,
+            self.root.keypair(),
+            &self.identity.did_key(),
+            &self.file,
+            conversation_id,
+            keystore,
+            self.attachment_tx.clone(),
+        )
+        .set_reply(reply_id)
+        .set_locations(vec![location])?
+        .set_voice_note()?;
+
+        let message_id = stream.message_id();
+
+        Ok((message_id, stream.boxed()))
+    }
+
     async fn store_direct_for_attachment(&mut self, message: MessageDocument) -> Result<(), Error> {
         let conversation_id = self.conversation_id;
         let message_id = message.id;
@@ -2478,6 +4627,9 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.index_message(&message).await;
+        self.add_to_thread(&message).await;
+
         let event = MessageEventKind::MessageSent {
             conversation_id,
             message_id,
@@ -2575,6 +4727,57 @@ impl ConversationTask {
         Ok(stream)
     }
 
+    pub fn export(
+        &self,
+        path: PathBuf,
+        options: ExportOptions,
+    ) -> Result<ExportProgressStream, Error> {
+        let members = self
+            .document
+            .recipients()
+            .iter()
+            .filter_map(|did| did.to_peer_id().ok())
+            .collect::<Vec<_>>();
+
+        let keystore = pubkey_or_keystore(self)?;
+
+        Ok(export::export_conversation(
+            self.ipfs.clone(),
+            self.root.keypair().clone(),
+            keystore,
+            self.document.clone(),
+            members,
+            path,
+            options,
+        ))
+    }
+
+    pub fn export_archive(
+        &self,
+        path: PathBuf,
+        format: ExportFormat,
+    ) -> Result<ExportProgressStream, Error> {
+        let members = self
+            .document
+            .recipients()
+            .iter()
+            .filter_map(|did| did.to_peer_id().ok())
+            .collect::<Vec<_>>();
+
+        let owner = self.root.keypair().to_did()?;
+        let keystore = pubkey_or_keystore(self)?.right();
+
+        Ok(archive::export_archive(
+            self.ipfs.clone(),
+            owner,
+            keystore,
+            self.document.clone(),
+            members,
+            path,
+            format,
+        ))
+    }
+
     pub async fn publish(
         &mut self,
         message_id: Option<Uuid>,
@@ -2582,12 +4785,26 @@ impl ConversationTask {
         queue: bool,
     ) -> Result<(), Error> {
         let event = serde_json::to_vec(&event)?;
-        let keypair = self.root.keypair();
-        let own_did = self.identity.did_key();
+        self.publish_bytes(message_id, &event, queue).await
+    }
 
-        let key = self.conversation_key(None)?;
+    /// Publish a serialized event, encrypting it with the ratchet session or the conversation key
+    async fn publish_bytes(
+        &mut self,
+        message_id: Option<Uuid>,
+        event: &[u8],
+        queue: bool,
+    ) -> Result<(), Error> {
+        let bytes = match self.ratchet_encrypt(event).await? {
+            Some(bytes) => bytes,
+            None => {
+                let key = self.conversation_key(None)?;
+                Cipher::direct_encrypt(event, &key)?
+            }
+        };
 
-        let bytes = Cipher::direct_encrypt(&event, &key)?;
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
 
         let payload = PayloadBuilder::new(keypair, bytes)
             .from_ipfs(&self.ipfs)
@@ -2650,6 +4867,64 @@ impl ConversationTask {
         self.save_queue().await
     }
 
+    /// Index the existing messages in the background, resolving them as they are indexed
+    async fn index_existing_messages(&self) {
+        let conversation_id = self.conversation_id;
+
+        if self.search_index.is_indexed(conversation_id).await {
+            return;
+        }
+
+        let Ok(keystore) = pubkey_or_keystore(self) else {
+            return;
+        };
+
+        let messages = match self
+            .document
+            .get_messages_stream(
+                &self.ipfs,
+                self.root.keypair(),
+                MessageOptions::default(),
+                keystore,
+            )
+            .await
+        {
+            Ok(messages) => messages,
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to index existing messages");
+                return;
+            }
+        };
+
+        let search_index = self.search_index.clone();
+        LocalExecutor.dispatch(async move {
+            search_index
+                .index_conversation(conversation_id, None, messages)
+                .await;
+        });
+    }
+
+    async fn index_message(&self, message: &MessageDocument) {
+        let conversation_id = self.conversation_id;
+        let keystore = match pubkey_or_keystore(self) {
+            Ok(keystore) => keystore,
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to index message");
+                return;
+            }
+        };
+
+        match message
+            .resolve(&self.ipfs, self.root.keypair(), true, keystore.as_ref())
+            .await
+        {
+            Ok(message) => self.search_index.insert(None, &message).await,
+            Err(e) => {
+                tracing::warn!(%conversation_id, message_id = %message.id, error = %e, "unable to index message")
+            }
+        }
+    }
+
     async fn save_queue(&self) {
         let key = format!("{}/{}", self.ipfs.messaging_queue(), self.conversation_id);
         let current_cid = self
@@ -2757,6 +5032,34 @@ impl ConversationTask {
     }
 }
 
+/// Replace the identity shared in a contact card with the latest document known to the identity cache,
+/// if that document is newer than the one embedded by the sender
+async fn refresh_contact_card(identity: &IdentityStore, message: &mut warp::raygun::Message) {
+    let Some(card) = message.contact_card() else {
+        return;
+    };
+
+    let did = card.did().clone();
+
+    let document = match did == identity.did_key() {
+        true => identity.own_identity_document().await.ok(),
+        false => identity
+            .cached_identities(std::slice::from_ref(&did))
+            .await
+            .pop(),
+    };
+
+    let Some(document) = document else {
+        return;
+    };
+
+    if document.verify().is_err() || document.modified <= card.modified() {
+        return;
+    }
+
+    message.set_contact_card(Some(ContactCard::from(&document)));
+}
+
 async fn message_event(
     this: &mut ConversationTask,
     sender: &DID,
@@ -2770,11 +5073,16 @@ async fn message_event(
     let keystore = pubkey_or_keystore(&*this)?;
 
     match events {
-        MessagingEvents::New { message } => {
+        MessagingEvents::New { mut message } => {
             if !message.verify() {
                 return Err(Error::InvalidMessage);
             }
 
+            // Votes and stops are only accepted through their own events once the message is received
+            message.votes.clear();
+            message.poll_closed = None;
+            message.geolocation_stopped = None;
+
             if this.document.id != message.conversation_id {
                 return Err(Error::InvalidConversation);
             }
@@ -2793,6 +5101,17 @@ async fn message_event(
                 return Err(Error::MessageFound);
             }
 
+            if let Some(retention) = this
+                .document
+                .message_retention
+                .and_then(|retention| chrono::Duration::from_std(retention).ok())
+            {
+                if message.date + retention <= Utc::now() {
+                    tracing::debug!(%conversation_id, %message_id, "message already expired. Skipping");
+                    return Ok(());
+                }
+            }
+
             let resolved_message = message
                 .resolve(&this.ipfs, keypair, false, keystore.as_ref())
                 .await?;
@@ -2826,21 +5145,71 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.search_index.insert(None, &resolved_message).await;
+            this.add_to_thread(&message).await;
+
+            // Resolve the identity shared in a contact card once when it is received so that reads can refresh
+            // the card from the identity cache without a network round-trip
+            if let Some(did) = resolved_message
+                .contact_card()
+                .map(|card| card.did().clone())
+                .filter(|did| did.ne(&own_did))
+            {
+                let identity = this.identity.clone();
+                LocalExecutor.dispatch(async move {
+                    if let Err(e) = identity.lookup(did.clone()).await {
+                        tracing::warn!(%conversation_id, %did, error = %e, "unable to lookup identity of contact card");
+                    }
+                });
+            }
+
+            if let Some(until) = resolved_message
+                .geolocation()
+                .filter(|geolocation| geolocation.is_live())
+                .and_then(|geolocation| geolocation.live_until())
+            {
+                this.live_geolocations
+                    .insert(message_id, (message.sender.to_did(), until));
+            }
+
+            let is_own_message = message.sender.to_did().eq(&own_did);
+            let mentioned = !is_own_message
+                && mention::is_mentioned(resolved_message.mentions(), &own_did, &[]);
+            let should_notify = !is_own_message && this.should_notify(mentioned).await;
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    should_notify,
                 })
             {
                 tracing::warn!(%conversation_id, "Error broadcasting event: {e}");
             }
+
+            if mentioned {
+                if let Err(e) = this.event_broadcast.send(MessageEventKind::Mentioned {
+                    conversation_id,
+                    message_id,
+                    should_notify,
+                }) {
+                    tracing::warn!(%conversation_id, "Error broadcasting event: {e}");
+                }
+            }
+
+            if message.sender.to_did().ne(&own_did) {
+                if let Err(e) = this.send_receipt(message_id, ReceiptKind::Delivered).await {
+                    tracing::warn!(%conversation_id, %message_id, error = %e, "unable to send delivery receipt");
+                }
+            }
         }
         MessagingEvents::Edit {
             conversation_id,
             message_id,
             modified,
             lines,
+            mentions,
             nonce,
             signature,
         } => {
@@ -2874,7 +5243,10 @@ async fn message_event(
                 });
             }
 
+            let was_mentioned = mention::is_mentioned(message.mentions(), &own_did, &[]);
+
             *message.lines_mut() = lines;
+            message.set_mentions(mentions);
             message.set_modified(modified);
 
             let sender = message.sender().to_owned();
@@ -2896,12 +5268,29 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.index_message(&message_document).await;
+
             if let Err(e) = this.event_broadcast.send(MessageEventKind::MessageEdited {
                 conversation_id,
                 message_id,
             }) {
                 tracing::error!(%conversation_id, error = %e, "Error broadcasting event");
             }
+
+            // Only notify if the edit added a mention
+            if sender.ne(&own_did)
+                && !was_mentioned
+                && mention::is_mentioned(&message_document.mentions, &own_did, &[])
+            {
+                let should_notify = this.should_notify(true).await;
+                if let Err(e) = this.event_broadcast.send(MessageEventKind::Mentioned {
+                    conversation_id,
+                    message_id,
+                    should_notify,
+                }) {
+                    tracing::error!(%conversation_id, error = %e, "Error broadcasting event");
+                }
+            }
         }
         MessagingEvents::Delete {
             conversation_id,
@@ -2925,6 +5314,10 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.search_index.remove(message_id).await;
+            this.receipts.remove(message_id).await;
+            this.remove_from_thread(message_id).await;
+
             if let Err(e) = this.event_broadcast.send(MessageEventKind::MessageDeleted {
                 conversation_id,
                 message_id,
@@ -3093,6 +5486,8 @@ async fn message_event(
             conversation.messages = this.document.messages;
             conversation.favorite = this.document.favorite;
             conversation.archived = this.document.archived;
+            // Retention can only be changed through `ConversationUpdateKind::ChangeMessageRetention`
+            conversation.message_retention = this.document.message_retention;
 
             match kind {
                 ConversationUpdateKind::AddParticipant { did } => {
@@ -3149,6 +5544,12 @@ async fn message_event(
 
                     this.replace_document(conversation).await?;
 
+                    if this.document.recipients.contains(&this.identity.did_key()) {
+                        if let Err(e) = this.rotate_key().await {
+                            tracing::warn!(%conversation_id, error = %e, "Failed to rotate key");
+                        }
+                    }
+
                     if can_emit {
                         if let Err(e) =
                             this.event_broadcast
@@ -3326,6 +5727,188 @@ async fn message_event(
                         tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
                     }
                 }
+                ConversationUpdateKind::ChangeMessageRetention { retention } => {
+                    if this.document.conversation_type == ConversationType::Group
+                        && !this.document.creator.as_ref().is_some_and(|c| c == sender)
+                        && !this
+                            .document
+                            .permissions
+                            .has_permission(sender, GroupPermission::EditGroupInfo)
+                    {
+                        return Err(Error::Unauthorized);
+                    }
+
+                    if retention.is_some_and(|retention| retention.is_zero()) {
+                        return Err(Error::InvalidConversation);
+                    }
+
+                    if this.document.message_retention == retention {
+                        return Ok(());
+                    }
+
+                    conversation.message_retention = retention;
+
+                    this.replace_document(conversation).await?;
+                    if let Err(e) = this.event_broadcast.send(
+                        MessageEventKind::ConversationMessageRetentionChanged {
+                            conversation_id,
+                            retention,
+                        },
+                    ) {
+                        tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+                    }
+
+                    this.remove_expired_messages().await;
+                }
+            }
+        }
+        MessagingEvents::Vote {
+            conversation_id,
+            message_id,
+            vote,
+        } => {
+            if !this.document.recipients().contains(&vote.voter) {
+                return Err(Error::IdentityDoesntExist);
+            }
+
+            let mut message_document = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await?;
+
+            let message = message_document
+                .resolve(&this.ipfs, keypair, true, keystore.as_ref())
+                .await?;
+
+            let poll = message.poll().ok_or(Error::InvalidMessage)?;
+
+            let voter = vote.voter.clone();
+            let options = vote.options.clone();
+
+            if !message_document.vote(poll, vote)? {
+                return Ok(());
+            }
+
+            this.document
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            let event = match options.is_empty() {
+                true => MessageEventKind::PollVoteRetracted {
+                    conversation_id,
+                    message_id,
+                    did_key: voter,
+                },
+                false => MessageEventKind::PollVoted {
+                    conversation_id,
+                    message_id,
+                    did_key: voter,
+                    options,
+                },
+            };
+
+            if let Err(e) = this.event_broadcast.send(event) {
+                tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+            }
+        }
+        MessagingEvents::ClosePoll {
+            conversation_id,
+            message_id,
+            close,
+        } => {
+            let mut message_document = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await?;
+
+            if !message_document.close_poll(close)? {
+                return Ok(());
+            }
+
+            this.document
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            if let Err(e) = this.event_broadcast.send(MessageEventKind::PollClosed {
+                conversation_id,
+                message_id,
+            }) {
+                tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+            }
+        }
+        MessagingEvents::StopGeolocation {
+            conversation_id,
+            message_id,
+            stop,
+        } => {
+            let mut message_document = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await?;
+
+            if !message_document.stop_geolocation(stop)? {
+                return Ok(());
+            }
+
+            this.document
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            this.live_geolocations.remove(&message_id);
+
+            if let Err(e) = this
+                .event_broadcast
+                .send(MessageEventKind::GeolocationStopped {
+                    conversation_id,
+                    message_id,
+                    did_key: message_document.sender.to_did(),
+                })
+            {
+                tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+            }
+        }
+        MessagingEvents::LinkPreviews {
+            conversation_id,
+            message_id,
+            previews,
+        } => {
+            let mut message_document = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await?;
+
+            if !message_document.apply_link_previews(previews)? {
+                return Ok(());
+            }
+
+            // Fetch the thumbnails from the sender so the previews can be resolved locally
+            if let Err(e) = message_document
+                .resolve(&this.ipfs, keypair, false, keystore.as_ref())
+                .await
+            {
+                tracing::warn!(%conversation_id, %message_id, error = %e, "unable to resolve link previews");
+            }
+
+            this.document
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            if let Err(e) = this
+                .event_broadcast
+                .send(MessageEventKind::LinkPreviewsUpdated {
+                    conversation_id,
+                    message_id,
+                })
+            {
+                tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
             }
         }
         _ => {}
@@ -3365,64 +5948,13 @@ async fn process_request_response_event(
                     return Err(Error::IdentityDoesntExist);
                 }
 
-                let keystore = &mut this.keystore;
-
-                let raw_key = match keystore.get_latest(keypair, &own_did) {
-                    Ok(key) => key,
-                    Err(Error::PublicKeyDoesntExist) => {
-                        let key = generate::<64>().into();
-                        keystore.insert(keypair, &own_did, &key)?;
-
-                        this.set_keystore(None).await?;
-                        key
-                    }
-                    Err(e) => {
-                        tracing::error!(%conversation_id, error = %e, "Error getting key from store");
-                        return Err(e);
-                    }
-                };
-
-                let key = ecdh_encrypt(keypair, Some(&sender), raw_key)?;
-
-                let response = ConversationRequestResponse::Response {
-                    conversation_id,
-                    kind: ConversationResponseKind::Key { key },
-                };
-
-                let topic = this.document.exchange_topic(&sender);
-
-                let bytes = ecdh_encrypt(keypair, Some(&sender), serde_json::to_vec(&response)?)?;
-
-                let payload = PayloadBuilder::new(keypair, bytes)
-                    .from_ipfs(&this.ipfs)
-                    .await?;
-
-                let peers = this.ipfs.pubsub_peers(Some(topic.clone())).await?;
-
-                let peer_id = sender.to_peer_id()?;
-
-                let bytes = payload.to_bytes()?;
-
-                tracing::trace!(%conversation_id, "Payload size: {} bytes", bytes.len());
+                if this.keystore.get_latest(keypair, &own_did).is_err() {
+                    this.keystore.insert(keypair, &own_did, generate::<64>())?;
+                    this.set_keystore(None).await?;
+                }
 
                 tracing::info!(%conversation_id, "Responding to {sender}");
-
-                if !peers.contains(&peer_id)
-                    || (peers.contains(&peer_id)
-                        && this
-                            .ipfs
-                            .pubsub_publish(topic.clone(), bytes)
-                            .await
-                            .is_err())
-                {
-                    tracing::warn!(%conversation_id, "Unable to publish to topic. Queuing event");
-                    // TODO
-                    this.queue_event(
-                        sender.clone(),
-                        QueueItem::direct(None, peer_id, topic.clone(), payload.message(None)?),
-                    )
-                    .await;
-                }
+                this.send_key(&sender).await?;
             }
             _ => {
                 tracing::info!(%conversation_id, "Unimplemented/Unsupported Event");
@@ -3432,7 +5964,7 @@ async fn process_request_response_event(
             conversation_id,
             kind,
         } => match kind {
-            ConversationResponseKind::Key { key } => {
+            ConversationResponseKind::Key { key, .. } => {
                 if !matches!(this.document.conversation_type(), ConversationType::Group) {
                     //Only group conversations support keys
                     tracing::error!(%conversation_id, "Invalid conversation type");
@@ -3475,7 +6007,13 @@ async fn process_pending_payload(this: &mut ConversationTask) {
 
     let mut processed_events: IndexSet<_> = IndexSet::new();
 
+    let recipients = _this.document.recipients();
+
     _this.pending_key_exchange.retain(|did, list| {
+        // Payloads from members removed while awaiting their key are discarded
+        if !recipients.contains(did) {
+            return false;
+        }
         list.retain(|(data, received)| {
             if *received {
                 processed_events.insert((did.clone(), data.clone()));
@@ -3494,8 +6032,7 @@ async fn process_pending_payload(this: &mut ConversationTask) {
 
         let event_fn = || {
             let keypair = root.keypair();
-            let key = store.get_latest(keypair, &sender)?;
-            let data = Cipher::direct_decrypt(&data, &key)?;
+            let data = store.try_decrypt(keypair, &sender, &data)?;
             let event = serde_json::from_slice(&data)?;
             Ok::<_, Error>(event)
         };
@@ -3525,34 +6062,149 @@ async fn process_conversation_event(
 
     let data = Cipher::direct_decrypt(&payload.message(None)?, &key)?;
 
-    let event = match serde_json::from_slice::<MessagingEvents>(&data)? {
-        event @ MessagingEvents::Event { .. } => event,
-        _ => return Err(Error::Other),
-    };
+    match serde_json::from_slice::<MessagingEvents>(&data)? {
+        MessagingEvents::Event {
+            conversation_id,
+            member,
+            event,
+            cancelled,
+        } => {
+            if member != sender || !this.document.recipients().contains(&member) {
+                return Err(Error::IdentityDoesntExist);
+            }
 
-    if let MessagingEvents::Event {
-        conversation_id,
-        member,
-        event,
-        cancelled,
-    } = event
-    {
-        let ev = match cancelled {
-            true => MessageEventKind::EventCancelled {
-                conversation_id,
-                did_key: member,
-                event,
-            },
-            false => MessageEventKind::EventReceived {
+            event.validate()?;
+
+            match cancelled {
+                true => this.active_events.remove(member.clone(), &event),
+                false => this.active_events.insert(member.clone(), &event),
+            }
+
+            let ev = match cancelled {
+                true => MessageEventKind::EventCancelled {
+                    conversation_id,
+                    did_key: member,
+                    event,
+                },
+                false => MessageEventKind::EventReceived {
+                    conversation_id,
+                    did_key: member,
+                    event,
+                },
+            };
+
+            if let Err(e) = this.event_broadcast.send(ev) {
+                tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+            }
+        }
+        MessagingEvents::Receipt {
+            conversation_id,
+            member,
+            message_ids,
+            kind,
+            date,
+        } => {
+            if member != sender || !this.document.recipients().contains(&member) {
+                return Err(Error::IdentityDoesntExist);
+            }
+
+            if message_ids.len() > MAX_RECEIPT_BATCH {
+                return Err(Error::InvalidLength {
+                    context: "message_ids".into(),
+                    current: message_ids.len(),
+                    minimum: None,
+                    maximum: Some(MAX_RECEIPT_BATCH),
+                });
+            }
+
+            let mut known = Vec::with_capacity(message_ids.len());
+            for message_id in message_ids {
+                if this.document.contains(&this.ipfs, message_id).await? {
+                    known.push(message_id);
+                }
+            }
+
+            let date = date.min(Utc::now());
+
+            for message_id in this.receipts.insert_many(&known, &member, kind, date).await {
+                let ev = match kind {
+                    ReceiptKind::Delivered => MessageEventKind::MessageDelivered {
+                        conversation_id,
+                        message_id,
+                        did_key: member.clone(),
+                    },
+                    ReceiptKind::Read => MessageEventKind::MessageRead {
+                        conversation_id,
+                        message_id,
+                        did_key: member.clone(),
+                    },
+                };
+
+                if let Err(e) = this.event_broadcast.send(ev) {
+                    tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+                }
+            }
+        }
+        MessagingEvents::GeolocationUpdate {
+            conversation_id,
+            message_id,
+            update,
+        } => {
+            if !this.document.recipients().contains(&sender) {
+                return Err(Error::IdentityDoesntExist);
+            }
+
+            if !update.verify(&sender, conversation_id, message_id) {
+                return Err(Error::InvalidSignature);
+            }
+
+            let live_until = match this.live_geolocations.get(&message_id) {
+                Some((did, until)) if did == &sender => *until,
+                Some(_) => return Err(Error::Unauthorized),
+                None => {
+                    // The geolocation may have been received before the task was started
+                    let message_document = this
+                        .document
+                        .get_message_document(&this.ipfs, message_id)
+                        .await?;
+
+                    if message_document.sender.to_did() != sender {
+                        return Err(Error::Unauthorized);
+                    }
+
+                    let keystore = pubkey_or_keystore(&*this)?;
+                    let message = message_document
+                        .resolve(&this.ipfs, this.root.keypair(), true, keystore.as_ref())
+                        .await?;
+
+                    let until = message
+                        .geolocation()
+                        .filter(|geolocation| geolocation.is_live())
+                        .and_then(|geolocation| geolocation.live_until())
+                        .ok_or(Error::OtherWithContext("geolocation is not live".into()))?;
+
+                    this.live_geolocations
+                        .insert(message_id, (sender.clone(), until));
+                    until
+                }
+            };
+
+            if update.date > live_until || live_until <= Utc::now() {
+                return Err(Error::OtherWithContext("geolocation is not live".into()));
+            }
+
+            let ev = MessageEventKind::GeolocationUpdated {
                 conversation_id,
-                did_key: member,
-                event,
-            },
-        };
+                message_id,
+                did_key: sender,
+                position: update.position,
+            };
 
-        if let Err(e) = this.event_broadcast.send(ev) {
-            tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+            if let Err(e) = this.event_broadcast.send(ev) {
+                tracing::error!(%conversation_id, error = %e, "error broadcasting event");
+            }
         }
+        _ => return Err(Error::Other),
     }
 
     Ok(())
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use indexmap::IndexMap;
+use rust_ipfs::Ipfs;
+use uuid::Uuid;
+use warp::raygun::{Message, MessagePage, Messages};
+
+use crate::store::{
+    conversation::reference::MessageReferenceList,
+    ds_key::{self, DataStoreKey},
+};
+
+type ThreadList = HashMap<String, IndexMap<String, DateTime<Utc>>>;
+
+/// Root of a thread, a reply to it and the date of the reply
+pub type Reply = (Uuid, Uuid, DateTime<Utc>);
+
+/// Replies to messages within a conversation or community, kept per root message
+pub struct Threads {
+    ipfs: Ipfs,
+    id: Uuid,
+    list: ThreadList,
+    loaded: bool,
+}
+
+impl Threads {
+    pub async fn load(ipfs: &Ipfs, id: Uuid) -> Self {
+        let key = format!("{}/{}", ipfs.message_threads(), id);
+
+        let list = ds_key::load_dag::<ThreadList>(ipfs, &key).await.ok();
+
+        Self {
+            ipfs: ipfs.clone(),
+            id,
+            loaded: list.is_some(),
+            list: list.unwrap_or_default(),
+        }
+    }
+
+    /// Returns true if the threads were previously stored
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Rebuild the threads from the replies of existing messages, keeping those recorded while they were collected
+    pub async fn rebuild(&mut self, replies: impl IntoIterator<Item = Reply>) {
+        for (root, message_id, date) in replies {
+            self.list
+                .entry(root.to_string())
+                .or_default()
+                .insert(message_id.to_string(), date);
+        }
+        self.loaded = true;
+        self.save().await;
+    }
+
+    /// Record `message_id` as a reply to `root`. Returns true if it was not previously recorded
+    pub async fn insert(&mut self, root: Uuid, message_id: Uuid, date: DateTime<Utc>) -> bool {
+        let replies = self.list.entry(root.to_string()).or_default();
+        if replies.insert(message_id.to_string(), date).is_some() {
+            return false;
+        }
+        self.save().await;
+        true
+    }
+
+    /// Remove the message from the threads, returning the root of the thread the message was a reply to
+    pub async fn remove(&mut self, message_id: Uuid) -> Option<Uuid> {
+        let id = message_id.to_string();
+        let mut changed = self.list.remove(&id).is_some();
+
+        let root = self
+            .list
+            .iter_mut()
+            .find_map(|(root, replies)| replies.shift_remove(&id).map(|_| root.clone()));
+
+        if let Some(root) = root.as_ref() {
+            if self.list.get(root).is_some_and(IndexMap::is_empty) {
+                self.list.remove(root);
+            }
+            changed = true;
+        }
+
+        if changed {
+            self.save().await;
+        }
+
+        root.and_then(|root| root.parse().ok())
+    }
+
+    /// Number of replies to `root` and the date of the latest reply
+    pub fn summary(&self, root: Uuid) -> (usize, Option<DateTime<Utc>>) {
+        summary(&self.list, root)
+    }
+
+    /// Replies to `root`, ordered by date
+    pub fn replies(&self, root: Uuid) -> Vec<Uuid> {
+        let Some(replies) = self.list.get(&root.to_string()) else {
+            return vec![];
+        };
+
+        let mut replies = replies
+            .iter()
+            .filter_map(|(id, date)| id.parse::<Uuid>().ok().map(|id| (id, *date)))
+            .collect::<Vec<_>>();
+
+        replies.sort_by_key(|(_, date)| *date);
+        replies.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// Set the reply count and latest reply of the message
+    pub fn apply(&self, message: &mut Message) {
+        apply(&self.list, message)
+    }
+
+    /// Set the reply count and latest reply of every message
+    pub fn apply_all(&self, messages: Messages) -> Messages {
+        match messages {
+            Messages::List(mut list) => {
+                list.iter_mut().for_each(|message| self.apply(message));
+                Messages::List(list)
+            }
+            Messages::Stream(stream) => {
+                let list = self.list.clone();
+                Messages::Stream(
+                    stream
+                        .map(move |mut message| {
+                            apply(&list, &mut message);
+                            message
+                        })
+                        .boxed(),
+                )
+            }
+            Messages::Page { pages, total } => {
+                let pages = pages
+                    .into_iter()
+                    .map(|page| {
+                        let mut messages = page.messages().to_vec();
+                        messages.iter_mut().for_each(|message| self.apply(message));
+                        MessagePage::new(page.id(), messages, page.total())
+                    })
+                    .collect();
+                Messages::Page { pages, total }
+            }
+        }
+    }
+
+    /// Remove all threads
+    pub async fn clear(&mut self) {
+        if self.list.is_empty() {
+            return;
+        }
+        self.list.clear();
+        self.save().await;
+    }
+
+    async fn save(&self) {
+        let key = format!("{}/{}", self.ipfs.message_threads(), self.id);
+        if let Err(e) = ds_key::store_dag(&self.ipfs, &key, &self.list).await {
+            tracing::error!(error = %e, "unable to save message threads");
+        }
+    }
+}
+
+/// Replies within the messages referenced by `refs`
+pub async fn collect_replies(ipfs: &Ipfs, refs: &MessageReferenceList) -> Vec<Reply> {
+    refs.list(ipfs)
+        .filter_map(|message| async move {
+            message.replied.map(|root| (root, message.id, message.date))
+        })
+        .collect()
+        .await
+}
+
+fn summary(list: &ThreadList, root: Uuid) -> (usize, Option<DateTime<Utc>>) {
+    list.get(&root.to_string())
+        .map(|replies| (replies.len(), replies.values().max().copied()))
+        .unwrap_or_default()
+}
+
+fn apply(list: &ThreadList, message: &mut Message) {
+    let (count, last_reply) = summary(list, message.id());
+    message.set_reply_count(count);
+    message.set_last_reply(last_reply);
+}
@@ -0,0 +1,269 @@
+use std::time::Duration;
+
+use warp::constellation::file::VoiceNote;
+use warp::error::Error;
+
+/// Number of values in the waveform of a voice note
+pub const WAVEFORM_SAMPLES: usize = 64;
+
+/// Opus always reports its granule position at 48kHz, regardless of the input sample rate
+const OPUS_SAMPLE_RATE: u64 = 48_000;
+
+const OGG_CAPTURE_PATTERN: &[u8] = b"OggS";
+const OGG_PAGE_HEADER_SIZE: usize = 27;
+const OPUS_HEAD: &[u8] = b"OpusHead";
+const OPUS_TAGS: &[u8] = b"OpusTags";
+
+/// Reads the Ogg pages of an Opus stream as it is being uploaded to describe the audio as a voice note.
+///
+/// Note: The audio is not decoded. Opus spends more bytes on louder and more complex frames while
+///       silence is encoded in a few bytes, so the size of each packet is used as its amplitude
+#[derive(Default)]
+pub struct VoiceNoteAnalyzer {
+    buffer: Vec<u8>,
+    serial: Option<u32>,
+    pre_skip: u64,
+    granule: u64,
+    headers: usize,
+    packet: Vec<u8>,
+    packets: Vec<usize>,
+    invalid: bool,
+}
+
+impl VoiceNoteAnalyzer {
+    pub fn update(&mut self, bytes: &[u8]) {
+        if self.invalid {
+            return;
+        }
+
+        self.buffer.extend_from_slice(bytes);
+
+        let mut offset = 0;
+
+        loop {
+            let page = &self.buffer[offset..];
+
+            if page.len() < OGG_PAGE_HEADER_SIZE {
+                break;
+            }
+
+            if !page.starts_with(OGG_CAPTURE_PATTERN) || page[4] != 0 {
+                self.invalid = true;
+                self.buffer.clear();
+                return;
+            }
+
+            let segments = page[26] as usize;
+            let header_size = OGG_PAGE_HEADER_SIZE + segments;
+
+            if page.len() < header_size {
+                break;
+            }
+
+            let lacing = &page[OGG_PAGE_HEADER_SIZE..header_size];
+            let body_size = lacing.iter().map(|lace| *lace as usize).sum::<usize>();
+
+            if page.len() < header_size + body_size {
+                break;
+            }
+
+            let granule = u64::from_le_bytes(page[6..14].try_into().expect("valid slice"));
+            let serial = u32::from_le_bytes(page[14..18].try_into().expect("valid slice"));
+            let lacing = lacing.to_vec();
+            let body = page[header_size..header_size + body_size].to_vec();
+
+            offset += header_size + body_size;
+
+            // Only the first logical stream is used if the container happens to be multiplexed
+            if *self.serial.get_or_insert(serial) != serial {
+                continue;
+            }
+
+            self.read_page(granule, &lacing, &body);
+
+            if self.invalid {
+                self.buffer.clear();
+                return;
+            }
+        }
+
+        self.buffer.drain(..offset);
+    }
+
+    fn read_page(&mut self, granule: u64, lacing: &[u8], body: &[u8]) {
+        let mut position = 0;
+        for lace in lacing {
+            let lace = *lace as usize;
+            self.packet
+                .extend_from_slice(&body[position..position + lace]);
+            position += lace;
+
+            // A lacing value below 255 terminates the packet, otherwise it continues in the next segment
+            if lace < 255 {
+                let packet = std::mem::take(&mut self.packet);
+                self.read_packet(&packet);
+            }
+        }
+
+        // A granule position of -1 indicates that no packet has finished on the page
+        if granule != u64::MAX {
+            self.granule = granule;
+        }
+    }
+
+    fn read_packet(&mut self, packet: &[u8]) {
+        match self.headers {
+            0 => {
+                if packet.len() < 19 || !packet.starts_with(OPUS_HEAD) {
+                    self.invalid = true;
+                    return;
+                }
+                self.pre_skip = u16::from_le_bytes([packet[10], packet[11]]) as u64;
+                self.headers += 1;
+            }
+            1 => {
+                if !packet.starts_with(OPUS_TAGS) {
+                    self.invalid = true;
+                    return;
+                }
+                self.headers += 1;
+            }
+            _ => self.packets.push(packet.len()),
+        }
+    }
+
+    pub fn finish(self) -> Result<VoiceNote, Error> {
+        if self.invalid || self.headers < 2 || self.packets.is_empty() {
+            return Err(Error::OtherWithContext(
+                "voice note must be an opus stream within an ogg container".into(),
+            ));
+        }
+
+        let samples = self.granule.saturating_sub(self.pre_skip) as u128;
+        let micros =
+            u64::try_from(samples * 1_000_000 / OPUS_SAMPLE_RATE as u128).map_err(|_| {
+                Error::OtherWithContext("voice note duration exceeds the supported range".into())
+            })?;
+        let duration = Duration::from_micros(micros);
+
+        Ok(VoiceNote::new(duration, waveform(&self.packets)))
+    }
+}
+
+/// Downsample the packet sizes into at most [`WAVEFORM_SAMPLES`] values, scaled to the loudest value
+fn waveform(packets: &[usize]) -> Vec<u8> {
+    let samples = packets.len().min(WAVEFORM_SAMPLES);
+
+    let buckets = (0..samples)
+        .map(|index| {
+            let start = index * packets.len() / samples;
+            let end = (index + 1) * packets.len() / samples;
+            let bucket = &packets[start..end];
+            bucket.iter().sum::<usize>() / bucket.len()
+        })
+        .collect::<Vec<_>>();
+
+    let max = buckets.iter().copied().max().unwrap_or_default();
+
+    if max == 0 {
+        return vec![0; samples];
+    }
+
+    buckets
+        .into_iter()
+        .map(|bucket| (bucket * u8::MAX as usize / max) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{VoiceNoteAnalyzer, WAVEFORM_SAMPLES};
+
+    fn page(serial: u32, granule: u64, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut lacing = vec![];
+        let mut body = vec![];
+        for packet in packets {
+            lacing.extend(vec![255; packet.len() / 255]);
+            lacing.push((packet.len() % 255) as u8);
+            body.extend_from_slice(packet);
+        }
+
+        let mut page = b"OggS".to_vec();
+        page.push(0);
+        page.push(0);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&[0; 8]);
+        page.push(lacing.len() as u8);
+        page.extend(lacing);
+        page.extend(body);
+        page
+    }
+
+    fn opus_head(pre_skip: u16) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 1]);
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        head
+    }
+
+    fn stream(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut stream = page(1, 0, &[opus_head(312)]);
+        stream.extend(page(1, 0, &[b"OpusTags".to_vec()]));
+        // Each packet holds 20ms of audio
+        for (index, chunk) in packets.chunks(10).enumerate() {
+            let granule = 312 + ((index * 10 + chunk.len()) as u64 * 960);
+            stream.extend(page(1, granule, chunk));
+        }
+        stream
+    }
+
+    #[test]
+    fn duration_and_waveform_from_pages() {
+        let mut packets = vec![vec![0; 3]; 100];
+        packets[50] = vec![0; 300];
+
+        let stream = stream(&packets);
+
+        let mut analyzer = VoiceNoteAnalyzer::default();
+        // Pages should be read regardless of how the stream is chunked
+        for chunk in stream.chunks(7) {
+            analyzer.update(chunk);
+        }
+
+        let voice_note = analyzer.finish().expect("valid voice note");
+        assert_eq!(voice_note.duration(), Duration::from_secs(2));
+        assert_eq!(voice_note.waveform().len(), WAVEFORM_SAMPLES);
+        assert_eq!(voice_note.waveform().iter().max(), Some(&u8::MAX));
+        assert!(voice_note.waveform()[0] < u8::MAX);
+    }
+
+    #[test]
+    fn duration_overflow_is_rejected() {
+        let mut analyzer = VoiceNoteAnalyzer::default();
+        analyzer.update(&page(1, 0, &[opus_head(0)]));
+        analyzer.update(&page(1, 0, &[b"OpusTags".to_vec()]));
+        analyzer.update(&page(1, u64::MAX - 1, &[vec![0; 3]]));
+        assert!(analyzer.finish().is_err());
+    }
+
+    #[test]
+    fn non_opus_stream_is_rejected() {
+        let mut analyzer = VoiceNoteAnalyzer::default();
+        analyzer.update(b"RIFF....WAVEfmt ");
+        assert!(analyzer.finish().is_err());
+
+        let mut analyzer = VoiceNoteAnalyzer::default();
+        analyzer.update(&page(1, 0, &[b"\x80theora".to_vec()]));
+        assert!(analyzer.finish().is_err());
+
+        let mut analyzer = VoiceNoteAnalyzer::default();
+        analyzer.update(&page(1, 0, &[opus_head(0)]));
+        analyzer.update(&page(1, 0, &[b"OpusTags".to_vec()]));
+        assert!(analyzer.finish().is_err());
+    }
+}
@@ -10,11 +10,14 @@ pub mod message;
 pub mod payload;
 pub mod phonebook;
 pub mod queue;
+pub mod ratchet;
+pub mod search;
 
 use chrono::{DateTime, Utc};
 use community::{CommunityChannelDocument, CommunityDocument, CommunityRoleDocument};
 use rust_ipfs as ipfs;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::store::community::CommunityInviteDocument;
@@ -31,11 +34,18 @@ use warp::{
     multipass::identity::IdentityStatus,
     raygun::{
         community::{CommunityChannelPermission, CommunityPermission, RoleId},
-        GroupPermissions, MessageEvent, PinState, ReactionState,
+        GroupPermissions, Mention, MessageEvent, PinState, ReactionState,
     },
 };
 
-use conversation::{message::MessageDocument, ConversationDocument};
+use conversation::{
+    geolocation::{GeolocationStopDocument, GeolocationUpdateDocument},
+    invite::ConversationInviteToken,
+    link_preview::LinkPreviewsDocument,
+    message::MessageDocument,
+    poll::{PollCloseDocument, PollVoteDocument},
+    ConversationDocument,
+};
 
 pub const MAX_THUMBNAIL_SIZE: usize = 5_242_880;
 pub const MAX_IMAGE_SIZE: usize = 2_097_152;
@@ -80,6 +90,22 @@ pub(crate) enum ConversationImageType {
 pub const MAX_CONVERSATION_DESCRIPTION: usize = 256;
 pub const MAX_COMMUNITY_DESCRIPTION: usize = 256;
 pub const MAX_REACTIONS: usize = 30;
+pub const MIN_POLL_OPTIONS: usize = 2;
+pub const MAX_POLL_OPTIONS: usize = 20;
+pub const MAX_POLL_OPTION_LENGTH: usize = 256;
+pub const MAX_SCHEDULED_MESSAGES: usize = 100;
+/// Attempts to send a scheduled message before it is dropped
+pub const MAX_SCHEDULED_MESSAGE_ATTEMPTS: usize = 5;
+/// Payloads set aside per member of a group conversation or community while awaiting their key
+pub const MAX_PENDING_PAYLOADS: usize = 256;
+/// Keys replaced by rotations that are handed out along with the latest key of a community member
+pub const MAX_KEY_HISTORY: usize = 32;
+/// Duration to wait for the inviter to add the own identity after accepting an invite
+pub const CONVERSATION_JOIN_TIMEOUT: Duration = Duration::from_secs(60);
+/// Interval at which delivery receipts are sent together
+pub const RECEIPT_BATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// Messages acknowledged within a single receipt event
+pub const MAX_RECEIPT_BATCH: usize = 256;
 
 pub(super) mod topics {
     use std::fmt::Display;
@@ -129,7 +155,12 @@ pub(super) mod topics {
 
 pub(super) mod ds_key {
 
+    use ipld_core::cid::Cid;
     use rust_ipfs::{Ipfs, Keypair, PeerId, PublicKey};
+    use serde::{de::DeserializeOwned, Serialize};
+    use warp::{crypto::zeroize::Zeroizing, error::Error};
+
+    use super::{ecdh_decrypt, ecdh_encrypt};
 
     pub trait DataStoreKey {
         fn base(&self) -> String;
@@ -149,6 +180,30 @@ pub(super) mod ds_key {
         fn request_queue(&self) -> String {
             self.base() + "/request_queue"
         }
+
+        fn search_index(&self) -> String {
+            self.base() + "/search_index"
+        }
+
+        fn message_receipts(&self) -> String {
+            self.base() + "/message_receipts"
+        }
+
+        fn message_threads(&self) -> String {
+            self.base() + "/message_threads"
+        }
+
+        fn prekeys(&self) -> String {
+            self.base() + "/prekeys"
+        }
+
+        fn ratchet_sessions(&self) -> String {
+            self.base() + "/ratchet_sessions"
+        }
+
+        fn attachment_pins(&self) -> String {
+            self.base() + "/attachment_pins"
+        }
     }
 
     impl DataStoreKey for Ipfs {
@@ -177,6 +232,119 @@ pub(super) mod ds_key {
             format!("/identity/{peer_id}")
         }
     }
+
+    async fn get_cid(ipfs: &Ipfs, key: &str) -> Option<Cid> {
+        ipfs.repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+            .and_then(|cid_str| cid_str.parse::<Cid>().ok())
+    }
+
+    /// Load the dag referenced by `key` within the data store
+    pub async fn load_dag<T: DeserializeOwned + Send>(ipfs: &Ipfs, key: &str) -> Result<T, Error> {
+        let cid = get_cid(ipfs, key).await.ok_or(Error::ObjectNotFound)?;
+        ipfs.get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(anyhow::Error::from)
+            .map_err(Error::from)
+    }
+
+    /// Store and pin `data`, referencing it by `key` within the data store and unpinning the dag it previously referenced
+    pub async fn store_dag<T: Serialize + Send>(
+        ipfs: &Ipfs,
+        key: &str,
+        data: T,
+    ) -> Result<Cid, Error> {
+        let current_cid = get_cid(ipfs, key).await;
+
+        let cid = ipfs.put_dag(data).pin(true).await?;
+
+        ipfs.repo()
+            .data_store()
+            .put(key.as_bytes(), cid.to_string().as_bytes())
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        if let Some(old_cid) = current_cid {
+            if old_cid != cid && ipfs.is_pinned(old_cid).await.unwrap_or_default() {
+                _ = ipfs.remove_pin(old_cid).recursive().await;
+            }
+        }
+
+        Ok(cid)
+    }
+
+    /// Load the dag referenced by `key` within the data store that was encrypted with `keypair`
+    pub async fn load_encrypted<T: DeserializeOwned>(
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        key: &str,
+    ) -> Result<T, Error> {
+        let bytes = load_dag::<Vec<u8>>(ipfs, key).await?;
+        let bytes = ecdh_decrypt(keypair, None, bytes)?;
+        serde_json::from_slice(&bytes).map_err(Error::from)
+    }
+
+    /// Encrypt `data` with `keypair` before storing it with [`store_dag`]
+    pub async fn store_encrypted<T: Serialize + Sync>(
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        key: &str,
+        data: &T,
+    ) -> Result<Cid, Error> {
+        let bytes = ecdh_encrypt(keypair, None, serde_json::to_vec(data)?)?;
+        store_dag(ipfs, key, bytes).await
+    }
+
+    /// Load the value stored with [`store_secret`]
+    pub async fn load_secret<T: DeserializeOwned>(
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        key: &str,
+    ) -> Result<T, Error> {
+        let bytes = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .map_err(anyhow::Error::from)?
+            .ok_or(Error::ObjectNotFound)?;
+        let bytes = Zeroizing::new(ecdh_decrypt(keypair, None, bytes)?);
+        serde_json::from_slice(&bytes).map_err(Error::from)
+    }
+
+    /// Encrypt `data` with `keypair` and store it within the data store itself, so that the previous value
+    /// is overwritten instead of being left behind in an unpinned block
+    pub async fn store_secret<T: Serialize + Sync>(
+        ipfs: &Ipfs,
+        keypair: &Keypair,
+        key: &str,
+        data: &T,
+    ) -> Result<(), Error> {
+        let bytes = Zeroizing::new(serde_json::to_vec(data)?);
+        let bytes = ecdh_encrypt(keypair, None, &*bytes)?;
+        ipfs.repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    /// Remove the value stored with [`store_secret`]
+    pub async fn remove_secret(ipfs: &Ipfs, key: &str) -> Result<(), Error> {
+        ipfs.repo()
+            .data_store()
+            .remove(key.as_bytes())
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
 }
 
 pub trait PeerIdExt {
@@ -306,6 +474,13 @@ pub enum ConversationEvents {
     DeleteConversation {
         conversation_id: Uuid,
     },
+    JoinConversation {
+        token: ConversationInviteToken,
+    },
+    JoinConversationRejected {
+        conversation_id: Uuid,
+        reason: String,
+    },
 
     NewCommunityInvite {
         community_id: Uuid,
@@ -335,6 +510,8 @@ pub enum ConversationRequestResponse {
 pub enum ConversationRequestKind {
     Acknowledge,
     Key,
+    /// Request for the latest key along with the keys it replaced, sent by a member that has yet to receive any of them
+    KeyHistory,
     Ping,
     RetrieveMessages {
         start: Option<DateTime<Utc>>,
@@ -348,9 +525,17 @@ pub enum ConversationRequestKind {
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ConversationResponseKind {
-    Key { key: Vec<u8> },
+    Key {
+        key: Vec<u8>,
+        /// Keys previously used by the sender, oldest first, so members that joined after
+        /// a rotation are able to read earlier messages
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        previous: Vec<Vec<u8>>,
+    },
     Pong,
-    HaveMessages { messages: Vec<Uuid> },
+    HaveMessages {
+        messages: Vec<Uuid>,
+    },
     AcknowledgementConfirmed,
 }
 
@@ -372,6 +557,8 @@ pub enum MessagingEvents {
         message_id: Uuid,
         modified: DateTime<Utc>,
         lines: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<Mention>,
         nonce: Vec<u8>,
         signature: Vec<u8>,
     },
@@ -392,6 +579,31 @@ pub enum MessagingEvents {
         state: ReactionState,
         emoji: String,
     },
+    Vote {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        vote: PollVoteDocument,
+    },
+    ClosePoll {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        close: PollCloseDocument,
+    },
+    GeolocationUpdate {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        update: GeolocationUpdateDocument,
+    },
+    StopGeolocation {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        stop: GeolocationStopDocument,
+    },
+    LinkPreviews {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        previews: LinkPreviewsDocument,
+    },
     UpdateConversation {
         conversation: ConversationDocument,
         kind: ConversationUpdateKind,
@@ -402,6 +614,13 @@ pub enum MessagingEvents {
         event: MessageEvent,
         cancelled: bool,
     },
+    Receipt {
+        conversation_id: Uuid,
+        member: DID,
+        message_ids: Vec<Uuid>,
+        kind: ReceiptKind,
+        date: DateTime<Utc>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -419,6 +638,8 @@ pub enum CommunityMessagingEvents {
         message_id: Uuid,
         modified: DateTime<Utc>,
         lines: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<Mention>,
         nonce: Vec<u8>,
         signature: Vec<u8>,
     },
@@ -442,6 +663,18 @@ pub enum CommunityMessagingEvents {
         state: ReactionState,
         emoji: String,
     },
+    Vote {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        vote: PollVoteDocument,
+    },
+    ClosePoll {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        close: PollCloseDocument,
+    },
     UpdateCommunity {
         community: CommunityDocument,
         kind: CommunityUpdateKind,
@@ -453,6 +686,21 @@ pub enum CommunityMessagingEvents {
         event: MessageEvent,
         cancelled: bool,
     },
+    Receipt {
+        community_id: Uuid,
+        channel_id: Uuid,
+        member: DID,
+        message_ids: Vec<Uuid>,
+        kind: ReceiptKind,
+        date: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -469,6 +717,7 @@ pub enum ConversationUpdateKind {
     RemovedIcon,
     RemovedBanner,
     ChangeDescription { description: Option<String> },
+    ChangeMessageRetention { retention: Option<Duration> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -452,6 +452,7 @@ mod test {
     use crate::store::payload::PayloadBuilder;
     use crate::store::PeerIdExt;
     use rust_ipfs::Keypair;
+    use warp::crypto::cipher::Cipher;
     use warp::crypto::rand::prelude::SliceRandom;
     use warp::crypto::{generate, rand};
 
@@ -608,6 +609,42 @@ mod test {
         Ok(())
     }
 
+    // Group conversations and communities encrypt each message once with a sender key distributed ahead of time
+    // instead of wrapping a key for every recipient, so the payload should not grow with the number of members
+    #[test]
+    fn payload_sender_key_compared_to_recipients() -> anyhow::Result<()> {
+        let data = String::from("Request");
+        let keypair = Keypair::generate_ed25519();
+        let sender_key = generate::<64>();
+
+        let mut previous_size = 0;
+
+        for count in [1, 10, 100] {
+            let members = (0..count)
+                .map(|_| Keypair::generate_ed25519().to_did())
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let per_recipient = PayloadBuilder::new(&keypair, data.clone())
+                .add_recipients(members)?
+                .build()?
+                .to_bytes()?;
+
+            let bytes = Cipher::direct_encrypt(data.as_bytes(), &sender_key)?;
+            let sender_keyed = PayloadBuilder::new(&keypair, bytes).build()?.to_bytes()?;
+
+            assert!(per_recipient.len() > previous_size);
+            assert!(sender_keyed.len() < per_recipient.len());
+
+            let de_payload: PayloadMessage<Vec<u8>> = PayloadMessage::from_bytes(&sender_keyed)?;
+            let message = Cipher::direct_decrypt(&de_payload.message(None)?, &sender_key)?;
+            assert_eq!(message, data.as_bytes());
+
+            previous_size = per_recipient.len();
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn payload_serde() -> anyhow::Result<()> {
         let data = String::from("Request");
@@ -0,0 +1,958 @@
+//! Sessions providing forward secrecy for direct conversations.
+//!
+//! A session is established with an X3DH-style key agreement against the signed prekey and one of
+//! the one-time prekeys that each identity publishes with its identity document, after which every
+//! message is encrypted with a key derived from a double ratchet. Prekeys are generated at random and
+//! their secrets are deleted once rotated out or used, and message keys are discarded once used, so a
+//! compromise of the identity key does not expose messages that were previously exchanged.
+//!
+//! If the key agreement refers to a prekey that no longer exists, such as a one-time prekey that was used by
+//! another peer, the responder replies with a [`SessionReset`] so the initiator establishes the session again
+//! and resends the messages that were not confirmed.
+//!
+//! Note: Forward secrecy only covers payloads exchanged over the network. Messages kept within the
+//!       conversation, including those synchronized to a shuttle node, are stored encrypted with a key
+//!       derived from the identity keys, so a compromise of the identity key still exposes the stored history.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use warp::crypto::{
+    cipher::Cipher,
+    did_key::{Generate, X25519KeyPair, ECDH},
+    generate,
+    hash::{hkdf_sha256, hmac_sha256, sha256_iter},
+    zeroize::{Zeroize, Zeroizing},
+    Ed25519KeyPair, KeyMaterial, DID,
+};
+use warp::error::Error;
+
+use super::{sealed::get_keypair_did, DidExt, PeerIdExt};
+
+/// Maximum number of message keys that can be skipped within a single chain
+const MAX_SKIP: u32 = 1000;
+
+/// Maximum number of skipped message keys retained for messages that have yet to arrive
+const MAX_SKIPPED_KEYS: usize = 2000;
+
+/// Maximum number of messages retained to be resent if the session is reset before the peer replies
+const MAX_UNCONFIRMED: usize = 100;
+
+const KEY_LENGTH: usize = 32;
+
+/// Number of one-time prekeys published with the identity
+pub const ONE_TIME_PREKEYS: usize = 20;
+
+/// Age after which the signed prekey is replaced
+const PREKEY_ROTATION_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Age after which a replaced signed prekey is deleted. Sessions initiated against it before then can still be established
+const PREKEY_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const PREKEY_CONTEXT: &[u8] = b"warp-prekey";
+const X3DH_INFO: &[u8] = b"warp-x3dh";
+const RATCHET_INFO: &[u8] = b"warp-ratchet";
+
+/// Prekey published with the identity document, allowing peers to establish a session with us while we are offline
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignedPreKey {
+    pub id: u32,
+    pub public_key: Bytes,
+    pub signature: Bytes,
+}
+
+impl SignedPreKey {
+    fn new(keypair: &Keypair, id: u32, public_key: Bytes) -> Result<Self, Error> {
+        let hash = prekey_hash(id, &public_key);
+        let signature = keypair.sign(&hash).expect("not RSA");
+
+        Ok(Self {
+            id,
+            public_key,
+            signature: signature.into(),
+        })
+    }
+
+    /// Verifies that the prekey was signed by `did`
+    pub fn verify(&self, did: &DID) -> Result<(), Error> {
+        if self.public_key.len() != KEY_LENGTH {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let public_key = did.to_public_key()?;
+
+        if !public_key.verify(&prekey_hash(self.id, &self.public_key), &self.signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+fn prekey_hash(id: u32, public_key: &[u8]) -> Vec<u8> {
+    sha256_iter(
+        [
+            Some(PREKEY_CONTEXT),
+            Some(&id.to_be_bytes()[..]),
+            Some(public_key),
+        ]
+        .into_iter(),
+        None,
+    )
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PreKeySecret {
+    id: u32,
+    secret: Vec<u8>,
+    created: DateTime<Utc>,
+}
+
+impl PreKeySecret {
+    fn generate(id: u32) -> Self {
+        Self {
+            id,
+            secret: generate::<KEY_LENGTH>().to_vec(),
+            created: Utc::now(),
+        }
+    }
+
+    fn keypair(&self) -> X25519KeyPair {
+        X25519KeyPair::from_secret_key(&self.secret)
+    }
+
+    fn sign(&self, keypair: &Keypair) -> Result<SignedPreKey, Error> {
+        SignedPreKey::new(keypair, self.id, self.keypair().public_key_bytes().into())
+    }
+
+    fn is_older_than(&self, age: Duration) -> bool {
+        chrono::Duration::from_std(age)
+            .map(|age| Utc::now() - self.created >= age)
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for PreKeySecret {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+/// Secrets of the prekeys published with our identity. These are generated at random, so they have to be
+/// stored, but are deleted once a prekey is rotated out or a one-time prekey has been used
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct PreKeyStore {
+    next_id: u32,
+    /// Signed prekeys, with the current one being last
+    signed: Vec<PreKeySecret>,
+    one_time: Vec<PreKeySecret>,
+}
+
+impl PreKeyStore {
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    /// Rotates the signed prekey, deletes replaced prekeys past their retention and replenishes the one-time prekeys.
+    /// Returns true if the prekeys changed and should be stored and published
+    pub fn refresh(&mut self) -> bool {
+        let mut changed = false;
+
+        if self.signed.last().map_or(true, |prekey| {
+            prekey.is_older_than(PREKEY_ROTATION_INTERVAL)
+        }) {
+            let id = self.next_id();
+            self.signed.push(PreKeySecret::generate(id));
+            changed = true;
+        }
+
+        let current = self.signed.pop();
+        let count = self.signed.len();
+        self.signed
+            .retain(|prekey| !prekey.is_older_than(PREKEY_RETENTION));
+        changed |= count != self.signed.len();
+        self.signed.extend(current);
+
+        while self.one_time.len() < ONE_TIME_PREKEYS {
+            let id = self.next_id();
+            self.one_time.push(PreKeySecret::generate(id));
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Current signed prekey to be published
+    pub fn signed_prekey(&self, keypair: &Keypair) -> Result<Option<SignedPreKey>, Error> {
+        self.signed
+            .last()
+            .map(|prekey| prekey.sign(keypair))
+            .transpose()
+    }
+
+    /// One-time prekeys to be published
+    pub fn one_time_prekeys(&self, keypair: &Keypair) -> Result<Vec<SignedPreKey>, Error> {
+        self.one_time
+            .iter()
+            .map(|prekey| prekey.sign(keypair))
+            .collect()
+    }
+
+    fn signed_secret(&self, id: u32) -> Result<X25519KeyPair, Error> {
+        self.signed
+            .iter()
+            .find(|prekey| prekey.id == id)
+            .map(PreKeySecret::keypair)
+            .ok_or(Error::OtherWithContext(
+                "signed prekey no longer exists".into(),
+            ))
+    }
+
+    fn one_time_secret(&self, id: u32) -> Result<X25519KeyPair, Error> {
+        self.one_time
+            .iter()
+            .find(|prekey| prekey.id == id)
+            .map(PreKeySecret::keypair)
+            .ok_or(Error::OtherWithContext(
+                "one-time prekey has already been used".into(),
+            ))
+    }
+
+    fn remove_one_time(&mut self, id: u32) {
+        self.one_time.retain(|prekey| prekey.id != id);
+    }
+
+    /// Returns true if the prekeys used by the key agreement still exist
+    fn contains(&self, init: &SessionInit) -> bool {
+        self.signed.iter().any(|prekey| prekey.id == init.prekey_id)
+            && init.one_time_prekey_id.map_or(true, |id| {
+                self.one_time.iter().any(|prekey| prekey.id == id)
+            })
+    }
+}
+
+fn identity_secret(keypair: &Keypair) -> Result<X25519KeyPair, Error> {
+    let did = get_keypair_did(keypair)?;
+    let secret = Zeroizing::new(did.private_key_bytes());
+    Ok(Ed25519KeyPair::from_secret_key(&secret).get_x25519())
+}
+
+fn identity_public(did: &DID) -> X25519KeyPair {
+    Ed25519KeyPair::from_public_key(&did.public_key_bytes()).get_x25519()
+}
+
+fn public_key(bytes: &[u8]) -> Result<X25519KeyPair, Error> {
+    if bytes.len() != KEY_LENGTH {
+        return Err(Error::PublicKeyInvalid);
+    }
+    Ok(X25519KeyPair::from_public_key(bytes))
+}
+
+fn x3dh(parts: Vec<Vec<u8>>) -> Zeroizing<[u8; KEY_LENGTH]> {
+    let material = Zeroizing::new(parts.concat());
+    for mut part in parts {
+        part.zeroize();
+    }
+    Zeroizing::new(hkdf_sha256(&[0u8; KEY_LENGTH], &material, X3DH_INFO))
+}
+
+fn kdf_root(root_key: &[u8], dh_output: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let output = Zeroizing::new(hkdf_sha256::<{ KEY_LENGTH * 2 }>(
+        root_key,
+        dh_output,
+        RATCHET_INFO,
+    ));
+    (output[..KEY_LENGTH].to_vec(), output[KEY_LENGTH..].to_vec())
+}
+
+fn kdf_chain(chain_key: &[u8]) -> (Vec<u8>, Zeroizing<Vec<u8>>) {
+    (
+        hmac_sha256(chain_key, &[0x02]),
+        Zeroizing::new(hmac_sha256(chain_key, &[0x01])),
+    )
+}
+
+/// Key agreement used by the initiator to establish a session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionInit {
+    pub prekey_id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_time_prekey_id: Option<u32>,
+    pub ephemeral_key: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RatchetHeader {
+    pub public_key: Bytes,
+    pub previous: u32,
+    pub index: u32,
+}
+
+/// Sent in reply to a key agreement that refers to a prekey that no longer exists, identifying the
+/// session by its ephemeral key. The payload carrying it is signed by the responder
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionReset {
+    pub reset: Bytes,
+}
+
+/// Payload of a message encrypted by a session
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RatchetMessage {
+    pub header: RatchetHeader,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init: Option<SessionInit>,
+    pub ciphertext: Bytes,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct SkippedKey {
+    public_key: Vec<u8>,
+    index: u32,
+    key: Vec<u8>,
+}
+
+impl Drop for SkippedKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RatchetSession {
+    dh_secret: Vec<u8>,
+    dh_public: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remote_dh: Option<Vec<u8>>,
+    root_key: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    send_chain: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recv_chain: Option<Vec<u8>>,
+    send_index: u32,
+    recv_index: u32,
+    previous_index: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<SkippedKey>,
+    /// Sent along with every message until the peer replies, so they are able to establish the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    local_init: Option<SessionInit>,
+    /// Key agreement used by the peer if they established the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remote_init: Option<SessionInit>,
+    /// Messages sent until the peer replies, so they can be resent if the session is reset
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unconfirmed: Vec<Vec<u8>>,
+}
+
+impl std::fmt::Debug for RatchetSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RatchetSession")
+            .field("send_index", &self.send_index)
+            .field("recv_index", &self.recv_index)
+            .field("skipped", &self.skipped.len())
+            .finish()
+    }
+}
+
+impl Drop for RatchetSession {
+    fn drop(&mut self) {
+        self.dh_secret.zeroize();
+        self.root_key.zeroize();
+        self.send_chain.zeroize();
+        self.recv_chain.zeroize();
+        self.unconfirmed.zeroize();
+    }
+}
+
+impl RatchetSession {
+    /// Establishes a session with `peer` using their signed prekey and one of their one-time prekeys, if any
+    pub fn initiate(
+        keypair: &Keypair,
+        peer: &DID,
+        prekey: &SignedPreKey,
+        one_time_prekey: Option<&SignedPreKey>,
+    ) -> Result<Self, Error> {
+        prekey.verify(peer)?;
+
+        let identity = identity_secret(keypair)?;
+        let ephemeral = X25519KeyPair::from_secret_key(&generate::<KEY_LENGTH>());
+        let remote_prekey = public_key(&prekey.public_key)?;
+
+        let mut parts = vec![
+            identity.key_exchange(&remote_prekey),
+            ephemeral.key_exchange(&identity_public(peer)),
+            ephemeral.key_exchange(&remote_prekey),
+        ];
+
+        if let Some(one_time_prekey) = one_time_prekey {
+            one_time_prekey.verify(peer)?;
+            parts.push(ephemeral.key_exchange(&public_key(&one_time_prekey.public_key)?));
+        }
+
+        let shared_key = x3dh(parts);
+
+        let ratchet = X25519KeyPair::from_secret_key(&generate::<KEY_LENGTH>());
+        let dh_output = Zeroizing::new(ratchet.key_exchange(&remote_prekey));
+        let (root_key, send_chain) = kdf_root(&*shared_key, &dh_output);
+
+        Ok(Self {
+            dh_secret: ratchet.private_key_bytes(),
+            dh_public: ratchet.public_key_bytes(),
+            remote_dh: Some(prekey.public_key.to_vec()),
+            root_key,
+            send_chain: Some(send_chain),
+            recv_chain: None,
+            send_index: 0,
+            recv_index: 0,
+            previous_index: 0,
+            skipped: vec![],
+            local_init: Some(SessionInit {
+                prekey_id: prekey.id,
+                one_time_prekey_id: one_time_prekey.map(|prekey| prekey.id),
+                ephemeral_key: ephemeral.public_key_bytes().into(),
+            }),
+            remote_init: None,
+            unconfirmed: vec![],
+        })
+    }
+
+    fn respond(
+        keypair: &Keypair,
+        peer: &DID,
+        init: &SessionInit,
+        prekeys: &PreKeyStore,
+    ) -> Result<Self, Error> {
+        let identity = identity_secret(keypair)?;
+        let prekey = prekeys.signed_secret(init.prekey_id)?;
+        let ephemeral = public_key(&init.ephemeral_key)?;
+
+        let mut parts = vec![
+            prekey.key_exchange(&identity_public(peer)),
+            identity.key_exchange(&ephemeral),
+            prekey.key_exchange(&ephemeral),
+        ];
+
+        if let Some(id) = init.one_time_prekey_id {
+            parts.push(prekeys.one_time_secret(id)?.key_exchange(&ephemeral));
+        }
+
+        let shared_key = x3dh(parts);
+
+        Ok(Self {
+            dh_secret: prekey.private_key_bytes(),
+            dh_public: prekey.public_key_bytes(),
+            remote_dh: None,
+            root_key: shared_key.to_vec(),
+            send_chain: None,
+            recv_chain: None,
+            send_index: 0,
+            recv_index: 0,
+            previous_index: 0,
+            skipped: vec![],
+            local_init: None,
+            remote_init: Some(init.clone()),
+            unconfirmed: vec![],
+        })
+    }
+
+    /// Decrypts a message from `peer`, establishing a new session if the message carries a key agreement.
+    /// Returns the plaintext along with the session that should be kept afterwards.
+    /// The one-time prekey used to establish a session is removed from `prekeys` once the session is kept
+    pub fn receive(
+        keypair: &Keypair,
+        peer: &DID,
+        session: Option<Self>,
+        message: &RatchetMessage,
+        prekeys: &mut PreKeyStore,
+    ) -> Result<(Vec<u8>, Self), Error> {
+        match (session, &message.init) {
+            (Some(mut session), init)
+                if init.is_none() || session.remote_init.as_ref() == init.as_ref() =>
+            {
+                let plaintext = session.decrypt(message)?;
+                Ok((plaintext, session))
+            }
+            (Some(session), Some(init))
+                if session.local_init.is_some()
+                    && keypair.to_did()?.to_string() < peer.to_string() =>
+            {
+                // Both sides initiated a session at the same time. The session of the identity that sorts first is kept,
+                // so the message is decrypted without replacing ours and the peer switches over once our message arrives.
+                // The one-time prekey is kept since messages from the peer may carry the key agreement until then
+                let mut remote_session = Self::respond(keypair, peer, init, prekeys)?;
+                let plaintext = remote_session.decrypt(message)?;
+                Ok((plaintext, session))
+            }
+            (_, Some(init)) => {
+                let mut session = Self::respond(keypair, peer, init, prekeys)?;
+                let plaintext = session.decrypt(message)?;
+                if let Some(id) = init.one_time_prekey_id {
+                    prekeys.remove_one_time(id);
+                }
+                Ok((plaintext, session))
+            }
+            (None, None) => Err(Error::OtherWithContext(
+                "no session has been established with the peer".into(),
+            )),
+        }
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<RatchetMessage, Error> {
+        let chain_key = self.send_chain.as_ref().ok_or(Error::OtherWithContext(
+            "session is unable to send until a message is received".into(),
+        ))?;
+
+        let (chain_key, message_key) = kdf_chain(chain_key);
+        self.send_chain = Some(chain_key);
+
+        let header = RatchetHeader {
+            public_key: self.dh_public.clone().into(),
+            previous: self.previous_index,
+            index: self.send_index,
+        };
+
+        self.send_index += 1;
+
+        let ciphertext = Cipher::direct_encrypt(plaintext, &message_key)?;
+
+        if self.local_init.is_some() {
+            self.unconfirmed.push(plaintext.to_vec());
+            if self.unconfirmed.len() > MAX_UNCONFIRMED {
+                let excess = self.unconfirmed.len() - MAX_UNCONFIRMED;
+                self.unconfirmed
+                    .drain(..excess)
+                    .for_each(|mut message| message.zeroize());
+            }
+        }
+
+        Ok(RatchetMessage {
+            header,
+            init: self.local_init.clone(),
+            ciphertext: ciphertext.into(),
+        })
+    }
+
+    /// Decrypts the message, leaving the session untouched if it fails
+    fn decrypt(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, Error> {
+        let mut session = self.clone();
+        let plaintext = session.try_decrypt(message)?;
+        *self = session;
+        Ok(plaintext)
+    }
+
+    fn try_decrypt(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, Error> {
+        let header = &message.header;
+
+        if let Some(position) = self
+            .skipped
+            .iter()
+            .position(|key| key.public_key == header.public_key && key.index == header.index)
+        {
+            let skipped = self.skipped.remove(position);
+            let plaintext = Cipher::direct_decrypt(&message.ciphertext, &skipped.key)?;
+            self.confirm();
+            return Ok(plaintext);
+        }
+
+        if self.remote_dh.as_deref() != Some(&header.public_key[..]) {
+            self.skip_message_keys(header.previous)?;
+            self.step(&header.public_key)?;
+        }
+
+        self.skip_message_keys(header.index)?;
+
+        let chain_key = self.recv_chain.as_ref().ok_or(Error::DecryptionError)?;
+        let (chain_key, message_key) = kdf_chain(chain_key);
+        self.recv_chain = Some(chain_key);
+        self.recv_index += 1;
+
+        let plaintext = Cipher::direct_decrypt(&message.ciphertext, &message_key)?;
+
+        // The peer replied, so they have established the session
+        self.confirm();
+
+        Ok(plaintext)
+    }
+
+    fn confirm(&mut self) {
+        self.local_init = None;
+        self.unconfirmed.zeroize();
+    }
+
+    /// Returns the reset to send to `peer` if `message` carries a key agreement against a prekey that no longer exists,
+    /// so they are able to establish the session again instead of sending messages that cannot be decrypted
+    pub fn reset(
+        keypair: &Keypair,
+        peer: &DID,
+        session: Option<&Self>,
+        message: &RatchetMessage,
+        prekeys: &PreKeyStore,
+    ) -> Result<Option<SessionReset>, Error> {
+        let Some(init) = &message.init else {
+            return Ok(None);
+        };
+
+        if prekeys.contains(init) {
+            return Ok(None);
+        }
+
+        if let Some(session) = session {
+            // The session was established with the key agreement before the one-time prekey was deleted
+            if session.remote_init.as_ref() == Some(init) {
+                return Ok(None);
+            }
+
+            // Our session is kept over the one initiated by the peer, who switches over once our message arrives
+            if session.local_init.is_some() && keypair.to_did()?.to_string() < peer.to_string() {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(SessionReset {
+            reset: init.ephemeral_key.clone(),
+        }))
+    }
+
+    /// Returns the messages that were not confirmed by the peer if `reset` refers to this session, in which case
+    /// the session should be discarded and established again
+    pub fn take_unconfirmed(&mut self, reset: &SessionReset) -> Option<Vec<Vec<u8>>> {
+        let init = self.local_init.as_ref()?;
+        if init.ephemeral_key != reset.reset {
+            return None;
+        }
+        Some(std::mem::take(&mut self.unconfirmed))
+    }
+
+    fn skip_message_keys(&mut self, until: u32) -> Result<(), Error> {
+        let Some(mut chain_key) = self.recv_chain.clone() else {
+            return Ok(());
+        };
+
+        if until.saturating_sub(self.recv_index) > MAX_SKIP {
+            return Err(Error::OtherWithContext(
+                "too many messages were skipped".into(),
+            ));
+        }
+
+        let public_key = self.remote_dh.clone().unwrap_or_default();
+
+        while self.recv_index < until {
+            let (next, message_key) = kdf_chain(&chain_key);
+            self.skipped.push(SkippedKey {
+                public_key: public_key.clone(),
+                index: self.recv_index,
+                key: message_key.to_vec(),
+            });
+            chain_key.zeroize();
+            chain_key = next;
+            self.recv_index += 1;
+        }
+
+        self.recv_chain = Some(chain_key);
+
+        if self.skipped.len() > MAX_SKIPPED_KEYS {
+            let excess = self.skipped.len() - MAX_SKIPPED_KEYS;
+            self.skipped.drain(..excess);
+        }
+
+        Ok(())
+    }
+
+    fn step(&mut self, remote_public_key: &[u8]) -> Result<(), Error> {
+        let remote = public_key(remote_public_key)?;
+
+        self.previous_index = self.send_index;
+        self.send_index = 0;
+        self.recv_index = 0;
+        self.remote_dh = Some(remote_public_key.to_vec());
+
+        let current = X25519KeyPair::from_secret_key(&self.dh_secret);
+        let dh_output = Zeroizing::new(current.key_exchange(&remote));
+        let (root_key, recv_chain) = kdf_root(&self.root_key, &dh_output);
+
+        let next = X25519KeyPair::from_secret_key(&generate::<KEY_LENGTH>());
+        let dh_output = Zeroizing::new(next.key_exchange(&remote));
+        let (root_key, send_chain) = kdf_root(&root_key, &dh_output);
+
+        self.dh_secret.zeroize();
+        self.root_key.zeroize();
+
+        self.dh_secret = next.private_key_bytes();
+        self.dh_public = next.public_key_bytes();
+        self.root_key = root_key;
+        self.recv_chain = Some(recv_chain);
+        self.send_chain = Some(send_chain);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rust_ipfs::Keypair;
+
+    use super::{PreKeyStore, RatchetMessage, RatchetSession, SignedPreKey, ONE_TIME_PREKEYS};
+    use crate::store::PeerIdExt;
+
+    struct Peer {
+        keypair: Keypair,
+        prekeys: PreKeyStore,
+    }
+
+    impl Peer {
+        fn new() -> Self {
+            let mut prekeys = PreKeyStore::default();
+            assert!(prekeys.refresh());
+            Self {
+                keypair: Keypair::generate_ed25519(),
+                prekeys,
+            }
+        }
+
+        fn prekeys(&self) -> anyhow::Result<(SignedPreKey, SignedPreKey)> {
+            let prekey = self
+                .prekeys
+                .signed_prekey(&self.keypair)?
+                .expect("signed prekey");
+            let one_time_prekey = self.prekeys.one_time_prekeys(&self.keypair)?.remove(0);
+            Ok((prekey, one_time_prekey))
+        }
+
+        fn initiate(&self, peer: &Peer) -> anyhow::Result<RatchetSession> {
+            let (prekey, one_time_prekey) = peer.prekeys()?;
+            Ok(RatchetSession::initiate(
+                &self.keypair,
+                &peer.keypair.to_did()?,
+                &prekey,
+                Some(&one_time_prekey),
+            )?)
+        }
+
+        fn receive(
+            &mut self,
+            peer: &Peer,
+            session: Option<RatchetSession>,
+            message: &RatchetMessage,
+        ) -> anyhow::Result<(String, RatchetSession)> {
+            let (plaintext, session) = RatchetSession::receive(
+                &self.keypair,
+                &peer.keypair.to_did()?,
+                session,
+                message,
+                &mut self.prekeys,
+            )?;
+            Ok((String::from_utf8(plaintext)?, session))
+        }
+    }
+
+    fn send(session: &mut RatchetSession, text: &str) -> RatchetMessage {
+        session.encrypt(text.as_bytes()).expect("encrypted")
+    }
+
+    #[test]
+    fn session_roundtrip() -> anyhow::Result<()> {
+        let mut alice = Peer::new();
+        let mut bob = Peer::new();
+
+        let mut alice_session = alice.initiate(&bob)?;
+
+        let message = send(&mut alice_session, "hello");
+        assert!(message.init.is_some());
+
+        let (text, mut bob_session) = bob.receive(&alice, None, &message)?;
+        assert_eq!(text, "hello");
+
+        let message = send(&mut bob_session, "hi");
+        assert!(message.init.is_none());
+
+        let (text, mut alice_session) = alice.receive(&bob, Some(alice_session), &message)?;
+        assert_eq!(text, "hi");
+
+        let message = send(&mut alice_session, "how are you?");
+        assert!(message.init.is_none());
+
+        let (text, _) = bob.receive(&alice, Some(bob_session), &message)?;
+        assert_eq!(text, "how are you?");
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_order_messages() -> anyhow::Result<()> {
+        let alice = Peer::new();
+        let mut bob = Peer::new();
+
+        let mut alice_session = alice.initiate(&bob)?;
+
+        let messages = ["one", "two", "three"].map(|text| send(&mut alice_session, text));
+
+        let (text, bob_session) = bob.receive(&alice, None, &messages[2])?;
+        assert_eq!(text, "three");
+        let (text, bob_session) = bob.receive(&alice, Some(bob_session), &messages[0])?;
+        assert_eq!(text, "one");
+        let (text, bob_session) = bob.receive(&alice, Some(bob_session), &messages[1])?;
+        assert_eq!(text, "two");
+
+        // Message keys are discarded once used
+        assert!(bob
+            .receive(&alice, Some(bob_session), &messages[1])
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn simultaneous_initiation() -> anyhow::Result<()> {
+        let mut alice = Peer::new();
+        let mut bob = Peer::new();
+
+        let mut alice_session = alice.initiate(&bob)?;
+        let mut bob_session = bob.initiate(&alice)?;
+
+        let from_alice = send(&mut alice_session, "from alice");
+        let from_bob = send(&mut bob_session, "from bob");
+
+        let (text, mut alice_session) = alice.receive(&bob, Some(alice_session), &from_bob)?;
+        assert_eq!(text, "from bob");
+        let (text, mut bob_session) = bob.receive(&alice, Some(bob_session), &from_alice)?;
+        assert_eq!(text, "from alice");
+
+        // Both sides should have settled on the same session
+        let message = send(&mut alice_session, "ping");
+        let (text, mut bob_session) = bob.receive(&alice, Some(bob_session), &message)?;
+        assert_eq!(text, "ping");
+
+        let message = send(&mut bob_session, "pong");
+        let (text, _) = alice.receive(&bob, Some(alice_session), &message)?;
+        assert_eq!(text, "pong");
+
+        Ok(())
+    }
+
+    #[test]
+    fn prekey_signature() -> anyhow::Result<()> {
+        let alice = Peer::new();
+        let bob = Peer::new();
+
+        let (mut prekey, _) = bob.prekeys()?;
+        assert!(prekey.verify(&bob.keypair.to_did()?).is_ok());
+        assert!(prekey.verify(&alice.keypair.to_did()?).is_err());
+
+        prekey.id += 1;
+        assert!(prekey.verify(&bob.keypair.to_did()?).is_err());
+        assert!(
+            RatchetSession::initiate(&alice.keypair, &bob.keypair.to_did()?, &prekey, None)
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn one_time_prekey_is_used_once() -> anyhow::Result<()> {
+        let alice = Peer::new();
+        let mut bob = Peer::new();
+
+        let mut first_session = alice.initiate(&bob)?;
+        let mut second_session = alice.initiate(&bob)?;
+
+        let message = send(&mut first_session, "first");
+        let (text, _) = bob.receive(&alice, None, &message)?;
+        assert_eq!(text, "first");
+
+        // The secret of the one-time prekey is deleted once a session is established with it
+        let message = send(&mut second_session, "second");
+        assert!(bob.receive(&alice, None, &message).is_err());
+
+        assert!(bob.prekeys.refresh());
+        assert_eq!(
+            bob.prekeys.one_time_prekeys(&bob.keypair)?.len(),
+            ONE_TIME_PREKEYS
+        );
+        assert!(!bob.prekeys.refresh());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reused_one_time_prekey_resets_session() -> anyhow::Result<()> {
+        let alice = Peer::new();
+        let mut carol = Peer::new();
+        let mut bob = Peer::new();
+        let bob_did = bob.keypair.to_did()?;
+
+        // Both peers picked the same one-time prekey from the published identity
+        let mut alice_session = alice.initiate(&bob)?;
+        let mut carol_session = carol.initiate(&bob)?;
+        assert_eq!(
+            alice_session
+                .local_init
+                .as_ref()
+                .map(|init| init.one_time_prekey_id),
+            carol_session
+                .local_init
+                .as_ref()
+                .map(|init| init.one_time_prekey_id),
+        );
+
+        let message = send(&mut alice_session, "from alice");
+        let (text, bob_alice_session) = bob.receive(&alice, None, &message)?;
+        assert_eq!(text, "from alice");
+
+        // A duplicate of a message that established the session does not reset it
+        assert!(bob
+            .receive(&alice, Some(bob_alice_session.clone()), &message)
+            .is_err());
+        assert!(RatchetSession::reset(
+            &bob.keypair,
+            &alice.keypair.to_did()?,
+            Some(&bob_alice_session),
+            &message,
+            &bob.prekeys
+        )?
+        .is_none());
+
+        let messages = ["one", "two"].map(|text| send(&mut carol_session, text));
+        assert!(bob.receive(&carol, None, &messages[0]).is_err());
+
+        let reset = RatchetSession::reset(
+            &bob.keypair,
+            &carol.keypair.to_did()?,
+            None,
+            &messages[0],
+            &bob.prekeys,
+        )?
+        .expect("reset");
+
+        // The reset only applies to the session it was sent for
+        assert!(alice_session.take_unconfirmed(&reset).is_none());
+
+        let unconfirmed = carol_session
+            .take_unconfirmed(&reset)
+            .expect("reset session");
+        assert_eq!(unconfirmed, [b"one".to_vec(), b"two".to_vec()]);
+
+        let (prekey, _) = bob.prekeys()?;
+        let mut carol_session = RatchetSession::initiate(&carol.keypair, &bob_did, &prekey, None)?;
+
+        let mut bob_session = None;
+        for (plaintext, expected) in unconfirmed.iter().zip(["one", "two"]) {
+            let message = carol_session.encrypt(plaintext)?;
+            let (text, session) = bob.receive(&carol, bob_session.take(), &message)?;
+            assert_eq!(text, expected);
+            bob_session = Some(session);
+        }
+
+        let mut bob_session = bob_session.expect("established session");
+        let message = send(&mut bob_session, "hi carol");
+        let (text, _) = carol.receive(&bob, Some(carol_session), &message)?;
+        assert_eq!(text, "hi carol");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,668 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, stream::BoxStream, StreamExt};
+use futures_timer::Delay;
+use ipld_core::cid::Cid;
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::{
+    crypto::DID,
+    error::Error,
+    raygun::{Message, SearchQuery, SearchResult},
+};
+
+use super::{
+    ds_key::{self, DataStoreKey},
+    ecdh_decrypt, ecdh_encrypt,
+};
+use crate::rt::{Executor, LocalExecutor};
+
+/// Amount of words surrounding the first match that would be included in a snippet
+const SNIPPET_WORDS_BEFORE: usize = 6;
+const SNIPPET_WORDS_AFTER: usize = 12;
+
+/// Delay before changes to the index are stored, so a burst of messages would be stored together
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Amount of existing messages that would be indexed at once
+const INDEX_BATCH_SIZE: usize = 100;
+
+/// Inverted index over the contents of messages from conversations and community channels.
+/// The messages are stored in a shard per conversation or channel, each encrypted with the local keypair,
+/// so that a change would only store the shard it belongs to.
+#[derive(Clone)]
+pub struct SearchIndex {
+    inner: Arc<RwLock<IndexDocument>>,
+    save_tx: mpsc::UnboundedSender<Uuid>,
+}
+
+impl SearchIndex {
+    pub async fn new(ipfs: &Ipfs, keypair: &Keypair) -> Self {
+        let key = ipfs.search_index();
+
+        let pointers: BTreeMap<String, Cid> =
+            ds_key::load_dag(ipfs, &key).await.unwrap_or_default();
+
+        let mut document = IndexDocument::default();
+        for (id, cid) in pointers {
+            let Ok(id) = id.parse::<Uuid>() else {
+                continue;
+            };
+
+            match load_shard(ipfs, keypair, cid).await {
+                Ok(shard) => document.insert_shard(id, shard),
+                Err(e) => tracing::warn!(%id, error = %e, "unable to load search index shard"),
+            }
+        }
+
+        let inner = Arc::new(RwLock::new(document));
+        let (save_tx, save_rx) = mpsc::unbounded();
+
+        LocalExecutor.dispatch(save_task(
+            ipfs.clone(),
+            keypair.clone(),
+            inner.clone(),
+            save_rx,
+        ));
+
+        Self { inner, save_tx }
+    }
+
+    /// Insert or replace a message within the index
+    pub async fn insert(&self, community_id: Option<Uuid>, message: &Message) {
+        let inner = &mut *self.inner.write().await;
+        inner.insert(community_id, message);
+        self.save(message.conversation_id());
+    }
+
+    /// Remove a message from the index
+    pub async fn remove(&self, message_id: Uuid) {
+        let inner = &mut *self.inner.write().await;
+        let Some(conversation_id) = inner.entries.get(&message_id).map(|e| e.conversation_id)
+        else {
+            return;
+        };
+        inner.remove(message_id);
+        self.save(conversation_id);
+    }
+
+    /// Remove every message belonging to a conversation or community
+    pub async fn remove_conversation(&self, id: Uuid) {
+        let inner = &mut *self.inner.write().await;
+        let mut shards = inner
+            .entries
+            .values()
+            .filter(|entry| entry.conversation_id == id || entry.community_id == Some(id))
+            .map(|entry| entry.conversation_id)
+            .collect::<BTreeSet<_>>();
+        shards.insert(id);
+        inner.remove_conversation(id);
+        for shard in shards {
+            self.save(shard);
+        }
+    }
+
+    /// Returns true if the existing messages of the conversation or channel have been indexed
+    pub async fn is_indexed(&self, conversation_id: Uuid) -> bool {
+        let inner = &*self.inner.read().await;
+        inner.indexed.contains(&conversation_id)
+    }
+
+    /// Index existing messages of a conversation or channel that may have been stored prior to the index existing.
+    /// The messages are indexed in batches as they are resolved so the index is not held for the whole history.
+    pub async fn index_conversation(
+        &self,
+        conversation_id: Uuid,
+        community_id: Option<Uuid>,
+        messages: BoxStream<'static, Message>,
+    ) {
+        let mut messages = messages.chunks(INDEX_BATCH_SIZE);
+        while let Some(batch) = messages.next().await {
+            let inner = &mut *self.inner.write().await;
+            for message in batch.iter() {
+                inner.insert(community_id, message);
+            }
+        }
+
+        let inner = &mut *self.inner.write().await;
+        inner.indexed.insert(conversation_id);
+        self.save(conversation_id);
+    }
+
+    pub async fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+        let inner = &*self.inner.read().await;
+        inner.search(query)
+    }
+
+    fn save(&self, shard: Uuid) {
+        _ = self.save_tx.unbounded_send(shard);
+    }
+}
+
+/// Stores the shards that changed, waiting for [`SAVE_DELAY`] after the first change so that further changes
+/// would be stored together. The task ends once every handle to the index is dropped.
+async fn save_task(
+    ipfs: Ipfs,
+    keypair: Keypair,
+    inner: Arc<RwLock<IndexDocument>>,
+    mut save_rx: mpsc::UnboundedReceiver<Uuid>,
+) {
+    let key = ipfs.search_index();
+
+    while let Some(id) = save_rx.next().await {
+        Delay::new(SAVE_DELAY).await;
+
+        let mut changed = BTreeSet::from([id]);
+        while let Ok(Some(id)) = save_rx.try_next() {
+            changed.insert(id);
+        }
+
+        let shards = {
+            let inner = &*inner.read().await;
+            changed
+                .into_iter()
+                .map(|id| (id, inner.shard(id)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut pointers: BTreeMap<String, Cid> =
+            ds_key::load_dag(&ipfs, &key).await.unwrap_or_default();
+
+        for (id, shard) in shards {
+            if shard.is_empty() {
+                pointers.remove(&id.to_string());
+                continue;
+            }
+
+            match store_shard(&ipfs, &keypair, &shard).await {
+                Ok(cid) => {
+                    pointers.insert(id.to_string(), cid);
+                }
+                Err(e) => tracing::error!(%id, error = %e, "unable to save search index shard"),
+            }
+        }
+
+        if let Err(e) = ds_key::store_dag(&ipfs, &key, pointers).await {
+            tracing::error!(error = %e, "unable to save search index");
+        }
+    }
+}
+
+async fn load_shard(ipfs: &Ipfs, keypair: &Keypair, cid: Cid) -> Result<IndexShard, Error> {
+    let bytes: Vec<u8> = ipfs.get_dag(cid).local().deserialized().await?;
+    let bytes = ecdh_decrypt(keypair, None, bytes)?;
+    serde_json::from_slice(&bytes).map_err(Error::from)
+}
+
+async fn store_shard(ipfs: &Ipfs, keypair: &Keypair, shard: &IndexShard) -> Result<Cid, Error> {
+    let bytes = ecdh_encrypt(keypair, None, serde_json::to_vec(shard)?)?;
+    // The shard is pinned recursively through the pointers to the shards
+    let cid = ipfs.put_dag(bytes).await?;
+    Ok(cid)
+}
+
+#[derive(Default, Debug)]
+struct IndexDocument {
+    /// token to the messages containing said token
+    terms: BTreeMap<String, BTreeSet<Uuid>>,
+    entries: HashMap<Uuid, IndexEntry>,
+    /// conversation or channel to the messages within it, so a shard is built without going over every entry
+    conversations: HashMap<Uuid, BTreeSet<Uuid>>,
+    /// conversations and channels that had their existing messages indexed
+    indexed: BTreeSet<Uuid>,
+}
+
+/// Messages of a single conversation or channel as they are stored
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+struct IndexShard {
+    entries: HashMap<Uuid, IndexEntry>,
+    indexed: bool,
+}
+
+impl IndexShard {
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty() && !self.indexed
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    conversation_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    community_id: Option<Uuid>,
+    sender: DID,
+    date: DateTime<Utc>,
+    lines: Vec<String>,
+}
+
+impl IndexEntry {
+    fn tokens(&self) -> Vec<String> {
+        self.lines.iter().flat_map(|line| tokenize(line)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum QueryTerm {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+impl IndexDocument {
+    fn insert_shard(&mut self, id: Uuid, shard: IndexShard) {
+        for (message_id, entry) in shard.entries {
+            self.insert_entry(message_id, entry);
+        }
+        if shard.indexed {
+            self.indexed.insert(id);
+        }
+    }
+
+    fn shard(&self, id: Uuid) -> IndexShard {
+        IndexShard {
+            entries: self
+                .conversations
+                .get(&id)
+                .into_iter()
+                .flatten()
+                .filter_map(|message_id| {
+                    self.entries
+                        .get(message_id)
+                        .map(|entry| (*message_id, entry.clone()))
+                })
+                .collect(),
+            indexed: self.indexed.contains(&id),
+        }
+    }
+
+    fn insert(&mut self, community_id: Option<Uuid>, message: &Message) {
+        let message_id = message.id();
+        self.remove(message_id);
+
+        let entry = IndexEntry {
+            conversation_id: message.conversation_id(),
+            community_id,
+            sender: message.sender().clone(),
+            date: message.date(),
+            lines: message.lines().to_vec(),
+        };
+
+        self.insert_entry(message_id, entry);
+    }
+
+    fn insert_entry(&mut self, message_id: Uuid, entry: IndexEntry) {
+        for token in entry.tokens() {
+            self.terms.entry(token).or_default().insert(message_id);
+        }
+
+        self.conversations
+            .entry(entry.conversation_id)
+            .or_default()
+            .insert(message_id);
+
+        self.entries.insert(message_id, entry);
+    }
+
+    fn remove(&mut self, message_id: Uuid) -> bool {
+        let Some(entry) = self.entries.remove(&message_id) else {
+            return false;
+        };
+
+        if let Some(messages) = self.conversations.get_mut(&entry.conversation_id) {
+            messages.remove(&message_id);
+            if messages.is_empty() {
+                self.conversations.remove(&entry.conversation_id);
+            }
+        }
+
+        for token in entry.tokens() {
+            if let Some(list) = self.terms.get_mut(&token) {
+                list.remove(&message_id);
+                if list.is_empty() {
+                    self.terms.remove(&token);
+                }
+            }
+        }
+
+        true
+    }
+
+    fn remove_conversation(&mut self, id: Uuid) {
+        let messages = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.conversation_id == id || entry.community_id == Some(id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for message_id in messages {
+            self.remove(message_id);
+        }
+
+        self.indexed.remove(&id);
+    }
+
+    fn postings(&self, term: &QueryTerm) -> BTreeSet<Uuid> {
+        match term {
+            QueryTerm::Word(word) => self.terms.get(word).cloned().unwrap_or_default(),
+            QueryTerm::Prefix(prefix) => self
+                .terms
+                .range(prefix.clone()..)
+                .take_while(|(token, _)| token.starts_with(prefix.as_str()))
+                .flat_map(|(_, list)| list.iter().copied())
+                .collect(),
+            QueryTerm::Phrase(words) => {
+                let mut iter = words.iter();
+                let Some(first) = iter.next() else {
+                    return BTreeSet::new();
+                };
+                let mut list = self.terms.get(first).cloned().unwrap_or_default();
+                for word in iter {
+                    let next = self.terms.get(word).cloned().unwrap_or_default();
+                    list = list.intersection(&next).copied().collect();
+                }
+                list.retain(|id| {
+                    self.entries
+                        .get(id)
+                        .map(|entry| occurrences(&entry.tokens(), term) > 0)
+                        .unwrap_or_default()
+                });
+                list
+            }
+        }
+    }
+
+    fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+        let terms = parse_query(query.query());
+
+        if terms.is_empty() {
+            return vec![];
+        }
+
+        let total = self.entries.len() as f32;
+
+        let mut candidates: Option<BTreeSet<Uuid>> = None;
+        let mut weights = Vec::with_capacity(terms.len());
+
+        for term in terms.iter() {
+            let list = self.postings(term);
+            // inverse document frequency so rarer terms would rank higher
+            weights.push((1.0 + total / (list.len().max(1) as f32)).ln());
+            candidates = Some(match candidates {
+                Some(current) => current.intersection(&list).copied().collect(),
+                None => list,
+            });
+        }
+
+        let date_range = query.date_range();
+
+        let mut results = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| self.entries.get(&id).map(|entry| (id, entry)))
+            .filter(|(_, entry)| {
+                query
+                    .conversation_id()
+                    .map(|id| entry.conversation_id == id || entry.community_id == Some(id))
+                    .unwrap_or(true)
+            })
+            .filter(|(_, entry)| {
+                query
+                    .sender()
+                    .map(|sender| entry.sender.eq(sender))
+                    .unwrap_or(true)
+            })
+            .filter(|(_, entry)| {
+                date_range
+                    .as_ref()
+                    .map(|range| entry.date >= range.start && entry.date <= range.end)
+                    .unwrap_or(true)
+            })
+            .map(|(id, entry)| {
+                let tokens = entry.tokens();
+                let score = terms
+                    .iter()
+                    .zip(weights.iter())
+                    .map(|(term, weight)| occurrences(&tokens, term) as f32 * weight)
+                    .sum::<f32>();
+
+                let mut result = SearchResult::default();
+                result.set_conversation_id(entry.conversation_id);
+                result.set_community_id(entry.community_id);
+                result.set_message_id(id);
+                result.set_sender(entry.sender.clone());
+                result.set_date(entry.date);
+                result.set_snippet(snippet(&entry.lines, &terms));
+                result.set_score(score);
+                result
+            })
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| {
+            b.score()
+                .total_cmp(&a.score())
+                .then_with(|| b.date().cmp(&a.date()))
+        });
+
+        if let Some(limit) = query.limit() {
+            results.truncate(limit);
+        }
+
+        results
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+fn parse_query(query: &str) -> Vec<QueryTerm> {
+    let mut terms = vec![];
+
+    for (index, segment) in query.split('"').enumerate() {
+        // Every odd segment is enclosed within quotes
+        if index % 2 == 1 {
+            let mut words = tokenize(segment);
+            match words.len() {
+                0 => {}
+                1 => terms.push(QueryTerm::Word(words.remove(0))),
+                _ => terms.push(QueryTerm::Phrase(words)),
+            }
+            continue;
+        }
+
+        for word in segment.split_whitespace() {
+            let prefix = word.ends_with('*');
+            let mut tokens = tokenize(word);
+            let last = tokens.pop();
+            terms.extend(tokens.into_iter().map(QueryTerm::Word));
+            if let Some(last) = last {
+                terms.push(match prefix {
+                    true => QueryTerm::Prefix(last),
+                    false => QueryTerm::Word(last),
+                });
+            }
+        }
+    }
+
+    terms
+}
+
+fn occurrences(tokens: &[String], term: &QueryTerm) -> usize {
+    match term {
+        QueryTerm::Word(word) => tokens.iter().filter(|token| token == &word).count(),
+        QueryTerm::Prefix(prefix) => tokens
+            .iter()
+            .filter(|token| token.starts_with(prefix.as_str()))
+            .count(),
+        QueryTerm::Phrase(words) => tokens
+            .windows(words.len())
+            .filter(|window| *window == words.as_slice())
+            .count(),
+    }
+}
+
+fn snippet(lines: &[String], terms: &[QueryTerm]) -> String {
+    let words = lines
+        .iter()
+        .flat_map(|line| line.split_whitespace())
+        .collect::<Vec<_>>();
+
+    let position = words
+        .iter()
+        .position(|word| {
+            let tokens = tokenize(word);
+            terms.iter().any(|term| match term {
+                QueryTerm::Phrase(phrase) => phrase
+                    .first()
+                    .map(|first| tokens.contains(first))
+                    .unwrap_or_default(),
+                term => occurrences(&tokens, term) > 0,
+            })
+        })
+        .unwrap_or_default();
+
+    let start = position.saturating_sub(SNIPPET_WORDS_BEFORE);
+    let end = (position + SNIPPET_WORDS_AFTER).min(words.len());
+
+    let mut snippet = words[start..end].join(" ");
+
+    if start > 0 {
+        snippet.insert_str(0, "... ");
+    }
+
+    if end < words.len() {
+        snippet.push_str(" ...");
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+    use warp::{
+        crypto::DID,
+        raygun::{Message, SearchQuery},
+    };
+
+    use super::{parse_query, IndexDocument, QueryTerm};
+
+    fn message(conversation_id: Uuid, sender: &DID, lines: &[&str]) -> Message {
+        let mut message = Message::default();
+        message.set_conversation_id(conversation_id);
+        message.set_sender(sender.clone());
+        message.set_lines(lines.iter().map(ToString::to_string).collect());
+        message
+    }
+
+    #[test]
+    fn parse_terms() {
+        let terms = parse_query(r#"Hello wor* "Good Morning""#);
+        assert_eq!(
+            terms,
+            vec![
+                QueryTerm::Word("hello".into()),
+                QueryTerm::Prefix("wor".into()),
+                QueryTerm::Phrase(vec!["good".into(), "morning".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn search_word_prefix_and_phrase() {
+        let mut index = IndexDocument::default();
+        let conversation_id = Uuid::new_v4();
+        let sender = DID::default();
+
+        let first = message(conversation_id, &sender, &["Good morning, world"]);
+        let second = message(conversation_id, &sender, &["morning good", "workers"]);
+
+        index.insert(None, &first);
+        index.insert(None, &second);
+
+        let results = index.search(&SearchQuery::new("morning"));
+        assert_eq!(results.len(), 2);
+
+        let results = index.search(&SearchQuery::new("wor*"));
+        assert_eq!(results.len(), 2);
+
+        let results = index.search(&SearchQuery::new(r#""good morning""#));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_id(), first.id());
+        assert_eq!(results[0].snippet(), "Good morning, world");
+
+        assert!(index.remove(first.id()));
+        let results = index.search(&SearchQuery::new(r#""good morning""#));
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn search_with_filters() {
+        let mut index = IndexDocument::default();
+        let conversation_id = Uuid::new_v4();
+        let sender_a = DID::default();
+        let sender_b = DID::default();
+
+        let mut old = message(conversation_id, &sender_a, &["status report"]);
+        old.set_date(Utc::now() - Duration::days(7));
+        let new = message(conversation_id, &sender_b, &["status report"]);
+
+        index.insert(None, &old);
+        index.insert(None, &new);
+
+        let results = index.search(&SearchQuery::new("status").set_sender(sender_a.clone()));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_id(), old.id());
+
+        let range = (Utc::now() - Duration::days(1))..Utc::now();
+        let results = index.search(&SearchQuery::new("report").set_date_range(range));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_id(), new.id());
+
+        index.remove_conversation(conversation_id);
+        assert!(index.search(&SearchQuery::new("report")).is_empty());
+    }
+
+    #[test]
+    fn restore_from_shards() {
+        let mut index = IndexDocument::default();
+        let first_id = Uuid::new_v4();
+        let second_id = Uuid::new_v4();
+        let sender = DID::default();
+
+        index.insert(None, &message(first_id, &sender, &["weekly status"]));
+        index.insert(None, &message(second_id, &sender, &["status update"]));
+        index.indexed.insert(first_id);
+
+        let first = index.shard(first_id);
+        assert_eq!(first.entries.len(), 1);
+        assert!(first.indexed);
+
+        let second = index.shard(second_id);
+        assert_eq!(second.entries.len(), 1);
+        assert!(!second.indexed);
+
+        let mut restored = IndexDocument::default();
+        restored.insert_shard(first_id, first);
+        restored.insert_shard(second_id, second);
+
+        assert_eq!(restored.search(&SearchQuery::new("status")).len(), 2);
+        assert!(restored.indexed.contains(&first_id));
+        assert!(!restored.indexed.contains(&second_id));
+
+        restored.remove_conversation(first_id);
+        assert!(restored.shard(first_id).is_empty());
+        assert!(!restored.conversations.contains_key(&first_id));
+        assert_eq!(restored.shard(second_id).entries.len(), 1);
+    }
+}
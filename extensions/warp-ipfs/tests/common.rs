@@ -9,7 +9,7 @@ use warp::{
     SingleHandle,
 };
 use warp_ipfs::{
-    config::{Bootstrap, Discovery},
+    config::{Bootstrap, Config, Discovery},
     WarpIpfsBuilder, WarpIpfsInstance,
 };
 
@@ -54,10 +54,33 @@ pub async fn mesh_connect(nodes: Vec<Ipfs>) -> anyhow::Result<()> {
 
 #[allow(dead_code)]
 pub async fn create_account(
+    username: Option<&str>,
+    passphrase: Option<&str>,
+    context: Option<String>,
+) -> anyhow::Result<(WarpIpfsInstance, DID, Identity)> {
+    create_account_with_config(username, passphrase, context, |_| {}).await
+}
+
+#[allow(dead_code)]
+pub async fn create_account_with_config(
     username: Option<&str>,
     passphrase: Option<&str>,
     _: Option<String>,
+    configure: impl FnOnce(&mut Config),
 ) -> anyhow::Result<(WarpIpfsInstance, DID, Identity)> {
+    let mut instance = open_account_with_config(configure).await?;
+
+    let profile = instance.create_identity(username, passphrase).await?;
+    let identity = profile.identity().clone();
+
+    Ok((instance, identity.did_key().clone(), identity))
+}
+
+/// Opens an instance without creating an identity, which is used to reopen a persisted account
+#[allow(dead_code)]
+pub async fn open_account_with_config(
+    configure: impl FnOnce(&mut Config),
+) -> anyhow::Result<WarpIpfsInstance> {
     let mut config = warp_ipfs::config::Config::development();
     *config.listen_on_mut() = vec![Multiaddr::empty().with(Protocol::Memory(0))];
     config.ipfs_setting_mut().memory_transport = true;
@@ -69,19 +92,26 @@ pub async fn create_account(
 
     *config.bootstrap_mut() = Bootstrap::None;
 
-    let mut instance = WarpIpfsBuilder::default().set_config(config).await;
+    configure(&mut config);
 
-    instance.tesseract().unlock(b"internal pass").unwrap();
+    let instance = WarpIpfsBuilder::default().set_config(config).await;
 
-    let profile = instance.create_identity(username, passphrase).await?;
-    let identity = profile.identity().clone();
+    instance.tesseract().unlock(b"internal pass")?;
 
-    Ok((instance, identity.did_key().clone(), identity))
+    Ok(instance)
 }
 
 #[allow(dead_code)]
 pub async fn create_accounts(
     infos: Vec<(Option<&str>, Option<&str>, Option<String>)>,
+) -> anyhow::Result<Vec<(WarpIpfsInstance, DID, Identity)>> {
+    create_accounts_with_config(infos, |_| {}).await
+}
+
+#[allow(dead_code)]
+pub async fn create_accounts_with_config(
+    infos: Vec<(Option<&str>, Option<&str>, Option<String>)>,
+    configure: impl Fn(&mut Config),
 ) -> anyhow::Result<Vec<(WarpIpfsInstance, DID, Identity)>> {
     let _ = tracing_subscriber::registry()
         .with(fmt::layer().pretty())
@@ -91,7 +121,7 @@ pub async fn create_accounts(
     let mut accounts = vec![];
     let mut nodes = vec![];
     for (username, passphrase, context) in infos {
-        let account = create_account(username, passphrase, context).await?;
+        let account = create_account_with_config(username, passphrase, context, &configure).await?;
         let ipfs = account
             .0
             .handle()
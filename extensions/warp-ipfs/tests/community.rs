@@ -25,7 +25,12 @@ mod test {
     #[cfg(not(target_arch = "wasm32"))]
     use tokio::test as async_test;
 
+    use ipld_core::cid::Cid;
+    use rust_ipfs::{Ipfs, IpfsPath, Keypair};
+    use warp::crypto::cipher::Cipher;
     use warp::error::Error;
+    use warp::SingleHandle;
+    use warp_ipfs::{store::keystore::Keystore, WarpIpfsInstance};
 
     use crate::common::create_accounts;
 
@@ -62,6 +67,50 @@ mod test {
         )
         .await
     }
+    /// Reads the keystore of the community stored by `instance`, along with the keypair its keys are encrypted with
+    async fn community_keystore(
+        instance: &WarpIpfsInstance,
+        community_id: Uuid,
+    ) -> anyhow::Result<(Keystore, Keypair)> {
+        let ipfs = instance
+            .handle()?
+            .downcast_ref::<Ipfs>()
+            .cloned()
+            .expect("ipfs handle");
+        let keypair = ipfs.keypair().clone();
+        let key = format!("/identity/{}/root", keypair.public().to_peer_id());
+        let root_cid = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await?
+            .map(String::from_utf8)
+            .transpose()?
+            .expect("root document")
+            .parse::<Cid>()?;
+        let path = IpfsPath::from(root_cid).sub_path(&format!("keystore/{community_id}"))?;
+        let keystore = ipfs.get_dag(path).local().deserialized().await?;
+        Ok((keystore, keypair))
+    }
+
+    async fn next_channel_message(
+        stream: &mut MessageEventStream,
+        expected: Uuid,
+    ) -> anyhow::Result<()> {
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::CommunityMessageReceived { message_id, .. }) =
+                    stream.next().await
+                {
+                    if message_id == expected {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+        Ok(())
+    }
     #[async_test]
     async fn get_community_stream() -> anyhow::Result<()> {
         let context = Some("test::get_community_stream".into());
@@ -2488,6 +2537,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let result = instance_b
@@ -2568,6 +2618,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let message = instance_b
@@ -2652,6 +2703,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let result = instance_b
@@ -2732,6 +2784,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let messages = instance_b
@@ -2823,6 +2876,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let result = instance_b
@@ -2902,6 +2956,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let count = instance_b
@@ -2985,6 +3040,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let result = instance_b
@@ -3064,6 +3120,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let reference = instance_b
@@ -3149,6 +3206,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let result = instance_b
@@ -3232,6 +3290,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let mut references = instance_b
@@ -3371,6 +3430,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let message = instance_b
@@ -3451,17 +3511,45 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
+        let status_b = instance_b
+            .community_channel_message_status(community.id(), channel.id(), message_id)
+            .await?;
+        assert_eq!(status_b, MessageStatus::Delivered);
+
+        assert_eq!(
+            next_event(&mut stream_a, Duration::from_secs(60)).await?,
+            MessageEventKind::CommunityMessageDelivered {
+                community_id: community.id(),
+                channel_id: channel.id(),
+                message_id,
+                did_key: did_b.clone(),
+            }
+        );
         let status_a = instance_a
             .community_channel_message_status(community.id(), channel.id(), message_id)
             .await?;
-        assert_eq!(status_a, MessageStatus::Sent);
-        let status_b = instance_b
+        assert_eq!(status_a, MessageStatus::Delivered);
+
+        instance_b
+            .mark_community_channel_message_read(community.id(), channel.id(), message_id)
+            .await?;
+        assert_eq!(
+            next_event(&mut stream_a, Duration::from_secs(60)).await?,
+            MessageEventKind::CommunityMessageRead {
+                community_id: community.id(),
+                channel_id: channel.id(),
+                message_id,
+                did_key: did_b.clone(),
+            }
+        );
+        let status_a = instance_a
             .community_channel_message_status(community.id(), channel.id(), message_id)
             .await?;
-        assert_eq!(status_b, MessageStatus::Sent);
+        assert_eq!(status_a, MessageStatus::Read);
         Ok(())
     }
 
@@ -3608,6 +3696,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -3708,6 +3797,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -3800,6 +3890,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -3900,6 +3991,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -3938,6 +4030,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
         let message = instance_b
@@ -4015,6 +4108,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4096,6 +4190,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4190,6 +4285,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4289,6 +4385,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4385,6 +4482,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4491,6 +4589,7 @@ mod test {
                 community_id: community.id(),
                 channel_id: channel.id(),
                 message_id,
+                should_notify: true,
             }
         );
 
@@ -4726,7 +4825,8 @@ mod test {
             MessageEventKind::CommunityMessageReceived {
                 community_id: community.id(),
                 channel_id: channel.id(),
-                message_id
+                message_id,
+                should_notify: true,
             }
         );
 
@@ -4835,7 +4935,8 @@ mod test {
             MessageEventKind::CommunityMessageReceived {
                 community_id: community.id(),
                 channel_id: channel.id(),
-                message_id
+                message_id,
+                should_notify: true,
             }
         );
         let path = PathBuf::from("test::authorized_download_from_community_channel_message");
@@ -4954,7 +5055,8 @@ mod test {
             MessageEventKind::CommunityMessageReceived {
                 community_id: community.id(),
                 channel_id: channel.id(),
-                message_id
+                message_id,
+                should_notify: true,
             }
         );
 
@@ -5063,7 +5165,8 @@ mod test {
             MessageEventKind::CommunityMessageReceived {
                 community_id: community.id(),
                 channel_id: channel.id(),
-                message_id
+                message_id,
+                should_notify: true,
             }
         );
         let mut download_stream = instance_b
@@ -5091,4 +5194,283 @@ mod test {
         assert_eq!(msg.attachments().len(), 1);
         Ok(())
     }
+
+    #[async_test]
+    async fn forward_attachment_to_community_channel() -> anyhow::Result<()> {
+        use warp::raygun::{
+            AttachmentKind, RayGun, RayGunAttachment, FORWARDED_CONVERSATION_KEY,
+            FORWARDED_MESSAGE_KEY, FORWARDED_SENDER_KEY,
+        };
+
+        let context = Some("test::forward_attachment_to_community_channel".into());
+        let acc = (None, None, context);
+        let accounts = create_accounts(vec![acc.clone(), acc]).await?;
+        let (instance_a, did_a, _) = &mut accounts[0].clone();
+        let (instance_b, did_b, _) = &mut accounts[1].clone();
+
+        let community = instance_a.create_community("Community0").await?;
+        let channel = instance_a
+            .create_community_channel(community.id(), "Channel0", CommunityChannelType::Standard)
+            .await?;
+
+        let mut rg_stream_a = instance_a.raygun_subscribe().await?;
+        let mut rg_stream_b = instance_b.raygun_subscribe().await?;
+        let invite = instance_a
+            .create_community_invite(community.id(), Some(did_b.clone()), None)
+            .await?;
+        assert_eq!(
+            next_event(&mut rg_stream_b, Duration::from_secs(60)).await?,
+            RayGunEventKind::CommunityInvited {
+                community_id: community.id(),
+                invite_id: invite.id()
+            }
+        );
+
+        let mut stream_a = instance_a.get_community_stream(community.id()).await?;
+        instance_b
+            .accept_community_invite(community.id(), invite.id())
+            .await?;
+        assert_eq!(
+            next_event(&mut stream_a, Duration::from_secs(60)).await?,
+            MessageEventKind::AcceptedCommunityInvite {
+                community_id: community.id(),
+                invite_id: invite.id(),
+                user: did_b.clone()
+            }
+        );
+
+        instance_a.create_conversation(did_b).await?;
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    rg_stream_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let file_name = "my_file.txt";
+        instance_a.put_buffer(file_name, &RED_PNG).await?;
+        let message = vec!["here is my file".to_string()];
+        let (_, mut attachment_event_stream) = instance_a
+            .attach(
+                conversation_id,
+                None,
+                vec![Location::Constellation {
+                    path: file_name.to_string(),
+                }],
+                message.clone(),
+            )
+            .await?;
+        while let Some(event) = attachment_event_stream.next().await {
+            if let AttachmentKind::Pending(result) = event {
+                result?;
+            }
+        }
+
+        let original_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut stream_b = instance_b.get_community_stream(community.id()).await?;
+        let message_id = instance_a
+            .forward_to_community_channel(
+                conversation_id,
+                original_id,
+                community.id(),
+                channel.id(),
+            )
+            .await?;
+        assert_eq!(
+            next_event(&mut stream_b, Duration::from_secs(60)).await?,
+            MessageEventKind::CommunityMessageReceived {
+                community_id: community.id(),
+                channel_id: channel.id(),
+                message_id,
+                should_notify: true,
+            }
+        );
+
+        let msg = instance_b
+            .get_community_channel_message(community.id(), channel.id(), message_id)
+            .await?;
+        assert_eq!(msg.lines(), &message);
+        assert_eq!(msg.attachments().len(), 1);
+        assert_eq!(
+            msg.metadata().get(FORWARDED_CONVERSATION_KEY),
+            Some(&conversation_id.to_string())
+        );
+        assert_eq!(
+            msg.metadata().get(FORWARDED_MESSAGE_KEY),
+            Some(&original_id.to_string())
+        );
+        assert_eq!(
+            msg.metadata().get(FORWARDED_SENDER_KEY),
+            Some(&did_a.to_string())
+        );
+
+        let bytes = instance_b
+            .download_stream_from_community_channel_message(
+                community.id(),
+                channel.id(),
+                message_id,
+                file_name,
+            )
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .concat();
+        assert_eq!(bytes, RED_PNG);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn removed_member_excluded_from_rotated_key() -> anyhow::Result<()> {
+        let context = Some("test::removed_member_excluded_from_rotated_key".into());
+        let acc = (None, None, context);
+        let accounts = create_accounts(vec![acc.clone(), acc.clone(), acc.clone(), acc]).await?;
+        let (instance_a, did_a, _) = &mut accounts[0].clone();
+        let (instance_b, did_b, _) = &mut accounts[1].clone();
+        let (instance_c, did_c, _) = &mut accounts[2].clone();
+        let (instance_d, did_d, _) = &mut accounts[3].clone();
+
+        let community = instance_a.create_community("Community0").await?;
+        let channel = instance_a
+            .create_community_channel(community.id(), "Channel0", CommunityChannelType::Standard)
+            .await?;
+
+        let mut stream_a = instance_a.get_community_stream(community.id()).await?;
+
+        let mut streams = vec![];
+        for (instance, did) in [(&mut *instance_b, &*did_b), (&mut *instance_c, &*did_c)] {
+            let mut rg_stream = instance.raygun_subscribe().await?;
+            let invite = instance_a
+                .create_community_invite(community.id(), Some(did.clone()), None)
+                .await?;
+            assert_eq!(
+                next_event(&mut rg_stream, Duration::from_secs(60)).await?,
+                RayGunEventKind::CommunityInvited {
+                    community_id: community.id(),
+                    invite_id: invite.id()
+                }
+            );
+            instance
+                .accept_community_invite(community.id(), invite.id())
+                .await?;
+            crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::AcceptedCommunityInvite { user, .. }) =
+                        stream_a.next().await
+                    {
+                        if user.eq(did) {
+                            break;
+                        }
+                    }
+                }
+            })
+            .await?;
+            streams.push(instance.get_community_stream(community.id()).await?);
+        }
+
+        let (mut stream_b, mut stream_c) = (streams.remove(0), streams.remove(0));
+
+        let before_id = instance_a
+            .send_community_channel_message(
+                community.id(),
+                channel.id(),
+                vec!["Before removal".into()],
+            )
+            .await?;
+        next_channel_message(&mut stream_b, before_id).await?;
+        next_channel_message(&mut stream_c, before_id).await?;
+
+        // Keep the keystore of C from before the removal to attempt to decrypt later messages with it
+        let (keystore_c, keypair_c) = community_keystore(instance_c, community.id()).await?;
+        let keys_c = keystore_c.get_all(&keypair_c, did_a)?;
+        assert!(!keys_c.is_empty());
+
+        instance_a
+            .remove_community_member(community.id(), did_c.clone())
+            .await?;
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::RemovedCommunityMember { member, .. }) =
+                    stream_b.next().await
+                {
+                    assert_eq!(&member, &*did_c);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let after_id = instance_a
+            .send_community_channel_message(
+                community.id(),
+                channel.id(),
+                vec!["After removal".into()],
+            )
+            .await?;
+        next_channel_message(&mut stream_b, after_id).await?;
+
+        let message = instance_b
+            .get_community_channel_message(community.id(), channel.id(), after_id)
+            .await?;
+        assert_eq!(message.lines(), ["After removal"]);
+
+        // B decrypted the message with the rotated key of A, which C never received
+        let (keystore_b, keypair_b) = community_keystore(instance_b, community.id()).await?;
+        let keys_b = keystore_b.get_all(&keypair_b, did_a)?;
+        let latest = keys_b.last().expect("rotated key");
+        assert!(keys_b.len() > keys_c.len());
+        assert!(!keys_c.contains(latest));
+
+        let captured = Cipher::direct_encrypt(b"After removal", latest)?;
+        assert!(keystore_b.try_decrypt(&keypair_b, did_a, &captured).is_ok());
+        assert!(keystore_c
+            .try_decrypt(&keypair_c, did_a, &captured)
+            .is_err());
+
+        // A member that joins after the rotation receives the previous keys of A as well
+        let mut rg_stream_d = instance_d.raygun_subscribe().await?;
+        let invite = instance_a
+            .create_community_invite(community.id(), Some(did_d.clone()), None)
+            .await?;
+        assert_eq!(
+            next_event(&mut rg_stream_d, Duration::from_secs(60)).await?,
+            RayGunEventKind::CommunityInvited {
+                community_id: community.id(),
+                invite_id: invite.id()
+            }
+        );
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok((keystore_d, keypair_d)) =
+                    community_keystore(instance_d, community.id()).await
+                {
+                    if keystore_d
+                        .get_all(&keypair_d, did_a)
+                        .is_ok_and(|keys| keys == keys_b)
+                    {
+                        break;
+                    }
+                }
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
 }
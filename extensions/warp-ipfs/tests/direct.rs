@@ -8,12 +8,14 @@ mod test {
         constellation::Progression,
         multipass::MultiPassEventKind,
         raygun::{
-            AttachmentKind, ConversationType, Location, MessageEvent, MessageEventKind,
-            MessageType, PinState, RayGunEventKind, ReactionState,
+            AttachmentKind, ConversationType, CustomEvent, Draft, GeoPosition, Location,
+            LocationKind, Mention, MessageEvent, MessageEventKind, MessageOptions, MessageStatus,
+            MessageType, Messages, NotificationLevel, NotificationSettings, PinState, Poll,
+            RayGunEventKind, ReactionState, SearchQuery,
         },
     };
 
-    use crate::common::{create_accounts, PROFILE_IMAGE};
+    use crate::common::{create_accounts, create_accounts_with_config, PROFILE_IMAGE};
 
     #[cfg(target_arch = "wasm32")]
     use wasm_bindgen_test::wasm_bindgen_test as async_test;
@@ -24,7 +26,7 @@ mod test {
     #[cfg(not(target_arch = "wasm32"))]
     use tokio::test as async_test;
     use warp::constellation::Constellation;
-    use warp::multipass::{Friends, MultiPassEvent};
+    use warp::multipass::{Friends, MultiPass, MultiPassEvent};
     use warp::raygun::{
         RayGun, RayGunAttachment, RayGunConversationInformation, RayGunEvents, RayGunStream,
     };
@@ -272,6 +274,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_b.next().await
                 {
                     break instance_a.get_message(conversation_id, message_id).await;
@@ -285,23 +288,112 @@ mod test {
     }
 
     #[async_test]
-    async fn send_and_download_attachment_in_conversation() -> anyhow::Result<()> {
+    async fn send_message_with_mention() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::send_message_with_mention".into())),
+            (None, None, Some("test::send_message_with_mention".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, identity_b) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let line = format!("Hello @{}#{}", identity_b.username(), identity_b.short_id());
+
+        let message_id = instance_a.send(conversation_id, vec![line]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::Mentioned {
+                    conversation_id: id,
+                    message_id: m_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(id, conversation_id);
+                    assert_eq!(m_id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.mentions(), [Mention::Identity(did_b.clone())]);
+        assert_eq!(instance_b.unread_mention_count(conversation_id).await?, 1);
+
+        // A mention of everyone notifies the recipient, while the sender is never notified of its own mentions
+        let message_id = instance_b
+            .send(conversation_id, vec!["@everyone, hello".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::Mentioned {
+                    message_id: m_id, ..
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(m_id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_a.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.sender(), &did_b);
+        assert_eq!(message.mentions(), [Mention::Everyone]);
+        assert_eq!(instance_a.unread_mention_count(conversation_id).await?, 1);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn conversation_notification_settings() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
             (
                 None,
                 None,
-                Some("test::send_and_download_attachment_in_conversation".into()),
+                Some("test::conversation_notification_settings".into()),
             ),
             (
                 None,
                 None,
-                Some("test::send_and_download_attachment_in_conversation".into()),
+                Some("test::conversation_notification_settings".into()),
             ),
         ])
         .await?;
 
         let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
-        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+        let (mut instance_b, did_b, identity_b) = accounts.last().cloned().unwrap();
 
         let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
         let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
@@ -328,104 +420,198 @@ mod test {
             }
         }).await?;
 
-        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
-        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+        assert_eq!(
+            instance_b
+                .get_notification_settings(conversation_id)
+                .await?,
+            NotificationSettings::default()
+        );
+
+        let mut settings = NotificationSettings::default();
+        settings.set_level(NotificationLevel::Mentions);
+        settings.set_hidden(true);
+        instance_b
+            .set_notification_settings(conversation_id, settings.clone())
+            .await?;
 
-        //upload file to constellation to attach file from constellation
+        let conversation = instance_b.get_conversation(conversation_id).await?;
+        assert_eq!(conversation.notification_settings(), &settings);
 
-        instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
 
-        let (_, mut stream) = instance_a
-            .attach(
-                conversation_id,
-                None,
-                vec![Location::Constellation {
-                    path: "image.png".into(),
-                }],
-                vec![],
-            )
+        // Only mentions notify
+        let message_id = instance_a
+            .send(conversation_id, vec!["Hello, World!".into()])
             .await?;
 
-        while let Some(event) = stream.next().await {
-            match event {
-                AttachmentKind::AttachedProgress(
-                    _location,
-                    Progression::CurrentProgress { .. },
-                ) => {}
-                AttachmentKind::AttachedProgress(
-                    _location,
-                    Progression::ProgressComplete { name, total },
-                ) => {
-                    assert_eq!(name, "image.png");
-                    assert_eq!(total, Some(PROFILE_IMAGE.len()));
-                }
-                AttachmentKind::AttachedProgress(_location, Progression::ProgressFailed { .. }) => {
-                    unreachable!("should not fail")
-                }
-                AttachmentKind::Pending(result) => {
-                    result?;
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    message_id: m_id,
+                    should_notify,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(m_id, message_id);
+                    assert!(!should_notify);
+                    break;
                 }
             }
-        }
+        })
+        .await?;
 
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+        let line = format!("Hello @{}#{}", identity_b.username(), identity_b.short_id());
+        let message_id = instance_a.send(conversation_id, vec![line.clone()]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageSent {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
+                if let Some(MessageEventKind::Mentioned {
+                    message_id: m_id,
+                    should_notify,
+                    ..
+                }) = conversation_b.next().await
                 {
-                    break instance_a.get_message(conversation_id, message_id).await;
+                    assert_eq!(m_id, message_id);
+                    assert!(should_notify);
+                    break;
                 }
             }
         })
-        .await??;
+        .await?;
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+        // Muting suppresses notifications, including mentions
+        settings.set_muted_until(Some(chrono::Utc::now() + chrono::Duration::hours(1)));
+        instance_b
+            .set_notification_settings(conversation_id, settings.clone())
+            .await?;
+
+        let message_id = instance_a.send(conversation_id, vec![line]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageReceived {
-                    conversation_id,
-                    message_id,
+                if let Some(MessageEventKind::Mentioned {
+                    message_id: m_id,
+                    should_notify,
+                    ..
                 }) = conversation_b.next().await
                 {
-                    break instance_b.get_message(conversation_id, message_id).await;
+                    assert_eq!(m_id, message_id);
+                    assert!(!should_notify);
+                    break;
                 }
             }
         })
-        .await??;
+        .await?;
 
-        assert_eq!(message_a, message_b);
-        assert_eq!(message_a.message_type(), MessageType::Attachment);
-        let attachments = message_a.attachments();
-        assert!(!attachments.is_empty());
+        // Restoring the default settings removes them from the root document
+        instance_b
+            .set_notification_settings(conversation_id, NotificationSettings::default())
+            .await?;
 
-        let file = attachments.first().expect("attachment exist");
+        let conversation = instance_b.get_conversation(conversation_id).await?;
+        assert!(conversation.notification_settings().is_default());
 
-        assert_eq!(file.name(), "image.png");
+        Ok(())
+    }
 
-        let stream = instance_b
-            .download_stream(conversation_id, message_a.id(), "image.png")
-            .await?;
+    #[async_test]
+    async fn send_message_with_ratchet_session() -> anyhow::Result<()> {
+        let accounts = create_accounts_with_config(
+            vec![
+                (
+                    None,
+                    None,
+                    Some("test::send_message_with_ratchet_session".into()),
+                ),
+                (
+                    None,
+                    None,
+                    Some("test::send_message_with_ratchet_session".into()),
+                ),
+            ],
+            |config| {
+                config.store_setting_mut().ratchet_sessions = true;
+            },
+        )
+        .await?;
 
-        let data = stream.try_collect::<Vec<_>>().await?.concat();
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        for (index, line) in ["Hello, World", "Hello again"].into_iter().enumerate() {
+            let (sender, receiver, stream) = match index % 2 {
+                0 => (&mut instance_a, &instance_b, &mut conversation_b),
+                _ => (&mut instance_b, &instance_a, &mut conversation_a),
+            };
+
+            sender.send(conversation_id, vec![line.into()]).await?;
+
+            let message = crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::MessageReceived {
+                        conversation_id,
+                        message_id,
+                        ..
+                    }) = stream.next().await
+                    {
+                        break receiver.get_message(conversation_id, message_id).await;
+                    }
+                }
+            })
+            .await??;
+
+            let expected_sender = match index % 2 {
+                0 => &did_a,
+                _ => &did_b,
+            };
+
+            assert_eq!(message.sender(), expected_sender);
+            assert_eq!(message.lines(), [line.to_string()]);
+        }
 
-        assert_eq!(data, PROFILE_IMAGE);
         Ok(())
     }
 
     #[async_test]
-    async fn send_attachment_stream_and_download_attachment_in_conversation() -> anyhow::Result<()>
-    {
+    async fn send_and_download_attachment_in_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
             (
                 None,
                 None,
-                Some("test::send_attachment_stream_and_download_attachment_in_conversation".into()),
+                Some("test::send_and_download_attachment_in_conversation".into()),
             ),
             (
                 None,
                 None,
-                Some("test::send_attachment_stream_and_download_attachment_in_conversation".into()),
+                Some("test::send_and_download_attachment_in_conversation".into()),
             ),
         ])
         .await?;
@@ -461,14 +647,16 @@ mod test {
         let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
         let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
 
+        //upload file to constellation to attach file from constellation
+
+        instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
+
         let (_, mut stream) = instance_a
             .attach(
                 conversation_id,
                 None,
-                vec![Location::Stream {
-                    name: "image.png".into(),
-                    stream: futures::stream::iter(vec![Ok(PROFILE_IMAGE.into())]).boxed(),
-                    size: Some(PROFILE_IMAGE.len()),
+                vec![Location::Constellation {
+                    path: "image.png".into(),
                 }],
                 vec![],
             )
@@ -514,6 +702,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_b.next().await
                 {
                     break instance_b.get_message(conversation_id, message_id).await;
@@ -542,17 +731,43 @@ mod test {
     }
 
     #[async_test]
-    async fn delete_message_in_conversation() -> anyhow::Result<()> {
+    async fn send_voice_note_in_conversation() -> anyhow::Result<()> {
+        // Builds an ogg page holding the packets of a single logical stream
+        fn page(granule: u64, packets: &[Vec<u8>]) -> Vec<u8> {
+            let mut page = b"OggS".to_vec();
+            page.extend_from_slice(&[0, 0]);
+            page.extend_from_slice(&granule.to_le_bytes());
+            page.extend_from_slice(&[1, 0, 0, 0]);
+            page.extend_from_slice(&[0; 8]);
+            page.push(packets.len() as u8);
+            page.extend(packets.iter().map(|packet| packet.len() as u8));
+            page.extend(packets.concat());
+            page
+        }
+
+        let mut opus_head = b"OpusHead".to_vec();
+        opus_head.extend_from_slice(&[1, 1, 0, 0]);
+        opus_head.extend_from_slice(&48_000u32.to_le_bytes());
+        opus_head.extend_from_slice(&[0, 0, 0]);
+
+        let mut audio = page(0, &[opus_head]);
+        audio.extend(page(0, &[b"OpusTags".to_vec()]));
+        // 50 packets of 20ms each
+        for index in 0..5u64 {
+            let packets = (0..10).map(|size| vec![0; size + 3]).collect::<Vec<_>>();
+            audio.extend(page((index + 1) * 10 * 960, &packets));
+        }
+
         let accounts = create_accounts(vec![
             (
                 None,
                 None,
-                Some("test::delete_message_in_conversation".into()),
+                Some("test::send_voice_note_in_conversation".into()),
             ),
             (
                 None,
                 None,
-                Some("test::delete_message_in_conversation".into()),
+                Some("test::send_voice_note_in_conversation".into()),
             ),
         ])
         .await?;
@@ -566,196 +781,2355 @@ mod test {
         instance_a.create_conversation(&did_b).await?;
 
         let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
-            let mut id_a = None;
-            let mut id_b = None;
-            loop {
-                tokio::select! {
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
-                        id_a.replace(conversation_id);
-                    },
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
-                        id_b.replace(conversation_id);
-                    },
-                }
-
-                if id_a.is_some() && id_b.is_some() {
-                    assert_eq!(id_a, id_b);
-                    break id_a.expect("valid conversation_id")
-                }
-            }
-        }).await?;
-
-        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
-        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
-
-        instance_a
-            .send(conversation_id, vec!["Hello, World".into()])
-            .await?;
-
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageSent {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
                 {
-                    break instance_a.get_message(conversation_id, message_id).await;
+                    break conversation_id;
                 }
             }
         })
-        .await??;
+        .await?;
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+        crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageReceived {
-                    conversation_id,
-                    message_id,
-                }) = conversation_b.next().await
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
                 {
-                    break instance_b.get_message(conversation_id, message_id).await;
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        assert!(instance_a
+            .attach_voice_note(
+                conversation_id,
+                None,
+                Location::Constellation {
+                    path: "voice.ogg".into()
+                }
+            )
+            .await
+            .is_err());
+
+        let (message_id, mut stream) = instance_a
+            .attach_voice_note(
+                conversation_id,
+                None,
+                Location::Stream {
+                    name: "voice.ogg".into(),
+                    size: Some(audio.len()),
+                    stream: futures::stream::iter(vec![Ok(audio.into())]).boxed(),
+                },
+            )
+            .await?;
+
+        while let Some(event) = stream.next().await {
+            if let AttachmentKind::Pending(result) = event {
+                result?;
+            }
+        }
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        let file = message
+            .attachments()
+            .first()
+            .cloned()
+            .expect("attachment exist");
+        let voice_note = file.voice_note().expect("voice note");
+        assert_eq!(voice_note.duration(), Duration::from_secs(1));
+        assert_eq!(voice_note.waveform().len(), 50);
+        assert_eq!(voice_note.waveform().iter().max(), Some(&u8::MAX));
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn forward_attachment_between_conversations() -> anyhow::Result<()> {
+        use warp::raygun::{FORWARDED_CONVERSATION_KEY, FORWARDED_MESSAGE_KEY};
+
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::forward_attachment_between_conversations".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forward_attachment_between_conversations".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forward_attachment_between_conversations".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (_, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let source_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        instance_a.create_conversation(&did_c).await?;
+
+        let target_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut source_a = instance_a.get_conversation_stream(source_id).await?;
+        let mut target_c = instance_c.get_conversation_stream(target_id).await?;
+
+        instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
+
+        let (_, mut stream) = instance_a
+            .attach(
+                source_id,
+                None,
+                vec![Location::Constellation {
+                    path: "image.png".into(),
+                }],
+                vec!["Look at this".into()],
+            )
+            .await?;
+
+        while let Some(event) = stream.next().await {
+            if let AttachmentKind::Pending(result) = event {
+                result?;
+            }
+        }
+
+        let original = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = source_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let forwarded_id = instance_a
+            .forward(source_id, original.id(), target_id)
+            .await?;
+
+        let forwarded = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = target_c.next().await
+                {
+                    break instance_c.get_message(conversation_id, message_id).await;
                 }
             }
         })
         .await??;
 
-        assert_eq!(message_a, message_b);
+        assert_eq!(forwarded.id(), forwarded_id);
+        assert_eq!(forwarded.message_type(), MessageType::Attachment);
+        assert_eq!(forwarded.lines(), original.lines());
+        assert_eq!(
+            forwarded.metadata().get(FORWARDED_CONVERSATION_KEY),
+            Some(&source_id.to_string())
+        );
+        assert_eq!(
+            forwarded.metadata().get(FORWARDED_MESSAGE_KEY),
+            Some(&original.id().to_string())
+        );
+
+        let stream = instance_c
+            .download_stream(target_id, forwarded_id, "image.png")
+            .await?;
+
+        let data = stream.try_collect::<Vec<_>>().await?.concat();
+
+        assert_eq!(data, PROFILE_IMAGE);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn forwarded_attachment_outlives_expired_original() -> anyhow::Result<()> {
+        use ipld_core::cid::Cid;
+        use rust_ipfs::Ipfs;
+        use warp::SingleHandle;
+
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::forwarded_attachment_outlives_expired_original".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forwarded_attachment_outlives_expired_original".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forwarded_attachment_outlives_expired_original".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (_, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let source_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        instance_a.create_conversation(&did_c).await?;
+
+        let target_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut source_a = instance_a.get_conversation_stream(source_id).await?;
+        let mut target_c = instance_c.get_conversation_stream(target_id).await?;
+
+        instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
+
+        let (original_id, mut stream) = instance_a
+            .attach(
+                source_id,
+                None,
+                vec![Location::Constellation {
+                    path: "image.png".into(),
+                }],
+                vec![],
+            )
+            .await?;
+
+        while let Some(event) = stream.next().await {
+            if let AttachmentKind::Pending(result) = event {
+                result?;
+            }
+        }
+
+        let forwarded_id = instance_a
+            .forward(source_id, original_id, target_id)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    target_c.next().await
+                {
+                    if message_id == forwarded_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        instance_a
+            .set_conversation_message_retention(source_id, Some(Duration::from_secs(1)))
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDeleted { message_id, .. }) =
+                    source_a.next().await
+                {
+                    if message_id == original_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        assert!(instance_a
+            .get_message(source_id, original_id)
+            .await
+            .is_err());
+
+        let reference = instance_a
+            .root_directory()
+            .get_item("image.png")?
+            .get_file()?
+            .reference()
+            .expect("file reference");
+        let cid = reference.trim_start_matches("/ipfs/").parse::<Cid>()?;
+
+        let ipfs_a = instance_a
+            .handle()?
+            .downcast_ref::<Ipfs>()
+            .cloned()
+            .expect("ipfs handle");
+
+        assert!(ipfs_a.is_pinned(cid).await?);
+
+        let stream = instance_c
+            .download_stream(target_id, forwarded_id, "image.png")
+            .await?;
+
+        let data = stream.try_collect::<Vec<_>>().await?.concat();
+
+        assert_eq!(data, PROFILE_IMAGE);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn send_attachment_stream_and_download_attachment_in_conversation() -> anyhow::Result<()>
+    {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::send_attachment_stream_and_download_attachment_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::send_attachment_stream_and_download_attachment_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let (_, mut stream) = instance_a
+            .attach(
+                conversation_id,
+                None,
+                vec![Location::Stream {
+                    name: "image.png".into(),
+                    stream: futures::stream::iter(vec![Ok(PROFILE_IMAGE.into())]).boxed(),
+                    size: Some(PROFILE_IMAGE.len()),
+                }],
+                vec![],
+            )
+            .await?;
+
+        while let Some(event) = stream.next().await {
+            match event {
+                AttachmentKind::AttachedProgress(
+                    _location,
+                    Progression::CurrentProgress { .. },
+                ) => {}
+                AttachmentKind::AttachedProgress(
+                    _location,
+                    Progression::ProgressComplete { name, total },
+                ) => {
+                    assert_eq!(name, "image.png");
+                    assert_eq!(total, Some(PROFILE_IMAGE.len()));
+                }
+                AttachmentKind::AttachedProgress(_location, Progression::ProgressFailed { .. }) => {
+                    unreachable!("should not fail")
+                }
+                AttachmentKind::Pending(result) => {
+                    result?;
+                }
+            }
+        }
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+        assert_eq!(message_a.message_type(), MessageType::Attachment);
+        let attachments = message_a.attachments();
+        assert!(!attachments.is_empty());
+
+        let file = attachments.first().expect("attachment exist");
+
+        assert_eq!(file.name(), "image.png");
+
+        let stream = instance_b
+            .download_stream(conversation_id, message_a.id(), "image.png")
+            .await?;
+
+        let data = stream.try_collect::<Vec<_>>().await?.concat();
+
+        assert_eq!(data, PROFILE_IMAGE);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn delete_message_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::delete_message_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::delete_message_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+
+        instance_a
+            .delete(conversation_id, Some(message_a.id()))
+            .await?;
+
+        // Maybe cross check the message id and convo
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_b.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn edit_message_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::edit_message_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::edit_message_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+
+        instance_a
+            .edit(conversation_id, message_a.id(), vec!["New Message".into()])
+            .await?;
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageEdited {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageEdited {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+
+        for instance in [&instance_a, &instance_b] {
+            let history = instance
+                .get_message_history(conversation_id, message_a.id())
+                .await?;
+            assert_eq!(history.len(), 2);
+            assert_eq!(history[0].lines(), ["Hello, World".to_string()]);
+            assert_eq!(history[1].lines(), ["New Message".to_string()]);
+            assert_eq!(history[1], message_a);
+            assert!(history
+                .iter()
+                .all(|revision| revision.sender() == message_a.sender()));
+        }
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn thread_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::thread_in_conversation".into())),
+            (None, None, Some("test::thread_in_conversation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let root_id = instance_a
+            .send(conversation_id, vec!["Root".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(message_id, root_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let reply_id = instance_b
+            .reply(conversation_id, root_id, vec!["Reply".into()])
+            .await?;
+
+        for stream in [&mut conversation_a, &mut conversation_b] {
+            crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::ThreadUpdated {
+                        message_id,
+                        reply_count,
+                        last_reply,
+                        ..
+                    }) = stream.next().await
+                    {
+                        assert_eq!(message_id, root_id);
+                        assert_eq!(reply_count, 1);
+                        assert!(last_reply.is_some());
+                        break;
+                    }
+                }
+            })
+            .await?;
+        }
+
+        for instance in [&instance_a, &instance_b] {
+            let root = instance.get_message(conversation_id, root_id).await?;
+            assert_eq!(root.reply_count(), 1);
+
+            let Messages::List(thread) = instance
+                .get_thread(conversation_id, root_id, MessageOptions::default())
+                .await?
+            else {
+                anyhow::bail!("invalid message type");
+            };
+
+            assert_eq!(thread.len(), 1);
+            assert_eq!(thread[0].id(), reply_id);
+            assert_eq!(thread[0].replied(), Some(root_id));
+            assert_eq!(Some(thread[0].date()), root.last_reply());
+        }
+
+        instance_b.delete(conversation_id, Some(reply_id)).await?;
+
+        for stream in [&mut conversation_a, &mut conversation_b] {
+            crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::ThreadUpdated {
+                        message_id,
+                        reply_count,
+                        ..
+                    }) = stream.next().await
+                    {
+                        assert_eq!(message_id, root_id);
+                        assert_eq!(reply_count, 0);
+                        break;
+                    }
+                }
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn poll_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::poll_in_conversation".into())),
+            (None, None, Some("test::poll_in_conversation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let poll = Poll::new("Lunch?", vec!["Pizza".into(), "Tacos".into()]);
+
+        let poll_id = instance_a.send_poll(conversation_id, poll).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(message_id, poll_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, poll_id).await?;
+        assert_eq!(message.message_type(), MessageType::Poll);
+        let poll = message.poll().expect("poll");
+        assert_eq!(poll.question(), "Lunch?");
+        assert_eq!(poll.options().len(), 2);
+
+        assert!(instance_b
+            .vote(conversation_id, poll_id, vec![0, 1])
+            .await
+            .is_err());
+
+        instance_b.vote(conversation_id, poll_id, vec![1]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::PollVoted {
+                    message_id,
+                    did_key,
+                    options,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(message_id, poll_id);
+                    assert_eq!(did_key, did_b);
+                    assert_eq!(options, vec![1]);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let message = instance.get_message(conversation_id, poll_id).await?;
+            assert_eq!(message.poll().expect("poll").tally(), vec![0, 1]);
+        }
+
+        assert!(instance_b
+            .close_poll(conversation_id, poll_id)
+            .await
+            .is_err());
+
+        instance_a.close_poll(conversation_id, poll_id).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::PollClosed { message_id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(message_id, poll_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, poll_id).await?;
+        assert!(message.poll().expect("poll").is_closed());
+
+        assert!(instance_b
+            .retract_vote(conversation_id, poll_id)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn live_geolocation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::live_geolocation".into())),
+            (None, None, Some("test::live_geolocation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let position = GeoPosition::new(52.52, 13.405)?;
+
+        assert!(instance_a
+            .send_geolocation(conversation_id, position, Some(Duration::ZERO))
+            .await
+            .is_err());
+
+        let message_id = instance_a
+            .send_geolocation(
+                conversation_id,
+                position,
+                Some(Duration::from_secs(15 * 60)),
+            )
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.message_type(), MessageType::Geolocation);
+        let geolocation = message.geolocation().expect("geolocation");
+        assert_eq!(geolocation.position(), position);
+        assert!(geolocation.is_live());
+
+        assert!(instance_b
+            .update_geolocation(conversation_id, message_id, position)
+            .await
+            .is_err());
+
+        let moved = GeoPosition::new(52.53, 13.41)?;
+        instance_a
+            .update_geolocation(conversation_id, message_id, moved)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::GeolocationUpdated {
+                    message_id: id,
+                    did_key,
+                    position,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    assert_eq!(did_key, did_a);
+                    assert_eq!(position, moved);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        instance_a
+            .stop_geolocation(conversation_id, message_id)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::GeolocationStopped { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert!(!message.geolocation().expect("geolocation").is_live());
+
+        assert!(instance_a
+            .update_geolocation(conversation_id, message_id, moved)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn share_contact_card() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::share_contact_card".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::share_contact_card".into()),
+            ),
+            (
+                Some("Foobar"),
+                None,
+                Some("test::share_contact_card".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (instance_c, did_c, identity_c) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        //used to wait for the nodes to discover eachother and provide their identity to each other
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if instance_a.get_identity(&did_c).await.is_ok() {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message_id = instance_a
+            .send_contact_card(conversation_id, &did_c)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.message_type(), MessageType::ContactCard);
+        let card = message.contact_card().expect("contact card");
+        assert_eq!(card.did(), &did_c);
+        assert_eq!(card.username(), identity_c.username());
+        assert_eq!(card.short_id(), identity_c.short_id());
+
+        instance_b.send_request(card.did()).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(true) = instance_c.received_friend_request_from(&did_b).await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn link_previews_in_conversation() -> anyhow::Result<()> {
+        use std::collections::HashMap;
+        use std::io::Cursor;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use warp::error::Error;
+        use warp::raygun::EmbedState;
+        use warp_ipfs::store::conversation::link_preview::{LinkPreviewFetcher, LinkResource};
+
+        struct LocalFetcher {
+            resources: HashMap<String, LinkResource>,
+            requests: AtomicUsize,
+        }
+
+        #[async_trait::async_trait]
+        impl LinkPreviewFetcher for LocalFetcher {
+            async fn fetch(&self, url: &str) -> Result<LinkResource, Error> {
+                self.requests.fetch_add(1, Ordering::SeqCst);
+                self.resources
+                    .get(url)
+                    .cloned()
+                    .ok_or(Error::ObjectNotFound)
+            }
+        }
+
+        let mut image = Cursor::new(vec![]);
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            256,
+            256,
+            image::Rgb([200, 30, 30]),
+        ))
+        .write_to(&mut image, image::ImageFormat::Png)?;
+
+        let page = r#"<html><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="Example article">
+            <meta property="og:description" content="An article about examples">
+            <meta property="og:site_name" content="Example">
+            <meta property="og:image" content="/preview.png">
+        </head><body></body></html>"#;
+
+        let fetcher = Arc::new(LocalFetcher {
+            resources: HashMap::from_iter([
+                (
+                    "https://example.com/article".to_string(),
+                    LinkResource {
+                        content_type: Some("text/html; charset=utf-8".into()),
+                        body: page.as_bytes().to_vec().into(),
+                    },
+                ),
+                (
+                    "https://example.com/preview.png".to_string(),
+                    LinkResource {
+                        content_type: Some("image/png".into()),
+                        body: image.into_inner().into(),
+                    },
+                ),
+            ]),
+            requests: AtomicUsize::new(0),
+        });
+
+        let accounts = create_accounts_with_config(
+            vec![
+                (
+                    None,
+                    None,
+                    Some("test::link_previews_in_conversation".into()),
+                ),
+                (
+                    None,
+                    None,
+                    Some("test::link_previews_in_conversation".into()),
+                ),
+            ],
+            |config| {
+                config.store_setting_mut().link_preview_fetcher = Some(fetcher.clone());
+            },
+        )
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let message_id = instance_a
+            .send(
+                conversation_id,
+                vec!["Have a look at https://example.com/article.".into()],
+            )
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        assert!(instance_b
+            .embeds(conversation_id, message_id, EmbedState::Enabled)
+            .await
+            .is_err());
+
+        instance_a
+            .embeds(conversation_id, message_id, EmbedState::Enabled)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::LinkPreviewsUpdated { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let message = instance.get_message(conversation_id, message_id).await?;
+            let [preview] = message.link_previews() else {
+                panic!("expected a single link preview");
+            };
+            assert_eq!(preview.url(), "https://example.com/article");
+            assert_eq!(preview.title(), Some("Example article"));
+            assert_eq!(preview.description(), Some("An article about examples"));
+            assert_eq!(preview.site_name(), Some("Example"));
+            assert!(!preview.thumbnail().is_empty());
+        }
+
+        // Only the sender fetched the page and its image
+        assert_eq!(fetcher.requests.load(Ordering::SeqCst), 2);
+
+        instance_a
+            .embeds(conversation_id, message_id, EmbedState::Disable)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::LinkPreviewsUpdated { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert!(message.link_previews().is_empty());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn get_messages_from_cursor_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::get_messages_from_cursor_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::get_messages_from_cursor_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+
+        let mut ids = vec![];
+
+        for index in 0..5 {
+            instance_a
+                .send(conversation_id, vec![format!("message {index}")])
+                .await?;
+
+            let message_id = crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                        conversation_a.next().await
+                    {
+                        break message_id;
+                    }
+                }
+            })
+            .await?;
+
+            ids.push(message_id);
+        }
+
+        let get_ids = |options: MessageOptions| {
+            let instance = instance_a.clone();
+            async move {
+                match instance.get_messages(conversation_id, options).await? {
+                    Messages::List(list) => Ok::<_, anyhow::Error>(
+                        list.iter().map(|message| message.id()).collect::<Vec<_>>(),
+                    ),
+                    _ => anyhow::bail!("invalid message type"),
+                }
+            }
+        };
+
+        let before = get_ids(MessageOptions::default().set_before(ids[3]).set_limit(2)).await?;
+        assert_eq!(before, ids[1..3]);
+
+        let after = get_ids(MessageOptions::default().set_after(ids[1]).set_limit(2)).await?;
+        assert_eq!(after, ids[2..4]);
+
+        let around = get_ids(MessageOptions::default().set_around(ids[2]).set_limit(3)).await?;
+        assert_eq!(around, ids[1..4]);
+
+        let after = get_ids(MessageOptions::default().set_after(ids[4])).await?;
+        assert!(after.is_empty());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn search_messages_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::search_messages_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::search_messages_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Good morning, World".into()])
+            .await?;
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let results = instance
+                .search_messages(SearchQuery::new(r#""good morning""#))
+                .await?;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].conversation_id(), conversation_id);
+            assert_eq!(results[0].message_id(), message_id);
+            assert_eq!(results[0].sender(), &did_a);
+
+            let results = instance
+                .search_messages(SearchQuery::new("wor*").set_sender(did_b.clone()))
+                .await?;
+            assert!(results.is_empty());
+        }
+
+        instance_a
+            .edit(conversation_id, message_id, vec!["Good evening".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageEdited { .. }) = conversation_b.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let results = instance
+                .search_messages(SearchQuery::new("morning"))
+                .await?;
+            assert!(results.is_empty());
+            let results = instance
+                .search_messages(SearchQuery::new("evening"))
+                .await?;
+            assert_eq!(results.len(), 1);
+        }
+
+        instance_a.delete(conversation_id, Some(message_id)).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_b.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let results = instance
+                .search_messages(SearchQuery::new("evening"))
+                .await?;
+            assert!(results.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn message_receipts_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::message_receipts_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::message_receipts_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDelivered {
+                    conversation_id: id,
+                    message_id: m_id,
+                    did_key,
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(id, conversation_id);
+                    assert_eq!(m_id, message_id);
+                    assert_eq!(did_key, did_b);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let status = instance_a
+            .message_status(conversation_id, message_id)
+            .await?;
+        assert_eq!(status, MessageStatus::Delivered);
+
+        let receipts = instance_a
+            .message_receipts(conversation_id, message_id)
+            .await?;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].did(), &did_b);
+        assert!(receipts[0].delivered().is_some());
+        assert!(receipts[0].read().is_none());
+
+        instance_b
+            .mark_message_read(conversation_id, message_id)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageRead {
+                    message_id: m_id,
+                    did_key,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(m_id, message_id);
+                    assert_eq!(did_key, did_b);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let status = instance.message_status(conversation_id, message_id).await?;
+            assert_eq!(status, MessageStatus::Read);
+        }
+
+        let receipts = instance_a
+            .message_receipts(conversation_id, message_id)
+            .await?;
+        assert!(receipts[0].read().is_some());
+
+        assert_eq!(
+            instance_b.last_read_message(conversation_id).await?,
+            Some(message_id)
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn unread_count_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::unread_count_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::unread_count_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let first_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+        let second_id = instance_a
+            .send(conversation_id, vec!["Are you there?".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let mut received = 0;
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    received += 1;
+                    if received == 2 {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(instance_a.unread_count(conversation_id).await?, 0);
+        assert_eq!(instance_b.unread_count(conversation_id).await?, 2);
+        assert_eq!(instance_b.unread_mention_count(conversation_id).await?, 0);
+        assert_eq!(instance_b.last_read_message(conversation_id).await?, None);
+
+        instance_b.mark_read(conversation_id, first_id).await?;
+        assert_eq!(instance_b.unread_count(conversation_id).await?, 1);
+        assert_eq!(
+            instance_b.last_read_message(conversation_id).await?,
+            Some(first_id)
+        );
+
+        instance_b.mark_read(conversation_id, second_id).await?;
+        assert_eq!(instance_b.unread_count(conversation_id).await?, 0);
+        assert_eq!(
+            instance_b
+                .message_status(conversation_id, second_id)
+                .await?,
+            MessageStatus::Read
+        );
+
+        // the marker does not move back to an older message
+        instance_b.mark_read(conversation_id, first_id).await?;
+        assert_eq!(instance_b.unread_count(conversation_id).await?, 0);
+        assert_eq!(
+            instance_b.last_read_message(conversation_id).await?,
+            Some(second_id)
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn scheduled_message_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::scheduled_message_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::scheduled_message_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let now = chrono::Utc::now();
+
+        assert!(instance_a
+            .schedule_send(
+                conversation_id,
+                vec!["Too late".into()],
+                now - chrono::Duration::seconds(1)
+            )
+            .await
+            .is_err());
+
+        let scheduled_id = instance_a
+            .schedule_send(
+                conversation_id,
+                vec!["Hello, World".into()],
+                now + chrono::Duration::hours(1),
+            )
+            .await?;
+
+        let cancelled_id = instance_a
+            .schedule_send(
+                conversation_id,
+                vec!["Never sent".into()],
+                now + chrono::Duration::hours(1),
+            )
+            .await?;
+
+        let scheduled = instance_a.list_scheduled_messages(conversation_id).await?;
+        assert_eq!(scheduled.len(), 2);
 
         instance_a
-            .delete(conversation_id, Some(message_a.id()))
+            .cancel_scheduled_message(conversation_id, cancelled_id)
             .await?;
 
-        // Maybe cross check the message id and convo
+        instance_a
+            .reschedule_message(
+                conversation_id,
+                scheduled_id,
+                chrono::Utc::now() + chrono::Duration::seconds(2),
+            )
+            .await?;
+
+        let scheduled = instance_a.list_scheduled_messages(conversation_id).await?;
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].id(), scheduled_id);
+        assert_eq!(scheduled[0].lines(), ["Hello, World".to_string()]);
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
         crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_a.next().await {
+                if let Some(MessageEventKind::MessageReceived {
+                    message_id: m_id, ..
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(m_id, message_id);
                     break;
                 }
             }
         })
         .await?;
 
+        let message = instance_b.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.lines(), ["Hello, World".to_string()]);
+
+        assert!(instance_a
+            .list_scheduled_messages(conversation_id)
+            .await?
+            .is_empty());
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn scheduled_message_sent_after_restart() -> anyhow::Result<()> {
+        use crate::common::{create_account_with_config, mesh_connect, open_account_with_config};
+        use rust_ipfs::Ipfs;
+        use warp::SingleHandle;
+
+        let path = std::env::temp_dir().join(format!("warp-ipfs-{}", uuid::Uuid::new_v4()));
+
+        let persistent = |config: &mut warp_ipfs::config::Config| {
+            *config.path_mut() = Some(path.clone());
+            *config.persist_mut() = true;
+        };
+
+        let (mut instance_a, _, _) =
+            create_account_with_config(None, None, None, persistent).await?;
+
+        let accounts = create_accounts(vec![(
+            None,
+            None,
+            Some("test::scheduled_message_sent_after_restart".into()),
+        )])
+        .await?;
+
+        let (instance_b, did_b, _) = accounts.first().cloned().unwrap();
+
+        let node_b = instance_b
+            .handle()?
+            .downcast_ref::<Ipfs>()
+            .cloned()
+            .unwrap();
+        let node_a = instance_a
+            .handle()?
+            .downcast_ref::<Ipfs>()
+            .cloned()
+            .unwrap();
+
+        mesh_connect(vec![node_a.clone(), node_b.clone()]).await?;
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let send_at = chrono::Utc::now() + chrono::Duration::seconds(5);
+
+        let scheduled_id = instance_a
+            .schedule_send(conversation_id, vec!["Hello, World".into()], send_at)
+            .await?;
+
+        // Shut the node down before the message is due and keep it offline until it passed
+        drop(chat_subscribe_a);
+        drop(instance_a);
+        node_a.exit_daemon().await;
+
+        tokio::time::sleep(Duration::from_secs(8)).await;
+
+        let mut instance_a = open_account_with_config(persistent).await?;
+
+        let node_a = instance_a
+            .handle()?
+            .downcast_ref::<Ipfs>()
+            .cloned()
+            .unwrap();
+        mesh_connect(vec![node_a, node_b]).await?;
+
         crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_b.next().await {
-                    break;
+                if let Ok(list) = instance_a.list_scheduled_messages(conversation_id).await {
+                    if list.is_empty() {
+                        break;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        let messages = instance_a
+            .get_messages(conversation_id, MessageOptions::default())
+            .await?;
+
+        let Messages::List(messages) = messages else {
+            unreachable!()
+        };
+
+        let message = messages
+            .iter()
+            .find(|message| message.id() == scheduled_id)
+            .expect("scheduled message sent");
+
+        assert_eq!(message.lines(), ["Hello, World".to_string()]);
+
+        _ = std::fs::remove_dir_all(&path);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn draft_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::draft_in_conversation".into())),
+            (None, None, Some("test::draft_in_conversation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        assert!(instance_a.get_draft(conversation_id).await?.is_none());
+
+        let reply_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let mut draft = Draft::new(vec!["Unsent".into()]);
+        draft.set_replied(Some(reply_id));
+        draft.set_attachments(vec![LocationKind::Disk {
+            path: "image.png".into(),
+        }]);
+
+        instance_a.set_draft(conversation_id, draft.clone()).await?;
+
+        let stored = instance_a
+            .get_draft(conversation_id)
+            .await?
+            .expect("draft stored");
+        assert_eq!(stored.lines(), draft.lines());
+        assert_eq!(stored.replied(), Some(reply_id));
+        assert_eq!(stored.attachments(), draft.attachments());
+        assert!(stored.modified().is_some());
+
+        let mut invalid = draft.clone();
+        invalid.set_attachments(vec![LocationKind::Stream {
+            name: "image.png".into(),
+        }]);
+        assert!(instance_a
+            .set_draft(conversation_id, invalid)
+            .await
+            .is_err());
+
+        instance_a.clear_draft(conversation_id).await?;
+        assert!(instance_a.get_draft(conversation_id).await?.is_none());
+
+        // storing an empty draft clears it
+        instance_a.set_draft(conversation_id, draft).await?;
+        instance_a
+            .set_draft(conversation_id, Draft::default())
+            .await?;
+        assert!(instance_a.get_draft(conversation_id).await?.is_none());
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn export_conversation() -> anyhow::Result<()> {
+        use warp_ipfs::store::conversation::export::{
+            ConversationExport, ExportFormat, ExportOptions, ExportProgression, EXPORT_HTML_FILE,
+            EXPORT_JSON_FILE,
+        };
+
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::export_conversation".into())),
+            (None, None, Some("test::export_conversation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
                 }
             }
         })
         .await?;
 
+        let first_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+        instance_a
+            .edit(conversation_id, first_id, vec!["Hello, Warp".into()])
+            .await?;
+        instance_a
+            .react(conversation_id, first_id, ReactionState::Add, "👍".into())
+            .await?;
+        instance_a
+            .pin(conversation_id, first_id, PinState::Pin)
+            .await?;
+        let reply_id = instance_a
+            .reply(conversation_id, first_id, vec!["</script>".into()])
+            .await?;
+
+        let path = std::env::temp_dir().join(format!("warp-export-{conversation_id}"));
+
+        let mut stream = instance_a
+            .raygun()
+            .export_conversation(
+                conversation_id,
+                path.clone(),
+                ExportOptions::new(ExportFormat::Directory),
+            )
+            .await?;
+
+        let mut completed = None;
+        while let Some(progress) = stream.next().await {
+            match progress {
+                ExportProgression::Complete { path } => completed = Some(path),
+                ExportProgression::Failed { error } => anyhow::bail!(error),
+                _ => {}
+            }
+        }
+
+        assert_eq!(completed.as_ref(), Some(&path));
+
+        let export: ConversationExport =
+            serde_json::from_slice(&std::fs::read(path.join(EXPORT_JSON_FILE))?)?;
+        assert_eq!(export.conversation.id, conversation_id);
+        assert_eq!(export.messages.len(), 2);
+
+        let first = &export.messages[0];
+        assert_eq!(first.id, first_id);
+        assert_eq!(first.lines, vec!["Hello, Warp".to_string()]);
+        assert_eq!(first.edits.len(), 1);
+        assert_eq!(first.edits[0].lines, vec!["Hello, World".to_string()]);
+        assert!(first.pinned);
+        assert!(first.reactions.contains_key("👍"));
+
+        let reply = &export.messages[1];
+        assert_eq!(reply.id, reply_id);
+        assert_eq!(reply.replied, Some(first_id));
+
+        let html = std::fs::read_to_string(path.join(EXPORT_HTML_FILE))?;
+        assert!(html.contains(&conversation_id.to_string()));
+        assert!(!html.contains("[\"</script>\"]"));
+
+        let _ = std::fs::remove_dir_all(&path);
         Ok(())
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[async_test]
-    async fn edit_message_in_conversation() -> anyhow::Result<()> {
+    async fn import_conversation_archive() -> anyhow::Result<()> {
+        use warp_ipfs::store::conversation::archive::ARCHIVE_MANIFEST_FILE;
+        use warp_ipfs::store::conversation::export::{ExportFormat, ExportProgression};
+
         let accounts = create_accounts(vec![
-            (
-                None,
-                None,
-                Some("test::edit_message_in_conversation".into()),
-            ),
-            (
-                None,
-                None,
-                Some("test::edit_message_in_conversation".into()),
-            ),
+            (None, None, Some("test::import_conversation_archive".into())),
+            (None, None, Some("test::import_conversation_archive".into())),
         ])
         .await?;
 
         let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
-        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
 
         let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
-        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
 
         instance_a.create_conversation(&did_b).await?;
 
         let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
-            let mut id_a = None;
-            let mut id_b = None;
             loop {
-                tokio::select! {
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
-                        id_a.replace(conversation_id);
-                    },
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
-                        id_b.replace(conversation_id);
-                    },
-                }
-
-                if id_a.is_some() && id_b.is_some() {
-                    assert_eq!(id_a, id_b);
-                    break id_a.expect("valid conversation_id")
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
                 }
             }
-        }).await?;
-
-        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
-        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+        })
+        .await?;
 
-        instance_a
+        let first_id = instance_a
             .send(conversation_id, vec!["Hello, World".into()])
             .await?;
+        let second_id = instance_a
+            .send(conversation_id, vec!["Goodbye, World".into()])
+            .await?;
 
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageSent {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
-                {
-                    break instance_a.get_message(conversation_id, message_id).await;
-                }
-            }
-        })
-        .await??;
+        let path = std::env::temp_dir().join(format!("warp-archive-{conversation_id}"));
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageReceived {
-                    conversation_id,
-                    message_id,
-                }) = conversation_b.next().await
-                {
-                    break instance_b.get_message(conversation_id, message_id).await;
-                }
+        let mut stream = instance_a
+            .raygun()
+            .export_conversation_archive(conversation_id, path.clone(), ExportFormat::Directory)
+            .await?;
+
+        while let Some(progress) = stream.next().await {
+            if let ExportProgression::Failed { error } = progress {
+                anyhow::bail!(error);
             }
-        })
-        .await??;
+        }
 
-        assert_eq!(message_a, message_b);
+        // tamper with the second message so it no longer matches its signature
+        let manifest_path = path.join(ARCHIVE_MANIFEST_FILE);
+        let mut manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&manifest_path)?)?;
+        manifest["messages"][1]["date"] = serde_json::json!(chrono::Utc::now());
+        std::fs::write(&manifest_path, serde_json::to_vec(&manifest)?)?;
+
+        // the archive cannot be imported while the conversation still exist
+        assert!(instance_a
+            .raygun()
+            .import_conversation_archive(path.clone())
+            .await
+            .is_err());
+
+        instance_a.delete(conversation_id, None).await?;
+
+        let summary = instance_a
+            .raygun()
+            .import_conversation_archive(path.clone())
+            .await?;
 
-        instance_a
-            .edit(conversation_id, message_a.id(), vec!["New Message".into()])
+        assert_eq!(summary.conversation_id, conversation_id);
+        assert_eq!(summary.messages, 2);
+        assert_eq!(summary.unverified, vec![second_id]);
+
+        // nor once it has already been imported
+        assert!(instance_a
+            .raygun()
+            .import_conversation_archive(path.clone())
+            .await
+            .is_err());
+
+        let imported = instance_a.raygun().list_imported_conversations().await?;
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].id, conversation_id);
+
+        let messages = instance_a
+            .raygun()
+            .get_imported_messages(conversation_id)
             .await?;
+        assert_eq!(messages.len(), 2);
 
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageEdited {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
-                {
-                    break instance_a.get_message(conversation_id, message_id).await;
-                }
-            }
-        })
-        .await??;
+        let first = messages
+            .iter()
+            .find(|imported| imported.message.id() == first_id)
+            .expect("message imported");
+        assert!(first.verified);
+        assert_eq!(first.message.lines(), ["Hello, World".to_string()]);
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageEdited {
-                    conversation_id,
-                    message_id,
-                }) = conversation_b.next().await
-                {
-                    break instance_b.get_message(conversation_id, message_id).await;
-                }
-            }
-        })
-        .await??;
+        let second = messages
+            .iter()
+            .find(|imported| imported.message.id() == second_id)
+            .expect("message imported");
+        assert!(!second.verified);
 
-        assert_eq!(message_a, message_b);
+        instance_a
+            .raygun()
+            .remove_imported_conversation(conversation_id)
+            .await?;
+        assert!(instance_a
+            .raygun()
+            .list_imported_conversations()
+            .await?
+            .is_empty());
+
+        let _ = std::fs::remove_dir_all(&path);
         Ok(())
     }
 
@@ -828,6 +3202,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_b.next().await
                 {
                     break instance_b.get_message(conversation_id, message_id).await;
@@ -1087,6 +3462,120 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn disappearing_messages_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::disappearing_messages_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::disappearing_messages_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let id_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(id_a, id_b);
+
+        let mut conversation_a = instance_a.get_conversation_stream(id_a).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(id_b).await?;
+
+        let retention = Duration::from_secs(5);
+
+        instance_a
+            .set_conversation_message_retention(id_a, Some(retention))
+            .await?;
+
+        for stream in [&mut conversation_a, &mut conversation_b] {
+            crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::ConversationMessageRetentionChanged {
+                        conversation_id,
+                        retention: current,
+                    }) = stream.next().await
+                    {
+                        assert_eq!(id_a, conversation_id);
+                        assert_eq!(current, Some(retention));
+                        break;
+                    }
+                }
+            })
+            .await?;
+        }
+
+        for instance in [&instance_a, &instance_b] {
+            let conversation = instance.get_conversation(id_a).await?;
+            assert_eq!(conversation.message_retention(), Some(retention));
+        }
+
+        instance_a.send(id_a, vec!["Hello, World".into()]).await?;
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        for stream in [&mut conversation_a, &mut conversation_b] {
+            crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(MessageEventKind::MessageDeleted { message_id: id, .. }) =
+                        stream.next().await
+                    {
+                        assert_eq!(id, message_id);
+                        break;
+                    }
+                }
+            })
+            .await?;
+        }
+
+        for instance in [&instance_a, &instance_b] {
+            assert!(instance.get_message(id_a, message_id).await.is_err());
+            assert_eq!(instance.get_message_count(id_a).await?, 0);
+        }
+
+        Ok(())
+    }
+
     #[async_test]
     async fn pin_message_in_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -1148,6 +3637,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_b.next().await
                 {
                     break instance_b.get_message(conversation_id, message_id).await;
@@ -1334,6 +3824,102 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn custom_event_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::custom_event_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::custom_event_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let invalid = CustomEvent::new("recording");
+        assert!(invalid.is_err());
+
+        let mut custom = CustomEvent::with_cbor("app.recording_voice", &50u8)?;
+        custom.set_ttl(Duration::from_secs(2))?;
+        let event = MessageEvent::Custom(custom);
+
+        instance_a
+            .send_event(conversation_id, event.clone())
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::EventReceived {
+                    conversation_id: _,
+                    did_key,
+                    event: received,
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(did_key, did_a);
+                    assert_eq!(received, event);
+                    let custom = received.custom().expect("custom event");
+                    assert_eq!(custom.name(), "app.recording_voice");
+                    assert_eq!(custom.decode::<u8>().expect("valid payload"), 50);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // The event was not sent again within its ttl so it should be cancelled
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::EventCancelled {
+                    conversation_id: _,
+                    did_key,
+                    event: cancelled,
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(did_key, did_a);
+                    assert_eq!(cancelled, event);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
     #[async_test]
     async fn delete_conversation_when_blocked() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -1,6 +1,7 @@
 mod common;
 #[cfg(test)]
 mod test {
+    use std::path::Path;
     use std::time::Duration;
 
     use crate::common::create_accounts;
@@ -26,6 +27,9 @@ mod test {
 
     use uuid::Uuid;
     use warp::error::Error;
+    use warp_ipfs::store::conversation::archive::ARCHIVE_MANIFEST_FILE;
+    use warp_ipfs::store::conversation::export::{ExportFormat, ExportProgression};
+    use warp_ipfs::WarpIpfsInstance;
 
     #[async_test]
     async fn create_empty_group_conversation() -> anyhow::Result<()> {
@@ -1215,6 +1219,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_b.next().await
                 {
                     break instance_b.get_message(conversation_id, message_id);
@@ -1229,6 +1234,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_c.next().await
                 {
                     break instance_c.get_message(conversation_id, message_id);
@@ -1243,6 +1249,7 @@ mod test {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
                     message_id,
+                    ..
                 }) = conversation_d.next().await
                 {
                     break instance_d.get_message(conversation_id, message_id);
@@ -1629,4 +1636,494 @@ mod test {
 
         Ok(())
     }
+
+    #[async_test]
+    async fn join_group_conversation_with_invite() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::join_group_conversation_with_invite".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::join_group_conversation_with_invite".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::join_group_conversation_with_invite".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::join_group_conversation_with_invite".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+        let (mut instance_d, _, _) = accounts[3].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![did_b.clone()], GroupPermissions::new())
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(id_a).await?;
+
+        let invite = instance_a
+            .create_conversation_invite(id_a, None, Some(1))
+            .await?;
+        assert_eq!(invite.conversation_id(), id_a);
+        assert_eq!(invite.uses(), 0);
+
+        let conversation_id = instance_c
+            .accept_conversation_invite(invite.token())
+            .await?;
+        assert_eq!(conversation_id, id_a);
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::RecipientAdded {
+                    conversation_id,
+                    recipient,
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(conversation_id, id_a);
+                    assert_eq!(recipient, did_c);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    assert_eq!(conversation_id, id_a);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let invites = instance_a.list_conversation_invites(id_a).await?;
+        assert_eq!(invites.len(), 1);
+        assert_eq!(invites[0].uses(), 1);
+
+        // The invite has no uses left so the inviter rejects the request
+        assert!(instance_d
+            .accept_conversation_invite(invite.token())
+            .await
+            .is_err());
+        assert!(instance_d.get_conversation(id_a).await.is_err());
+
+        // Members without `AddParticipants` cannot create invites
+        assert!(instance_b
+            .create_conversation_invite(id_a, None, None)
+            .await
+            .is_err());
+
+        instance_a
+            .revoke_conversation_invite(id_a, invite.id())
+            .await?;
+        assert!(instance_a.list_conversation_invites(id_a).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn rotate_key_after_removing_recipient() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::rotate_key_after_removing_recipient".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::rotate_key_after_removing_recipient".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::rotate_key_after_removing_recipient".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(
+                None,
+                vec![did_b.clone(), did_c.clone()],
+                GroupPermissions::new(),
+            )
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let id_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) =
+                    chat_subscribe_c.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(id_a).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(id_b).await?;
+        let mut conversation_c = instance_c.get_conversation_stream(id_a).await?;
+
+        let before_id = instance_a.send(id_a, vec!["Before removal".into()]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_c.next().await
+                {
+                    assert_eq!(message_id, before_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // Keep the keystore of C from before the removal to attempt to decrypt later messages with it
+        let path_c = std::env::temp_dir().join(format!("warp-archive-{id_a}-{did_c}"));
+        export_archive(&instance_c, id_a, &path_c).await?;
+
+        instance_a.remove_recipient(id_a, &did_c).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::RecipientRemoved { recipient, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(recipient, did_c);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationDeleted { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    assert_eq!(conversation_id, id_a);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // Messages sent with the rotated keys should still reach the remaining members
+        let after_id = instance_a.send(id_a, vec!["After removal".into()]).await?;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(message_b.lines(), ["After removal"]);
+
+        // Capture the message sent after the removal and hand it to C alongside its previous keystore
+        let path_b = std::env::temp_dir().join(format!("warp-archive-{id_b}-{did_b}"));
+        export_archive(&instance_b, id_b, &path_b).await?;
+
+        let manifest_b: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path_b.join(ARCHIVE_MANIFEST_FILE))?)?;
+        let captured = manifest_b["messages"]
+            .as_array()
+            .and_then(|messages| {
+                messages
+                    .iter()
+                    .find(|message| message["id"] == serde_json::json!(after_id))
+            })
+            .cloned()
+            .expect("message captured");
+
+        let manifest_path = path_c.join(ARCHIVE_MANIFEST_FILE);
+        let mut manifest_c: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&manifest_path)?)?;
+        manifest_c["messages"]
+            .as_array_mut()
+            .expect("messages")
+            .push(captured);
+        std::fs::write(&manifest_path, serde_json::to_vec(&manifest_c)?)?;
+
+        let summary = instance_c
+            .raygun()
+            .import_conversation_archive(path_c.clone())
+            .await?;
+        assert_eq!(summary.messages, 2);
+        assert!(summary.unverified.is_empty());
+
+        let messages = instance_c.raygun().get_imported_messages(id_a).await?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.id(), before_id);
+        assert_eq!(messages[0].message.lines(), ["Before removal"]);
+
+        std::fs::remove_dir_all(&path_b)?;
+        std::fs::remove_dir_all(&path_c)?;
+
+        instance_b.send(id_b, vec!["Reply".into()]).await?;
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(message_a.lines(), ["Reply"]);
+
+        instance_a.rotate_conversation_key(id_a).await?;
+        instance_a.send(id_a, vec!["After rotation".into()]).await?;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(message_b.lines(), ["After rotation"]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn forward_message_to_group_conversation() -> anyhow::Result<()> {
+        use warp::raygun::{
+            FORWARDED_CONVERSATION_KEY, FORWARDED_MESSAGE_KEY, FORWARDED_SENDER_KEY,
+        };
+
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::forward_message_to_group_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forward_message_to_group_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::forward_message_to_group_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let source_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut source_b = instance_b.get_conversation_stream(source_id).await?;
+        let original_id = instance_a
+            .send(source_id, vec!["Forward me".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    source_b.next().await
+                {
+                    assert_eq!(message_id, original_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        instance_a
+            .create_group_conversation(
+                None,
+                vec![did_b.clone(), did_c.clone()],
+                GroupPermissions::new(),
+            )
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    if conversation_id != source_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        let target_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut target_c = instance_c.get_conversation_stream(target_id).await?;
+
+        let forwarded_id = instance_b
+            .forward(source_id, original_id, target_id)
+            .await?;
+
+        let forwarded = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                    ..
+                }) = target_c.next().await
+                {
+                    break instance_c.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(forwarded.id(), forwarded_id);
+        assert_eq!(forwarded.sender(), &did_b);
+        assert_eq!(forwarded.lines(), ["Forward me"]);
+        assert_eq!(
+            forwarded.metadata().get(FORWARDED_CONVERSATION_KEY),
+            Some(&source_id.to_string())
+        );
+        assert_eq!(
+            forwarded.metadata().get(FORWARDED_MESSAGE_KEY),
+            Some(&original_id.to_string())
+        );
+        assert_eq!(
+            forwarded.metadata().get(FORWARDED_SENDER_KEY),
+            Some(&did_a.to_string())
+        );
+
+        Ok(())
+    }
+
+    async fn export_archive(
+        instance: &WarpIpfsInstance,
+        conversation_id: Uuid,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let mut stream = instance
+            .raygun()
+            .export_conversation_archive(
+                conversation_id,
+                path.to_path_buf(),
+                ExportFormat::Directory,
+            )
+            .await?;
+
+        while let Some(progress) = stream.next().await {
+            if let ExportProgression::Failed { error } = progress {
+                anyhow::bail!(error);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -27,6 +27,10 @@ struct Opt {
     /// Password to unlock keystore
     #[clap(long)]
     password: Option<String>,
+
+    /// Compact the message references of every conversation
+    #[clap(long)]
+    compact: bool,
 }
 
 async fn setup<P: AsRef<Path>>(
@@ -129,7 +133,9 @@ async fn main() -> anyhow::Result<()> {
 
     let mut table = Table::new();
     table.set_header(vec!["ID", "Name", "Type", "Recipients", "# of Messages"]);
+    let mut conversation_ids = vec![];
     for convo in conversations {
+        conversation_ids.push(convo.id());
         let recipients = instance
             .get_identity(convo.recipients())
             .map(|id| format!("{}#{}", id.username(), id.short_id()))
@@ -149,5 +155,24 @@ async fn main() -> anyhow::Result<()> {
 
     println!("{table}");
 
+    if opt.compact {
+        let mut table = Table::new();
+        table.set_header(vec!["ID", "Pages", "# of Messages", "Holes", "Time"]);
+        for id in conversation_ids {
+            let start_time = Instant::now();
+            let stats = instance.raygun().compact_messages(id).await?;
+            let end_time = start_time.elapsed();
+            table.add_row(vec![
+                id.to_string(),
+                stats.pages.to_string(),
+                stats.messages.to_string(),
+                stats.holes.to_string(),
+                format!("{}ms", end_time.as_millis()),
+            ]);
+        }
+
+        println!("{table}");
+    }
+
     Ok(())
 }
@@ -8,6 +8,7 @@ use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Seek};
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 use super::item::FormatType;
@@ -80,6 +81,10 @@ pub struct File {
     /// External reference pointing to the source of the file
     reference: Arc<RwLock<Option<String>>>,
 
+    /// Duration and waveform of the audio if the file was attached as a voice note
+    #[serde(default)]
+    voice_note: Arc<RwLock<Option<VoiceNote>>>,
+
     /// Path to file
     #[serde(default)]
     path: Arc<String>,
@@ -136,6 +141,7 @@ impl Default for File {
             file_type: Default::default(),
             hash: Default::default(),
             reference: Default::default(),
+            voice_note: Default::default(),
             path: Arc::new("/".into()),
             signal: Arc::default(),
         }
@@ -321,6 +327,14 @@ impl File {
         self.file_type.read().clone()
     }
 
+    pub fn set_voice_note(&self, voice_note: Option<VoiceNote>) {
+        *self.voice_note.write() = voice_note;
+    }
+
+    pub fn voice_note(&self) -> Option<VoiceNote> {
+        self.voice_note.read().clone()
+    }
+
     pub fn path(&self) -> &str {
         &self.path
     }
@@ -383,6 +397,30 @@ impl File {
     }
 }
 
+/// Metadata of an audio file attached as a voice note
+#[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct VoiceNote {
+    /// Length of the audio
+    duration: Duration,
+
+    /// Downsampled amplitude of the audio, from 0 (silent) to 255 (loudest)
+    waveform: Vec<u8>,
+}
+
+impl VoiceNote {
+    pub fn new(duration: Duration, waveform: Vec<u8>) -> Self {
+        Self { duration, waveform }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn waveform(&self) -> &[u8] {
+        &self.waveform
+    }
+}
+
 #[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
 pub struct Hash {
     #[serde(skip_serializing_if = "Option::is_none")]
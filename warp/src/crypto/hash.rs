@@ -1,5 +1,6 @@
 #![allow(clippy::result_large_err)]
 use digest::Digest;
+use hmac::{Hmac, Mac};
 use sha2::Sha256;
 use std::io::Read;
 
@@ -40,6 +41,33 @@ pub fn sha256_iter(
     hasher.finalize().to_vec()
 }
 
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives `N` bytes of key material from `ikm` using HKDF-SHA256 (RFC 5869)
+pub fn hkdf_sha256<const N: usize>(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; N] {
+    assert!(N <= 255 * 32, "HKDF output is too long");
+
+    let prk = hmac_sha256(salt, ikm);
+
+    let mut okm = [0u8; N];
+    let mut block = Vec::new();
+
+    for (index, chunk) in okm.chunks_mut(32).enumerate() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&prk).expect("HMAC can take key of any size");
+        mac.update(&block);
+        mac.update(info);
+        mac.update(&[index as u8 + 1]);
+        block = mac.finalize().into_bytes().to_vec();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+
+    okm
+}
+
 #[cfg(test)]
 mod test {
     use crate::crypto::hash::*;
@@ -65,4 +93,22 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn hkdf_sha256_test() -> anyhow::Result<()> {
+        // RFC 5869, test case 1
+        let ikm = [0x0b; 22];
+        let salt = hex::decode("000102030405060708090a0b0c")?;
+        let info = hex::decode("f0f1f2f3f4f5f6f7f8f9")?;
+
+        let okm = hkdf_sha256::<42>(&salt, &ikm, &info);
+
+        assert_eq!(
+            hex::encode(okm),
+            String::from(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+            )
+        );
+        Ok(())
+    }
 }
@@ -12,8 +12,9 @@ use crate::crypto::DID;
 use crate::raygun::{Error, Location};
 
 use super::{
-    AttachmentEventStream, ConversationImage, Message, MessageEvent, MessageEventStream,
-    MessageOptions, MessageReference, MessageStatus, Messages, PinState, ReactionState,
+    AttachmentEventStream, ConversationImage, Draft, Message, MessageEvent, MessageEventStream,
+    MessageOptions, MessageReceipt, MessageReference, MessageStatus, Messages,
+    NotificationSettings, PinState, Poll, ReactionState,
 };
 
 pub type RoleId = Uuid;
@@ -180,6 +181,8 @@ pub struct CommunityChannel {
     modified: DateTime<Utc>,
     channel_type: CommunityChannelType,
     permissions: CommunityChannelPermissions,
+    #[serde(default)]
+    notification_settings: NotificationSettings,
 }
 
 impl CommunityChannel {
@@ -204,6 +207,9 @@ impl CommunityChannel {
     pub fn permissions(&self) -> &CommunityChannelPermissions {
         &self.permissions
     }
+    pub fn notification_settings(&self) -> &NotificationSettings {
+        &self.notification_settings
+    }
 }
 impl CommunityChannel {
     pub fn set_id(&mut self, id: Uuid) {
@@ -227,6 +233,9 @@ impl CommunityChannel {
     pub fn set_permissions(&mut self, permissions: CommunityChannelPermissions) {
         self.permissions = permissions;
     }
+    pub fn set_notification_settings(&mut self, settings: NotificationSettings) {
+        self.notification_settings = settings;
+    }
 }
 
 #[derive(Default, Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -267,6 +276,9 @@ pub enum CommunityPermission {
 
     DeleteMessages,
     PinMessages,
+
+    /// Mention `@everyone`, `@here` or a role
+    MentionEveryone,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -551,6 +563,16 @@ pub trait RayGunCommunity: Sync + Send {
     ) -> Result<Messages, Error> {
         Err(Error::Unimplemented)
     }
+    /// Retrieve the replies to a message in a channel
+    async fn get_community_channel_thread(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _root_message_id: Uuid,
+        _options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        Err(Error::Unimplemented)
+    }
 
     /// Get a number of messages in a conversation
     async fn get_community_channel_message_count(
@@ -591,6 +613,102 @@ pub trait RayGunCommunity: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Get the delivery and read receipts of a message in a community channel
+    async fn community_channel_message_receipts(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Mark a message in a community channel as read
+    async fn mark_community_channel_message_read(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Set the last read message of a community channel, marking every message up to it as read.
+    /// The marker only moves forward
+    async fn mark_community_channel_read(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Get the last read message of a community channel
+    async fn get_community_channel_last_read_message(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<Option<Uuid>, Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Get the number of messages sent by others after the last read message of a community channel
+    async fn get_community_channel_unread_count(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Get the number of unread messages in a community channel that mention own identity
+    async fn get_community_channel_unread_mention_count(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Store the unsent message of a community channel, replacing any existing draft
+    async fn set_community_channel_draft(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _draft: Draft,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Get the unsent message of a community channel
+    async fn get_community_channel_draft(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<Option<Draft>, Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Remove the unsent message of a community channel
+    async fn clear_community_channel_draft(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Update the local notification settings of a community channel
+    async fn set_community_channel_notification_settings(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+    /// Get the local notification settings of a community channel
+    async fn get_community_channel_notification_settings(
+        &self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Sends a message to a conversation.
     async fn send_community_channel_message(
         &mut self,
@@ -623,6 +741,18 @@ pub trait RayGunCommunity: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Forward a message from a conversation into a community channel, reusing the attachments
+    /// of the original message
+    async fn forward_to_community_channel(
+        &mut self,
+        _conversation_id: Uuid,
+        _message_id: Uuid,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Delete message from a conversation
     async fn delete_community_channel_message(
         &mut self,
@@ -656,6 +786,47 @@ pub trait RayGunCommunity: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Sends a poll to a community channel
+    async fn send_community_channel_poll(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _poll: Poll,
+    ) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Vote on a poll, replacing any previous vote. Options are indexes of [`Poll::options`]
+    async fn vote_on_community_channel_poll(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+        _options: Vec<usize>,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Retract a vote on a poll
+    async fn retract_community_channel_poll_vote(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Close a poll so it no longer accepts votes. Only the creator of the poll can close it
+    async fn close_community_channel_poll(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _message_id: Uuid,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Send an event to a conversation
     async fn send_community_channel_messsage_event(
         &mut self,
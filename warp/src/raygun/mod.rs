@@ -5,6 +5,7 @@ use crate::constellation::file::{File, FileType};
 use crate::constellation::{ConstellationProgressStream, Progression};
 use crate::crypto::DID;
 use crate::error::Error;
+use crate::multipass::identity::ShortId;
 use crate::raygun::community::RayGunCommunity;
 use crate::{Extension, SingleHandle};
 
@@ -23,6 +24,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, HashSet};
 use std::fmt::Debug;
 use std::path::PathBuf;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[allow(unused_imports)]
@@ -35,6 +37,7 @@ pub enum RayGunEventKind {
     ConversationArchived { conversation_id: Uuid },
     ConversationUnarchived { conversation_id: Uuid },
     ConversationDeleted { conversation_id: Uuid },
+    ConversationJoinRejected { conversation_id: Uuid },
     CommunityCreated { community_id: Uuid },
     CommunityInvited { community_id: Uuid, invite_id: Uuid },
     CommunityDeleted { community_id: Uuid },
@@ -52,6 +55,15 @@ pub enum MessageEventKind {
     MessageReceived {
         conversation_id: Uuid,
         message_id: Uuid,
+        /// Whether a notification should be shown, based on the [`NotificationSettings`] of the conversation
+        should_notify: bool,
+    },
+    /// A received message mentions own identity, either directly or through a mass mention
+    Mentioned {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        /// Whether a notification should be shown, based on the [`NotificationSettings`] of the conversation
+        should_notify: bool,
     },
     MessageEdited {
         conversation_id: Uuid,
@@ -81,6 +93,62 @@ pub enum MessageEventKind {
         did_key: DID,
         reaction: String,
     },
+    MessageDelivered {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    MessageRead {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    /// A reply to the message was added or removed
+    ThreadUpdated {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        reply_count: usize,
+        last_reply: Option<DateTime<Utc>>,
+    },
+    PollVoted {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+        options: Vec<usize>,
+    },
+    PollVoteRetracted {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    PollClosed {
+        conversation_id: Uuid,
+        message_id: Uuid,
+    },
+    /// Live position shared in a message was updated by its sender
+    GeolocationUpdated {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+        position: GeoPosition,
+    },
+    /// Sender stopped sharing its live position, either explicitly or by it expiring
+    GeolocationStopped {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    /// Link previews of the message were generated or removed by the sender
+    LinkPreviewsUpdated {
+        conversation_id: Uuid,
+        message_id: Uuid,
+    },
+    /// Scheduled message could not be sent after being retried and was removed from the schedule
+    ScheduledMessageFailed {
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+        error: String,
+    },
     ConversationNameUpdated {
         conversation_id: Uuid,
         name: String,
@@ -95,6 +163,10 @@ pub enum MessageEventKind {
         conversation_id: Uuid,
         description: Option<String>,
     },
+    ConversationMessageRetentionChanged {
+        conversation_id: Uuid,
+        retention: Option<Duration>,
+    },
     RecipientAdded {
         conversation_id: Uuid,
         recipient: DID,
@@ -257,6 +329,17 @@ pub enum MessageEventKind {
         community_id: Uuid,
         channel_id: Uuid,
         message_id: Uuid,
+        /// Whether a notification should be shown, based on the [`NotificationSettings`] of the channel
+        should_notify: bool,
+    },
+    /// A received message in a community channel mentions own identity, either directly, through a role
+    /// or through a mass mention
+    CommunityMentioned {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        /// Whether a notification should be shown, based on the [`NotificationSettings`] of the channel
+        should_notify: bool,
     },
     CommunityMessageEdited {
         community_id: Uuid,
@@ -292,15 +375,244 @@ pub enum MessageEventKind {
         did_key: DID,
         reaction: String,
     },
+    CommunityMessageDelivered {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    CommunityMessageRead {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    /// A reply to the message was added or removed
+    CommunityThreadUpdated {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        reply_count: usize,
+        last_reply: Option<DateTime<Utc>>,
+    },
+    CommunityPollVoted {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+        options: Vec<usize>,
+    },
+    CommunityPollVoteRetracted {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
+    CommunityPollClosed {
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    },
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 #[repr(C)]
 pub enum MessageEvent {
     /// Event that represents typing
     Typing,
-    //TODO: Custom events?
+    /// Application defined event
+    Custom(CustomEvent),
+}
+
+impl MessageEvent {
+    /// Check that the event is within the limits allowed to be sent
+    pub fn validate(&self) -> Result<(), Error> {
+        match self {
+            MessageEvent::Typing => Ok(()),
+            MessageEvent::Custom(event) => event.validate(),
+        }
+    }
+
+    /// Returns the custom event, if any
+    pub fn custom(&self) -> Option<&CustomEvent> {
+        match self {
+            MessageEvent::Custom(event) => Some(event),
+            _ => None,
+        }
+    }
+}
+
+impl From<CustomEvent> for MessageEvent {
+    fn from(event: CustomEvent) -> Self {
+        MessageEvent::Custom(event)
+    }
+}
+
+/// Maximum length of a custom event name
+pub const MAX_CUSTOM_EVENT_NAME_LENGTH: usize = 64;
+/// Maximum size of a custom event payload, in bytes
+pub const MAX_CUSTOM_EVENT_PAYLOAD_SIZE: usize = 1_024;
+/// Time a custom event remains active if not refreshed or given a ttl
+pub const DEFAULT_CUSTOM_EVENT_TTL: Duration = Duration::from_secs(10);
+/// Maximum time a custom event can remain active without being refreshed
+pub const MAX_CUSTOM_EVENT_TTL: Duration = Duration::from_secs(60);
+
+/// [`Message::metadata`] key holding the conversation a forwarded message originated from
+pub const FORWARDED_CONVERSATION_KEY: &str = "forwarded_conversation_id";
+/// [`Message::metadata`] key holding the id of the original message that was forwarded
+pub const FORWARDED_MESSAGE_KEY: &str = "forwarded_message_id";
+/// [`Message::metadata`] key holding the sender of the original message that was forwarded.
+/// This is claimed by the member forwarding the message and is not verified
+pub const FORWARDED_SENDER_KEY: &str = "forwarded_sender";
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadEncoding {
+    #[default]
+    Json,
+    Cbor,
+}
+
+/// Ephemeral event defined by an application, identified by a namespaced name (eg `app.recording_voice`).
+///
+/// The event is considered active on the receiving end until it is cancelled or,
+/// if it is not sent again within its ttl, expired.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CustomEvent {
+    name: String,
+    #[serde(default)]
+    encoding: PayloadEncoding,
+    #[serde(default, skip_serializing_if = "Bytes::is_empty")]
+    payload: Bytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<Duration>,
+}
+
+impl CustomEvent {
+    /// Create an event without a payload
+    pub fn new(name: impl Into<String>) -> Result<Self, Error> {
+        let event = Self {
+            name: name.into(),
+            encoding: PayloadEncoding::default(),
+            payload: Bytes::new(),
+            ttl: None,
+        };
+        event.validate()?;
+        Ok(event)
+    }
+
+    /// Create an event with a payload encoded as json
+    pub fn with_json<T: Serialize>(name: impl Into<String>, payload: &T) -> Result<Self, Error> {
+        let payload = serde_json::to_vec(payload)?;
+        Self::with_payload(name, PayloadEncoding::Json, payload)
+    }
+
+    /// Create an event with a payload encoded as cbor
+    pub fn with_cbor<T: Serialize>(name: impl Into<String>, payload: &T) -> Result<Self, Error> {
+        let payload = serde_cbor::to_vec(payload)?;
+        Self::with_payload(name, PayloadEncoding::Cbor, payload)
+    }
+
+    fn with_payload(
+        name: impl Into<String>,
+        encoding: PayloadEncoding,
+        payload: Vec<u8>,
+    ) -> Result<Self, Error> {
+        let event = Self {
+            name: name.into(),
+            encoding,
+            payload: payload.into(),
+            ttl: None,
+        };
+        event.validate()?;
+        Ok(event)
+    }
+
+    /// Set the time the event remains active if it is not sent again
+    pub fn set_ttl(&mut self, ttl: Duration) -> Result<(), Error> {
+        if ttl.is_zero() || ttl > MAX_CUSTOM_EVENT_TTL {
+            return Err(Error::OtherWithContext(format!(
+                "ttl must be greater than zero and no more than {}s",
+                MAX_CUSTOM_EVENT_TTL.as_secs()
+            )));
+        }
+        self.ttl = Some(ttl);
+        Ok(())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl.unwrap_or(DEFAULT_CUSTOM_EVENT_TTL)
+    }
+
+    /// Decode the payload based on its encoding
+    pub fn decode<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        let item = match self.encoding {
+            PayloadEncoding::Json => serde_json::from_slice(&self.payload)?,
+            PayloadEncoding::Cbor => serde_cbor::from_slice(&self.payload)?,
+        };
+        Ok(item)
+    }
+
+    /// Check that the name is namespaced and that the event is within the size limits
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.is_empty() || self.name.len() > MAX_CUSTOM_EVENT_NAME_LENGTH {
+            return Err(Error::InvalidLength {
+                context: "name".into(),
+                current: self.name.len(),
+                minimum: Some(1),
+                maximum: Some(MAX_CUSTOM_EVENT_NAME_LENGTH),
+            });
+        }
+
+        let mut segments = self.name.split('.');
+        let valid = self.name.contains('.')
+            && segments.all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            });
+
+        if !valid {
+            return Err(Error::OtherWithContext(format!(
+                "event name \"{}\" must be namespaced (eg \"app.event\")",
+                self.name
+            )));
+        }
+
+        if self.payload.len() > MAX_CUSTOM_EVENT_PAYLOAD_SIZE {
+            return Err(Error::InvalidLength {
+                context: "payload".into(),
+                current: self.payload.len(),
+                minimum: None,
+                maximum: Some(MAX_CUSTOM_EVENT_PAYLOAD_SIZE),
+            });
+        }
+
+        if let Some(ttl) = self.ttl {
+            if ttl.is_zero() || ttl > MAX_CUSTOM_EVENT_TTL {
+                return Err(Error::OtherWithContext(format!(
+                    "ttl must be greater than zero and no more than {}s",
+                    MAX_CUSTOM_EVENT_TTL.as_secs()
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub enum AttachmentKind {
@@ -326,6 +638,27 @@ pub struct MessageOptions {
     range: Option<Range<usize>>,
     limit: Option<u8>,
     skip: Option<i64>,
+    cursor: Option<MessageCursor>,
+}
+
+/// Position within a conversation or channel, anchored on a message, from which messages are retrieved
+#[derive(Debug, Hash, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageCursor {
+    /// Messages sent prior to the message, excluding the message itself
+    Before(Uuid),
+    /// Messages sent after the message, excluding the message itself
+    After(Uuid),
+    /// Messages surrounding the message, including the message itself
+    Around(Uuid),
+}
+
+impl MessageCursor {
+    pub fn message_id(&self) -> Uuid {
+        match self {
+            MessageCursor::Before(id) | MessageCursor::After(id) | MessageCursor::Around(id) => *id,
+        }
+    }
 }
 
 impl MessageOptions {
@@ -380,6 +713,29 @@ impl MessageOptions {
         self.messages_type = r#type;
         self
     }
+
+    /// Select messages prior to `message_id`, up to [`MessageOptions::limit`]
+    pub fn set_before(mut self, message_id: Uuid) -> Self {
+        self.cursor = Some(MessageCursor::Before(message_id));
+        self
+    }
+
+    /// Select messages after `message_id`, up to [`MessageOptions::limit`]
+    pub fn set_after(mut self, message_id: Uuid) -> Self {
+        self.cursor = Some(MessageCursor::After(message_id));
+        self
+    }
+
+    /// Select messages surrounding `message_id`, up to [`MessageOptions::limit`]
+    pub fn set_around(mut self, message_id: Uuid) -> Self {
+        self.cursor = Some(MessageCursor::Around(message_id));
+        self
+    }
+
+    pub fn set_cursor(mut self, cursor: MessageCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
 }
 
 impl MessageOptions {
@@ -422,6 +778,161 @@ impl MessageOptions {
     pub fn reverse(&self) -> bool {
         self.reverse
     }
+
+    pub fn cursor(&self) -> Option<MessageCursor> {
+        self.cursor
+    }
+}
+
+/// Query used to search messages across all conversations and community channels.
+///
+/// Terms ending with `*` are matched by prefix and terms wrapped in double quotes are matched as a phrase
+/// (eg `hel* "good morning"`).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    query: String,
+    sender: Option<DID>,
+    date_range: Option<Range<DateTime<Utc>>>,
+    conversation_id: Option<Uuid>,
+    limit: Option<usize>,
+}
+
+impl SearchQuery {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_sender(mut self, sender: DID) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn set_date_range(mut self, range: Range<DateTime<Utc>>) -> Self {
+        self.date_range = Some(range);
+        self
+    }
+
+    pub fn set_conversation_id(mut self, conversation_id: Uuid) -> Self {
+        self.conversation_id = Some(conversation_id);
+        self
+    }
+
+    pub fn set_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl SearchQuery {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn sender(&self) -> Option<&DID> {
+        self.sender.as_ref()
+    }
+
+    pub fn date_range(&self) -> Option<Range<DateTime<Utc>>> {
+        self.date_range.clone()
+    }
+
+    pub fn conversation_id(&self) -> Option<Uuid> {
+        self.conversation_id
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+}
+
+/// Ranked hit returned from [`RayGun::search_messages`]
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// ID of the conversation or community channel
+    conversation_id: Uuid,
+
+    /// ID of the community if the message belongs to a community channel
+    community_id: Option<Uuid>,
+
+    /// ID of the message
+    message_id: Uuid,
+
+    /// ID of the sender of the message
+    sender: DID,
+
+    /// Timestamp of the message
+    date: DateTime<Utc>,
+
+    /// Portion of the message surrounding the match
+    snippet: String,
+
+    /// Relevance of the hit. Higher is better
+    score: f32,
+}
+
+// Getter functions
+impl SearchResult {
+    pub fn conversation_id(&self) -> Uuid {
+        self.conversation_id
+    }
+
+    pub fn community_id(&self) -> Option<Uuid> {
+        self.community_id
+    }
+
+    pub fn message_id(&self) -> Uuid {
+        self.message_id
+    }
+
+    pub fn sender(&self) -> &DID {
+        &self.sender
+    }
+
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+    }
+
+    pub fn snippet(&self) -> &str {
+        &self.snippet
+    }
+
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+}
+
+// Setter functions
+impl SearchResult {
+    pub fn set_conversation_id(&mut self, id: Uuid) {
+        self.conversation_id = id;
+    }
+
+    pub fn set_community_id(&mut self, id: Option<Uuid>) {
+        self.community_id = id;
+    }
+
+    pub fn set_message_id(&mut self, id: Uuid) {
+        self.message_id = id;
+    }
+
+    pub fn set_sender(&mut self, sender: DID) {
+        self.sender = sender;
+    }
+
+    pub fn set_date(&mut self, date: DateTime<Utc>) {
+        self.date = date;
+    }
+
+    pub fn set_snippet(&mut self, snippet: String) {
+        self.snippet = snippet;
+    }
+
+    pub fn set_score(&mut self, score: f32) {
+        self.score = score;
+    }
 }
 
 #[derive(Default, Debug, Hash, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display)]
@@ -534,23 +1045,105 @@ impl Ord for MessagePage {
     }
 }
 
+/// Invite allowing anyone holding its token to join a group conversation
 #[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
-pub struct ConversationImage {
-    data: Vec<u8>,
-    image_type: FileType,
+pub struct ConversationInvite {
+    id: Uuid,
+    conversation_id: Uuid,
+    inviter: DID,
+    created: DateTime<Utc>,
+    expiry: Option<DateTime<Utc>>,
+    max_uses: Option<u32>,
+    uses: u32,
+    /// Signed token that can be shared as a string or QR payload
+    token: String,
 }
 
-impl ConversationImage {
-    pub fn set_data(&mut self, data: Vec<u8>) {
-        self.data = data
+impl ConversationInvite {
+    pub fn id(&self) -> Uuid {
+        self.id
     }
 
-    pub fn set_image_type(&mut self, image_type: FileType) {
-        self.image_type = image_type
+    pub fn conversation_id(&self) -> Uuid {
+        self.conversation_id
     }
-}
 
-impl ConversationImage {
+    pub fn inviter(&self) -> &DID {
+        &self.inviter
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    pub fn expiry(&self) -> Option<DateTime<Utc>> {
+        self.expiry
+    }
+
+    pub fn max_uses(&self) -> Option<u32> {
+        self.max_uses
+    }
+
+    pub fn uses(&self) -> u32 {
+        self.uses
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl ConversationInvite {
+    pub fn set_id(&mut self, id: Uuid) {
+        self.id = id
+    }
+
+    pub fn set_conversation_id(&mut self, conversation_id: Uuid) {
+        self.conversation_id = conversation_id
+    }
+
+    pub fn set_inviter(&mut self, inviter: DID) {
+        self.inviter = inviter
+    }
+
+    pub fn set_created(&mut self, created: DateTime<Utc>) {
+        self.created = created
+    }
+
+    pub fn set_expiry(&mut self, expiry: Option<DateTime<Utc>>) {
+        self.expiry = expiry
+    }
+
+    pub fn set_max_uses(&mut self, max_uses: Option<u32>) {
+        self.max_uses = max_uses
+    }
+
+    pub fn set_uses(&mut self, uses: u32) {
+        self.uses = uses
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = token
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConversationImage {
+    data: Vec<u8>,
+    image_type: FileType,
+}
+
+impl ConversationImage {
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.data = data
+    }
+
+    pub fn set_image_type(&mut self, image_type: FileType) {
+        self.image_type = image_type
+    }
+}
+
+impl ConversationImage {
     pub fn data(&self) -> &[u8] {
         &self.data
     }
@@ -798,6 +1391,10 @@ pub struct Conversation {
     archived: bool,
     recipients: Vec<DID>,
     description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message_retention: Option<Duration>,
+    #[serde(default)]
+    notification_settings: NotificationSettings,
 }
 
 impl core::hash::Hash for Conversation {
@@ -831,6 +1428,8 @@ impl Default for Conversation {
             archived: false,
             recipients,
             description: None,
+            message_retention: None,
+            notification_settings: NotificationSettings::default(),
         }
     }
 }
@@ -879,6 +1478,16 @@ impl Conversation {
     pub fn archived(&self) -> bool {
         self.archived
     }
+
+    /// Period of time messages are kept in the conversation before they are removed
+    pub fn message_retention(&self) -> Option<Duration> {
+        self.message_retention
+    }
+
+    /// Local notification settings of the conversation
+    pub fn notification_settings(&self) -> &NotificationSettings {
+        &self.notification_settings
+    }
 }
 
 impl Conversation {
@@ -925,6 +1534,14 @@ impl Conversation {
     pub fn set_archived(&mut self, archived: bool) {
         self.archived = archived;
     }
+
+    pub fn set_message_retention(&mut self, retention: Option<Duration>) {
+        self.message_retention = retention;
+    }
+
+    pub fn set_notification_settings(&mut self, settings: NotificationSettings) {
+        self.notification_settings = settings;
+    }
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -962,6 +1579,385 @@ pub enum MessageType {
     /// TBD
     #[display(fmt = "event")]
     Event,
+    /// Poll that members of the conversation are able to vote on
+    #[display(fmt = "poll")]
+    Poll,
+    /// Geographic position shared by the sender
+    #[display(fmt = "geolocation")]
+    Geolocation,
+    /// Contact card of an identity shared by the sender
+    #[display(fmt = "contact_card")]
+    ContactCard,
+}
+
+/// Poll sent as a message of type [`MessageType::Poll`]
+#[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct Poll {
+    /// Question being asked
+    question: String,
+
+    /// Options that can be voted on
+    options: Vec<String>,
+
+    /// Allow more than one option to be voted on
+    #[serde(default)]
+    multiple_choice: bool,
+
+    /// Timestamp of when the poll stops accepting votes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closes: Option<DateTime<Utc>>,
+
+    /// Options voted on by each member, as indexes of `options`
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    votes: IndexMap<DID, Vec<usize>>,
+
+    /// Timestamp of when the poll was closed by its creator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closed: Option<DateTime<Utc>>,
+}
+
+impl Poll {
+    pub fn new(question: impl Into<String>, options: Vec<String>) -> Self {
+        Self {
+            question: question.into(),
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn question(&self) -> &str {
+        &self.question
+    }
+
+    pub fn options(&self) -> &[String] {
+        &self.options
+    }
+
+    pub fn multiple_choice(&self) -> bool {
+        self.multiple_choice
+    }
+
+    pub fn closes(&self) -> Option<DateTime<Utc>> {
+        self.closes
+    }
+
+    pub fn votes(&self) -> &IndexMap<DID, Vec<usize>> {
+        &self.votes
+    }
+
+    pub fn closed(&self) -> Option<DateTime<Utc>> {
+        self.closed
+    }
+
+    /// Returns true if the poll was closed or is past its closing time
+    pub fn is_closed(&self) -> bool {
+        self.closed.is_some() || self.closes.is_some_and(|closes| closes <= Utc::now())
+    }
+
+    /// Number of votes for each option, in the order of `options`
+    pub fn tally(&self) -> Vec<usize> {
+        let mut tally = vec![0; self.options.len()];
+        for index in self.votes.values().flatten() {
+            if let Some(count) = tally.get_mut(*index) {
+                *count += 1;
+            }
+        }
+        tally
+    }
+}
+
+impl Poll {
+    pub fn set_multiple_choice(&mut self, multiple_choice: bool) {
+        self.multiple_choice = multiple_choice
+    }
+
+    pub fn set_closes(&mut self, closes: Option<DateTime<Utc>>) {
+        self.closes = closes
+    }
+
+    pub fn set_votes(&mut self, votes: IndexMap<DID, Vec<usize>>) {
+        self.votes = votes
+    }
+
+    pub fn set_closed(&mut self, closed: Option<DateTime<Utc>>) {
+        self.closed = closed
+    }
+}
+
+/// Maximum time a position can be shared live
+pub const MAX_LIVE_GEOLOCATION_DURATION: Duration = Duration::from_secs(8 * 60 * 60);
+
+/// Geographic position in decimal degrees
+#[derive(Default, Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub struct GeoPosition {
+    latitude: f64,
+    longitude: f64,
+
+    /// Accuracy of the position, in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accuracy: Option<f64>,
+}
+
+// Note: Values are checked to be finite by `GeoPosition::validate`, so comparisons are reflexive
+impl Eq for GeoPosition {}
+
+impl GeoPosition {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, Error> {
+        let position = Self {
+            latitude,
+            longitude,
+            accuracy: None,
+        };
+        position.validate()?;
+        Ok(position)
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+
+    pub fn accuracy(&self) -> Option<f64> {
+        self.accuracy
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Option<f64>) -> Result<(), Error> {
+        if accuracy.is_some_and(|accuracy| !accuracy.is_finite() || accuracy < 0.0) {
+            return Err(Error::OtherWithContext(
+                "accuracy must be a positive number".into(),
+            ));
+        }
+        self.accuracy = accuracy;
+        Ok(())
+    }
+
+    /// Check that the latitude and longitude are within range and that the accuracy is valid
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.latitude.is_finite() || !(-90.0..=90.0).contains(&self.latitude) {
+            return Err(Error::OtherWithContext(
+                "latitude must be between -90 and 90".into(),
+            ));
+        }
+
+        if !self.longitude.is_finite() || !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(Error::OtherWithContext(
+                "longitude must be between -180 and 180".into(),
+            ));
+        }
+
+        if self
+            .accuracy
+            .is_some_and(|accuracy| !accuracy.is_finite() || accuracy < 0.0)
+        {
+            return Err(Error::OtherWithContext(
+                "accuracy must be a positive number".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Position shared as a message of type [`MessageType::Geolocation`]
+#[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct Geolocation {
+    /// Position at the time the message was sent
+    position: GeoPosition,
+
+    /// Timestamp until the sender is able to stream updates of its position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    live_until: Option<DateTime<Utc>>,
+
+    /// Timestamp of when the sender stopped sharing its live position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stopped: Option<DateTime<Utc>>,
+}
+
+impl Geolocation {
+    pub fn new(position: GeoPosition) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn position(&self) -> GeoPosition {
+        self.position
+    }
+
+    pub fn live_until(&self) -> Option<DateTime<Utc>> {
+        self.live_until
+    }
+
+    pub fn stopped(&self) -> Option<DateTime<Utc>> {
+        self.stopped
+    }
+
+    /// Returns true if the position is shared live and has not been stopped or expired
+    pub fn is_live(&self) -> bool {
+        self.stopped.is_none() && self.live_until.is_some_and(|until| until > Utc::now())
+    }
+}
+
+impl Geolocation {
+    pub fn set_live_until(&mut self, live_until: Option<DateTime<Utc>>) {
+        self.live_until = live_until
+    }
+
+    pub fn set_stopped(&mut self, stopped: Option<DateTime<Utc>>) {
+        self.stopped = stopped
+    }
+}
+
+/// Identity shared as a message of type [`MessageType::ContactCard`].
+/// The card can be used to view the profile of the identity or to send a friend request with [`Friends::send_request`]
+///
+/// [`Friends::send_request`]: crate::multipass::Friends::send_request
+#[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct ContactCard {
+    did: DID,
+
+    username: String,
+
+    short_id: ShortId,
+
+    /// Cid of the profile picture of the identity, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    picture: Option<String>,
+
+    /// Timestamp of when the identity was last modified
+    modified: DateTime<Utc>,
+}
+
+impl ContactCard {
+    pub fn new(did: DID, username: impl Into<String>, short_id: ShortId) -> Self {
+        Self {
+            did,
+            username: username.into(),
+            short_id,
+            picture: None,
+            modified: Utc::now(),
+        }
+    }
+
+    pub fn did(&self) -> &DID {
+        &self.did
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn short_id(&self) -> ShortId {
+        self.short_id
+    }
+
+    pub fn picture(&self) -> Option<&str> {
+        self.picture.as_deref()
+    }
+
+    pub fn modified(&self) -> DateTime<Utc> {
+        self.modified
+    }
+}
+
+impl ContactCard {
+    pub fn set_username(&mut self, username: impl Into<String>) {
+        self.username = username.into()
+    }
+
+    pub fn set_short_id(&mut self, short_id: ShortId) {
+        self.short_id = short_id
+    }
+
+    pub fn set_picture(&mut self, picture: Option<String>) {
+        self.picture = picture
+    }
+
+    pub fn set_modified(&mut self, modified: DateTime<Utc>) {
+        self.modified = modified
+    }
+}
+
+/// Preview of a link found within the lines of a message, generated by the sender
+#[derive(Default, Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct LinkPreview {
+    /// Url the preview was generated from
+    url: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    site_name: Option<String>,
+
+    /// Thumbnail of the image referenced by the page, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    thumbnail: Vec<u8>,
+
+    #[serde(default)]
+    thumbnail_format: FileType,
+}
+
+impl LinkPreview {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn site_name(&self) -> Option<&str> {
+        self.site_name.as_deref()
+    }
+
+    pub fn thumbnail(&self) -> &[u8] {
+        &self.thumbnail
+    }
+
+    pub fn thumbnail_format(&self) -> &FileType {
+        &self.thumbnail_format
+    }
+}
+
+impl LinkPreview {
+    pub fn set_title(&mut self, title: Option<String>) {
+        self.title = title
+    }
+
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.description = description
+    }
+
+    pub fn set_site_name(&mut self, site_name: Option<String>) {
+        self.site_name = site_name
+    }
+
+    pub fn set_thumbnail(&mut self, thumbnail: Vec<u8>) {
+        self.thumbnail = thumbnail
+    }
+
+    pub fn set_thumbnail_format(&mut self, format: FileType) {
+        self.thumbnail_format = format
+    }
 }
 
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
@@ -1072,6 +2068,74 @@ impl MessageReference {
     }
 }
 
+/// Mention within a message, parsed from its lines by the sender
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mention {
+    /// `@username#shortid`
+    Identity(DID),
+    /// `@everyone`
+    Everyone,
+    /// `@here`, intended for members that are currently online
+    Here,
+    /// `@role` within a community
+    Role(RoleId),
+}
+
+impl Mention {
+    /// Returns true if the mention notifies more than a single identity
+    pub fn is_mass_mention(&self) -> bool {
+        !matches!(self, Mention::Identity(_))
+    }
+}
+
+impl std::fmt::Display for Mention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mention::Identity(did) => write!(f, "{did}"),
+            Mention::Everyone => write!(f, "@everyone"),
+            Mention::Here => write!(f, "@here"),
+            Mention::Role(id) => write!(f, "@role:{id}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Mention {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mention = match s {
+            "@everyone" => Mention::Everyone,
+            "@here" => Mention::Here,
+            s => match s.strip_prefix("@role:") {
+                Some(id) => {
+                    Mention::Role(RoleId::parse_str(id).map_err(|_| Error::InvalidConversion)?)
+                }
+                None => Mention::Identity(s.parse()?),
+            },
+        };
+        Ok(mention)
+    }
+}
+
+// Note: Mentions are serialized as strings so messages that only contain identities remain compatible
+impl Serialize for Mention {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'d> Deserialize<'d> for Mention {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'d>,
+    {
+        let mention = <String>::deserialize(deserializer)?;
+        mention.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
 pub struct Message {
     /// ID of the Message
@@ -1100,20 +2164,44 @@ pub struct Message {
     /// List of the reactions for the `Message`
     reactions: IndexMap<String, Vec<DID>>,
 
-    /// List of users public keys mentioned in this message
+    /// List of mentions in this message
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    mentions: Vec<DID>,
+    mentions: Vec<Mention>,
 
     /// ID of the message being replied to
     #[serde(skip_serializing_if = "Option::is_none")]
     replied: Option<Uuid>,
 
+    /// Number of replies to the message
+    #[serde(default)]
+    reply_count: usize,
+
+    /// Timestamp of the latest reply to the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_reply: Option<DateTime<Utc>>,
+
     /// Message context for `Message`
     lines: Vec<String>,
 
+    /// Poll for `MessageType::Poll`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    poll: Option<Poll>,
+
+    /// Position for `MessageType::Geolocation`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    geolocation: Option<Geolocation>,
+
+    /// Identity for `MessageType::ContactCard`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contact_card: Option<ContactCard>,
+
     /// List of Attachment
     attachment: Vec<File>,
 
+    /// Previews of the links within `lines`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    link_previews: Vec<LinkPreview>,
+
     /// Metadata related to the message. Can be used externally, but more internally focused
     #[serde(flatten)]
     metadata: IndexMap<String, String>,
@@ -1132,8 +2220,14 @@ impl Default for Message {
             reactions: IndexMap::new(),
             mentions: Vec::new(),
             replied: None,
+            reply_count: 0,
+            last_reply: None,
             lines: Vec::new(),
+            poll: None,
+            geolocation: None,
+            contact_card: None,
             attachment: Vec::new(),
+            link_previews: Vec::new(),
             metadata: IndexMap::new(),
         }
     }
@@ -1191,7 +2285,7 @@ impl Message {
         &self.reactions
     }
 
-    pub fn mentions(&self) -> &[DID] {
+    pub fn mentions(&self) -> &[Mention] {
         &self.mentions
     }
 
@@ -1210,18 +2304,42 @@ impl Message {
     pub fn replied(&self) -> Option<Uuid> {
         self.replied
     }
-}
 
-impl Message {
-    pub fn set_id(&mut self, id: Uuid) {
-        self.id = id
+    pub fn reply_count(&self) -> usize {
+        self.reply_count
     }
 
-    pub fn set_message_type(&mut self, message_type: MessageType) {
-        self.message_type = message_type;
+    pub fn last_reply(&self) -> Option<DateTime<Utc>> {
+        self.last_reply
     }
 
-    pub fn set_conversation_id(&mut self, id: Uuid) {
+    pub fn poll(&self) -> Option<&Poll> {
+        self.poll.as_ref()
+    }
+
+    pub fn geolocation(&self) -> Option<&Geolocation> {
+        self.geolocation.as_ref()
+    }
+
+    pub fn contact_card(&self) -> Option<&ContactCard> {
+        self.contact_card.as_ref()
+    }
+
+    pub fn link_previews(&self) -> &[LinkPreview] {
+        &self.link_previews
+    }
+}
+
+impl Message {
+    pub fn set_id(&mut self, id: Uuid) {
+        self.id = id
+    }
+
+    pub fn set_message_type(&mut self, message_type: MessageType) {
+        self.message_type = message_type;
+    }
+
+    pub fn set_conversation_id(&mut self, id: Uuid) {
         self.conversation_id = id
     }
 
@@ -1245,7 +2363,7 @@ impl Message {
         self.reactions = reaction
     }
 
-    pub fn set_mentions(&mut self, mentions: Vec<DID>) {
+    pub fn set_mentions(&mut self, mentions: Vec<Mention>) {
         self.mentions = mentions
     }
 
@@ -1264,6 +2382,30 @@ impl Message {
     pub fn set_replied(&mut self, replied: Option<Uuid>) {
         self.replied = replied
     }
+
+    pub fn set_reply_count(&mut self, count: usize) {
+        self.reply_count = count
+    }
+
+    pub fn set_last_reply(&mut self, date: Option<DateTime<Utc>>) {
+        self.last_reply = date
+    }
+
+    pub fn set_poll(&mut self, poll: Option<Poll>) {
+        self.poll = poll
+    }
+
+    pub fn set_geolocation(&mut self, geolocation: Option<Geolocation>) {
+        self.geolocation = geolocation
+    }
+
+    pub fn set_contact_card(&mut self, contact_card: Option<ContactCard>) {
+        self.contact_card = contact_card
+    }
+
+    pub fn set_link_previews(&mut self, link_previews: Vec<LinkPreview>) {
+        self.link_previews = link_previews
+    }
 }
 
 // Mutable functions
@@ -1297,9 +2439,255 @@ pub enum MessageStatus {
     #[display(fmt = "sent")]
     Sent,
 
-    /// Confirmation of message being delivered. May be used in the future
+    /// Confirmation of message being delivered to the recipient(s)
     #[display(fmt = "delivered")]
     Delivered,
+
+    /// Confirmation of message being read by the recipient(s)
+    #[display(fmt = "read")]
+    Read,
+}
+
+/// Delivery and read acknowledgement of a message from a single recipient
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageReceipt {
+    /// DID of the recipient
+    did: DID,
+
+    /// Timestamp of when the message was delivered to the recipient
+    delivered: Option<DateTime<Utc>>,
+
+    /// Timestamp of when the message was read by the recipient
+    read: Option<DateTime<Utc>>,
+}
+
+impl MessageReceipt {
+    pub fn new(did: DID, delivered: Option<DateTime<Utc>>, read: Option<DateTime<Utc>>) -> Self {
+        Self {
+            did,
+            delivered,
+            read,
+        }
+    }
+
+    pub fn did(&self) -> &DID {
+        &self.did
+    }
+
+    pub fn delivered(&self) -> Option<DateTime<Utc>> {
+        self.delivered
+    }
+
+    pub fn read(&self) -> Option<DateTime<Utc>> {
+        self.read
+    }
+}
+
+/// Message that is held back and sent to a conversation at a later time
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    /// ID of the scheduled message, which is kept by the message once sent
+    id: Uuid,
+
+    /// ID of the conversation the message will be sent to
+    conversation_id: Uuid,
+
+    /// Lines of the message
+    lines: Vec<String>,
+
+    /// Timestamp of when the message should be sent
+    send_at: DateTime<Utc>,
+
+    /// Timestamp of when the message was scheduled
+    created: DateTime<Utc>,
+}
+
+impl ScheduledMessage {
+    pub fn new(conversation_id: Uuid, lines: Vec<String>, send_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            conversation_id,
+            lines,
+            send_at,
+            created: Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn conversation_id(&self) -> Uuid {
+        self.conversation_id
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn send_at(&self) -> DateTime<Utc> {
+        self.send_at
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    /// Returns true if the message is due to be sent
+    pub fn is_due(&self) -> bool {
+        self.send_at <= Utc::now()
+    }
+
+    pub fn set_send_at(&mut self, send_at: DateTime<Utc>) {
+        self.send_at = send_at;
+    }
+}
+
+/// Unsent message of a conversation or community channel
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Draft {
+    /// Lines of the message
+    lines: Vec<String>,
+
+    /// ID of the message being replied to
+    replied: Option<Uuid>,
+
+    /// Attachments staged for the message.
+    /// Note: Only [`LocationKind::Constellation`] and [`LocationKind::Disk`] can be stored
+    attachments: Vec<LocationKind>,
+
+    /// Timestamp of when the draft was last stored
+    modified: Option<DateTime<Utc>>,
+}
+
+impl Draft {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            ..Default::default()
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn replied(&self) -> Option<Uuid> {
+        self.replied
+    }
+
+    pub fn attachments(&self) -> &[LocationKind] {
+        &self.attachments
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.modified
+    }
+
+    /// Returns true if the draft has no content, reply target or attachments
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+            && self.replied.is_none()
+            && self.attachments.is_empty()
+    }
+
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+    }
+
+    pub fn set_replied(&mut self, replied: Option<Uuid>) {
+        self.replied = replied;
+    }
+
+    pub fn set_attachments(&mut self, attachments: Vec<LocationKind>) {
+        self.attachments = attachments;
+    }
+
+    pub fn set_modified(&mut self, modified: DateTime<Utc>) {
+        self.modified = Some(modified);
+    }
+}
+
+/// Which received messages should notify the user
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationLevel {
+    /// Notify on every message
+    #[display(fmt = "all")]
+    #[default]
+    All,
+    /// Notify only on messages that mention own identity
+    #[display(fmt = "mentions")]
+    Mentions,
+    /// Never notify
+    #[display(fmt = "none")]
+    None,
+}
+
+/// Local notification settings of a conversation or community channel.
+/// These are kept in the root document and are not shared with other participants
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    /// Level of notifications while not muted
+    #[serde(default)]
+    level: NotificationLevel,
+
+    /// Notifications are suppressed until this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    muted_until: Option<DateTime<Utc>>,
+
+    /// Hide the conversation or channel from the list
+    #[serde(default)]
+    hidden: bool,
+}
+
+impl NotificationSettings {
+    pub fn level(&self) -> NotificationLevel {
+        self.level
+    }
+
+    pub fn muted_until(&self) -> Option<DateTime<Utc>> {
+        self.muted_until
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Returns true if notifications are muted at this time
+    pub fn is_muted(&self) -> bool {
+        self.muted_until.is_some_and(|until| until > Utc::now())
+    }
+
+    /// Returns true if a received message should notify, given whether it mentions own identity
+    pub fn should_notify(&self, mentioned: bool) -> bool {
+        if self.is_muted() {
+            return false;
+        }
+
+        match self.level {
+            NotificationLevel::All => true,
+            NotificationLevel::Mentions => mentioned,
+            NotificationLevel::None => false,
+        }
+    }
+
+    /// Returns true if the settings are the same as the default
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn set_level(&mut self, level: NotificationLevel) {
+        self.level = level;
+    }
+
+    pub fn set_muted_until(&mut self, muted_until: Option<DateTime<Utc>>) {
+        self.muted_until = muted_until;
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -1446,6 +2834,17 @@ pub trait RayGun:
         Err(Error::Unimplemented)
     }
 
+    /// Retrieve every revision of a message, from the original to the latest edit.
+    /// [`Message::modified`] reflects when the revision was made
+    async fn get_message_history(&self, _: Uuid, _: Uuid) -> Result<Vec<Message>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Retrieve the replies to a message in a conversation
+    async fn get_thread(&self, _: Uuid, _: Uuid, _: MessageOptions) -> Result<Messages, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Get a number of messages in a conversation
     async fn get_message_count(&self, _: Uuid) -> Result<usize, Error> {
         Err(Error::Unimplemented)
@@ -1456,6 +2855,97 @@ pub trait RayGun:
         Err(Error::Unimplemented)
     }
 
+    /// Get the delivery and read receipts of a message in a conversation
+    async fn message_receipts(&self, _: Uuid, _: Uuid) -> Result<Vec<MessageReceipt>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Mark a message in a conversation as read. Same as [`RayGun::mark_read`]
+    async fn mark_message_read(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Set the last read message of a conversation, marking every message up to it as read.
+    /// The marker only moves forward. A read receipt is sent for the message unless read receipts are disabled
+    async fn mark_read(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get the last read message of a conversation
+    async fn last_read_message(&self, _: Uuid) -> Result<Option<Uuid>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get the number of messages sent by others after the last read message of a conversation
+    async fn unread_count(&self, _: Uuid) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get the number of unread messages in a conversation that mention own identity
+    async fn unread_mention_count(&self, _: Uuid) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Schedule a message to be sent to a conversation at a later time.
+    /// Returns the id of the scheduled message
+    async fn schedule_send(
+        &mut self,
+        _: Uuid,
+        _: Vec<String>,
+        _: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// List the messages waiting to be sent to a conversation
+    async fn list_scheduled_messages(&self, _: Uuid) -> Result<Vec<ScheduledMessage>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Cancel a scheduled message before it is sent
+    async fn cancel_scheduled_message(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Change when a scheduled message is sent
+    async fn reschedule_message(
+        &mut self,
+        _: Uuid,
+        _: Uuid,
+        _: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Store the unsent message of a conversation, replacing any existing draft
+    async fn set_draft(&mut self, _: Uuid, _: Draft) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get the unsent message of a conversation
+    async fn get_draft(&self, _: Uuid) -> Result<Option<Draft>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Remove the unsent message of a conversation
+    async fn clear_draft(&mut self, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Update the local notification settings of a conversation
+    async fn set_notification_settings(
+        &mut self,
+        _: Uuid,
+        _: NotificationSettings,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get the local notification settings of a conversation
+    async fn get_notification_settings(&self, _: Uuid) -> Result<NotificationSettings, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Retrieve all message references from a conversation
     async fn get_message_references(
         &self,
@@ -1477,6 +2967,11 @@ pub trait RayGun:
         options: MessageOptions,
     ) -> Result<Messages, Error>;
 
+    /// Search messages across all conversations and community channels
+    async fn search_messages(&self, _: SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Sends a message to a conversation.
     async fn send(&mut self, conversation_id: Uuid, message: Vec<String>) -> Result<Uuid, Error>;
 
@@ -1512,6 +3007,53 @@ pub trait RayGun:
         state: PinState,
     ) -> Result<(), Error>;
 
+    /// Sends a poll to a conversation
+    async fn send_poll(&mut self, _: Uuid, _: Poll) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Vote on a poll, replacing any previous vote. Options are indexes of [`Poll::options`]
+    async fn vote(&mut self, _: Uuid, _: Uuid, _: Vec<usize>) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Retract a vote on a poll
+    async fn retract_vote(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Close a poll so it no longer accepts votes. Only the creator of the poll can close it
+    async fn close_poll(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Share a position with a conversation. If `live` is provided, the position can be updated
+    /// with [`RayGun::update_geolocation`] for that duration, up to [`MAX_LIVE_GEOLOCATION_DURATION`]
+    async fn send_geolocation(
+        &mut self,
+        _: Uuid,
+        _: GeoPosition,
+        _: Option<Duration>,
+    ) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Send an update of a live position shared by own identity.
+    /// Updates are not stored and are only received by members that are online
+    async fn update_geolocation(&mut self, _: Uuid, _: Uuid, _: GeoPosition) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Stop sharing a live position before it expires
+    async fn stop_geolocation(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Share the contact card of an identity with a conversation
+    async fn send_contact_card(&mut self, _: Uuid, _: &DID) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Reply to a message within a conversation
     async fn reply(
         &mut self,
@@ -1520,6 +3062,17 @@ pub trait RayGun:
         message: Vec<String>,
     ) -> Result<Uuid, Error>;
 
+    /// Forward a message into another conversation, reusing the attachments of the original message.
+    /// The origin of the message is provided in the metadata of the new message. Only the forwarder
+    /// signs the new message, so the original sender in the metadata is unverified
+    async fn forward(&mut self, _: Uuid, _: Uuid, _: Uuid) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Generate previews for the links within a message sent by the own identity when enabled,
+    /// or remove them when disabled. Previews are fetched by the sender and attached to the message
+    /// so recipients do not contact the link themselves.
+    /// Previews are generated in the background and [`MessageEventKind::LinkPreviewsUpdated`] is emitted once stored
     async fn embeds(
         &mut self,
         conversation_id: Uuid,
@@ -1584,6 +3137,41 @@ pub trait RayGunGroupConversation: Sync + Send {
     async fn remove_recipient(&mut self, _: Uuid, _: &DID) -> Result<(), Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Create an invite to the conversation that expires at `expiry` and can be used up to `max_uses` times.
+    /// Members joining through the invite are added by the identity that created it
+    async fn create_conversation_invite(
+        &mut self,
+        _: Uuid,
+        _: Option<DateTime<Utc>>,
+        _: Option<u32>,
+    ) -> Result<ConversationInvite, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// List the invites to the conversation created by the own identity
+    async fn list_conversation_invites(&self, _: Uuid) -> Result<Vec<ConversationInvite>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Revoke an invite so its token can no longer be used
+    async fn revoke_conversation_invite(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Request to join a conversation using the token of an invite, returning the id of the conversation once joined.
+    /// The conversation is received from the inviter, or from shuttle if the inviter added the own identity while
+    /// it was unreachable. An error is returned if the inviter rejects the request or no answer is received in time,
+    /// although [`RayGunEventKind::ConversationCreated`] is still emitted if the inviter adds the own identity later
+    async fn accept_conversation_invite(&mut self, _: &str) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Rotate the key used to encrypt our messages in a group conversation, distributing the new key to the current members.
+    /// Keys are rotated automatically whenever a member is removed from the conversation
+    async fn rotate_conversation_key(&mut self, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -1600,6 +3188,17 @@ pub trait RayGunAttachment: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Send an Opus stream within an Ogg container, provided by [`Location::Stream`], as a voice note.
+    /// The duration and waveform of the audio are available from [`File::voice_note`] of the attachment
+    async fn attach_voice_note(
+        &mut self,
+        _: Uuid,
+        _: Option<Uuid>,
+        _: Location,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Downloads a file that been attached to a message
     /// Note: Must use the filename associated when downloading
     async fn download(
@@ -1658,4 +3257,58 @@ pub trait RayGunConversationInformation: Sync + Send {
         conversation_id: Uuid,
         description: Option<&str>,
     ) -> Result<(), Error>;
+
+    /// Set how long messages are kept in a conversation before they are removed.
+    /// Setting `None` will keep messages indefinitely
+    async fn set_conversation_message_retention(
+        &mut self,
+        _: Uuid,
+        _: Option<Duration>,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CustomEvent, MessageEvent, MAX_CUSTOM_EVENT_PAYLOAD_SIZE};
+    use std::time::Duration;
+
+    #[test]
+    fn custom_event_name() {
+        assert!(CustomEvent::new("app.recording_voice").is_ok());
+        assert!(CustomEvent::new("app.media.screen-share").is_ok());
+        assert!(CustomEvent::new("recording").is_err());
+        assert!(CustomEvent::new("app..recording").is_err());
+        assert!(CustomEvent::new("app.recording voice").is_err());
+        assert!(CustomEvent::new(format!("app.{}", "x".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn custom_event_payload() {
+        let event = CustomEvent::with_json("app.progress", &42u8).unwrap();
+        assert_eq!(event.decode::<u8>().unwrap(), 42);
+
+        let event = CustomEvent::with_cbor("app.progress", &"half").unwrap();
+        assert_eq!(event.decode::<String>().unwrap(), "half");
+
+        let payload = vec![0u8; MAX_CUSTOM_EVENT_PAYLOAD_SIZE];
+        assert!(CustomEvent::with_cbor("app.blob", &payload).is_err());
+
+        let mut event = CustomEvent::new("app.progress").unwrap();
+        assert!(event.set_ttl(Duration::ZERO).is_err());
+        assert!(event.set_ttl(Duration::from_secs(600)).is_err());
+        assert!(event.set_ttl(Duration::from_secs(30)).is_ok());
+
+        let event = MessageEvent::from(event);
+        let bytes = serde_json::to_vec(&event).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<MessageEvent>(&bytes).unwrap(),
+            event
+        );
+        assert_eq!(
+            serde_json::to_vec(&MessageEvent::Typing).unwrap(),
+            br#""typing""#
+        );
+    }
 }
@@ -22,10 +22,12 @@ use crate::raygun::{
     community::{
         Community, CommunityChannel, CommunityChannelType, CommunityInvite, RayGunCommunity,
     },
-    AttachmentEventStream, Conversation, ConversationImage, EmbedState, GroupPermissionOpt,
-    Location, Message, MessageEvent, MessageEventStream, MessageOptions, MessageReference,
-    MessageStatus, Messages, PinState, RayGun, RayGunAttachment, RayGunConversationInformation,
+    AttachmentEventStream, Conversation, ConversationImage, ConversationInvite, Draft, EmbedState,
+    GeoPosition, GroupPermissionOpt, Location, Message, MessageEvent, MessageEventStream,
+    MessageOptions, MessageReceipt, MessageReference, MessageStatus, Messages,
+    NotificationSettings, PinState, Poll, RayGun, RayGunAttachment, RayGunConversationInformation,
     RayGunEventStream, RayGunEvents, RayGunGroupConversation, RayGunStream, ReactionState,
+    ScheduledMessage, SearchQuery, SearchResult,
 };
 use crate::tesseract::Tesseract;
 use crate::warp::dummy::Dummy;
@@ -36,6 +38,7 @@ use futures::stream::BoxStream;
 use indexmap::IndexSet;
 use std::any::Any;
 use std::path::PathBuf;
+use std::time::Duration;
 use uuid::Uuid;
 
 pub struct Warp<M, R, C>
@@ -896,6 +899,17 @@ where
             .get_community_channel_messages(community_id, channel_id, options)
             .await
     }
+    async fn get_community_channel_thread(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        self.raygun
+            .get_community_channel_thread(community_id, channel_id, root_message_id, options)
+            .await
+    }
     async fn get_community_channel_message_count(
         &self,
         community_id: Uuid,
@@ -935,6 +949,110 @@ where
             .community_channel_message_status(community_id, channel_id, message_id)
             .await
     }
+    async fn community_channel_message_receipts(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        self.raygun
+            .community_channel_message_receipts(community_id, channel_id, message_id)
+            .await
+    }
+    async fn mark_community_channel_message_read(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .mark_community_channel_message_read(community_id, channel_id, message_id)
+            .await
+    }
+    async fn mark_community_channel_read(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .mark_community_channel_read(community_id, channel_id, message_id)
+            .await
+    }
+    async fn get_community_channel_last_read_message(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Option<Uuid>, Error> {
+        self.raygun
+            .get_community_channel_last_read_message(community_id, channel_id)
+            .await
+    }
+    async fn get_community_channel_unread_count(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        self.raygun
+            .get_community_channel_unread_count(community_id, channel_id)
+            .await
+    }
+    async fn get_community_channel_unread_mention_count(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<usize, Error> {
+        self.raygun
+            .get_community_channel_unread_mention_count(community_id, channel_id)
+            .await
+    }
+    async fn set_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        draft: Draft,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_community_channel_draft(community_id, channel_id, draft)
+            .await
+    }
+    async fn get_community_channel_draft(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Option<Draft>, Error> {
+        self.raygun
+            .get_community_channel_draft(community_id, channel_id)
+            .await
+    }
+    async fn clear_community_channel_draft(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .clear_community_channel_draft(community_id, channel_id)
+            .await
+    }
+    async fn set_community_channel_notification_settings(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_community_channel_notification_settings(community_id, channel_id, settings)
+            .await
+    }
+    async fn get_community_channel_notification_settings(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        self.raygun
+            .get_community_channel_notification_settings(community_id, channel_id)
+            .await
+    }
     async fn send_community_channel_message(
         &mut self,
         community_id: Uuid,
@@ -967,6 +1085,17 @@ where
             .reply_to_community_channel_message(community_id, channel_id, message_id, message)
             .await
     }
+    async fn forward_to_community_channel(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        community_id: Uuid,
+        channel_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        self.raygun
+            .forward_to_community_channel(conversation_id, message_id, community_id, channel_id)
+            .await
+    }
     async fn delete_community_channel_message(
         &mut self,
         community_id: Uuid,
@@ -1000,6 +1129,47 @@ where
             .react_to_community_channel_message(community_id, channel_id, message_id, state, emoji)
             .await
     }
+    async fn send_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        poll: Poll,
+    ) -> Result<Uuid, Error> {
+        self.raygun
+            .send_community_channel_poll(community_id, channel_id, poll)
+            .await
+    }
+    async fn vote_on_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        self.raygun
+            .vote_on_community_channel_poll(community_id, channel_id, message_id, options)
+            .await
+    }
+    async fn retract_community_channel_poll_vote(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .retract_community_channel_poll_vote(community_id, channel_id, message_id)
+            .await
+    }
+    async fn close_community_channel_poll(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .close_community_channel_poll(community_id, channel_id, message_id)
+            .await
+    }
     async fn send_community_channel_messsage_event(
         &mut self,
         community_id: Uuid,
@@ -1104,6 +1274,42 @@ where
             .remove_recipient(conversation_id, identity)
             .await
     }
+
+    async fn create_conversation_invite(
+        &mut self,
+        conversation_id: Uuid,
+        expiry: Option<DateTime<Utc>>,
+        max_uses: Option<u32>,
+    ) -> Result<ConversationInvite, Error> {
+        self.raygun
+            .create_conversation_invite(conversation_id, expiry, max_uses)
+            .await
+    }
+
+    async fn list_conversation_invites(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ConversationInvite>, Error> {
+        self.raygun.list_conversation_invites(conversation_id).await
+    }
+
+    async fn revoke_conversation_invite(
+        &mut self,
+        conversation_id: Uuid,
+        invite_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .revoke_conversation_invite(conversation_id, invite_id)
+            .await
+    }
+
+    async fn accept_conversation_invite(&mut self, token: &str) -> Result<Uuid, Error> {
+        self.raygun.accept_conversation_invite(token).await
+    }
+
+    async fn rotate_conversation_key(&mut self, conversation_id: Uuid) -> Result<(), Error> {
+        self.raygun.rotate_conversation_key(conversation_id).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1125,6 +1331,17 @@ where
             .await
     }
 
+    async fn attach_voice_note(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        location: Location,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        self.raygun
+            .attach_voice_note(conversation_id, message_id, location)
+            .await
+    }
+
     async fn download(
         &self,
         conversation_id: Uuid,
@@ -1189,6 +1406,16 @@ where
             .set_conversation_description(conversation_id, description)
             .await
     }
+
+    async fn set_conversation_message_retention(
+        &mut self,
+        conversation_id: Uuid,
+        retention: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_conversation_message_retention(conversation_id, retention)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1235,6 +1462,27 @@ where
         self.raygun.get_message(conversation_id, message_id).await
     }
 
+    async fn get_message_history(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<Message>, Error> {
+        self.raygun
+            .get_message_history(conversation_id, message_id)
+            .await
+    }
+
+    async fn get_thread(
+        &self,
+        conversation_id: Uuid,
+        root_message_id: Uuid,
+        options: MessageOptions,
+    ) -> Result<Messages, Error> {
+        self.raygun
+            .get_thread(conversation_id, root_message_id, options)
+            .await
+    }
+
     async fn get_message_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
         self.raygun.get_message_count(conversation_id).await
     }
@@ -1249,6 +1497,110 @@ where
             .await
     }
 
+    async fn message_receipts(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Vec<MessageReceipt>, Error> {
+        self.raygun
+            .message_receipts(conversation_id, message_id)
+            .await
+    }
+
+    async fn mark_message_read(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .mark_message_read(conversation_id, message_id)
+            .await
+    }
+
+    async fn mark_read(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.raygun.mark_read(conversation_id, message_id).await
+    }
+
+    async fn last_read_message(&self, conversation_id: Uuid) -> Result<Option<Uuid>, Error> {
+        self.raygun.last_read_message(conversation_id).await
+    }
+
+    async fn unread_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        self.raygun.unread_count(conversation_id).await
+    }
+
+    async fn unread_mention_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        self.raygun.unread_mention_count(conversation_id).await
+    }
+
+    async fn schedule_send(
+        &mut self,
+        conversation_id: Uuid,
+        lines: Vec<String>,
+        send_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        self.raygun
+            .schedule_send(conversation_id, lines, send_at)
+            .await
+    }
+
+    async fn list_scheduled_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ScheduledMessage>, Error> {
+        self.raygun.list_scheduled_messages(conversation_id).await
+    }
+
+    async fn cancel_scheduled_message(
+        &mut self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .cancel_scheduled_message(conversation_id, scheduled_id)
+            .await
+    }
+
+    async fn reschedule_message(
+        &mut self,
+        conversation_id: Uuid,
+        scheduled_id: Uuid,
+        send_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        self.raygun
+            .reschedule_message(conversation_id, scheduled_id, send_at)
+            .await
+    }
+
+    async fn set_draft(&mut self, conversation_id: Uuid, draft: Draft) -> Result<(), Error> {
+        self.raygun.set_draft(conversation_id, draft).await
+    }
+
+    async fn get_draft(&self, conversation_id: Uuid) -> Result<Option<Draft>, Error> {
+        self.raygun.get_draft(conversation_id).await
+    }
+
+    async fn clear_draft(&mut self, conversation_id: Uuid) -> Result<(), Error> {
+        self.raygun.clear_draft(conversation_id).await
+    }
+
+    async fn set_notification_settings(
+        &mut self,
+        conversation_id: Uuid,
+        settings: NotificationSettings,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_notification_settings(conversation_id, settings)
+            .await
+    }
+
+    async fn get_notification_settings(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<NotificationSettings, Error> {
+        self.raygun.get_notification_settings(conversation_id).await
+    }
+
     async fn get_message_references(
         &self,
         conversation_id: Uuid,
@@ -1277,6 +1629,10 @@ where
         self.raygun.get_messages(conversation_id, options).await
     }
 
+    async fn search_messages(&self, query: SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        self.raygun.search_messages(query).await
+    }
+
     async fn send(&mut self, conversation_id: Uuid, message: Vec<String>) -> Result<Uuid, Error> {
         self.raygun.send(conversation_id, message).await
     }
@@ -1319,6 +1675,63 @@ where
         self.raygun.pin(conversation_id, message_id, state).await
     }
 
+    async fn send_poll(&mut self, conversation_id: Uuid, poll: Poll) -> Result<Uuid, Error> {
+        self.raygun.send_poll(conversation_id, poll).await
+    }
+
+    async fn vote(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        options: Vec<usize>,
+    ) -> Result<(), Error> {
+        self.raygun.vote(conversation_id, message_id, options).await
+    }
+
+    async fn retract_vote(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.raygun.retract_vote(conversation_id, message_id).await
+    }
+
+    async fn close_poll(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.raygun.close_poll(conversation_id, message_id).await
+    }
+
+    async fn send_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        position: GeoPosition,
+        live: Option<Duration>,
+    ) -> Result<Uuid, Error> {
+        self.raygun
+            .send_geolocation(conversation_id, position, live)
+            .await
+    }
+
+    async fn update_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        position: GeoPosition,
+    ) -> Result<(), Error> {
+        self.raygun
+            .update_geolocation(conversation_id, message_id, position)
+            .await
+    }
+
+    async fn stop_geolocation(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(), Error> {
+        self.raygun
+            .stop_geolocation(conversation_id, message_id)
+            .await
+    }
+
+    async fn send_contact_card(&mut self, conversation_id: Uuid, did: &DID) -> Result<Uuid, Error> {
+        self.raygun.send_contact_card(conversation_id, did).await
+    }
+
     async fn reply(
         &mut self,
         conversation_id: Uuid,
@@ -1330,9 +1743,24 @@ where
             .await
     }
 
-    //TODO: Remove
-    async fn embeds(&mut self, _: Uuid, _: Uuid, _: EmbedState) -> Result<(), Error> {
-        unreachable!()
+    async fn forward(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        target_conversation_id: Uuid,
+    ) -> Result<Uuid, Error> {
+        self.raygun
+            .forward(conversation_id, message_id, target_conversation_id)
+            .await
+    }
+
+    async fn embeds(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        state: EmbedState,
+    ) -> Result<(), Error> {
+        self.raygun.embeds(conversation_id, message_id, state).await
     }
 
     async fn update_conversation_permissions<P: Into<GroupPermissionOpt> + Send + Sync>(